@@ -0,0 +1,63 @@
+extern crate blockly_parser;
+extern crate criterion;
+
+use criterion::{
+    criterion_group,
+    criterion_main,
+    Criterion,
+};
+
+use blockly_parser::program_from_xml;
+
+// A single stack of `length` blocks joined with `<next>`, the shape of large
+// generated workspaces
+fn flat_stack(length: usize) -> String {
+    let mut xml = String::from(r#"<xml xmlns="https://developers.google.com/blockly/xml">"#);
+    for index in 0..length {
+        if index > 0 {
+            xml.push_str("<next>");
+        }
+        xml.push_str(&format!(r#"<block type="led_on" id="b{}"><field name="TIME">{}</field>"#, index, index));
+    }
+    for index in 0..length {
+        xml.push_str("</block>");
+        if index + 1 < length {
+            xml.push_str("</next>");
+        }
+    }
+    xml.push_str("</xml>");
+    xml
+}
+
+// `count` stacks of loops each holding a few blocks
+fn nested(count: usize) -> String {
+    let mut xml = String::from(r#"<xml xmlns="https://developers.google.com/blockly/xml">"#);
+    for index in 0..count {
+        xml.push_str(&format!(r#"
+            <block type="controls_repeat_ext" id="loop{0}" x="{0}" y="{0}">
+                <value name="TIMES">
+                    <shadow type="math_number" id="times{0}"><field name="NUM">10</field></shadow>
+                </value>
+                <statement name="DO">
+                    <block type="led_on" id="on{0}">
+                        <field name="TIME">300</field>
+                        <next><block type="led_off" id="off{0}"></block></next>
+                    </block>
+                </statement>
+            </block>
+        "#, index));
+    }
+    xml.push_str("</xml>");
+    xml
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let flat = flat_stack(2000);
+    c.bench_function("flat stack of 2000 blocks", |b| b.iter(|| program_from_xml(&flat).unwrap()));
+
+    let nested = nested(200);
+    c.bench_function("200 nested loops", |b| b.iter(|| program_from_xml(&nested).unwrap()));
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,95 @@
+//! `#[derive(FromBlock)]` for the `blockly-parser` crate. Enable the parent
+//! crate's `derive` feature rather than depending on this crate directly.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input,
+    Data,
+    DeriveInput,
+    Fields,
+    LitStr,
+};
+
+#[proc_macro_derive(FromBlock, attributes(blockly))]
+pub fn derive_from_block(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+
+    let mut block_type = to_snake_case(&name.to_string());
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("blockly")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("block_type") {
+                block_type = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("expected `block_type = \"...\"`"))
+            }
+        })?;
+    }
+
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(input, "FromBlock requires a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(input, "FromBlock can only be derived for structs")),
+    };
+
+    let mut initializers = Vec::new();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().expect("named field");
+        let mut field_name = ident.to_string().to_uppercase();
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("blockly")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("field") {
+                    field_name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `field = \"...\"`"))
+                }
+            })?;
+        }
+        initializers.push(quote! {
+            #ident: ::blockly_parser::FromField::from_field(block, #field_name)
+                .map_err(::blockly_parser::FromBlockError::Field)?
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::blockly_parser::FromBlock for #name #ty_generics #where_clause {
+            fn from_block(block: &::blockly_parser::Block) -> ::std::result::Result<Self, ::blockly_parser::FromBlockError> {
+                ::blockly_parser::expect_block_type(block, #block_type)?;
+                ::std::result::Result::Ok(#name {
+                    #(#initializers,)*
+                })
+            }
+        }
+    })
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
@@ -0,0 +1,50 @@
+use blockly_parser::{
+    program_from_xml,
+    BlockBuilder,
+    FieldError,
+    FromBlock,
+    FromBlockError,
+};
+
+#[derive(FromBlock, Debug, PartialEq)]
+struct LedOn {
+    time: u32,
+}
+
+#[derive(FromBlock, Debug, PartialEq)]
+#[blockly(block_type = "inner_loop")]
+struct Repeat {
+    #[blockly(field = "COUNT")]
+    times: u8,
+    label: Option<String>,
+}
+
+#[test]
+fn test_derive_from_block() {
+    let xml: &str = r#"
+        <xml xmlns="https://developers.google.com/blockly/xml">
+            <block type="inner_loop" id="]Lb|t?wfd#;s)[llJx8Y">
+                <field name="COUNT">3</field>
+                <statement name="BODY">
+                    <block type="led_on" id="^3xb.m4E9i0;3$R10(=5">
+                        <field name="TIME">300</field>
+                    </block>
+                </statement>
+            </block>
+        </xml>
+    "#;
+    let program = program_from_xml(xml).unwrap();
+    let inner_loop_block = &program.groups[0].blocks[0];
+    let led_on_block = &inner_loop_block.statements["BODY"].blocks[0];
+
+    assert_eq!(Repeat::from_block(inner_loop_block), Ok(Repeat { times: 3, label: None }));
+    assert_eq!(LedOn::from_block(led_on_block), Ok(LedOn { time: 300 }));
+    assert_eq!(LedOn::from_block(inner_loop_block), Err(FromBlockError::WrongBlockType {
+        expected: "led_on".to_string(),
+        found: "inner_loop".to_string(),
+    }));
+    assert_eq!(
+        Repeat::from_block(&BlockBuilder::new("inner_loop").build()),
+        Err(FromBlockError::Field(FieldError::Missing("COUNT".to_string())))
+    );
+}
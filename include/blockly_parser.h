@@ -0,0 +1,49 @@
+/*
+ * C interface to blockly-parser, built with the `ffi` feature:
+ *
+ *     cargo rustc --lib --release --features ffi --crate-type staticlib
+ *
+ * Programs and strings returned here are owned by the caller. Release them
+ * with blockly_program_free and blockly_string_free.
+ */
+
+#ifndef BLOCKLY_PARSER_H
+#define BLOCKLY_PARSER_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A parsed workspace. */
+typedef struct BlocklyProgram BlocklyProgram;
+
+/*
+ * Parse a workspace from NUL-terminated XML. Returns NULL if it cannot be
+ * parsed, or if parsing panics, and then stores the error message in *error
+ * unless error is NULL.
+ */
+BlocklyProgram *blockly_parse(const char *xml, char **error);
+
+void blockly_program_free(BlocklyProgram *program);
+
+/* The number of blocks in the program, counting nested and shadow blocks. */
+size_t blockly_block_count(const BlocklyProgram *program);
+
+/*
+ * The text of a field on the block at path, e.g. "0/1/BODY/2/VALUE": the
+ * top-level stack, the position in it, then each input followed, with a
+ * position after statement inputs. Returns NULL if there is no such block or
+ * field. A step that is a number is read as a position, so statement inputs
+ * with numeric names cannot be reached.
+ */
+char *blockly_field(const BlocklyProgram *program, const char *path, const char *field);
+
+void blockly_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BLOCKLY_PARSER_H */
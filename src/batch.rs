@@ -0,0 +1,58 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::{
+    Program,
+    ParseError,
+    ParserOptions,
+    program_from_xml_with_options,
+};
+
+// Results are often handed to other threads once parsed
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ParseError>();
+    assert_send_sync::<super::Workspace>();
+};
+
+/// Parse many XML workspaces, e.g. every file in a class's submissions. The
+/// results are in the order of the input. With the `parallel` feature the
+/// workspaces are parsed on rayon's thread pool.
+pub fn parse_batch<I, S>(workspaces: I) -> Vec<Result<Program, ParseError>>
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str> + Send
+{
+    parse_batch_with_options(workspaces, &ParserOptions::default())
+}
+
+pub fn parse_batch_with_options<I, S>(workspaces: I, options: &ParserOptions) -> Vec<Result<Program, ParseError>>
+    where I: IntoIterator<Item = S>,
+          S: AsRef<str> + Send
+{
+    let workspaces: Vec<S> = workspaces.into_iter().collect();
+    #[cfg(feature = "parallel")]
+    let iter = workspaces.into_par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let iter = workspaces.into_iter();
+    iter.map(|xml| program_from_xml_with_options(xml.as_ref(), options)).collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_batch() {
+        let workspaces = vec![
+            r#"<xml><block type="led_on" id="a"></block></xml>"#.to_string(),
+            "<workspace></workspace>".to_string(),
+            r#"<xml><block type="led_off" id="b"></block></xml>"#.to_string(),
+        ];
+        let results = parse_batch(&workspaces);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().groups[0].blocks[0].id, "a");
+        assert_eq!(results[1].as_ref().unwrap_err(), &ParseError::MissingRootElement);
+        assert_eq!(results[2].as_ref().unwrap().groups[0].blocks[0].id, "b");
+    }
+}
@@ -0,0 +1,183 @@
+//! Inspect and convert Blockly workspaces from the command line.
+//!
+//! Every command reads a workspace's XML from a file, or from stdin when the
+//! path is `-`. Exits with 1 when `validate` finds problems and 2 on errors.
+
+extern crate blockly_parser;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+use blockly_parser::{
+    BlockRegistry,
+    ParseError,
+    Program,
+    Toolbox,
+    program_from_file,
+    program_from_reader,
+    program_to_json,
+    toolbox_from_json,
+    toolbox_from_xml,
+};
+
+const USAGE: &str = "\
+Usage: blockly-parser <command> [options] <file.xml | ->
+
+Commands:
+    inspect     Print the workspace as an indented tree of blocks
+    to-json     Print the workspace in Blockly's JSON serialization format
+    stats       Print block counts and nesting depth
+    validate    Check block types and ids
+        --toolbox <toolbox.xml>     Only allow block types listed in an XML or JSON toolbox
+        --definitions <blocks.json> Check blocks against Blockly JSON definitions";
+
+const COMMANDS: &[&str] = &["inspect", "to-json", "stats", "validate"];
+
+fn main() {
+    match run(env::args().skip(1).collect()) {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(message) => {
+            eprintln!("blockly-parser: {}", message);
+            process::exit(2);
+        }
+    }
+}
+
+// Returns whether the command succeeded; `validate` fails when it finds issues
+fn run(args: Vec<String>) -> Result<bool, String> {
+    let mut args = args.into_iter();
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Err(USAGE.to_string()),
+    };
+
+    let mut path = None;
+    let mut toolbox = None;
+    let mut definitions = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--toolbox" => toolbox = Some(args.next().ok_or("--toolbox needs a file")?),
+            "--definitions" => definitions = Some(args.next().ok_or("--definitions needs a file")?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(true);
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`\n\n{}", arg, USAGE)),
+        }
+    }
+    if command == "-h" || command == "--help" || command == "help" {
+        println!("{}", USAGE);
+        return Ok(true);
+    }
+    if !COMMANDS.contains(&command.as_str()) {
+        return Err(format!("unknown command `{}`\n\n{}", command, USAGE));
+    }
+    let path = path.ok_or_else(|| format!("missing workspace file\n\n{}", USAGE))?;
+    if command != "validate" && (toolbox.is_some() || definitions.is_some()) {
+        return Err(format!("`{}` does not take --toolbox or --definitions", command));
+    }
+
+    let program = read_program(&path)?;
+    match command.as_str() {
+        "inspect" => {
+            print!("{}", program.render_tree());
+            Ok(true)
+        },
+        "to-json" => {
+            println!("{}", program_to_json(&program));
+            Ok(true)
+        },
+        "stats" => {
+            let stats = program.stats();
+            println!("blocks: {}", stats.block_count);
+            println!("stacks: {}", stats.stack_count);
+            println!("max depth: {}", stats.max_depth);
+            let mut by_type: Vec<_> = stats.blocks_by_type.iter().collect();
+            by_type.sort();
+            for (block_type, count) in by_type {
+                println!("  {}: {}", block_type, count);
+            }
+            Ok(true)
+        },
+        "validate" => {
+            let mut problems = Vec::new();
+            if let Err(error) = program.check_unique_ids() {
+                problems.push(error.to_string());
+            }
+            if let Some(toolbox) = toolbox {
+                let allowed = parse_toolbox(&read_file(&toolbox)?)
+                    .map_err(|error| format!("{}: {}", toolbox, error))?;
+                for block in program.iter_blocks() {
+                    if !allowed.contains(&block.block_type) {
+                        problems.push(format!("block {} has type `{}`, which is not in the toolbox", block.id, block.block_type));
+                    }
+                }
+            }
+            if let Some(definitions) = definitions {
+                let registry = BlockRegistry::from_json(&read_file(&definitions)?)
+                    .map_err(|error| format!("{}: {}", definitions, error))?;
+                for issue in program.validate(&registry) {
+                    problems.push(issue.to_string());
+                }
+            }
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            Ok(problems.is_empty())
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn read_program(path: &str) -> Result<Program, String> {
+    let result = if path == "-" {
+        let stdin = io::stdin();
+        let lock = stdin.lock();
+        program_from_reader(lock)
+    } else {
+        program_from_file(path)
+    };
+    result.map_err(|error| format!("{}: {}", path, error))
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))
+}
+
+// A toolbox in JSON if it looks like JSON, or else in XML
+fn parse_toolbox(text: &str) -> Result<Toolbox, ParseError> {
+    if text.trim_start().starts_with('{') {
+        toolbox_from_json(text)
+    } else {
+        toolbox_from_xml(text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_toolbox() {
+        let toolbox = parse_toolbox(r#"
+            <xml>
+                <category name="LEDs"><block type="led_on"><value name="B"><shadow type="math_number"/></value></block></category>
+                <block type="main_loop"/>
+            </xml>
+        "#).unwrap();
+        let types: Vec<_> = toolbox.block_types().into_iter().collect();
+        assert_eq!(types, vec!["led_on", "main_loop", "math_number"]);
+        let toolbox = parse_toolbox(r#"{ "kind": "flyoutToolbox", "contents": [{ "kind": "block", "type": "led_on" }] }"#).unwrap();
+        assert!(toolbox.contains("led_on"));
+    }
+
+    #[test]
+    fn test_run_errors() {
+        assert!(run(vec!["bogus".to_string(), "file.xml".to_string()]).unwrap_err().starts_with("unknown command `bogus`"));
+        assert!(run(vec!["stats".to_string()]).unwrap_err().starts_with("missing workspace file"));
+    }
+}
@@ -0,0 +1,437 @@
+use std::borrow::Cow;
+
+use sxd_document::Package;
+use sxd_document::dom::{
+    Element,
+    ChildOfElement,
+};
+
+use super::{
+    BlockFlags,
+    Diagnostics,
+    ParseError,
+    ParserOptions,
+    check_colour_field,
+    check_depth,
+    check_input_children,
+    get_child_element,
+    get_next_block_element,
+    get_xml_element,
+    missing_attribute,
+    parse_bool,
+    parse_coordinate,
+    parse_package,
+    unexpected_element,
+};
+
+/// A parsed XML document, kept alive so a `ProgramRef` can borrow its strings.
+///
+/// ```
+/// use blockly_parser::{XmlDocument, ParserOptions};
+///
+/// let document = XmlDocument::parse(r#"<xml><block type="led_on" id="a"></block></xml>"#, &ParserOptions::default()).unwrap();
+/// let program = document.program().unwrap();
+/// assert_eq!(program.groups[0].blocks[0].block_type, "led_on");
+/// ```
+pub struct XmlDocument {
+    package: Package,
+    options: ParserOptions,
+}
+
+/// A read-only view of a workspace that borrows types, ids and field values
+/// from an `XmlDocument` instead of copying them. Mutations, comments and
+/// `<data>` are not included; use `program_from_xml` for those.
+#[derive(PartialEq, Debug)]
+pub struct ProgramRef<'d> {
+    pub groups: Vec<StatementBodyRef<'d>>,
+    pub variables: Vec<VariableRef<'d>>,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct VariableRef<'d> {
+    pub name: Cow<'d, str>,
+    pub id: &'d str,
+    pub var_type: Option<&'d str>,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct StatementBodyRef<'d> {
+    pub blocks: Vec<BlockRef<'d>>,
+    pub shadow: bool,
+}
+
+/// Inputs and fields are kept in document order.
+#[derive(PartialEq, Debug)]
+pub struct BlockRef<'d> {
+    pub block_type: &'d str,
+    pub id: &'d str,
+    pub fields: Vec<(&'d str, FieldValueRef<'d>)>,
+    pub statements: Vec<(&'d str, StatementBodyRef<'d>)>,
+    pub values: Vec<(&'d str, InputContentRef<'d>)>,
+    pub position: Option<(i32, i32)>,
+    pub flags: BlockFlags,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum InputContentRef<'d> {
+    Block(BlockRef<'d>),
+    Shadow(BlockRef<'d>),
+}
+
+#[derive(PartialEq, Debug)]
+pub enum FieldValueRef<'d> {
+    /// Borrowed unless the text was split across several nodes.
+    SimpleField(Cow<'d, str>),
+    ExpressionField(Box<BlockRef<'d>>),
+}
+
+impl XmlDocument {
+    pub fn parse(xml: &str, options: &ParserOptions) -> Result<Self, ParseError> {
+        if let Some(max_depth) = options.max_depth {
+            check_depth(xml, max_depth)?;
+        }
+        Ok(Self {
+            package: parse_package(xml)?,
+            options: options.clone(),
+        })
+    }
+
+    pub fn program(&self) -> Result<ProgramRef<'_>, ParseError> {
+        let options = &self.options;
+        let document = self.package.as_document();
+        let xml_element = get_xml_element(document).ok_or(ParseError::MissingRootElement)?;
+
+        let mut program = ProgramRef {
+            groups: Vec::new(),
+            variables: Vec::new(),
+        };
+        for el in child_elements(xml_element) {
+            match el.name().local_part() {
+                "block" => {
+                    program.groups.push(StatementBodyRef::from_element(Some(el), options)?);
+                },
+                "variables" => {
+                    for variable_el in child_elements(el) {
+                        if variable_el.name().local_part() == "variable" {
+                            program.variables.push(VariableRef::from_element(variable_el, options)?);
+                        } else if options.strict_unknown_elements {
+                            return Err(unexpected_element(el, variable_el));
+                        }
+                    }
+                },
+                "comment" => {},
+                _ => {
+                    if options.strict_unknown_elements {
+                        return Err(unexpected_element(xml_element, el));
+                    }
+                }
+            }
+        }
+        Ok(program)
+    }
+}
+
+impl<'d> ProgramRef<'d> {
+    /// All blocks in document order, as for `Program::iter_blocks`.
+    pub fn iter_blocks(&self) -> BlockRefs<'_, 'd> {
+        let mut stack: Vec<_> = self.groups.iter()
+            .flat_map(|group| group.blocks.iter())
+            .collect();
+        stack.reverse();
+        BlockRefs {
+            stack
+        }
+    }
+}
+
+pub struct BlockRefs<'a, 'd: 'a> {
+    stack: Vec<&'a BlockRef<'d>>,
+}
+
+impl<'a, 'd> Iterator for BlockRefs<'a, 'd> {
+    type Item = &'a BlockRef<'d>;
+
+    fn next(&mut self) -> Option<&'a BlockRef<'d>> {
+        let block = self.stack.pop()?;
+        let mut children: Vec<&'a BlockRef<'d>> = Vec::new();
+        for (_, field) in block.fields.iter() {
+            if let FieldValueRef::ExpressionField(ref expression) = *field {
+                children.push(expression);
+            }
+        }
+        for (_, input) in block.values.iter() {
+            children.push(input.block());
+        }
+        for (_, statement) in block.statements.iter() {
+            children.extend(statement.blocks.iter());
+        }
+        children.reverse();
+        self.stack.extend(children);
+        Some(block)
+    }
+}
+
+impl<'d> StatementBodyRef<'d> {
+    fn from_element(first_block: Option<Element<'d>>, options: &ParserOptions) -> Result<Self, ParseError> {
+        let mut blocks = Vec::new();
+        let mut block_el = first_block;
+        while let Some(el) = block_el {
+            blocks.push(BlockRef::from_element(el, options)?);
+            block_el = get_next_block_element(el);
+        }
+        Ok(Self {
+            blocks,
+            shadow: false,
+        })
+    }
+}
+
+impl<'d> BlockRef<'d> {
+    pub fn field(&self, name: &str) -> Option<&FieldValueRef<'d>> {
+        self.fields.iter().find(|&&(field_name, _)| field_name == name).map(|(_, value)| value)
+    }
+
+    pub fn statement(&self, name: &str) -> Option<&StatementBodyRef<'d>> {
+        self.statements.iter().find(|&&(input_name, _)| input_name == name).map(|(_, body)| body)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&InputContentRef<'d>> {
+        self.values.iter().find(|&&(input_name, _)| input_name == name).map(|(_, content)| content)
+    }
+
+    fn from_element(block_el: Element<'d>, options: &ParserOptions) -> Result<Self, ParseError> {
+        let block_type = block_el.attribute_value("type").ok_or_else(|| missing_attribute(block_el, "type"))?;
+        let id = block_el.attribute_value("id").unwrap_or("");
+        if options.require_ids && id.is_empty() {
+            return Err(missing_attribute(block_el, "id"));
+        }
+        let mut block = Self {
+            block_type,
+            id,
+            fields: Vec::new(),
+            statements: Vec::new(),
+            values: Vec::new(),
+            position: None,
+            flags: BlockFlags::default(),
+        };
+
+        let mut x = None;
+        let mut y = None;
+        for attribute in block_el.attributes().iter() {
+            if attribute.name().namespace_uri().is_some() {
+                continue;
+            }
+            let name = attribute.name().local_part();
+            let value = attribute.value();
+            match name {
+                "x" => { x = Some(parse_coordinate(block_el, name, value)?); },
+                "y" => { y = Some(parse_coordinate(block_el, name, value)?); },
+                "deletable" => { block.flags.deletable = parse_bool(block_el, name, value)?; },
+                "movable" => { block.flags.movable = parse_bool(block_el, name, value)?; },
+                "editable" => { block.flags.editable = parse_bool(block_el, name, value)?; },
+                "collapsed" => { block.flags.collapsed = parse_bool(block_el, name, value)?; },
+                "disabled" => { block.flags.disabled = parse_bool(block_el, name, value)?; },
+                _ => {}
+            }
+        }
+        if let (Some(x), Some(y)) = (x, y) {
+            block.position = Some((x, y));
+        }
+
+        for child_el in child_elements(block_el) {
+            match child_el.name().local_part() {
+                "statement" => {
+                    let name = input_name(child_el)?;
+                    check_input_children(child_el, options, &mut Diagnostics::new())?;
+                    let body = if let Some(first) = get_child_element(child_el, "block") {
+                        StatementBodyRef::from_element(Some(first), options)?
+                    } else if let Some(first) = get_child_element(child_el, "shadow") {
+                        let mut body = StatementBodyRef::from_element(Some(first), options)?;
+                        body.shadow = true;
+                        body
+                    } else {
+                        StatementBodyRef::from_element(None, options)?
+                    };
+                    block.statements.push((name, body));
+                },
+                "value" => {
+                    let name = input_name(child_el)?;
+                    check_input_children(child_el, options, &mut Diagnostics::new())?;
+                    if let Some(block_el) = get_child_element(child_el, "block") {
+                        block.values.push((name, InputContentRef::Block(BlockRef::from_element(block_el, options)?)));
+                    } else if let Some(shadow_el) = get_child_element(child_el, "shadow") {
+                        block.values.push((name, InputContentRef::Shadow(BlockRef::from_element(shadow_el, options)?)));
+                    }
+                },
+                "field" => {
+                    let name = input_name(child_el)?;
+                    let value = FieldValueRef::from_element(child_el, options)?;
+                    if let FieldValueRef::SimpleField(ref text) = value {
+                        check_colour_field(name, text, options, None)?;
+                    }
+                    block.fields.push((name, value));
+                },
+                "mutation" | "comment" | "next" | "data" => {},
+                _ => {
+                    if options.strict_unknown_elements {
+                        return Err(unexpected_element(block_el, child_el));
+                    }
+                }
+            }
+        }
+
+        Ok(block)
+    }
+}
+
+impl<'d> InputContentRef<'d> {
+    pub fn block(&self) -> &BlockRef<'d> {
+        match *self {
+            InputContentRef::Block(ref block) | InputContentRef::Shadow(ref block) => block,
+        }
+    }
+
+    pub fn is_shadow(&self) -> bool {
+        match *self {
+            InputContentRef::Block(_) => false,
+            InputContentRef::Shadow(_) => true,
+        }
+    }
+}
+
+impl<'d> FieldValueRef<'d> {
+    fn from_element(field_el: Element<'d>, options: &ParserOptions) -> Result<Self, ParseError> {
+        if let Some(child_el) = child_elements(field_el).next() {
+            return match child_el.name().local_part() {
+                "block" | "shadow" => {
+                    let block = BlockRef::from_element(child_el, options)?;
+                    Ok(FieldValueRef::ExpressionField(Box::new(block)))
+                },
+                _ => Err(unexpected_element(field_el, child_el))
+            };
+        }
+        Ok(FieldValueRef::SimpleField(get_text(field_el)))
+    }
+}
+
+impl<'d> VariableRef<'d> {
+    fn from_element(variable_el: Element<'d>, options: &ParserOptions) -> Result<Self, ParseError> {
+        let id = variable_el.attribute_value("id").unwrap_or("");
+        if options.require_ids && id.is_empty() {
+            return Err(missing_attribute(variable_el, "id"));
+        }
+        Ok(Self {
+            name: get_text(variable_el),
+            id,
+            var_type: variable_el.attribute_value("type").and_then(|var_type| {
+                if var_type.is_empty() { None } else { Some(var_type) }
+            }),
+        })
+    }
+}
+
+fn input_name<'d>(input_el: Element<'d>) -> Result<&'d str, ParseError> {
+    input_el.attribute_value("name").ok_or_else(|| missing_attribute(input_el, "name"))
+}
+
+fn child_elements<'d>(element: Element<'d>) -> impl Iterator<Item = Element<'d>> {
+    element.children()
+        .into_iter()
+        .filter_map(|child| {
+            if let ChildOfElement::Element(el) = child {
+                return Some(el);
+            }
+            None
+        })
+}
+
+// Borrows the text when it is a single node, which is the usual case
+fn get_text<'d>(element: Element<'d>) -> Cow<'d, str> {
+    let mut texts = element.children()
+        .into_iter()
+        .filter_map(|child| {
+            if let ChildOfElement::Text(text_node) = child {
+                return Some(text_node.text());
+            }
+            None
+        });
+    let first = match texts.next() {
+        Some(first) => first,
+        None => return Cow::Borrowed("")
+    };
+    match texts.next() {
+        None => Cow::Borrowed(first),
+        Some(second) => {
+            let mut text = String::from(first);
+            text.push_str(second);
+            text.extend(texts);
+            Cow::Owned(text)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    const XML: &str = r#"
+        <xml xmlns="https://developers.google.com/blockly/xml">
+            <variables>
+                <variable id="v1">count</variable>
+            </variables>
+            <block type="main_loop" id="main" x="10" y="20">
+                <statement name="BODY">
+                    <block type="led_on" id="on">
+                        <field name="TIME">300</field>
+                        <value name="BRIGHTNESS">
+                            <shadow type="math_number" id="num">
+                                <field name="NUM">80</field>
+                            </shadow>
+                        </value>
+                        <next>
+                            <block type="led_off" id="off"></block>
+                        </next>
+                    </block>
+                </statement>
+            </block>
+        </xml>
+    "#;
+
+    #[test]
+    fn test_program_ref() {
+        let document = XmlDocument::parse(XML, &ParserOptions::default()).unwrap();
+        let program = document.program().unwrap();
+
+        assert_eq!(program.variables, vec![VariableRef { name: Cow::Borrowed("count"), id: "v1", var_type: None }]);
+
+        let main_loop_block = &program.groups[0].blocks[0];
+        assert_eq!(main_loop_block.position, Some((10, 20)));
+        let led_on_block = &main_loop_block.statement("BODY").unwrap().blocks[0];
+        match led_on_block.field("TIME") {
+            Some(FieldValueRef::SimpleField(Cow::Borrowed(time))) => assert_eq!(*time, "300"),
+            other => panic!("unexpected field {:?}", other),
+        }
+        assert!(led_on_block.value("BRIGHTNESS").unwrap().is_shadow());
+
+        // Same blocks, in the same order, as the owned parser
+        let owned = program_from_xml(XML).unwrap();
+        let ids: Vec<&str> = program.iter_blocks().map(|block| block.id).collect();
+        let owned_ids: Vec<&str> = owned.iter_blocks().map(|block| block.id.as_str()).collect();
+        assert_eq!(ids, owned_ids);
+    }
+
+    #[test]
+    fn test_program_ref_errors() {
+        let document = XmlDocument::parse(r#"<xml><block id="a"></block></xml>"#, &ParserOptions::default()).unwrap();
+        assert_eq!(document.program().unwrap_err(), ParseError::MissingAttribute {
+            element: "block".to_string(),
+            attribute: "type".to_string(),
+            span: None,
+        });
+
+        let document = XmlDocument::parse(r#"<xml><block type="a"></block></xml>"#, &ParserOptions::strict()).unwrap();
+        assert!(document.program().is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    InputContent,
+    Mutation,
+    Variable,
+    RepeatedIdError,
+};
+use super::ids::IdGenerator;
+
+/// Builds a block, or a stack of blocks joined with `next`.
+///
+/// ```
+/// use blockly_parser::BlockBuilder;
+///
+/// let stack = BlockBuilder::new("led_on")
+///     .field("TIME", "300")
+///     .next(BlockBuilder::new("led_off").field("TIME", "100"))
+///     .build_stack();
+/// assert_eq!(stack.blocks.len(), 2);
+/// ```
+#[derive(Debug)]
+pub struct BlockBuilder {
+    block: Block,
+    next: Option<Box<BlockBuilder>>,
+}
+
+impl BlockBuilder {
+    pub fn new<T: Into<String>>(block_type: T) -> Self {
+        Self {
+            block: Block::new(block_type),
+            next: None,
+        }
+    }
+
+    pub fn id<T: Into<String>>(mut self, id: T) -> Self {
+        self.block.id = id.into();
+        self
+    }
+
+    pub fn position(mut self, x: i32, y: i32) -> Self {
+        self.block.position = Some((x, y));
+        self
+    }
+
+    pub fn field<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.block.fields.insert(name.into(), FieldValue::SimpleField(value.into()));
+        self
+    }
+
+    /// Connect a block to a value input. Blocks chained to it with `next` are ignored.
+    pub fn value<N: Into<String>>(mut self, name: N, block: BlockBuilder) -> Self {
+        self.block.values.insert(name.into(), InputContent::Block(block.build()));
+        self
+    }
+
+    /// Put a shadow block in a value input.
+    pub fn shadow<N: Into<String>>(mut self, name: N, block: BlockBuilder) -> Self {
+        self.block.values.insert(name.into(), InputContent::Shadow(block.build()));
+        self
+    }
+
+    /// Put a stack of blocks, starting at `first`, in a statement input.
+    pub fn statement<N: Into<String>>(mut self, name: N, first: BlockBuilder) -> Self {
+        self.block.statements.insert(name.into(), first.build_stack());
+        self
+    }
+
+    pub fn mutation(mut self, mutation: Mutation) -> Self {
+        self.block.mutation = Some(mutation);
+        self
+    }
+
+    pub fn collapsed(mut self, collapsed: bool) -> Self {
+        self.block.flags.collapsed = collapsed;
+        self
+    }
+
+    pub fn disabled(mut self, disabled: bool) -> Self {
+        self.block.flags.disabled = disabled;
+        self
+    }
+
+    /// Attach a block below this one. Calling `next` again appends to the end
+    /// of the stack, so `a.next(b).next(c)` builds `a`, `b`, `c`.
+    pub fn next(mut self, block: BlockBuilder) -> Self {
+        self.next = Some(Box::new(match self.next.take() {
+            Some(existing) => existing.next(block),
+            None => block
+        }));
+        self
+    }
+
+    /// The block itself, without the blocks attached below it with `next`.
+    pub fn build(self) -> Block {
+        self.block
+    }
+
+    /// This block and every block attached below it with `next`.
+    pub fn build_stack(self) -> StatementBody {
+        let mut blocks = Vec::new();
+        let mut builder = Some(self);
+        while let Some(current) = builder {
+            blocks.push(current.block);
+            builder = current.next.map(|next| *next);
+        }
+        StatementBody {
+            blocks,
+            shadow: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ProgramBuilder {
+    program: Program,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a top-level stack starting at `first`.
+    pub fn group(mut self, first: BlockBuilder) -> Self {
+        self.program.groups.push(first.build_stack());
+        self
+    }
+
+    pub fn variable<N: Into<String>, I: Into<String>>(mut self, name: N, id: I) -> Self {
+        self.program.variables.push(Variable {
+            name: name.into(),
+            id: id.into(),
+            var_type: None,
+        });
+        self
+    }
+
+    pub fn build(self) -> Program {
+        self.program
+    }
+
+    /// Build the program, giving the blocks and variables that were not
+    /// given an id one from `ids`.
+    pub fn build_with_ids<G: IdGenerator>(mut self, ids: &mut G) -> Result<Program, RepeatedIdError> {
+        self.program.fill_missing_ids(ids)?;
+        Ok(self.program)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_builder_matches_parsed_program() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="main" x="50" y="50">
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <field name="TIME">300</field>
+                            <value name="BRIGHTNESS">
+                                <block type="math_number" id="num">
+                                    <field name="NUM">80</field>
+                                </block>
+                            </value>
+                            <next>
+                                <block type="led_off" id="off">
+                                    <field name="TIME">100</field>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#;
+        let parsed = program_from_xml(xml).unwrap();
+
+        let built = ProgramBuilder::new()
+            .group(BlockBuilder::new("main_loop")
+                .id("main")
+                .position(50, 50)
+                .statement("BODY", BlockBuilder::new("led_on")
+                    .id("on")
+                    .field("TIME", "300")
+                    .value("BRIGHTNESS", BlockBuilder::new("math_number").id("num").field("NUM", "80"))
+                    .next(BlockBuilder::new("led_off").id("off").field("TIME", "100"))))
+            .build();
+
+        assert_eq!(built.groups, parsed.groups);
+    }
+
+    #[test]
+    fn test_next_appends_to_stack() {
+        let stack = BlockBuilder::new("a")
+            .next(BlockBuilder::new("b"))
+            .next(BlockBuilder::new("c"))
+            .build_stack();
+        let types: Vec<&str> = stack.blocks.iter().map(|block| block.block_type.as_str()).collect();
+        assert_eq!(types, vec!["a", "b", "c"]);
+    }
+
+    struct Counter(usize);
+
+    impl IdGenerator for Counter {
+        fn next_id(&mut self) -> String {
+            self.0 += 1;
+            format!("id{}", self.0)
+        }
+    }
+
+    #[test]
+    fn test_build_with_ids() {
+        let program = ProgramBuilder::new()
+            .variable("count", "")
+            .group(BlockBuilder::new("a").id("id1").next(BlockBuilder::new("b")))
+            .build_with_ids(&mut Counter(0))
+            .unwrap();
+        assert_eq!(program.groups[0].blocks[0].id, "id1");
+        assert_eq!(program.groups[0].blocks[1].id, "id2");
+        assert_eq!(program.variables[0].id, "id3");
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io::{
+    BufRead,
+    BufReader,
+    Read,
+};
+use std::path::Path;
+
+use serde_json::{
+    Map,
+    Value,
+};
+
+use super::{
+    Program,
+    ParseError,
+    program_from_json,
+    program_from_xml,
+    program_to_xml,
+};
+use super::json::{
+    as_object,
+    get_string,
+    parse_json,
+    require_string,
+};
+
+/// Named workspaces stored together, e.g. every submission in a class.
+///
+/// Bundles are saved as JSON Lines, one workspace per line:
+/// `{"name": "ada.xml", "xml": "<xml>...</xml>"}`. A line may hold Blockly's
+/// JSON serialization under `"json"` instead. Blank lines are skipped.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Bundle {
+    pub entries: Vec<BundleEntry>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct BundleEntry {
+    pub name: String,
+    pub source: BundleSource,
+}
+
+/// A workspace as stored in a bundle. It is only parsed when read through
+/// `Bundle::programs`, so one broken submission does not fail the rest.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum BundleSource {
+    Xml(String),
+    /// Blockly's JSON serialization, as JSON text.
+    Json(String),
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a program, stored as XML.
+    pub fn push<N: Into<String>>(&mut self, name: N, program: &Program) {
+        self.entries.push(BundleEntry {
+            name: name.into(),
+            source: BundleSource::Xml(program_to_xml(program)),
+        });
+    }
+
+    /// Parse each workspace, in bundle order, with its name.
+    pub fn programs(&self) -> impl Iterator<Item = (&str, Result<Program, ParseError>)> {
+        self.entries.iter().map(|entry| (entry.name.as_str(), entry.parse()))
+    }
+
+    pub fn to_jsonl(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.entries {
+            let mut line = Map::new();
+            line.insert("name".to_string(), Value::from(entry.name.as_str()));
+            match entry.source {
+                BundleSource::Xml(ref xml) => {
+                    line.insert("xml".to_string(), Value::from(xml.as_str()));
+                },
+                BundleSource::Json(ref json) => {
+                    let json = serde_json::from_str(json).unwrap_or_else(|_| Value::from(json.as_str()));
+                    line.insert("json".to_string(), json);
+                },
+            }
+            out.push_str(&Value::Object(line).to_string());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl BundleEntry {
+    pub fn parse(&self) -> Result<Program, ParseError> {
+        match self.source {
+            BundleSource::Xml(ref xml) => program_from_xml(xml),
+            BundleSource::Json(ref json) => program_from_json(json),
+        }
+    }
+}
+
+/// Read a bundle saved as JSON Lines. Only the lines are checked here; see
+/// `Bundle::programs` for the workspaces in them. JSON errors give the line
+/// in the bundle.
+pub fn bundle_from_jsonl(text: &str) -> Result<Bundle, ParseError> {
+    let mut bundle = Bundle::new();
+    for (index, line) in text.lines().enumerate() {
+        if let Some(entry) = entry_from_line(line, index + 1)? {
+            bundle.entries.push(entry);
+        }
+    }
+    Ok(bundle)
+}
+
+pub fn bundle_from_reader<R: Read>(reader: R) -> Result<Bundle, ParseError> {
+    let mut bundle = Bundle::new();
+    for (index, line) in BufReader::new(reader).lines().enumerate() {
+        if let Some(entry) = entry_from_line(&line?, index + 1)? {
+            bundle.entries.push(entry);
+        }
+    }
+    Ok(bundle)
+}
+
+pub fn bundle_from_file<P: AsRef<Path>>(path: P) -> Result<Bundle, ParseError> {
+    bundle_from_reader(File::open(path)?)
+}
+
+fn entry_from_line(line: &str, line_number: usize) -> Result<Option<BundleEntry>, ParseError> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    let value = parse_json(line).map_err(|error| match error {
+        ParseError::Json { column, message, .. } => ParseError::Json { line: line_number, column, message },
+        error => error,
+    })?;
+    let object = as_object(&value, "bundle entry")?;
+    let name = require_string(object, "bundle entry", "name")?;
+    let source = if let Some(xml) = get_string(object, "xml") {
+        BundleSource::Xml(xml)
+    } else if let Some(json) = object.get("json") {
+        match *json {
+            Value::String(ref json) => BundleSource::Json(json.clone()),
+            ref json => BundleSource::Json(json.to_string()),
+        }
+    } else {
+        return Err(ParseError::UnexpectedStructure(
+            format!("bundle entry {:?} on line {} has no \"xml\" or \"json\" workspace", name, line_number)
+        ));
+    };
+    Ok(Some(BundleEntry { name, source }))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        Block,
+        StatementBody,
+        program_to_json,
+    };
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let mut bundle = Bundle::new();
+        bundle.push("ada", &program_from_xml(r#"<xml><block type="led_on" id="a"></block></xml>"#).unwrap());
+        bundle.entries.push(BundleEntry {
+            name: "grace".to_string(),
+            source: BundleSource::Json(r#"{"blocks": {"blocks": [{"type": "led_off", "id": "b"}]}}"#.to_string()),
+        });
+        bundle.entries.push(BundleEntry {
+            name: "broken".to_string(),
+            source: BundleSource::Xml("<workspace></workspace>".to_string()),
+        });
+
+        let text = bundle.to_jsonl();
+        assert_eq!(text.lines().count(), 3);
+        let read = bundle_from_jsonl(&format!("\n{}", text)).unwrap();
+        assert_eq!(read.entries.len(), 3);
+        assert_eq!(read.entries[0], bundle.entries[0]);
+
+        let programs: Vec<_> = read.programs().collect();
+        assert_eq!(programs[0].1.as_ref().unwrap().groups[0].blocks[0].block_type, "led_on");
+        assert_eq!(programs[1].0, "grace");
+        assert_eq!(programs[1].1.as_ref().unwrap().groups[0].blocks[0].block_type, "led_off");
+        assert_eq!(programs[2].1, Err(ParseError::MissingRootElement));
+    }
+
+    #[test]
+    fn test_bundle_errors() {
+        let text = "{\"name\": \"ada\", \"xml\": \"<xml></xml>\"}\n{\"name\": ";
+        assert!(matches!(bundle_from_jsonl(text), Err(ParseError::Json { line: 2, .. })));
+        assert!(matches!(
+            bundle_from_jsonl(r#"{"xml": "<xml></xml>"}"#),
+            Err(ParseError::MissingAttribute { ref attribute, .. }) if attribute == "name"
+        ));
+        assert!(bundle_from_reader("{\"name\": \"ada\", \"xml\": \"<xml></xml>\"}\n".as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_bundle_long_stack() {
+        let mut program = Program::new();
+        program.groups.push(StatementBody {
+            blocks: (0..500).map(|_| Block::new("led_on")).collect(),
+            shadow: false,
+        });
+        let line = format!(r#"{{"name": "ada", "json": {}}}"#, program_to_json(&program));
+        let bundle = bundle_from_jsonl(&line).unwrap();
+        let programs: Vec<_> = bundle.programs().collect();
+        assert_eq!(programs[0].1.as_ref().unwrap().groups[0].blocks.len(), 500);
+    }
+}
@@ -0,0 +1,332 @@
+//! Lowering programs to a compact bytecode, so small devices can run them
+//! without parsing XML or source code.
+//!
+//! The bytecode runs on a stack machine with 32-bit signed integer values,
+//! numbered variable slots and host functions that are called by index. A
+//! host function pops its arguments and pushes its result, if it has one.
+//!
+//! # Binary encoding
+//!
+//! All integers are little-endian.
+//!
+//! | Bytes    | Contents                                              |
+//! |----------|-------------------------------------------------------|
+//! | 4        | magic `BLKB`                                          |
+//! | 1        | format version, currently 1                           |
+//! | 2        | number of host functions                              |
+//! | ...      | each function name: 1 byte length, then UTF-8 bytes   |
+//! | 2        | number of variable slots                              |
+//! | 4        | length of the code in bytes                           |
+//! | ...      | the code                                              |
+//!
+//! Each instruction is a one-byte opcode, followed by its operand if it has
+//! one. Jump targets are byte offsets from the start of the code.
+//!
+//! | Opcode | Instruction  | Operand |
+//! |--------|--------------|---------|
+//! | `0x00` | `Halt`       |         |
+//! | `0x01` | `Push`       | i32     |
+//! | `0x02` | `Pop`        |         |
+//! | `0x03` | `Load`       | u16     |
+//! | `0x04` | `Store`      | u16     |
+//! | `0x05` | `Call`       | u16     |
+//! | `0x06` | `Jump`       | u32     |
+//! | `0x07` | `JumpIfZero` | u32     |
+//! | `0x10` | `Add`        |         |
+//! | `0x11` | `Sub`        |         |
+//! | `0x12` | `Mul`        |         |
+//! | `0x13` | `Div`        |         |
+//! | `0x14` | `Mod`        |         |
+//! | `0x15` | `Neg`        |         |
+//! | `0x20` | `Eq`         |         |
+//! | `0x21` | `Ne`         |         |
+//! | `0x22` | `Lt`         |         |
+//! | `0x23` | `Le`         |         |
+//! | `0x24` | `Gt`         |         |
+//! | `0x25` | `Ge`         |         |
+//! | `0x26` | `Not`        |         |
+
+use alloc::collections::BTreeMap;
+use alloc::string::{
+    String,
+    ToString,
+};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str;
+
+const MAGIC: &[u8] = b"BLKB";
+const VERSION: u8 = 1;
+
+/// One bytecode instruction. Binary operators pop the right operand, then
+/// the left one, and push the result; comparisons push 1 or 0.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Instr {
+    /// End the program.
+    Halt,
+    Push(i32),
+    Pop,
+    /// Push the value of a variable slot.
+    Load(u16),
+    /// Pop a value into a variable slot.
+    Store(u16),
+    /// Call a host function by its index in `Bytecode::functions`.
+    Call(u16),
+    /// Continue at an instruction, by its index in `Bytecode::code`.
+    Jump(u32),
+    /// Pop a value, and jump if it is zero.
+    JumpIfZero(u32),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Neg,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Not,
+}
+
+impl Instr {
+    fn opcode(&self) -> u8 {
+        match *self {
+            Instr::Halt => 0x00,
+            Instr::Push(_) => 0x01,
+            Instr::Pop => 0x02,
+            Instr::Load(_) => 0x03,
+            Instr::Store(_) => 0x04,
+            Instr::Call(_) => 0x05,
+            Instr::Jump(_) => 0x06,
+            Instr::JumpIfZero(_) => 0x07,
+            Instr::Add => 0x10,
+            Instr::Sub => 0x11,
+            Instr::Mul => 0x12,
+            Instr::Div => 0x13,
+            Instr::Mod => 0x14,
+            Instr::Neg => 0x15,
+            Instr::Eq => 0x20,
+            Instr::Ne => 0x21,
+            Instr::Lt => 0x22,
+            Instr::Le => 0x23,
+            Instr::Gt => 0x24,
+            Instr::Ge => 0x25,
+            Instr::Not => 0x26,
+        }
+    }
+
+    // The encoded size in bytes
+    fn size(&self) -> usize {
+        match *self {
+            Instr::Push(_) | Instr::Jump(_) | Instr::JumpIfZero(_) => 5,
+            Instr::Load(_) | Instr::Store(_) | Instr::Call(_) => 3,
+            _ => 1,
+        }
+    }
+}
+
+/// A lowered program.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Bytecode {
+    /// The host functions the code calls, so a device can check it provides
+    /// them all before running it.
+    pub functions: Vec<String>,
+    /// How many variable slots the code uses. All start at 0.
+    pub variables: u16,
+    pub code: Vec<Instr>,
+}
+
+impl Bytecode {
+    /// The binary encoding described in the module documentation.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut offsets = Vec::with_capacity(self.code.len() + 1);
+        let mut offset = 0;
+        for instr in &self.code {
+            offsets.push(offset as u32);
+            offset += instr.size();
+        }
+        offsets.push(offset as u32);
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.functions.len() as u16).to_le_bytes());
+        for name in &self.functions {
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        bytes.extend_from_slice(&self.variables.to_le_bytes());
+        bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+        for instr in &self.code {
+            bytes.push(instr.opcode());
+            match *instr {
+                Instr::Push(value) => bytes.extend_from_slice(&value.to_le_bytes()),
+                Instr::Load(slot) | Instr::Store(slot) | Instr::Call(slot) => bytes.extend_from_slice(&slot.to_le_bytes()),
+                Instr::Jump(target) | Instr::JumpIfZero(target) => {
+                    let target = offsets.get(target as usize).copied().unwrap_or(offset as u32);
+                    bytes.extend_from_slice(&target.to_le_bytes());
+                },
+                _ => {},
+            }
+        }
+        bytes
+    }
+
+    /// Read back the output of `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytecodeError> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(BytecodeError::BadMagic);
+        }
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+        let mut functions = Vec::new();
+        for _ in 0..reader.u16()? {
+            let length = reader.take(1)?[0] as usize;
+            let name = str::from_utf8(reader.take(length)?).map_err(|_| BytecodeError::InvalidName)?;
+            functions.push(name.to_string());
+        }
+        let variables = reader.u16()?;
+        let length = reader.u32()? as usize;
+        let code_bytes = reader.take(length)?;
+
+        let mut code = Vec::new();
+        // Instruction indices by byte offset
+        let mut indices = BTreeMap::new();
+        let mut reader = Reader { bytes: code_bytes, position: 0 };
+        while reader.position < code_bytes.len() {
+            indices.insert(reader.position as u32, code.len() as u32);
+            let offset = reader.position;
+            let instr = match reader.take(1)?[0] {
+                0x00 => Instr::Halt,
+                0x01 => Instr::Push(reader.u32()? as i32),
+                0x02 => Instr::Pop,
+                0x03 => Instr::Load(reader.u16()?),
+                0x04 => Instr::Store(reader.u16()?),
+                0x05 => Instr::Call(reader.u16()?),
+                0x06 => Instr::Jump(reader.u32()?),
+                0x07 => Instr::JumpIfZero(reader.u32()?),
+                0x10 => Instr::Add,
+                0x11 => Instr::Sub,
+                0x12 => Instr::Mul,
+                0x13 => Instr::Div,
+                0x14 => Instr::Mod,
+                0x15 => Instr::Neg,
+                0x20 => Instr::Eq,
+                0x21 => Instr::Ne,
+                0x22 => Instr::Lt,
+                0x23 => Instr::Le,
+                0x24 => Instr::Gt,
+                0x25 => Instr::Ge,
+                0x26 => Instr::Not,
+                opcode => return Err(BytecodeError::UnknownOpcode { opcode, offset }),
+            };
+            code.push(instr);
+        }
+        indices.insert(code_bytes.len() as u32, code.len() as u32);
+
+        for instr in code.iter_mut() {
+            if let Instr::Jump(ref mut target) | Instr::JumpIfZero(ref mut target) = *instr {
+                *target = *indices.get(target).ok_or(BytecodeError::InvalidJump(*target))?;
+            }
+        }
+        Ok(Bytecode { functions, variables, code })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], BytecodeError> {
+        let bytes = self.bytes.get(self.position..self.position + length).ok_or(BytecodeError::Truncated)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Returned by `Bytecode::from_bytes` for bytes that are not valid bytecode.
+#[derive(PartialEq, Eq, Debug)]
+pub enum BytecodeError {
+    /// The bytes do not start with the bytecode magic number.
+    BadMagic,
+    UnsupportedVersion(u8),
+    /// The bytes end in the middle of the header or of an instruction.
+    Truncated,
+    /// A host function name is not UTF-8.
+    InvalidName,
+    /// The byte at `offset` into the code is not an opcode.
+    UnknownOpcode { opcode: u8, offset: usize },
+    /// A jump targets this offset, which is not the start of an instruction.
+    InvalidJump(u32),
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BytecodeError::BadMagic => write!(f, "not blockly bytecode"),
+            BytecodeError::UnsupportedVersion(version) => write!(f, "unsupported bytecode version {}", version),
+            BytecodeError::Truncated => write!(f, "bytecode is truncated"),
+            BytecodeError::InvalidName => write!(f, "host function name is not valid UTF-8"),
+            BytecodeError::UnknownOpcode { opcode, offset } => {
+                write!(f, "unknown opcode {:#04x} at offset {}", opcode, offset)
+            },
+            BytecodeError::InvalidJump(offset) => write!(f, "jump to offset {}, which is inside an instruction", offset),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BytecodeError {}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_bytecode_encoding() {
+        let bytecode = Bytecode {
+            functions: vec!["led_on".to_string()],
+            variables: 1,
+            code: vec![
+                Instr::Push(-2),
+                Instr::JumpIfZero(3),
+                Instr::Call(0),
+                Instr::Halt,
+            ],
+        };
+        let bytes = bytecode.to_bytes();
+        assert_eq!(bytes, [
+            b'B', b'L', b'K', b'B', 1,
+            1, 0, 6, b'l', b'e', b'd', b'_', b'o', b'n',
+            1, 0,
+            14, 0, 0, 0,
+            0x01, 0xfe, 0xff, 0xff, 0xff,
+            0x07, 13, 0, 0, 0,
+            0x05, 0, 0,
+            0x00,
+        ]);
+        assert_eq!(Bytecode::from_bytes(&bytes), Ok(bytecode));
+
+        assert_eq!(Bytecode::from_bytes(b"XML!"), Err(BytecodeError::BadMagic));
+        assert_eq!(Bytecode::from_bytes(&bytes[..bytes.len() - 1]), Err(BytecodeError::Truncated));
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use super::super::{
+    Block,
+    FieldValue,
+    Program,
+};
+use super::super::procedures::parameters;
+
+/// Blockly's `Names` database, which turns user-chosen names into
+/// identifiers that are legal in the target language, distinct from each
+/// other and from its reserved words. Names are matched case-insensitively,
+/// and each new identifier is remembered, so later ones get a numeric suffix
+/// instead of colliding.
+#[derive(Default)]
+pub(crate) struct Names {
+    reserved: &'static [&'static str],
+    // By lowercased name
+    variables: HashMap<String, String>,
+    procedures: HashMap<String, String>,
+    taken: HashSet<String>,
+}
+
+impl Names {
+    pub(crate) fn new(reserved: &'static [&'static str]) -> Self {
+        Self {
+            reserved,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn variable(&mut self, name: &str) -> String {
+        if let Some(identifier) = self.variables.get(&name.to_lowercase()) {
+            return identifier.clone();
+        }
+        let identifier = self.distinct(name);
+        self.variables.insert(name.to_lowercase(), identifier.clone());
+        identifier
+    }
+
+    pub(crate) fn procedure(&mut self, name: &str) -> String {
+        if let Some(identifier) = self.procedures.get(&name.to_lowercase()) {
+            return identifier.clone();
+        }
+        let identifier = self.distinct(name);
+        self.procedures.insert(name.to_lowercase(), identifier.clone());
+        identifier
+    }
+
+    /// A fresh identifier based on `name`, e.g. for a loop counter.
+    pub(crate) fn distinct(&mut self, name: &str) -> String {
+        let safe = safe_name(name);
+        let mut identifier = safe.clone();
+        let mut suffix = 1;
+        while self.taken.contains(&identifier) || self.reserved.contains(&identifier.as_str()) {
+            suffix += 1;
+            identifier = format!("{}{}", safe, suffix);
+        }
+        self.taken.insert(identifier.clone());
+        identifier
+    }
+}
+
+// Like Blockly, which runs the name through `encodeURI` and then replaces
+// anything that is not a word character
+fn safe_name(name: &str) -> String {
+    if name.is_empty() {
+        return "unnamed".to_string();
+    }
+    let mut safe = String::with_capacity(name.len());
+    for ch in name.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '_' {
+            safe.push(ch);
+        } else if ch == ' ' || ";,/?:@&=+$-.!~*'()#".contains(ch) {
+            safe.push('_');
+        } else {
+            let mut buffer = [0; 4];
+            for byte in ch.encode_utf8(&mut buffer).bytes() {
+                safe.push_str(&format!("_{:02X}", byte));
+            }
+        }
+    }
+    if safe.starts_with(|ch: char| ch.is_ascii_digit()) {
+        safe.insert_str(0, "my_");
+    }
+    safe
+}
+
+/// The names of the variables blocks refer to, in the order Blockly's
+/// `allUsedVarModels` finds them: breadth first from the top blocks, with a
+/// block's `next` block after its inputs.
+pub(crate) fn used_variables(program: &Program) -> Vec<String> {
+    let mut queue: VecDeque<(&[Block], usize)> = program.groups.iter()
+        .filter(|group| !group.blocks.is_empty())
+        .map(|group| (&group.blocks[..], 0))
+        .collect();
+    let mut names: Vec<String> = Vec::new();
+    while let Some((stack, index)) = queue.pop_front() {
+        let block = &stack[index];
+        for name in block_variables(block) {
+            if !names.iter().any(|known| known.to_lowercase() == name.to_lowercase()) {
+                names.push(name.to_string());
+            }
+        }
+        for field in block.fields.values() {
+            if let FieldValue::ExpressionField(ref expression) = *field {
+                queue.push_back((::std::slice::from_ref(&**expression), 0));
+            }
+        }
+        for input in block.values.values() {
+            queue.push_back((::std::slice::from_ref(input.block()), 0));
+        }
+        for body in block.statements.values() {
+            if !body.blocks.is_empty() {
+                queue.push_back((&body.blocks[..], 0));
+            }
+        }
+        if index + 1 < stack.len() {
+            queue.push_back((stack, index + 1));
+        }
+    }
+    names
+}
+
+fn block_variables(block: &Block) -> Vec<&str> {
+    let mut names: Vec<&str> = block.fields.iter()
+        .filter(|&(name, _)| name == "VAR" || block.field_variables.contains_key(name))
+        .filter_map(|(_, field)| match *field {
+            FieldValue::SimpleField(ref name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    if block.block_type.starts_with("procedures_def") || block.block_type.starts_with("procedures_call") {
+        if let Some(ref mutation) = block.mutation {
+            names.extend(parameters(mutation));
+        }
+    }
+    names
+}
+
+/// Procedure names in the order Blockly reserves them: procedures without a
+/// return value, then those with one, each sorted case-insensitively.
+pub(crate) fn procedure_names(program: &Program) -> Vec<String> {
+    let procedures = program.procedures();
+    let mut definitions = procedures.definitions();
+    definitions.sort_by_key(|procedure| (procedure.returns, procedure.name.to_lowercase()));
+    definitions.iter().map(|procedure| procedure.name.to_string()).collect()
+}
+
+/// A number as JavaScript's `String(number)` writes it, since Blockly's
+/// generators format numbers in the browser.
+pub(crate) fn format_number(number: f64) -> String {
+    if number.is_nan() {
+        "NaN".to_string()
+    } else if number.is_infinite() {
+        if number > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if number == 0.0 {
+        "0".to_string()
+    } else if number.abs() >= 1e21 || number.abs() < 1e-6 {
+        let exponential = format!("{:e}", number);
+        if exponential.contains("e-") {
+            exponential
+        } else {
+            exponential.replace('e', "e+")
+        }
+    } else {
+        format!("{}", number)
+    }
+}
+
+/// JavaScript's `Number(text)`.
+pub(crate) fn parse_number(text: &str) -> f64 {
+    let text = text.trim();
+    match text.trim_start_matches(['+', '-']) {
+        "" if text.is_empty() => 0.0,
+        "Infinity" => if text.starts_with('-') { f64::NEG_INFINITY } else { f64::INFINITY },
+        digits if digits.starts_with(|ch: char| ch.is_ascii_digit() || ch == '.') => {
+            text.parse().unwrap_or(f64::NAN)
+        },
+        _ => f64::NAN,
+    }
+}
+
+/// JavaScript's `parseInt(text, 10)`: the leading integer, if any.
+pub(crate) fn parse_int(text: &str) -> Option<i64> {
+    let text = text.trim_start();
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+    let end = digits.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(digits.len());
+    if end == 0 {
+        return None;
+    }
+    let number: i64 = digits[..end].parse().ok()?;
+    Some(if text.starts_with('-') { -number } else { number })
+}
+
+/// Blockly's `stringUtils.isNumber`: an optionally negative decimal.
+pub(crate) fn is_number(code: &str) -> bool {
+    let code = code.trim();
+    let code = code.strip_prefix('-').unwrap_or(code);
+    let (whole, fraction) = match code.find('.') {
+        Some(dot) => (&code[..dot], Some(&code[dot + 1..])),
+        None => (code, None),
+    };
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    digits(whole) && fraction.is_none_or(digits)
+}
+
+/// Whether `code` is a single identifier or number, which Blockly
+/// generators use without caching it in a variable.
+pub(crate) fn is_word(code: &str) -> bool {
+    !code.is_empty() && code.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+}
+
+/// Whether `code` is a single-quoted string literal, as Blockly's text
+/// generators test before converting a value to a string.
+pub(crate) fn is_string_literal(code: &str) -> bool {
+    let code = code.trim();
+    if code.len() < 2 || !code.starts_with('\'') || !code.ends_with('\'') {
+        return false;
+    }
+    let inner = &code[1..code.len() - 1];
+    inner.match_indices('\'').all(|(index, _)| inner[..index].ends_with('\\'))
+}
+
+/// A block's comment as line comments starting with `prefix`.
+pub(crate) fn comment(block: &Block, prefix: &str) -> String {
+    match block.comment {
+        Some(ref comment) if !comment.text.is_empty() => comment.text.split('\n')
+            .map(|line| format!("{}{}\n", prefix, line))
+            .collect(),
+        _ => String::new(),
+    }
+}
+
+/// Drop the blank lines the driver leaves for stacks that produce no code,
+/// such as procedure definitions.
+pub(crate) fn drop_empty_stacks(code: &str) -> String {
+    let mut dropped = String::with_capacity(code.len());
+    let mut newlines = 0;
+    for ch in code.trim_start_matches('\n').chars() {
+        if ch == '\n' {
+            newlines += 1;
+            if newlines > 2 {
+                continue;
+            }
+        } else {
+            newlines = 0;
+        }
+        dropped.push(ch);
+    }
+    dropped
+}
+
+/// The clean-up Blockly's `workspaceToCode` applies to the finished code:
+/// no leading blank lines, a single trailing newline and no trailing spaces.
+pub(crate) fn tidy(code: &str) -> String {
+    let start = code.len() - code.trim_start().len();
+    let code = match code[..start].rfind('\n') {
+        Some(newline) => &code[newline + 1..],
+        None => code,
+    };
+    let body = code.trim_end();
+    let trailing = &code[body.len()..];
+    let mut tidy = body.split('\n')
+        .map(|line| line.trim_end_matches([' ', '\t']))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if trailing.contains('\n') {
+        tidy.push('\n');
+    } else {
+        tidy.push_str(trailing);
+    }
+    tidy
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        let mut names = Names::new(&["for", "count"]);
+        assert_eq!(names.variable("my var"), "my_var");
+        assert_eq!(names.variable("My Var"), "my_var");
+        assert_eq!(names.variable("my_var"), "my_var2");
+        assert_eq!(names.variable("for"), "for2");
+        assert_eq!(names.variable("2nd"), "my_2nd");
+        assert_eq!(names.variable("café"), "caf_C3_A9");
+        assert_eq!(names.procedure("my var"), "my_var3");
+        assert_eq!(names.distinct("count"), "count2");
+        assert_eq!(names.distinct("count"), "count3");
+    }
+
+    #[test]
+    fn test_number_helpers() {
+        assert_eq!(format_number(parse_number("3.0")), "3");
+        assert_eq!(format_number(parse_number(" -0.5 ")), "-0.5");
+        assert_eq!(format_number(parse_number("1e21")), "1e+21");
+        assert_eq!(format_number(parse_number("")), "0");
+        assert!(parse_number("inf").is_nan());
+        assert_eq!(parse_int("3.7"), Some(3));
+        assert!(is_number("-12.5") && !is_number("1e3") && !is_number("x"));
+    }
+}
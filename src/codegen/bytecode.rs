@@ -0,0 +1,310 @@
+//! Lowering programs to the bytecode that `Bytecode` encodes.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use super::super::{
+    Program,
+    StatementBody,
+    Block,
+    Bytecode,
+    Instr,
+    CodegenError,
+    TraversalOptions,
+};
+
+pub type LowerHandler = Box<dyn Fn(&Block, &mut LowerCtx) -> Result<(), CodegenError>>;
+
+/// Lowers programs to bytecode with one handler per block type. A statement
+/// block's handler emits its instructions; a value block's handler emits
+/// instructions that leave exactly one value on the stack.
+///
+/// ```
+/// use blockly_parser::codegen::{Instr, Lowering};
+/// use blockly_parser::program_from_xml;
+///
+/// let lowering = Lowering::new()
+///     .on("inner_loop", |block, ctx| {
+///         ctx.emit(Instr::Push(block.field_i64("COUNT")? as i32));
+///         ctx.repeat(block, "BODY")
+///     })
+///     .on("led_on", |block, ctx| {
+///         ctx.emit(Instr::Push(block.field_i64("TIME")? as i32));
+///         ctx.call("led_on")
+///     });
+///
+/// let program = program_from_xml(r#"
+///     <xml>
+///         <block type="inner_loop">
+///             <field name="COUNT">3</field>
+///             <statement name="BODY">
+///                 <block type="led_on"><field name="TIME">300</field></block>
+///             </statement>
+///         </block>
+///     </xml>
+/// "#).unwrap();
+/// let bytecode = lowering.lower(&program).unwrap();
+/// assert_eq!(bytecode.functions, vec!["led_on"]);
+/// let bytes = bytecode.to_bytes();
+/// ```
+#[derive(Default)]
+pub struct Lowering {
+    handlers: HashMap<String, LowerHandler>,
+}
+
+impl Lowering {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on<T, F>(mut self, block_type: T, handler: F) -> Self
+        where T: Into<String>,
+              F: Fn(&Block, &mut LowerCtx) -> Result<(), CodegenError> + 'static
+    {
+        self.handlers.insert(block_type.into(), Box::new(handler));
+        self
+    }
+
+    /// Lower every top-level stack in order, followed by `Instr::Halt`.
+    pub fn lower(&self, program: &Program) -> Result<Bytecode, CodegenError> {
+        self.lower_with(program, &TraversalOptions::default())
+    }
+
+    /// Like `lower`, skipping disabled blocks or the insides of collapsed
+    /// blocks as `options` say.
+    pub fn lower_with(&self, program: &Program, options: &TraversalOptions) -> Result<Bytecode, CodegenError> {
+        let mut ctx = LowerCtx {
+            lowering: self,
+            traversal: *options,
+            code: Vec::new(),
+            functions: Vec::new(),
+            variables: Vec::new(),
+        };
+        for group in &program.groups {
+            ctx.stack(group)?;
+        }
+        ctx.emit(Instr::Halt);
+        Ok(Bytecode {
+            functions: ctx.functions,
+            variables: ctx.variables.len() as u16,
+            code: ctx.code,
+        })
+    }
+}
+
+/// State for a single lowering run, passed to every handler.
+pub struct LowerCtx<'l> {
+    lowering: &'l Lowering,
+    traversal: TraversalOptions,
+    code: Vec<Instr>,
+    functions: Vec<String>,
+    // By slot; temporaries have no name
+    variables: Vec<Option<String>>,
+}
+
+impl<'l> LowerCtx<'l> {
+    /// Append an instruction, returning its index.
+    pub fn emit(&mut self, instr: Instr) -> u32 {
+        self.code.push(instr);
+        self.code.len() as u32 - 1
+    }
+
+    /// The index the next instruction will get, e.g. to jump back to.
+    pub fn position(&self) -> u32 {
+        self.code.len() as u32
+    }
+
+    /// Point the jump at index `at`, emitted before its target was known, to
+    /// `target`.
+    pub fn patch(&mut self, at: u32, target: u32) {
+        if let Some(Instr::Jump(ref mut old) | Instr::JumpIfZero(ref mut old)) = self.code.get_mut(at as usize) {
+            *old = target;
+        }
+    }
+
+    /// The index of a host function, adding it to the table if needed.
+    pub fn function(&mut self, name: &str) -> Result<u16, CodegenError> {
+        if let Some(index) = self.functions.iter().position(|function| function == name) {
+            return Ok(index as u16);
+        }
+        if name.len() > u8::MAX as usize {
+            return Err(CodegenError::Custom(format!("host function name `{}` is too long", name)));
+        }
+        let index = u16::try_from(self.functions.len())
+            .map_err(|_| CodegenError::Custom("too many host functions".to_string()))?;
+        self.functions.push(name.to_string());
+        Ok(index)
+    }
+
+    /// Emit a call to a host function.
+    pub fn call(&mut self, name: &str) -> Result<(), CodegenError> {
+        let index = self.function(name)?;
+        self.emit(Instr::Call(index));
+        Ok(())
+    }
+
+    /// The slot of a Blockly variable. Like Blockly, names are matched
+    /// case-insensitively.
+    pub fn variable(&mut self, name: &str) -> Result<u16, CodegenError> {
+        let known = self.variables.iter().position(|variable| match *variable {
+            Some(ref variable) => variable.to_lowercase() == name.to_lowercase(),
+            None => false,
+        });
+        match known {
+            Some(slot) => Ok(slot as u16),
+            None => self.add_slot(Some(name.to_string())),
+        }
+    }
+
+    /// A fresh slot no variable uses, e.g. for a loop counter.
+    pub fn temporary(&mut self) -> Result<u16, CodegenError> {
+        self.add_slot(None)
+    }
+
+    fn add_slot(&mut self, name: Option<String>) -> Result<u16, CodegenError> {
+        let slot = u16::try_from(self.variables.len())
+            .map_err(|_| CodegenError::Custom("too many variables".to_string()))?;
+        self.variables.push(name);
+        Ok(slot)
+    }
+
+    pub fn block(&mut self, block: &Block) -> Result<(), CodegenError> {
+        let lowering = self.lowering;
+        match lowering.handlers.get(&block.block_type) {
+            Some(handler) => handler(block, self),
+            None => Err(CodegenError::UnsupportedBlock {
+                block_type: block.block_type.clone(),
+                id: block.id.clone(),
+            }),
+        }
+    }
+
+    /// Lower every block in a stack, in order.
+    pub fn stack(&mut self, body: &StatementBody) -> Result<(), CodegenError> {
+        for block in self.traversal.stack(&body.blocks) {
+            self.block(block)?;
+        }
+        Ok(())
+    }
+
+    /// Lower the statement input `name` of `block`. An empty or missing input
+    /// emits nothing.
+    pub fn statement(&mut self, block: &Block, name: &str) -> Result<(), CodegenError> {
+        match block.statements.get(name) {
+            Some(body) if self.traversal.enters(block) => self.stack(body),
+            _ => Ok(()),
+        }
+    }
+
+    /// Lower the value input `name` of `block`, pushing `default` if it is
+    /// empty.
+    pub fn value(&mut self, block: &Block, name: &str, default: i32) -> Result<(), CodegenError> {
+        match block.values.get(name) {
+            Some(input) if self.traversal.enters(block) && self.traversal.includes(input.block()) => {
+                self.block(input.block())
+            },
+            _ => {
+                self.emit(Instr::Push(default));
+                Ok(())
+            },
+        }
+    }
+
+    /// Pop a count, and run the statement input `name` of `block` that many
+    /// times.
+    pub fn repeat(&mut self, block: &Block, name: &str) -> Result<(), CodegenError> {
+        let counter = self.temporary()?;
+        self.emit(Instr::Store(counter));
+        let start = self.emit(Instr::Load(counter));
+        self.emit(Instr::Push(0));
+        self.emit(Instr::Gt);
+        let exit = self.emit(Instr::JumpIfZero(0));
+        self.emit(Instr::Load(counter));
+        self.emit(Instr::Push(1));
+        self.emit(Instr::Sub);
+        self.emit(Instr::Store(counter));
+        self.statement(block, name)?;
+        self.emit(Instr::Jump(start));
+        let end = self.position();
+        self.patch(exit, end);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::super::program_from_xml;
+
+    fn led_lowering() -> Lowering {
+        Lowering::new()
+            .on("main_loop", |block, ctx| {
+                let start = ctx.position();
+                ctx.statement(block, "BODY")?;
+                ctx.emit(Instr::Jump(start));
+                Ok(())
+            })
+            .on("inner_loop", |block, ctx| {
+                ctx.emit(Instr::Push(block.field_i64("COUNT")? as i32));
+                ctx.repeat(block, "BODY")
+            })
+            .on("led_on", |block, ctx| {
+                ctx.emit(Instr::Push(block.field_i64("TIME")? as i32));
+                ctx.call("led_on")
+            })
+            .on("led_off", |block, ctx| {
+                ctx.emit(Instr::Push(block.field_i64("TIME")? as i32));
+                ctx.call("led_off")
+            })
+    }
+
+    #[test]
+    fn test_lower() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="main_loop">
+                    <statement name="BODY">
+                        <block type="inner_loop">
+                            <field name="COUNT">3</field>
+                            <statement name="BODY">
+                                <block type="led_on"><field name="TIME">300</field></block>
+                            </statement>
+                            <next><block type="led_off"><field name="TIME">100</field></block></next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let bytecode = led_lowering().lower(&program).unwrap();
+        assert_eq!(bytecode, Bytecode {
+            functions: vec!["led_on".to_string(), "led_off".to_string()],
+            variables: 1,
+            code: vec![
+                Instr::Push(3),
+                Instr::Store(0),
+                Instr::Load(0),
+                Instr::Push(0),
+                Instr::Gt,
+                Instr::JumpIfZero(13),
+                Instr::Load(0),
+                Instr::Push(1),
+                Instr::Sub,
+                Instr::Store(0),
+                Instr::Push(300),
+                Instr::Call(0),
+                Instr::Jump(2),
+                Instr::Push(100),
+                Instr::Call(1),
+                Instr::Jump(0),
+                Instr::Halt,
+            ],
+        });
+
+        let program = program_from_xml(r#"<xml><block type="buzzer" id="b1"></block></xml>"#).unwrap();
+        assert_eq!(led_lowering().lower(&program), Err(CodegenError::UnsupportedBlock {
+            block_type: "buzzer".to_string(),
+            id: "b1".to_string(),
+        }));
+    }
+}
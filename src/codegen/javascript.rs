@@ -0,0 +1,761 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+
+use super::{
+    BlockHandler,
+    GenCtx,
+    Generator,
+    Order,
+};
+use super::blockly::{
+    Names,
+    comment,
+    drop_empty_stacks,
+    format_number,
+    is_number,
+    is_string_literal,
+    is_word,
+    parse_number,
+    procedure_names,
+    tidy,
+    used_variables,
+};
+use super::super::{
+    Block,
+    CodegenError,
+    Program,
+};
+use super::super::procedures::parameters;
+
+/// Generates JavaScript from Blockly's standard logic, loop, math, text,
+/// variable and procedure blocks, producing the same code as Blockly's own
+/// `javascriptGenerator`: the same parentheses, two-space indentation, `var`
+/// declarations and renaming of variables that collide with each other or
+/// with reserved words. Register handlers with `on` for custom blocks, or to
+/// replace a built-in one; their values are treated as binding loosest.
+///
+/// Blockly skips disabled blocks, so pair this with
+/// `TraversalOptions::skip_disabled()` for identical output. Stacks are
+/// generated in document order rather than by position, and comments are
+/// not re-wrapped.
+///
+/// ```
+/// use blockly_parser::codegen::{generate, JavaScriptGenerator};
+/// use blockly_parser::program_from_xml;
+///
+/// let program = program_from_xml(r#"
+///     <xml>
+///         <block type="text_print">
+///             <value name="TEXT"><block type="text"><field name="TEXT">Hi!</field></block></value>
+///         </block>
+///     </xml>
+/// "#).unwrap();
+/// assert_eq!(generate(&program, &JavaScriptGenerator::new()).unwrap(), "window.alert('Hi!');\n");
+/// ```
+#[derive(Default)]
+pub struct JavaScriptGenerator {
+    overrides: HashMap<String, BlockHandler>,
+    state: RefCell<State>,
+}
+
+#[derive(Default)]
+struct State {
+    names: Names,
+    // In the order they were added, like Blockly's `definitions_`
+    definitions: IndexMap<String, String>,
+}
+
+// Operator precedence, as in Blockly's JavaScript generator: lower binds
+// tighter
+mod order {
+    use super::Order;
+
+    pub const ATOMIC: Order = Order::ATOMIC;
+    pub const MEMBER: Order = Order(1.2);
+    pub const FUNCTION_CALL: Order = Order(2.0);
+    pub const UNARY_NEGATION: Order = Order(4.3);
+    pub const LOGICAL_NOT: Order = Order(4.4);
+    pub const MULTIPLICATION: Order = Order(5.1);
+    pub const DIVISION: Order = Order(5.2);
+    pub const MODULUS: Order = Order(5.3);
+    pub const SUBTRACTION: Order = Order(6.1);
+    pub const ADDITION: Order = Order(6.2);
+    pub const RELATIONAL: Order = Order(8.0);
+    pub const EQUALITY: Order = Order(9.0);
+    pub const LOGICAL_AND: Order = Order(13.0);
+    pub const LOGICAL_OR: Order = Order(14.0);
+    pub const CONDITIONAL: Order = Order(15.0);
+    pub const ASSIGNMENT: Order = Order(16.0);
+    pub const NONE: Order = Order::NONE;
+
+    /// `(outer, inner)` pairs that need no parentheses, e.g. `a * (b * c)`.
+    pub const OVERRIDES: &[(Order, Order)] = &[
+        (FUNCTION_CALL, MEMBER),
+        (FUNCTION_CALL, FUNCTION_CALL),
+        (MEMBER, MEMBER),
+        (MEMBER, FUNCTION_CALL),
+        (LOGICAL_NOT, LOGICAL_NOT),
+        (MULTIPLICATION, MULTIPLICATION),
+        (ADDITION, ADDITION),
+        (LOGICAL_AND, LOGICAL_AND),
+        (LOGICAL_OR, LOGICAL_OR),
+    ];
+}
+
+impl JavaScriptGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generate blocks of `block_type` with `handler` instead of the
+    /// built-in code.
+    pub fn on<T, F>(mut self, block_type: T, handler: F) -> Self
+        where T: Into<String>,
+              F: Fn(&Block, &mut GenCtx) -> Result<String, CodegenError> + 'static
+    {
+        self.overrides.insert(block_type.into(), Box::new(handler));
+        self
+    }
+
+    // `None` if `block` is not a built-in statement block
+    fn statement(&self, block: &Block, ctx: &mut GenCtx) -> Result<Option<String>, CodegenError> {
+        let code = match block.block_type.as_str() {
+            // Logic
+            "controls_if" => {
+                let mutation = |name| block.mutation.as_ref()
+                    .and_then(|mutation| mutation.attribute(name))
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let mut code = String::new();
+                for branch in 0..=mutation("elseif") {
+                    if branch > 0 {
+                        code.push_str(" else ");
+                    }
+                    let condition = value(ctx, block, &format!("IF{}", branch), order::NONE, "false")?;
+                    let body = ctx.statement_to_code(block, &format!("DO{}", branch))?;
+                    code.push_str(&format!("if ({}) {{\n{}}}", condition, body));
+                }
+                if mutation("else") > 0 {
+                    code.push_str(&format!(" else {{\n{}}}", ctx.statement_to_code(block, "ELSE")?));
+                }
+                code + "\n"
+            },
+
+            // Loops
+            "controls_repeat_ext" | "controls_repeat" => {
+                let repeats = if block.block_type == "controls_repeat" {
+                    format_number(parse_number(block.field_str("TIMES")?))
+                } else {
+                    value(ctx, block, "TIMES", order::ASSIGNMENT, "0")?
+                };
+                let body = ctx.statement_to_code(block, "DO")?;
+                let mut code = String::new();
+                let counter = self.state.borrow_mut().names.distinct("count");
+                let end = if is_word(&repeats) || is_number(&repeats) {
+                    repeats
+                } else {
+                    let end = self.state.borrow_mut().names.distinct("repeat_end");
+                    code.push_str(&format!("var {} = {};\n", end, repeats));
+                    end
+                };
+                code.push_str(&format!(
+                    "for (var {0} = 0; {0} < {1}; {0}++) {{\n{2}}}\n",
+                    counter, end, body
+                ));
+                code
+            },
+            "controls_whileUntil" => {
+                let until = block.field_str("MODE")? == "UNTIL";
+                let outer = if until { order::LOGICAL_NOT } else { order::NONE };
+                let mut condition = value(ctx, block, "BOOL", outer, "false")?;
+                let body = ctx.statement_to_code(block, "DO")?;
+                if until {
+                    condition = format!("!{}", condition);
+                }
+                format!("while ({}) {{\n{}}}\n", condition, body)
+            },
+            "controls_for" => {
+                let variable = self.variable(block.field_str("VAR")?);
+                let from = value(ctx, block, "FROM", order::ASSIGNMENT, "0")?;
+                let to = value(ctx, block, "TO", order::ASSIGNMENT, "0")?;
+                let by = value(ctx, block, "BY", order::ASSIGNMENT, "1")?;
+                let body = ctx.statement_to_code(block, "DO")?;
+                if is_number(&from) && is_number(&to) && is_number(&by) {
+                    let up = parse_number(&from) <= parse_number(&to);
+                    let step = parse_number(&by).abs();
+                    let update = match (step == 1.0, up) {
+                        (true, true) => "++".to_string(),
+                        (true, false) => "--".to_string(),
+                        (false, true) => format!(" += {}", format_number(step)),
+                        (false, false) => format!(" -= {}", format_number(step)),
+                    };
+                    format!(
+                        "for ({0} = {1}; {0} {2} {3}; {0}{4}) {{\n{5}}}\n",
+                        variable, from, if up { "<=" } else { ">=" }, to, update, body
+                    )
+                } else {
+                    let mut code = String::new();
+                    let mut cache = |argument: String, suffix: &str| {
+                        if is_word(&argument) || is_number(&argument) {
+                            return argument;
+                        }
+                        let name = self.state.borrow_mut().names.distinct(&format!("{}{}", variable, suffix));
+                        code.push_str(&format!("var {} = {};\n", name, argument));
+                        name
+                    };
+                    let start = cache(from, "_start");
+                    let end = cache(to, "_end");
+                    let increment = self.state.borrow_mut().names.distinct(&format!("{}_inc", variable));
+                    let step = if is_number(&by) {
+                        format_number(parse_number(&by).abs())
+                    } else {
+                        format!("Math.abs({})", by)
+                    };
+                    code.push_str(&format!("var {} = {};\n", increment, step));
+                    code.push_str(&format!("if ({0} > {1}) {{\n{2}{3} = -{3};\n}}\n", start, end, ctx.indent(), increment));
+                    code.push_str(&format!(
+                        "for ({0} = {1}; {2} >= 0 ? {0} <= {3} : {0} >= {3}; {0} += {2}) {{\n{4}}}\n",
+                        variable, start, increment, end, body
+                    ));
+                    code
+                }
+            },
+            "controls_flow_statements" => match block.field_str("FLOW")? {
+                "BREAK" => "break;\n".to_string(),
+                "CONTINUE" => "continue;\n".to_string(),
+                flow => return Err(unknown_option(block, "FLOW", flow)),
+            },
+
+            // Math
+            "math_change" => {
+                let delta = value(ctx, block, "DELTA", order::ADDITION, "0")?;
+                let variable = self.variable(block.field_str("VAR")?);
+                format!("{0} = (typeof {0} === 'number' ? {0} : 0) + {1};\n", variable, delta)
+            },
+
+            // Text
+            "text_append" => {
+                let variable = self.variable(block.field_str("VAR")?);
+                let text = value(ctx, block, "TEXT", order::NONE, "''")?;
+                format!("{} += {};\n", variable, force_string(text).0)
+            },
+            "text_print" => format!("window.alert({});\n", value(ctx, block, "TEXT", order::NONE, "''")?),
+
+            // Variables
+            "variables_set" => {
+                let assigned = value(ctx, block, "VALUE", order::ASSIGNMENT, "0")?;
+                format!("{} = {};\n", self.variable(block.field_str("VAR")?), assigned)
+            },
+
+            // Procedures
+            "procedures_defnoreturn" | "procedures_defreturn" => {
+                self.define_procedure(block, ctx)?;
+                String::new()
+            },
+            "procedures_callnoreturn" => format!("{};\n", self.call(block, ctx)?),
+            "procedures_ifreturn" => {
+                let condition = value(ctx, block, "CONDITION", order::NONE, "false")?;
+                let statement = if has_return_value(block) {
+                    format!("return {};\n", value(ctx, block, "VALUE", order::NONE, "null")?)
+                } else {
+                    "return;\n".to_string()
+                };
+                format!("if ({}) {{\n{}{}}}\n", condition, ctx.indent(), statement)
+            },
+
+            _ => return Ok(None),
+        };
+        Ok(Some(code))
+    }
+
+    fn expression(&self, block: &Block, ctx: &mut GenCtx) -> Result<(String, Order), CodegenError> {
+        if let Some(handler) = self.overrides.get(&block.block_type) {
+            return Ok((handler(block, ctx)?, order::NONE));
+        }
+        let expression = match block.block_type.as_str() {
+            // Logic
+            "logic_compare" => {
+                let operator = match block.field_str("OP")? {
+                    "EQ" => "==",
+                    "NEQ" => "!=",
+                    "LT" => "<",
+                    "LTE" => "<=",
+                    "GT" => ">",
+                    "GTE" => ">=",
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                let order = if operator == "==" || operator == "!=" { order::EQUALITY } else { order::RELATIONAL };
+                let a = value(ctx, block, "A", order, "0")?;
+                let b = value(ctx, block, "B", order, "0")?;
+                (format!("{} {} {}", a, operator, b), order)
+            },
+            "logic_operation" => {
+                let (operator, order) = match block.field_str("OP")? {
+                    "AND" => ("&&", order::LOGICAL_AND),
+                    "OR" => ("||", order::LOGICAL_OR),
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                let a = value(ctx, block, "A", order, "")?;
+                let b = value(ctx, block, "B", order, "")?;
+                let (a, b) = logic_operands(a, b, if operator == "&&" { "true" } else { "false" }, "false");
+                (format!("{} {} {}", a, operator, b), order)
+            },
+            "logic_negate" => {
+                let operand = value(ctx, block, "BOOL", order::LOGICAL_NOT, "true")?;
+                (format!("!{}", operand), order::LOGICAL_NOT)
+            },
+            "logic_boolean" => match block.field_str("BOOL")? {
+                "TRUE" => ("true".to_string(), order::ATOMIC),
+                _ => ("false".to_string(), order::ATOMIC),
+            },
+            "logic_null" => ("null".to_string(), order::ATOMIC),
+            "logic_ternary" => {
+                let condition = value(ctx, block, "IF", order::CONDITIONAL, "false")?;
+                let then = value(ctx, block, "THEN", order::CONDITIONAL, "null")?;
+                let otherwise = value(ctx, block, "ELSE", order::CONDITIONAL, "null")?;
+                (format!("{} ? {} : {}", condition, then, otherwise), order::CONDITIONAL)
+            },
+
+            // Math
+            "math_number" => {
+                let number = parse_number(block.field_str("NUM")?);
+                (format_number(number), if number >= 0.0 { order::ATOMIC } else { order::UNARY_NEGATION })
+            },
+            "math_arithmetic" => {
+                let (operator, order) = match block.field_str("OP")? {
+                    "ADD" => (" + ", order::ADDITION),
+                    "MINUS" => (" - ", order::SUBTRACTION),
+                    "MULTIPLY" => (" * ", order::MULTIPLICATION),
+                    "DIVIDE" => (" / ", order::DIVISION),
+                    "POWER" => ("", order::NONE),
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                let a = value(ctx, block, "A", order, "0")?;
+                let b = value(ctx, block, "B", order, "0")?;
+                if operator.is_empty() {
+                    (format!("Math.pow({}, {})", a, b), order::FUNCTION_CALL)
+                } else {
+                    (format!("{}{}{}", a, operator, b), order)
+                }
+            },
+            "math_single" | "math_round" | "math_trig" => {
+                let operator = block.field_str("OP")?;
+                if operator == "NEG" {
+                    let mut number = value(ctx, block, "NUM", order::UNARY_NEGATION, "0")?;
+                    // `--3` is not legal
+                    if number.starts_with('-') {
+                        number.insert(0, ' ');
+                    }
+                    return Ok((format!("-{}", number), order::UNARY_NEGATION));
+                }
+                let outer = match operator {
+                    "SIN" | "COS" | "TAN" => order::DIVISION,
+                    _ => order::NONE,
+                };
+                let number = value(ctx, block, "NUM", outer, "0")?;
+                match operator {
+                    "ABS" => (format!("Math.abs({})", number), order::FUNCTION_CALL),
+                    "ROOT" => (format!("Math.sqrt({})", number), order::FUNCTION_CALL),
+                    "LN" => (format!("Math.log({})", number), order::FUNCTION_CALL),
+                    "EXP" => (format!("Math.exp({})", number), order::FUNCTION_CALL),
+                    "POW10" => (format!("Math.pow(10,{})", number), order::FUNCTION_CALL),
+                    "ROUND" => (format!("Math.round({})", number), order::FUNCTION_CALL),
+                    "ROUNDUP" => (format!("Math.ceil({})", number), order::FUNCTION_CALL),
+                    "ROUNDDOWN" => (format!("Math.floor({})", number), order::FUNCTION_CALL),
+                    "SIN" => (format!("Math.sin({} / 180 * Math.PI)", number), order::FUNCTION_CALL),
+                    "COS" => (format!("Math.cos({} / 180 * Math.PI)", number), order::FUNCTION_CALL),
+                    "TAN" => (format!("Math.tan({} / 180 * Math.PI)", number), order::FUNCTION_CALL),
+                    "LOG10" => (format!("Math.log({}) / Math.log(10)", number), order::DIVISION),
+                    "ASIN" => (format!("Math.asin({}) / Math.PI * 180", number), order::DIVISION),
+                    "ACOS" => (format!("Math.acos({}) / Math.PI * 180", number), order::DIVISION),
+                    "ATAN" => (format!("Math.atan({}) / Math.PI * 180", number), order::DIVISION),
+                    op => return Err(unknown_option(block, "OP", op)),
+                }
+            },
+            "math_constant" => match block.field_str("CONSTANT")? {
+                "PI" => ("Math.PI".to_string(), order::MEMBER),
+                "E" => ("Math.E".to_string(), order::MEMBER),
+                "GOLDEN_RATIO" => ("(1 + Math.sqrt(5)) / 2".to_string(), order::DIVISION),
+                "SQRT2" => ("Math.SQRT2".to_string(), order::MEMBER),
+                "SQRT1_2" => ("Math.SQRT1_2".to_string(), order::MEMBER),
+                "INFINITY" => ("Infinity".to_string(), order::ATOMIC),
+                constant => return Err(unknown_option(block, "CONSTANT", constant)),
+            },
+            "math_number_property" => {
+                let property = block.field_str("PROPERTY")?;
+                let (suffix, inner, outer) = match property {
+                    "EVEN" => (" % 2 === 0", order::MODULUS, order::EQUALITY),
+                    "ODD" => (" % 2 === 1", order::MODULUS, order::EQUALITY),
+                    "WHOLE" => (" % 1 === 0", order::MODULUS, order::EQUALITY),
+                    "POSITIVE" => (" > 0", order::RELATIONAL, order::RELATIONAL),
+                    "NEGATIVE" => (" < 0", order::RELATIONAL, order::RELATIONAL),
+                    "DIVISIBLE_BY" => ("", order::MODULUS, order::EQUALITY),
+                    "PRIME" => ("", order::NONE, order::FUNCTION_CALL),
+                    property => return Err(unknown_option(block, "PROPERTY", property)),
+                };
+                let number = value(ctx, block, "NUMBER_TO_CHECK", inner, "0")?;
+                let code = match property {
+                    "PRIME" => format!("{}({})", self.provide_function("mathIsPrime", IS_PRIME), number),
+                    "DIVISIBLE_BY" => {
+                        let divisor = value(ctx, block, "DIVISOR", order::MODULUS, "0")?;
+                        format!("{} % {} === 0", number, divisor)
+                    },
+                    _ => format!("{}{}", number, suffix),
+                };
+                (code, outer)
+            },
+            "math_modulo" => {
+                let dividend = value(ctx, block, "DIVIDEND", order::MODULUS, "0")?;
+                let divisor = value(ctx, block, "DIVISOR", order::MODULUS, "0")?;
+                (format!("{} % {}", dividend, divisor), order::MODULUS)
+            },
+            "math_constrain" => {
+                let number = value(ctx, block, "VALUE", order::NONE, "0")?;
+                let low = value(ctx, block, "LOW", order::NONE, "0")?;
+                let high = value(ctx, block, "HIGH", order::NONE, "Infinity")?;
+                (format!("Math.min(Math.max({}, {}), {})", number, low, high), order::FUNCTION_CALL)
+            },
+
+            // Text
+            "text" => (quote(block.field_str("TEXT").unwrap_or("")), order::ATOMIC),
+            "text_join" => {
+                let items = block.mutation.as_ref()
+                    .and_then(|mutation| mutation.attribute("items"))
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(2);
+                let mut values = Vec::new();
+                for item in 0..items {
+                    values.push(value(ctx, block, &format!("ADD{}", item), order::NONE, "''")?);
+                }
+                match items {
+                    0 => ("''".to_string(), order::ATOMIC),
+                    1 => force_string(values.remove(0)),
+                    2 => {
+                        let b = force_string(values.pop().unwrap_or_default()).0;
+                        let a = force_string(values.pop().unwrap_or_default()).0;
+                        (format!("{} + {}", a, b), order::ADDITION)
+                    },
+                    _ => (format!("[{}].join('')", values.join(",")), order::FUNCTION_CALL),
+                }
+            },
+            "text_length" => {
+                let text = value(ctx, block, "VALUE", order::MEMBER, "''")?;
+                (format!("{}.length", text), order::MEMBER)
+            },
+            "text_isEmpty" => {
+                let text = value(ctx, block, "VALUE", order::MEMBER, "''")?;
+                (format!("!{}.length", text), order::LOGICAL_NOT)
+            },
+
+            // Variables
+            "variables_get" => (self.variable(block.field_str("VAR")?), order::ATOMIC),
+
+            // Procedures
+            "procedures_callreturn" => (self.call(block, ctx)?, order::FUNCTION_CALL),
+
+            _ => {
+                return Err(CodegenError::UnsupportedBlock {
+                    block_type: block.block_type.clone(),
+                    id: block.id.clone(),
+                });
+            }
+        };
+        Ok(expression)
+    }
+
+    fn variable(&self, name: &str) -> String {
+        self.state.borrow_mut().names.variable(name)
+    }
+
+    fn call(&self, block: &Block, ctx: &mut GenCtx) -> Result<String, CodegenError> {
+        let mutation = block.mutation.as_ref();
+        let name = mutation.and_then(|mutation| mutation.attribute("name"))
+            .ok_or_else(|| CodegenError::Custom(format!("call block {:?} names no procedure", block.id)))?;
+        let function = self.state.borrow_mut().names.procedure(name);
+        let mut arguments = Vec::new();
+        for index in 0..mutation.map(|mutation| parameters(mutation).len()).unwrap_or(0) {
+            arguments.push(value(ctx, block, &format!("ARG{}", index), order::NONE, "null")?);
+        }
+        Ok(format!("{}({})", function, arguments.join(", ")))
+    }
+
+    // Definitions are collected and written out ahead of the code by `finish`
+    fn define_procedure(&self, block: &Block, ctx: &mut GenCtx) -> Result<(), CodegenError> {
+        let function = self.state.borrow_mut().names.procedure(block.field_str("NAME")?);
+        let body = ctx.statement_to_code(block, "STACK")?;
+        let result = value(ctx, block, "RETURN", order::NONE, "")?;
+        let arguments: Vec<String> = block.mutation.as_ref()
+            .map(parameters)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|name| self.variable(name))
+            .collect();
+
+        let mut code = comment(block, "// ");
+        code.push_str(&format!("function {}({}) {{\n{}", function, arguments.join(", "), body));
+        if !result.is_empty() {
+            code.push_str(&format!("{}return {};\n", ctx.indent(), result));
+        }
+        code.push('}');
+        self.state.borrow_mut().definitions.insert(format!("%{}", function), code);
+        Ok(())
+    }
+
+    // Like Blockly's `provideFunction_`: define a helper once, under a name
+    // that collides with nothing else
+    fn provide_function(&self, desired: &str, template: &str) -> String {
+        let mut state = self.state.borrow_mut();
+        if let Some(name) = state.definitions.get(desired).and_then(|code| function_name(code)) {
+            return name.to_string();
+        }
+        let name = state.names.distinct(desired);
+        state.definitions.insert(desired.to_string(), template.replace("{name}", &name));
+        name
+    }
+}
+
+impl Generator for JavaScriptGenerator {
+    fn generate_block(&self, block: &Block, ctx: &mut GenCtx) -> Result<String, CodegenError> {
+        if let Some(handler) = self.overrides.get(&block.block_type) {
+            return handler(block, ctx);
+        }
+        match self.statement(block, ctx)? {
+            Some(code) if code.is_empty() => Ok(code),
+            Some(code) => Ok(comment(block, "// ") + &code),
+            None => self.generate_expression(block, ctx).map(|(code, _)| code),
+        }
+    }
+
+    fn generate_expression(&self, block: &Block, ctx: &mut GenCtx) -> Result<(String, Order), CodegenError> {
+        self.expression(block, ctx)
+    }
+
+    fn order_overrides(&self) -> &[(Order, Order)] {
+        order::OVERRIDES
+    }
+
+    fn prepare(&self, program: &Program, ctx: &mut GenCtx) -> Result<(), CodegenError> {
+        let mut state = State {
+            names: Names::new(RESERVED),
+            definitions: IndexMap::new(),
+        };
+        let variables: Vec<String> = used_variables(program).iter()
+            .map(|name| state.names.variable(name))
+            .collect();
+        for name in procedure_names(program) {
+            state.names.procedure(&name);
+        }
+        if !variables.is_empty() {
+            state.definitions.insert("variables".to_string(), format!("var {};", variables.join(", ")));
+        }
+        *self.state.borrow_mut() = state;
+        ctx.set_indent("  ");
+        Ok(())
+    }
+
+    fn finish(&self, _program: &Program, code: String) -> Result<String, CodegenError> {
+        let state = self.state.replace(State::default());
+        let definitions: Vec<&str> = state.definitions.values().map(String::as_str).collect();
+        Ok(tidy(&format!("{}\n\n\n{}", definitions.join("\n\n"), drop_empty_stacks(&code))))
+    }
+}
+
+// An empty input produces `default`
+fn value(ctx: &mut GenCtx, block: &Block, name: &str, outer: Order, default: &str) -> Result<String, CodegenError> {
+    let code = ctx.value_to_code_with_order(block, name, outer)?;
+    if code.is_empty() {
+        Ok(default.to_string())
+    } else {
+        Ok(code)
+    }
+}
+
+fn logic_operands(a: String, b: String, default: &str, both_empty: &str) -> (String, String) {
+    if a.is_empty() && b.is_empty() {
+        return (both_empty.to_string(), both_empty.to_string());
+    }
+    let or_default = |code: String| if code.is_empty() { default.to_string() } else { code };
+    (or_default(a), or_default(b))
+}
+
+fn has_return_value(block: &Block) -> bool {
+    match block.mutation {
+        Some(ref mutation) => mutation.attribute("value") == Some("1"),
+        None => true,
+    }
+}
+
+// Text blocks join values with `+`, so anything that is not already a
+// string literal is converted
+fn force_string(code: String) -> (String, Order) {
+    if is_string_literal(&code) {
+        (code, order::ATOMIC)
+    } else {
+        (format!("String({})", code), order::FUNCTION_CALL)
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\n', "\\\n").replace('\'', "\\'"))
+}
+
+fn function_name(code: &str) -> Option<&str> {
+    code.strip_prefix("function ")
+        .and_then(|rest| rest.split('(').next())
+}
+
+fn unknown_option(block: &Block, field: &str, option: &str) -> CodegenError {
+    CodegenError::Custom(format!("block {:?} has unknown {} `{}`", block.id, field, option))
+}
+
+const IS_PRIME: &str = "\
+function {name}(n) {
+  // https://en.wikipedia.org/wiki/Primality_test#Naive_methods
+  if (n == 2 || n == 3) {
+    return true;
+  }
+  // False if n is NaN, negative, is 1, or not whole.
+  // And false if n is divisible by 2 or 3.
+  if (isNaN(n) || n <= 1 || n % 1 !== 0 || n % 2 === 0 || n % 3 === 0) {
+    return false;
+  }
+  // Check all the numbers of form 6k +/- 1, up to sqrt(n).
+  for (var x = 6; x <= Math.sqrt(n) + 1; x += 6) {
+    if (n % (x - 1) === 0 || n % (x + 1) === 0) {
+      return false;
+    }
+  }
+  return true;
+}";
+
+const RESERVED: &[&str] = &[
+    "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete", "do",
+    "else", "export", "extends", "finally", "for", "function", "if", "import", "in", "instanceof",
+    "new", "return", "super", "switch", "this", "throw", "try", "typeof", "var", "void", "while",
+    "with", "yield", "enum", "implements", "interface", "let", "package", "private", "protected",
+    "public", "static", "await", "null", "true", "false", "arguments",
+    // Globals a browser defines
+    "Array", "Boolean", "Date", "Error", "Function", "Infinity", "JSON", "Math", "NaN", "Number",
+    "Object", "Promise", "RegExp", "String", "Symbol", "alert", "console", "document", "eval",
+    "globalThis", "isFinite", "isNaN", "parseFloat", "parseInt", "prompt", "undefined", "window",
+];
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::generate;
+    use super::super::super::program_from_xml;
+
+    #[test]
+    fn test_javascript_generator() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables>
+                    <variable id="c">count</variable>
+                    <variable id="w">while</variable>
+                </variables>
+                <block type="procedures_defreturn" id="def">
+                    <mutation><arg name="x"></arg></mutation>
+                    <field name="NAME">double it</field>
+                    <comment pinned="false">Twice x</comment>
+                    <value name="RETURN">
+                        <block type="math_arithmetic" id="mul">
+                            <field name="OP">MULTIPLY</field>
+                            <value name="A">
+                                <block type="math_arithmetic" id="add">
+                                    <field name="OP">ADD</field>
+                                    <value name="A"><block type="variables_get" id="x"><field name="VAR">x</field></block></value>
+                                    <value name="B"><block type="math_number" id="one"><field name="NUM">1</field></block></value>
+                                </block>
+                            </value>
+                            <value name="B"><block type="math_number" id="two"><field name="NUM">2.0</field></block></value>
+                        </block>
+                    </value>
+                </block>
+                <block type="variables_set" id="set">
+                    <field name="VAR" id="w">while</field>
+                    <value name="VALUE"><block type="text" id="hi"><field name="TEXT">it's</field></block></value>
+                    <next>
+                        <block type="controls_repeat_ext" id="repeat">
+                            <value name="TIMES">
+                                <block type="procedures_callreturn" id="call">
+                                    <mutation name="double it"><arg name="x"></arg></mutation>
+                                    <value name="ARG0"><block type="math_number" id="three"><field name="NUM">3</field></block></value>
+                                </block>
+                            </value>
+                            <statement name="DO">
+                                <block type="controls_if" id="if">
+                                    <mutation else="1"></mutation>
+                                    <value name="IF0">
+                                        <block type="logic_negate" id="not">
+                                            <value name="BOOL">
+                                                <block type="logic_compare" id="cmp">
+                                                    <field name="OP">EQ</field>
+                                                    <value name="A"><block type="variables_get" id="get"><field name="VAR">count</field></block></value>
+                                                    <value name="B"><block type="math_number" id="four"><field name="NUM">4</field></block></value>
+                                                </block>
+                                            </value>
+                                        </block>
+                                    </value>
+                                    <statement name="DO0">
+                                        <block type="text_print" id="print">
+                                            <value name="TEXT"><block type="variables_get" id="msg"><field name="VAR">while</field></block></value>
+                                        </block>
+                                    </statement>
+                                    <statement name="ELSE">
+                                        <block type="math_change" id="change">
+                                            <field name="VAR">count</field>
+                                            <value name="DELTA"><block type="math_number" id="minus"><field name="NUM">-1</field></block></value>
+                                        </block>
+                                    </statement>
+                                </block>
+                            </statement>
+                        </block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let code = generate(&program, &JavaScriptGenerator::new()).unwrap();
+        assert_eq!(code, "\
+var x, while2, count;
+
+// Twice x
+function double_it(x) {
+  return (x + 1) * 2;
+}
+
+
+while2 = 'it\\'s';
+var repeat_end = double_it(3);
+for (var count2 = 0; count2 < repeat_end; count2++) {
+  if (!(count == 4)) {
+    window.alert(while2);
+  } else {
+    count = (typeof count === 'number' ? count : 0) + -1;
+  }
+}
+");
+    }
+
+    #[test]
+    fn test_javascript_generator_overrides() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="text_print" id="print">
+                    <value name="TEXT">
+                        <block type="text_join" id="join">
+                            <value name="ADD0"><block type="sensor" id="sensor"></block></value>
+                            <value name="ADD1"><block type="text" id="text"><field name="TEXT">!</field></block></value>
+                        </block>
+                    </value>
+                </block>
+            </xml>
+        "#).unwrap();
+        let generator = JavaScriptGenerator::new().on("sensor", |_block, _ctx| Ok("read() || 0".to_string()));
+        assert_eq!(generate(&program, &generator).unwrap(), "window.alert(String(read() || 0) + '!');\n");
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+
+pub(crate) mod blockly;
+mod bytecode;
+mod javascript;
+mod python;
+mod rust;
+mod source_map;
+mod template;
+
+pub use self::bytecode::{
+    LowerCtx,
+    LowerHandler,
+    Lowering,
+};
+pub use self::javascript::JavaScriptGenerator;
+pub use self::python::PythonGenerator;
+pub use self::rust::RustGenerator;
+pub use self::source_map::{CodeMap, CodeRange};
+pub use self::template::TemplateGenerator;
+pub use super::{
+    Bytecode,
+    Instr,
+};
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    CodegenError,
+    TraversalOptions,
+};
+
+/// Turns blocks into source code for some target language.
+///
+/// Statement blocks return one or more complete lines, each ending in a
+/// newline; the driver takes care of `next` chains and of indenting nested
+/// statement inputs. Value blocks return an expression without a newline.
+pub trait Generator {
+    fn generate_block(&self, block: &Block, ctx: &mut GenCtx) -> Result<String, CodegenError>;
+
+    /// The code for a value block and how tightly it binds, for
+    /// `GenCtx::value_to_code_with_order`. By default this is the code from
+    /// `generate_block`, treated as atomic so it is never parenthesized.
+    fn generate_expression(&self, block: &Block, ctx: &mut GenCtx) -> Result<(String, Order), CodegenError> {
+        Ok((self.generate_block(block, ctx)?, Order::ATOMIC))
+    }
+
+    /// `(outer, inner)` pairs that need no parentheses although `inner` does
+    /// not bind tighter than `outer`, e.g. `a && b` nested in another `&&`.
+    fn order_overrides(&self) -> &[(Order, Order)] {
+        &[]
+    }
+
+    /// Called before any block is generated, e.g. to work out variable types
+    /// or to change the indentation.
+    fn prepare(&self, _program: &Program, _ctx: &mut GenCtx) -> Result<(), CodegenError> {
+        Ok(())
+    }
+
+    /// Post-process the code for the whole program, e.g. to add a prologue.
+    fn finish(&self, _program: &Program, code: String) -> Result<String, CodegenError> {
+        Ok(code)
+    }
+}
+
+/// How tightly an expression binds, like the `ORDER_*` constants of
+/// Blockly's generators: lower binds tighter. Only the whole part is compared,
+/// so a generator can number related operators 5.1, 5.2 and so on and still
+/// tell them apart in `Generator::order_overrides`.
+#[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+pub struct Order(pub f64);
+
+impl Order {
+    /// Literals, names and anything already bracketed.
+    pub const ATOMIC: Order = Order(0.0);
+    /// Binds loosest, e.g. a whole function argument or right-hand side.
+    pub const NONE: Order = Order(99.0);
+}
+
+/// State for a single code generation run, passed to every `generate_block` call.
+pub struct GenCtx<'g> {
+    generator: &'g dyn Generator,
+    indent: String,
+    traversal: TraversalOptions,
+    // The ids of the blocks marked so far, when building a source map
+    marked: Option<Vec<String>>,
+}
+
+impl<'g> GenCtx<'g> {
+    pub fn new(generator: &'g dyn Generator) -> Self {
+        Self {
+            generator,
+            indent: "  ".to_string(),
+            traversal: TraversalOptions::default(),
+            marked: None,
+        }
+    }
+
+    /// Which disabled and collapsed blocks the `*_to_code` helpers skip.
+    /// Skipped blocks produce no code.
+    pub fn set_traversal(&mut self, traversal: TraversalOptions) {
+        self.traversal = traversal;
+    }
+
+    pub fn traversal(&self) -> &TraversalOptions {
+        &self.traversal
+    }
+
+    /// The string prepended to each line of a nested statement input.
+    pub fn set_indent<T: Into<String>>(&mut self, indent: T) {
+        self.indent = indent.into();
+    }
+
+    pub fn indent(&self) -> &str {
+        &self.indent
+    }
+
+    pub fn block_to_code(&mut self, block: &Block) -> Result<String, CodegenError> {
+        let generator = self.generator;
+        generator.generate_block(block, self)
+    }
+
+    pub fn expression_to_code(&mut self, block: &Block) -> Result<(String, Order), CodegenError> {
+        let generator = self.generator;
+        generator.generate_expression(block, self)
+    }
+
+    /// Code for every block in a stack, in order, without extra indentation.
+    pub fn stack_to_code(&mut self, body: &StatementBody) -> Result<String, CodegenError> {
+        let mut code = String::new();
+        for block in self.traversal.stack(&body.blocks) {
+            let block_code = self.block_to_code(block)?;
+            match self.marked {
+                Some(ref mut marked) if !block_code.is_empty() => {
+                    code.push_str(&source_map::mark(block_code, marked.len()));
+                    marked.push(block.id.clone());
+                },
+                _ => code.push_str(&block_code),
+            }
+        }
+        Ok(code)
+    }
+
+    /// Code for the statement input `name` of `block`, indented one level.
+    /// An empty or missing input produces an empty string.
+    pub fn statement_to_code(&mut self, block: &Block, name: &str) -> Result<String, CodegenError> {
+        let code = match block.statements.get(name) {
+            Some(body) if self.traversal.enters(block) => self.stack_to_code(body)?,
+            _ => return Ok(String::new())
+        };
+        Ok(prefix_lines(&code, &self.indent))
+    }
+
+    /// The expression for the value input `name` of `block`. Shadow blocks
+    /// generate code like real blocks; an empty input produces an empty string.
+    pub fn value_to_code(&mut self, block: &Block, name: &str) -> Result<String, CodegenError> {
+        match block.values.get(name) {
+            Some(input) if self.traversal.enters(block) && self.traversal.includes(input.block()) => {
+                self.block_to_code(input.block())
+            },
+            _ => Ok(String::new())
+        }
+    }
+
+    /// Like `value_to_code`, but in parentheses if the expression binds less
+    /// tightly than the `outer` context needs, e.g. `Order::NONE` for a
+    /// function argument.
+    pub fn value_to_code_with_order(&mut self, block: &Block, name: &str, outer: Order) -> Result<String, CodegenError> {
+        match block.values.get(name) {
+            Some(input) if self.traversal.enters(block) && self.traversal.includes(input.block()) => {
+                let (code, inner) = self.expression_to_code(input.block())?;
+                Ok(parenthesize(code, inner, outer, self.generator.order_overrides()))
+            },
+            _ => Ok(String::new())
+        }
+    }
+}
+
+/// Generate code for a whole program, one top-level stack after another.
+pub fn generate(program: &Program, generator: &dyn Generator) -> Result<String, CodegenError> {
+    generate_with(program, generator, &TraversalOptions::default())
+}
+
+/// Like `generate`, skipping disabled blocks or the insides of collapsed
+/// blocks as `options` say. Pass `TraversalOptions::skip_disabled()` to
+/// match Blockly's own generators.
+pub fn generate_with(program: &Program, generator: &dyn Generator, options: &TraversalOptions) -> Result<String, CodegenError> {
+    let mut ctx = GenCtx::new(generator);
+    ctx.set_traversal(*options);
+    run(program, generator, &mut ctx)
+}
+
+/// Like `generate_with`, also mapping lines of the code back to the
+/// statement blocks that produced them, e.g. to highlight the block behind
+/// a runtime error.
+pub fn generate_with_source_map(program: &Program, generator: &dyn Generator, options: &TraversalOptions) -> Result<(String, CodeMap), CodegenError> {
+    let mut ctx = GenCtx::new(generator);
+    ctx.set_traversal(*options);
+    ctx.marked = Some(Vec::new());
+    let code = run(program, generator, &mut ctx)?;
+    Ok(source_map::unmark(&code, &ctx.marked.unwrap_or_default()))
+}
+
+fn run(program: &Program, generator: &dyn Generator, ctx: &mut GenCtx) -> Result<String, CodegenError> {
+    generator.prepare(program, ctx)?;
+    let mut stacks = Vec::new();
+    for group in program.groups.iter() {
+        stacks.push(ctx.stack_to_code(group)?);
+    }
+    generator.finish(program, stacks.join("\n"))
+}
+
+pub type BlockHandler = Box<dyn Fn(&Block, &mut GenCtx) -> Result<String, CodegenError>>;
+
+pub type ExpressionHandler = Box<dyn Fn(&Block, &mut GenCtx) -> Result<(String, Order), CodegenError>>;
+
+/// A generator assembled from one handler per block type.
+///
+/// ```
+/// use blockly_parser::codegen::{generate, DispatchGenerator};
+/// use blockly_parser::program_from_xml;
+///
+/// let generator = DispatchGenerator::new()
+///     .on("led_on", |block, _ctx| Ok(format!("led_on({});\n", block.field_str("TIME")?)));
+///
+/// let program = program_from_xml(
+///     r#"<xml><block type="led_on"><field name="TIME">300</field></block></xml>"#
+/// ).unwrap();
+/// assert_eq!(generate(&program, &generator).unwrap(), "led_on(300);\n");
+/// ```
+#[derive(Default)]
+pub struct DispatchGenerator {
+    handlers: HashMap<String, BlockHandler>,
+    expressions: HashMap<String, ExpressionHandler>,
+    order_overrides: Vec<(Order, Order)>,
+}
+
+impl DispatchGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on<T, F>(mut self, block_type: T, handler: F) -> Self
+        where T: Into<String>,
+              F: Fn(&Block, &mut GenCtx) -> Result<String, CodegenError> + 'static
+    {
+        self.handlers.insert(block_type.into(), Box::new(handler));
+        self
+    }
+
+    /// Handle a value block, returning its code and how tightly it binds.
+    pub fn on_expression<T, F>(mut self, block_type: T, handler: F) -> Self
+        where T: Into<String>,
+              F: Fn(&Block, &mut GenCtx) -> Result<(String, Order), CodegenError> + 'static
+    {
+        self.expressions.insert(block_type.into(), Box::new(handler));
+        self
+    }
+
+    /// See `Generator::order_overrides`.
+    pub fn order_overrides(mut self, overrides: Vec<(Order, Order)>) -> Self {
+        self.order_overrides = overrides;
+        self
+    }
+}
+
+impl Generator for DispatchGenerator {
+    fn generate_block(&self, block: &Block, ctx: &mut GenCtx) -> Result<String, CodegenError> {
+        if let Some(handler) = self.handlers.get(&block.block_type) {
+            return handler(block, ctx);
+        }
+        match self.expressions.get(&block.block_type) {
+            Some(handler) => handler(block, ctx).map(|(code, _)| code),
+            None => Err(CodegenError::UnsupportedBlock {
+                block_type: block.block_type.clone(),
+                id: block.id.clone(),
+            })
+        }
+    }
+
+    fn generate_expression(&self, block: &Block, ctx: &mut GenCtx) -> Result<(String, Order), CodegenError> {
+        match self.expressions.get(&block.block_type) {
+            Some(handler) => handler(block, ctx),
+            None => Ok((self.generate_block(block, ctx)?, Order::ATOMIC)),
+        }
+    }
+
+    fn order_overrides(&self) -> &[(Order, Order)] {
+        &self.order_overrides
+    }
+}
+
+/// Wrap `code`, an expression of order `inner`, in parentheses if it binds
+/// no tighter than the `outer` context, as Blockly's `valueToCode` does.
+/// Atomic code in an atomic context and code in a `NONE` context are left
+/// alone, as are the `(outer, inner)` pairs in `overrides`.
+pub fn parenthesize(code: String, inner: Order, outer: Order, overrides: &[(Order, Order)]) -> String {
+    if code.is_empty() {
+        return code;
+    }
+    let (outer_class, inner_class) = (outer.0.floor(), inner.0.floor());
+    let trivial = outer_class == inner_class && (outer_class == Order::ATOMIC.0 || outer_class == Order::NONE.0);
+    if outer_class <= inner_class && !trivial && !overrides.contains(&(outer, inner)) {
+        format!("({})", code)
+    } else {
+        code
+    }
+}
+
+pub(crate) fn prefix_lines(code: &str, prefix: &str) -> String {
+    let mut prefixed = String::with_capacity(code.len());
+    for line in code.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            prefixed.push_str(line);
+        } else {
+            prefixed.push_str(prefix);
+            prefixed.push_str(line);
+        }
+    }
+    prefixed
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        program_from_xml,
+        FieldError,
+    };
+
+    fn led_generator() -> DispatchGenerator {
+        DispatchGenerator::new()
+            .on("main_loop", |block, ctx| {
+                Ok(format!("loop {{\n{}}}\n", ctx.statement_to_code(block, "BODY")?))
+            })
+            .on("inner_loop", |block, ctx| {
+                Ok(format!(
+                    "for _ in 0..{} {{\n{}}}\n",
+                    block.field_i64("COUNT")?,
+                    ctx.statement_to_code(block, "BODY")?
+                ))
+            })
+            .on("led_on", |block, _ctx| Ok(format!("led_on({});\n", block.field_i64("TIME")?)))
+            .on("led_off", |block, _ctx| Ok(format!("led_off({});\n", block.field_i64("TIME")?)))
+    }
+
+    #[test]
+    fn test_generate() {
+        let xml: &str = r#"
+            <xml xmlns="http://www.w3.org/1999/xhtml">
+                <block type="main_loop" id="[.)/fqUYv92(mzb{?:~u">
+                    <statement name="BODY">
+                        <block type="inner_loop" id="]Lb|t?wfd#;s)[llJx8Y">
+                            <field name="COUNT">3</field>
+                            <statement name="BODY">
+                                <block type="led_on" id="^3xb.m4E9i0;3$R10(=5">
+                                    <field name="TIME">300</field>
+                                    <next>
+                                        <block type="led_off" id="HX4*sB9=gbJtq$Y{ke6b">
+                                            <field name="TIME">100</field>
+                                        </block>
+                                    </next>
+                                </block>
+                            </statement>
+                            <next>
+                                <block type="led_on" id="kB~f~7W`wkGa0i4z3mHw">
+                                    <field name="TIME">100</field>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#;
+        let program = program_from_xml(xml).unwrap();
+
+        let code = generate(&program, &led_generator()).unwrap();
+        assert_eq!(code, "\
+loop {
+  for _ in 0..3 {
+    led_on(300);
+    led_off(100);
+  }
+  led_on(100);
+}
+");
+    }
+
+    #[test]
+    fn test_generate_errors() {
+        let program = program_from_xml(r#"<xml><block type="buzzer" id="b1"></block></xml>"#).unwrap();
+        assert_eq!(generate(&program, &led_generator()), Err(CodegenError::UnsupportedBlock {
+            block_type: "buzzer".to_string(),
+            id: "b1".to_string(),
+        }));
+
+        let program = program_from_xml(r#"<xml><block type="led_on" id="b2"></block></xml>"#).unwrap();
+        assert_eq!(
+            generate(&program, &led_generator()),
+            Err(CodegenError::Field(FieldError::Missing("TIME".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_generate_with_disabled_blocks() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="on" disabled="true">
+                            <field name="TIME">300</field>
+                            <next>
+                                <block type="led_off" id="off"><field name="TIME">100</field></block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        assert_eq!(generate(&program, &led_generator()).unwrap(), "loop {\n  led_on(300);\n  led_off(100);\n}\n");
+        assert_eq!(
+            generate_with(&program, &led_generator(), &TraversalOptions::skip_disabled()).unwrap(),
+            "loop {\n  led_off(100);\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_with_orders() {
+        const MULTIPLY: Order = Order(5.1);
+        const ADD: Order = Order(6.0);
+        let generator = DispatchGenerator::new()
+            .on("print", |block, ctx| Ok(format!("print({});\n", ctx.value_to_code_with_order(block, "VALUE", Order::NONE)?)))
+            .on_expression("number", |block, _ctx| Ok((block.field_str("NUM")?.to_string(), Order::ATOMIC)))
+            .on_expression("arithmetic", |block, ctx| {
+                let (operator, order) = match block.field_str("OP")? {
+                    "ADD" => ("+", ADD),
+                    _ => ("*", MULTIPLY),
+                };
+                let a = ctx.value_to_code_with_order(block, "A", order)?;
+                let b = ctx.value_to_code_with_order(block, "B", order)?;
+                Ok((format!("{} {} {}", a, operator, b), order))
+            })
+            .order_overrides(vec![(MULTIPLY, MULTIPLY)]);
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="print">
+                    <value name="VALUE">
+                        <block type="arithmetic">
+                            <field name="OP">MULTIPLY</field>
+                            <value name="A">
+                                <block type="arithmetic">
+                                    <field name="OP">ADD</field>
+                                    <value name="A"><block type="number"><field name="NUM">1</field></block></value>
+                                    <value name="B"><block type="number"><field name="NUM">2</field></block></value>
+                                </block>
+                            </value>
+                            <value name="B">
+                                <block type="arithmetic">
+                                    <field name="OP">MULTIPLY</field>
+                                    <value name="A"><block type="number"><field name="NUM">3</field></block></value>
+                                    <value name="B"><block type="number"><field name="NUM">4</field></block></value>
+                                </block>
+                            </value>
+                        </block>
+                    </value>
+                </block>
+            </xml>
+        "#).unwrap();
+        assert_eq!(generate(&program, &generator).unwrap(), "print((1 + 2) * 3 * 4);\n");
+
+        assert_eq!(parenthesize("a + b".to_string(), ADD, MULTIPLY, &[]), "(a + b)");
+        assert_eq!(parenthesize("a * b".to_string(), MULTIPLY, ADD, &[]), "a * b");
+        assert_eq!(parenthesize("a * b".to_string(), Order(5.2), MULTIPLY, &[(MULTIPLY, MULTIPLY)]), "(a * b)");
+    }
+}
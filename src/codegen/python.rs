@@ -0,0 +1,834 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+
+use super::{
+    BlockHandler,
+    GenCtx,
+    Generator,
+    Order,
+};
+use super::blockly::{
+    Names,
+    comment,
+    drop_empty_stacks,
+    format_number,
+    is_number,
+    is_string_literal,
+    is_word,
+    parse_int,
+    parse_number,
+    procedure_names,
+    tidy,
+    used_variables,
+};
+use super::super::{
+    Block,
+    CodegenError,
+    Program,
+};
+use super::super::procedures::parameters;
+
+/// Generates Python 3 from Blockly's standard logic, loop, math, text,
+/// variable and procedure blocks, producing the same code as Blockly's own
+/// `pythonGenerator`: the same parentheses, two-space indentation, `None`
+/// initializers, `global` statements and renaming of variables that collide
+/// with each other or with reserved words. Register handlers with `on` for
+/// custom blocks, or to replace a built-in one; their values are treated as
+/// binding loosest.
+///
+/// Blockly skips disabled blocks, so pair this with
+/// `TraversalOptions::skip_disabled()` for identical output. Stacks are
+/// generated in document order rather than by position, and comments are
+/// not re-wrapped.
+///
+/// ```
+/// use blockly_parser::codegen::{generate, PythonGenerator};
+/// use blockly_parser::program_from_xml;
+///
+/// let program = program_from_xml(r#"
+///     <xml>
+///         <block type="text_print">
+///             <value name="TEXT"><block type="text"><field name="TEXT">Hi!</field></block></value>
+///         </block>
+///     </xml>
+/// "#).unwrap();
+/// assert_eq!(generate(&program, &PythonGenerator::new()).unwrap(), "print('Hi!')\n");
+/// ```
+#[derive(Default)]
+pub struct PythonGenerator {
+    overrides: HashMap<String, BlockHandler>,
+    state: RefCell<State>,
+}
+
+#[derive(Default)]
+struct State {
+    names: Names,
+    // By Blockly name, for the `global` statements of procedures
+    variables: Vec<String>,
+    // In the order they were added, like Blockly's `definitions_`
+    definitions: IndexMap<String, String>,
+    // Helper functions, by the name they were asked for
+    functions: HashMap<String, String>,
+}
+
+// Operator precedence, as in Blockly's Python generator: lower binds tighter
+mod order {
+    use super::Order;
+
+    pub const ATOMIC: Order = Order::ATOMIC;
+    pub const MEMBER: Order = Order(2.1);
+    pub const FUNCTION_CALL: Order = Order(2.2);
+    pub const EXPONENTIATION: Order = Order(3.0);
+    pub const UNARY_SIGN: Order = Order(4.0);
+    pub const MULTIPLICATIVE: Order = Order(5.0);
+    pub const ADDITIVE: Order = Order(6.0);
+    pub const RELATIONAL: Order = Order(11.0);
+    pub const LOGICAL_NOT: Order = Order(12.0);
+    pub const LOGICAL_AND: Order = Order(13.0);
+    pub const LOGICAL_OR: Order = Order(14.0);
+    pub const CONDITIONAL: Order = Order(15.0);
+    pub const NONE: Order = Order::NONE;
+
+    /// `(outer, inner)` pairs that need no parentheses, e.g. `a and (b and c)`.
+    pub const OVERRIDES: &[(Order, Order)] = &[
+        (FUNCTION_CALL, MEMBER),
+        (FUNCTION_CALL, FUNCTION_CALL),
+        (MEMBER, MEMBER),
+        (MEMBER, FUNCTION_CALL),
+        (LOGICAL_NOT, LOGICAL_NOT),
+        (LOGICAL_AND, LOGICAL_AND),
+        (LOGICAL_OR, LOGICAL_OR),
+    ];
+}
+
+impl PythonGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generate blocks of `block_type` with `handler` instead of the
+    /// built-in code.
+    pub fn on<T, F>(mut self, block_type: T, handler: F) -> Self
+        where T: Into<String>,
+              F: Fn(&Block, &mut GenCtx) -> Result<String, CodegenError> + 'static
+    {
+        self.overrides.insert(block_type.into(), Box::new(handler));
+        self
+    }
+
+    // `None` if `block` is not a built-in statement block
+    fn statement(&self, block: &Block, ctx: &mut GenCtx) -> Result<Option<String>, CodegenError> {
+        let code = match block.block_type.as_str() {
+            // Logic
+            "controls_if" => {
+                let mutation = |name| block.mutation.as_ref()
+                    .and_then(|mutation| mutation.attribute(name))
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(0);
+                let mut code = String::new();
+                for branch in 0..=mutation("elseif") {
+                    let condition = value(ctx, block, &format!("IF{}", branch), order::NONE, "False")?;
+                    let body = body_or_pass(ctx, block, &format!("DO{}", branch))?;
+                    let keyword = if branch == 0 { "if" } else { "elif" };
+                    code.push_str(&format!("{} {}:\n{}", keyword, condition, body));
+                }
+                if mutation("else") > 0 {
+                    code.push_str(&format!("else:\n{}", body_or_pass(ctx, block, "ELSE")?));
+                }
+                code
+            },
+
+            // Loops
+            "controls_repeat_ext" | "controls_repeat" => {
+                let repeats = if block.block_type == "controls_repeat" {
+                    parse_int(block.field_str("TIMES")?).map_or("NaN".to_string(), |times| times.to_string())
+                } else {
+                    value(ctx, block, "TIMES", order::NONE, "0")?
+                };
+                let repeats = match parse_int(&repeats) {
+                    Some(times) if is_number(&repeats) => times.to_string(),
+                    _ => format!("int({})", repeats),
+                };
+                let body = body_or_pass(ctx, block, "DO")?;
+                let counter = self.state.borrow_mut().names.distinct("count");
+                format!("for {} in range({}):\n{}", counter, repeats, body)
+            },
+            "controls_whileUntil" => {
+                let until = block.field_str("MODE")? == "UNTIL";
+                let outer = if until { order::LOGICAL_NOT } else { order::NONE };
+                let mut condition = value(ctx, block, "BOOL", outer, "False")?;
+                let body = body_or_pass(ctx, block, "DO")?;
+                if until {
+                    condition = format!("not {}", condition);
+                }
+                format!("while {}:\n{}", condition, body)
+            },
+            "controls_for" => self.for_loop(block, ctx)?,
+            "controls_flow_statements" => match block.field_str("FLOW")? {
+                "BREAK" => "break\n".to_string(),
+                "CONTINUE" => "continue\n".to_string(),
+                flow => return Err(unknown_option(block, "FLOW", flow)),
+            },
+
+            // Math
+            "math_change" => {
+                self.define("from_numbers_import_Number", "from numbers import Number");
+                let delta = value(ctx, block, "DELTA", order::ADDITIVE, "0")?;
+                let variable = self.variable(block.field_str("VAR")?);
+                format!("{0} = ({0} if isinstance({0}, Number) else 0) + {1}\n", variable, delta)
+            },
+
+            // Text
+            "text_append" => {
+                let variable = self.variable(block.field_str("VAR")?);
+                let text = value(ctx, block, "TEXT", order::NONE, "''")?;
+                format!("{0} = str({0}) + {1}\n", variable, force_string(text).0)
+            },
+            "text_print" => format!("print({})\n", value(ctx, block, "TEXT", order::NONE, "''")?),
+
+            // Variables
+            "variables_set" => {
+                let assigned = value(ctx, block, "VALUE", order::NONE, "0")?;
+                format!("{} = {}\n", self.variable(block.field_str("VAR")?), assigned)
+            },
+
+            // Procedures
+            "procedures_defnoreturn" | "procedures_defreturn" => {
+                self.define_procedure(block, ctx)?;
+                String::new()
+            },
+            "procedures_callnoreturn" => format!("{}\n", self.call(block, ctx)?),
+            "procedures_ifreturn" => {
+                let condition = value(ctx, block, "CONDITION", order::NONE, "False")?;
+                let statement = if has_return_value(block) {
+                    format!("return {}\n", value(ctx, block, "VALUE", order::NONE, "None")?)
+                } else {
+                    "return\n".to_string()
+                };
+                format!("if {}:\n{}{}", condition, ctx.indent(), statement)
+            },
+
+            _ => return Ok(None),
+        };
+        Ok(Some(code))
+    }
+
+    fn expression(&self, block: &Block, ctx: &mut GenCtx) -> Result<(String, Order), CodegenError> {
+        if let Some(handler) = self.overrides.get(&block.block_type) {
+            return Ok((handler(block, ctx)?, order::NONE));
+        }
+        let expression = match block.block_type.as_str() {
+            // Logic
+            "logic_compare" => {
+                let operator = match block.field_str("OP")? {
+                    "EQ" => "==",
+                    "NEQ" => "!=",
+                    "LT" => "<",
+                    "LTE" => "<=",
+                    "GT" => ">",
+                    "GTE" => ">=",
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                let a = value(ctx, block, "A", order::RELATIONAL, "0")?;
+                let b = value(ctx, block, "B", order::RELATIONAL, "0")?;
+                (format!("{} {} {}", a, operator, b), order::RELATIONAL)
+            },
+            "logic_operation" => {
+                let (operator, order) = match block.field_str("OP")? {
+                    "AND" => ("and", order::LOGICAL_AND),
+                    "OR" => ("or", order::LOGICAL_OR),
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                let a = value(ctx, block, "A", order, "")?;
+                let b = value(ctx, block, "B", order, "")?;
+                let (a, b) = logic_operands(a, b, if operator == "and" { "True" } else { "False" }, "False");
+                (format!("{} {} {}", a, operator, b), order)
+            },
+            "logic_negate" => {
+                let operand = value(ctx, block, "BOOL", order::LOGICAL_NOT, "True")?;
+                (format!("not {}", operand), order::LOGICAL_NOT)
+            },
+            "logic_boolean" => match block.field_str("BOOL")? {
+                "TRUE" => ("True".to_string(), order::ATOMIC),
+                _ => ("False".to_string(), order::ATOMIC),
+            },
+            "logic_null" => ("None".to_string(), order::ATOMIC),
+            "logic_ternary" => {
+                let condition = value(ctx, block, "IF", order::CONDITIONAL, "False")?;
+                let then = value(ctx, block, "THEN", order::CONDITIONAL, "None")?;
+                let otherwise = value(ctx, block, "ELSE", order::CONDITIONAL, "None")?;
+                (format!("{} if {} else {}", then, condition, otherwise), order::CONDITIONAL)
+            },
+
+            // Math
+            "math_number" => {
+                let number = parse_number(block.field_str("NUM")?);
+                let code = if number == f64::INFINITY {
+                    "float(\"inf\")".to_string()
+                } else if number == f64::NEG_INFINITY {
+                    "-float(\"inf\")".to_string()
+                } else {
+                    format_number(number)
+                };
+                (code, if number < 0.0 { order::UNARY_SIGN } else { order::ATOMIC })
+            },
+            "math_arithmetic" => {
+                let (operator, order) = match block.field_str("OP")? {
+                    "ADD" => (" + ", order::ADDITIVE),
+                    "MINUS" => (" - ", order::ADDITIVE),
+                    "MULTIPLY" => (" * ", order::MULTIPLICATIVE),
+                    "DIVIDE" => (" / ", order::MULTIPLICATIVE),
+                    "POWER" => (" ** ", order::EXPONENTIATION),
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                let a = value(ctx, block, "A", order, "0")?;
+                let b = value(ctx, block, "B", order, "0")?;
+                (format!("{}{}{}", a, operator, b), order)
+            },
+            "math_single" | "math_round" | "math_trig" => {
+                let operator = block.field_str("OP")?;
+                if operator == "NEG" {
+                    let number = value(ctx, block, "NUM", order::UNARY_SIGN, "0")?;
+                    return Ok((format!("-{}", number), order::UNARY_SIGN));
+                }
+                self.define("import_math", "import math");
+                let outer = match operator {
+                    "SIN" | "COS" | "TAN" => order::MULTIPLICATIVE,
+                    _ => order::NONE,
+                };
+                let number = value(ctx, block, "NUM", outer, "0")?;
+                match operator {
+                    "ABS" => (format!("math.fabs({})", number), order::FUNCTION_CALL),
+                    "ROOT" => (format!("math.sqrt({})", number), order::FUNCTION_CALL),
+                    "LN" => (format!("math.log({})", number), order::FUNCTION_CALL),
+                    "LOG10" => (format!("math.log10({})", number), order::FUNCTION_CALL),
+                    "EXP" => (format!("math.exp({})", number), order::FUNCTION_CALL),
+                    "POW10" => (format!("math.pow(10,{})", number), order::FUNCTION_CALL),
+                    "ROUND" => (format!("round({})", number), order::FUNCTION_CALL),
+                    "ROUNDUP" => (format!("math.ceil({})", number), order::FUNCTION_CALL),
+                    "ROUNDDOWN" => (format!("math.floor({})", number), order::FUNCTION_CALL),
+                    "SIN" => (format!("math.sin({} / 180.0 * math.pi)", number), order::FUNCTION_CALL),
+                    "COS" => (format!("math.cos({} / 180.0 * math.pi)", number), order::FUNCTION_CALL),
+                    "TAN" => (format!("math.tan({} / 180.0 * math.pi)", number), order::FUNCTION_CALL),
+                    "ASIN" => (format!("math.asin({}) / math.pi * 180", number), order::MULTIPLICATIVE),
+                    "ACOS" => (format!("math.acos({}) / math.pi * 180", number), order::MULTIPLICATIVE),
+                    "ATAN" => (format!("math.atan({}) / math.pi * 180", number), order::MULTIPLICATIVE),
+                    op => return Err(unknown_option(block, "OP", op)),
+                }
+            },
+            "math_constant" => {
+                let constant = block.field_str("CONSTANT")?;
+                if constant != "INFINITY" {
+                    self.define("import_math", "import math");
+                }
+                match constant {
+                    "PI" => ("math.pi".to_string(), order::MEMBER),
+                    "E" => ("math.e".to_string(), order::MEMBER),
+                    "GOLDEN_RATIO" => ("(1 + math.sqrt(5)) / 2".to_string(), order::MULTIPLICATIVE),
+                    "SQRT2" => ("math.sqrt(2)".to_string(), order::MEMBER),
+                    "SQRT1_2" => ("math.sqrt(1.0 / 2)".to_string(), order::MEMBER),
+                    "INFINITY" => ("float('inf')".to_string(), order::ATOMIC),
+                    constant => return Err(unknown_option(block, "CONSTANT", constant)),
+                }
+            },
+            "math_number_property" => {
+                let property = block.field_str("PROPERTY")?;
+                let (suffix, inner, outer) = match property {
+                    "EVEN" => (" % 2 == 0", order::MULTIPLICATIVE, order::RELATIONAL),
+                    "ODD" => (" % 2 == 1", order::MULTIPLICATIVE, order::RELATIONAL),
+                    "WHOLE" => (" % 1 == 0", order::MULTIPLICATIVE, order::RELATIONAL),
+                    "POSITIVE" => (" > 0", order::RELATIONAL, order::RELATIONAL),
+                    "NEGATIVE" => (" < 0", order::RELATIONAL, order::RELATIONAL),
+                    "DIVISIBLE_BY" => ("", order::MULTIPLICATIVE, order::RELATIONAL),
+                    "PRIME" => ("", order::NONE, order::FUNCTION_CALL),
+                    property => return Err(unknown_option(block, "PROPERTY", property)),
+                };
+                let number = value(ctx, block, "NUMBER_TO_CHECK", inner, "0")?;
+                let code = match property {
+                    "PRIME" => {
+                        self.define("import_math", "import math");
+                        self.define("from_numbers_import_Number", "from numbers import Number");
+                        format!("{}({})", self.provide_function("math_isPrime", IS_PRIME), number)
+                    },
+                    "DIVISIBLE_BY" => {
+                        let divisor = value(ctx, block, "DIVISOR", order::MULTIPLICATIVE, "0")?;
+                        format!("{} % {} == 0", number, divisor)
+                    },
+                    _ => format!("{}{}", number, suffix),
+                };
+                (code, outer)
+            },
+            "math_modulo" => {
+                let dividend = value(ctx, block, "DIVIDEND", order::MULTIPLICATIVE, "0")?;
+                let divisor = value(ctx, block, "DIVISOR", order::MULTIPLICATIVE, "0")?;
+                (format!("{} % {}", dividend, divisor), order::MULTIPLICATIVE)
+            },
+            "math_constrain" => {
+                let number = value(ctx, block, "VALUE", order::NONE, "0")?;
+                let low = value(ctx, block, "LOW", order::NONE, "0")?;
+                let high = value(ctx, block, "HIGH", order::NONE, "float('inf')")?;
+                (format!("min(max({}, {}), {})", number, low, high), order::FUNCTION_CALL)
+            },
+
+            // Text
+            "text" => (quote(block.field_str("TEXT").unwrap_or("")), order::ATOMIC),
+            "text_join" => {
+                let items = block.mutation.as_ref()
+                    .and_then(|mutation| mutation.attribute("items"))
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(2);
+                let mut values = Vec::new();
+                for item in 0..items {
+                    values.push(value(ctx, block, &format!("ADD{}", item), order::NONE, "''")?);
+                }
+                match items {
+                    0 => ("''".to_string(), order::ATOMIC),
+                    1 => (format!("str({})", values[0]), order::FUNCTION_CALL),
+                    2 => (format!("str({}) + str({})", values[0], values[1]), order::ADDITIVE),
+                    _ => {
+                        let item = self.state.borrow_mut().names.distinct("x");
+                        let code = format!("''.join([str({0}) for {0} in [{1}]])", item, values.join(", "));
+                        (code, order::FUNCTION_CALL)
+                    },
+                }
+            },
+            "text_length" => {
+                let text = value(ctx, block, "VALUE", order::NONE, "''")?;
+                (format!("len({})", text), order::FUNCTION_CALL)
+            },
+            "text_isEmpty" => {
+                let text = value(ctx, block, "VALUE", order::NONE, "''")?;
+                (format!("not len({})", text), order::LOGICAL_NOT)
+            },
+
+            // Variables
+            "variables_get" => (self.variable(block.field_str("VAR")?), order::ATOMIC),
+
+            // Procedures
+            "procedures_callreturn" => (self.call(block, ctx)?, order::FUNCTION_CALL),
+
+            _ => {
+                return Err(CodegenError::UnsupportedBlock {
+                    block_type: block.block_type.clone(),
+                    id: block.id.clone(),
+                });
+            }
+        };
+        Ok(expression)
+    }
+
+    // Blockly counts up or down depending on the bounds, with helper
+    // generators when they are not all whole numbers
+    fn for_loop(&self, block: &Block, ctx: &mut GenCtx) -> Result<String, CodegenError> {
+        let variable = self.variable(block.field_str("VAR")?);
+        let from = value(ctx, block, "FROM", order::NONE, "0")?;
+        let to = value(ctx, block, "TO", order::NONE, "0")?;
+        let by = value(ctx, block, "BY", order::NONE, "1")?;
+        let body = body_or_pass(ctx, block, "DO")?;
+        let mut code = String::new();
+
+        let range = if is_number(&from) && is_number(&to) && is_number(&by) {
+            let (start, end, step) = (parse_number(&from), parse_number(&to), parse_number(&by).abs());
+            if start.fract() == 0.0 && end.fract() == 0.0 && step.fract() == 0.0 {
+                let arguments = if start <= end {
+                    let end = end + 1.0;
+                    let mut arguments = if start == 0.0 && step == 1.0 {
+                        format_number(end)
+                    } else {
+                        format!("{}, {}", format_number(start), format_number(end))
+                    };
+                    if step != 1.0 {
+                        arguments.push_str(&format!(", {}", format_number(step)));
+                    }
+                    arguments
+                } else {
+                    format!("{}, {}, -{}", format_number(start), format_number(end - 1.0), format_number(step))
+                };
+                format!("range({})", arguments)
+            } else {
+                let helper = if start < end { self.up_range() } else { self.down_range() };
+                format!("{}({}, {}, {})", helper, format_number(start), format_number(end), format_number(step))
+            }
+        } else {
+            // Cache anything more than a name or a number in a variable
+            let mut cache = |argument: String, suffix: &str| {
+                if is_number(&argument) {
+                    return (format_number(parse_number(&argument)), true);
+                }
+                if is_word(&argument) {
+                    return (argument, false);
+                }
+                let name = self.state.borrow_mut().names.distinct(&format!("{}{}", variable, suffix));
+                code.push_str(&format!("{} = {}\n", name, argument));
+                (name, false)
+            };
+            let (start, start_is_number) = cache(from, "_start");
+            let (end, end_is_number) = cache(to, "_end");
+            let (increment, _) = cache(by, "_inc");
+            if start_is_number && end_is_number {
+                let helper = if parse_number(&start) < parse_number(&end) { self.up_range() } else { self.down_range() };
+                format!("{}({}, {}, {})", helper, start, end, increment)
+            } else {
+                format!(
+                    "({0} <= {1}) and {2}({0}, {1}, {3}) or {4}({0}, {1}, {3})",
+                    start, end, self.up_range(), increment, self.down_range()
+                )
+            }
+        };
+        code.push_str(&format!("for {} in {}:\n{}", variable, range, body));
+        Ok(code)
+    }
+
+    fn up_range(&self) -> String {
+        self.provide_function("upRange", UP_RANGE)
+    }
+
+    fn down_range(&self) -> String {
+        self.provide_function("downRange", DOWN_RANGE)
+    }
+
+    fn variable(&self, name: &str) -> String {
+        self.state.borrow_mut().names.variable(name)
+    }
+
+    fn define(&self, key: &str, code: &str) {
+        self.state.borrow_mut().definitions.insert(key.to_string(), code.to_string());
+    }
+
+    fn call(&self, block: &Block, ctx: &mut GenCtx) -> Result<String, CodegenError> {
+        let mutation = block.mutation.as_ref();
+        let name = mutation.and_then(|mutation| mutation.attribute("name"))
+            .ok_or_else(|| CodegenError::Custom(format!("call block {:?} names no procedure", block.id)))?;
+        let function = self.state.borrow_mut().names.procedure(name);
+        let mut arguments = Vec::new();
+        for index in 0..mutation.map(|mutation| parameters(mutation).len()).unwrap_or(0) {
+            arguments.push(value(ctx, block, &format!("ARG{}", index), order::NONE, "None")?);
+        }
+        Ok(format!("{}({})", function, arguments.join(", ")))
+    }
+
+    // Definitions are collected and written out ahead of the code by `finish`
+    fn define_procedure(&self, block: &Block, ctx: &mut GenCtx) -> Result<(), CodegenError> {
+        let function = self.state.borrow_mut().names.procedure(block.field_str("NAME")?);
+        let parameters = block.mutation.as_ref().map(parameters).unwrap_or_default();
+        // Every variable that is not a parameter is the global one
+        let variables = self.state.borrow().variables.clone();
+        let globals: Vec<String> = variables.iter()
+            .filter(|name| !parameters.contains(&name.as_str()))
+            .map(|name| self.variable(name))
+            .collect();
+
+        let mut body = ctx.statement_to_code(block, "STACK")?;
+        let result = value(ctx, block, "RETURN", order::NONE, "")?;
+        if result.is_empty() && body.is_empty() {
+            body = format!("{}pass\n", ctx.indent());
+        }
+        let arguments: Vec<String> = parameters.iter().map(|name| self.variable(name)).collect();
+
+        let mut code = comment(block, "# ");
+        code.push_str(&format!("def {}({}):\n", function, arguments.join(", ")));
+        if !globals.is_empty() {
+            code.push_str(&format!("{}global {}\n", ctx.indent(), globals.join(", ")));
+        }
+        code.push_str(&body);
+        if !result.is_empty() {
+            code.push_str(&format!("{}return {}\n", ctx.indent(), result));
+        }
+        self.state.borrow_mut().definitions.insert(format!("%{}", function), code);
+        Ok(())
+    }
+
+    // Like Blockly's `provideFunction_`: define a helper once, under a name
+    // that collides with nothing else
+    fn provide_function(&self, desired: &str, template: &str) -> String {
+        let mut state = self.state.borrow_mut();
+        if let Some(name) = state.functions.get(desired) {
+            return name.clone();
+        }
+        let name = state.names.distinct(desired);
+        state.definitions.insert(desired.to_string(), template.replace("{name}", &name));
+        state.functions.insert(desired.to_string(), name.clone());
+        name
+    }
+}
+
+impl Generator for PythonGenerator {
+    fn generate_block(&self, block: &Block, ctx: &mut GenCtx) -> Result<String, CodegenError> {
+        if let Some(handler) = self.overrides.get(&block.block_type) {
+            return handler(block, ctx);
+        }
+        match self.statement(block, ctx)? {
+            Some(code) if code.is_empty() => Ok(code),
+            Some(code) => Ok(comment(block, "# ") + &code),
+            None => self.generate_expression(block, ctx).map(|(code, _)| code),
+        }
+    }
+
+    fn generate_expression(&self, block: &Block, ctx: &mut GenCtx) -> Result<(String, Order), CodegenError> {
+        self.expression(block, ctx)
+    }
+
+    fn order_overrides(&self) -> &[(Order, Order)] {
+        order::OVERRIDES
+    }
+
+    fn prepare(&self, program: &Program, ctx: &mut GenCtx) -> Result<(), CodegenError> {
+        let variables = used_variables(program);
+        let mut state = State {
+            names: Names::new(RESERVED),
+            variables: variables.clone(),
+            ..State::default()
+        };
+        let declarations: Vec<String> = variables.iter()
+            .map(|name| format!("{} = None", state.names.variable(name)))
+            .collect();
+        for name in procedure_names(program) {
+            state.names.procedure(&name);
+        }
+        if !declarations.is_empty() {
+            state.definitions.insert("variables".to_string(), declarations.join("\n"));
+        }
+        *self.state.borrow_mut() = state;
+        ctx.set_indent("  ");
+        Ok(())
+    }
+
+    // Imports come first, then the other definitions, then the code
+    fn finish(&self, _program: &Program, code: String) -> Result<String, CodegenError> {
+        let state = self.state.replace(State::default());
+        let (imports, definitions): (Vec<&str>, Vec<&str>) = state.definitions.values()
+            .map(String::as_str)
+            .partition(|definition| is_import(definition));
+        let mut prologue = String::new();
+        for line in format!("{}\n\n{}", imports.join("\n"), definitions.join("\n\n")).split_inclusive('\n') {
+            if !(line == "\n" && prologue.ends_with("\n\n")) {
+                prologue.push_str(line);
+            }
+        }
+        let prologue = prologue.trim_end_matches('\n');
+        Ok(tidy(&format!("{}\n\n\n{}", prologue, drop_empty_stacks(&code))))
+    }
+}
+
+fn body_or_pass(ctx: &mut GenCtx, block: &Block, name: &str) -> Result<String, CodegenError> {
+    let body = ctx.statement_to_code(block, name)?;
+    if body.is_empty() {
+        Ok(format!("{}pass\n", ctx.indent()))
+    } else {
+        Ok(body)
+    }
+}
+
+// An empty input produces `default`
+fn value(ctx: &mut GenCtx, block: &Block, name: &str, outer: Order, default: &str) -> Result<String, CodegenError> {
+    let code = ctx.value_to_code_with_order(block, name, outer)?;
+    if code.is_empty() {
+        Ok(default.to_string())
+    } else {
+        Ok(code)
+    }
+}
+
+fn logic_operands(a: String, b: String, default: &str, both_empty: &str) -> (String, String) {
+    if a.is_empty() && b.is_empty() {
+        return (both_empty.to_string(), both_empty.to_string());
+    }
+    let or_default = |code: String| if code.is_empty() { default.to_string() } else { code };
+    (or_default(a), or_default(b))
+}
+
+fn has_return_value(block: &Block) -> bool {
+    match block.mutation {
+        Some(ref mutation) => mutation.attribute("value") == Some("1"),
+        None => true,
+    }
+}
+
+fn is_import(definition: &str) -> bool {
+    let definition = match definition.strip_prefix("from ") {
+        Some(rest) => match rest.trim_start().split_once(char::is_whitespace) {
+            Some((_, rest)) => rest.trim_start(),
+            None => return false,
+        },
+        None => definition,
+    };
+    definition.strip_prefix("import").is_some_and(|rest| rest.starts_with(char::is_whitespace))
+}
+
+// Text blocks join values with `+`, so anything that is not already a
+// string literal is converted
+fn force_string(code: String) -> (String, Order) {
+    if is_string_literal(&code) {
+        (code, order::ATOMIC)
+    } else {
+        (format!("str({})", code), order::FUNCTION_CALL)
+    }
+}
+
+// Quoted like Python's `repr`, which prefers single quotes
+fn quote(text: &str) -> String {
+    let mut text = text.replace('\\', "\\\\").replace('\n', "\\\n");
+    let mut quote = '\'';
+    if text.contains('\'') {
+        if text.contains('"') {
+            text = text.replace('\'', "\\'");
+        } else {
+            quote = '"';
+        }
+    }
+    format!("{0}{1}{0}", quote, text)
+}
+
+fn unknown_option(block: &Block, field: &str, option: &str) -> CodegenError {
+    CodegenError::Custom(format!("block {:?} has unknown {} `{}`", block.id, field, option))
+}
+
+const UP_RANGE: &str = "\
+def {name}(start, stop, step):
+  while start <= stop:
+    yield start
+    start += abs(step)";
+
+const DOWN_RANGE: &str = "\
+def {name}(start, stop, step):
+  while start >= stop:
+    yield start
+    start -= abs(step)";
+
+const IS_PRIME: &str = "\
+def {name}(n):
+  # https://en.wikipedia.org/wiki/Primality_test#Naive_methods
+  # If n is not a number but a string, try parsing it.
+  if not isinstance(n, Number):
+    try:
+      n = float(n)
+    except:
+      return False
+  if n == 2 or n == 3:
+    return True
+  # False if n is negative, is 1, or not whole, or if n is divisible by 2 or 3.
+  if n <= 1 or n % 1 != 0 or n % 2 == 0 or n % 3 == 0:
+    return False
+  # Check all the numbers of form 6k +/- 1, up to sqrt(n).
+  for x in range(6, int(math.sqrt(n)) + 2, 6):
+    if n % (x - 1) == 0 or n % (x + 1) == 0:
+      return False
+  return True";
+
+const RESERVED: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "break", "class", "continue", "def", "del",
+    "elif", "else", "except", "exec", "finally", "for", "from", "global", "if", "import", "in",
+    "is", "lambda", "nonlocal", "not", "or", "pass", "print", "raise", "return", "try", "while",
+    "with", "yield", "NotImplemented", "Ellipsis", "__debug__", "quit", "exit", "copyright",
+    "license", "credits",
+    // Built-in functions, and the modules generated code imports
+    "abs", "all", "any", "bin", "bool", "bytearray", "bytes", "callable", "chr", "classmethod",
+    "compile", "complex", "delattr", "dict", "dir", "divmod", "enumerate", "eval", "filter", "float",
+    "format", "frozenset", "getattr", "globals", "hasattr", "hash", "help", "hex", "id", "input",
+    "int", "isinstance", "issubclass", "iter", "len", "list", "locals", "map", "max", "memoryview",
+    "min", "next", "object", "oct", "open", "ord", "pow", "property", "range", "repr", "reversed",
+    "round", "set", "setattr", "slice", "sorted", "staticmethod", "str", "sum", "super", "tuple",
+    "type", "vars", "zip", "__import__", "math", "random", "Number",
+];
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::generate;
+    use super::super::super::program_from_xml;
+
+    #[test]
+    fn test_python_generator() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="procedures_defnoreturn" id="def">
+                    <mutation><arg name="x"></arg></mutation>
+                    <field name="NAME">greet</field>
+                    <statement name="STACK">
+                        <block type="text_print" id="print">
+                            <value name="TEXT">
+                                <block type="text_join" id="join">
+                                    <mutation items="2"></mutation>
+                                    <value name="ADD0"><block type="variables_get" id="name"><field name="VAR">name</field></block></value>
+                                    <value name="ADD1"><block type="variables_get" id="x"><field name="VAR">x</field></block></value>
+                                </block>
+                            </value>
+                        </block>
+                    </statement>
+                </block>
+                <block type="variables_set" id="set">
+                    <field name="VAR">name</field>
+                    <value name="VALUE"><block type="text" id="hi"><field name="TEXT">it's</field></block></value>
+                    <next>
+                        <block type="controls_for" id="for">
+                            <field name="VAR">len</field>
+                            <value name="FROM"><block type="math_number" id="ten"><field name="NUM">10</field></block></value>
+                            <value name="TO"><block type="math_number" id="one"><field name="NUM">1</field></block></value>
+                            <value name="BY"><block type="math_number" id="two"><field name="NUM">2</field></block></value>
+                            <statement name="DO">
+                                <block type="controls_if" id="if">
+                                    <mutation elseif="1"></mutation>
+                                    <value name="IF0">
+                                        <block type="logic_operation" id="and">
+                                            <field name="OP">AND</field>
+                                            <value name="A">
+                                                <block type="logic_compare" id="cmp">
+                                                    <field name="OP">GT</field>
+                                                    <value name="A">
+                                                        <block type="math_single" id="root">
+                                                            <field name="OP">ROOT</field>
+                                                            <value name="NUM"><block type="variables_get" id="get"><field name="VAR">len</field></block></value>
+                                                        </block>
+                                                    </value>
+                                                    <value name="B"><block type="math_number" id="two2"><field name="NUM">2</field></block></value>
+                                                </block>
+                                            </value>
+                                        </block>
+                                    </value>
+                                    <statement name="DO0">
+                                        <block type="procedures_callnoreturn" id="call">
+                                            <mutation name="greet"><arg name="x"></arg></mutation>
+                                            <value name="ARG0"><block type="variables_get" id="arg"><field name="VAR">len</field></block></value>
+                                        </block>
+                                    </statement>
+                                </block>
+                            </statement>
+                        </block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let code = generate(&program, &PythonGenerator::new()).unwrap();
+        assert_eq!(code, "\
+import math
+
+x = None
+name = None
+len2 = None
+
+def greet(x):
+  global name, len2
+  print(str(name) + str(x))
+
+
+name = \"it's\"
+for len2 in range(10, 0, -2):
+  if math.sqrt(len2) > 2 and True:
+    greet(len2)
+  elif False:
+    pass
+");
+    }
+
+    #[test]
+    fn test_python_quote() {
+        assert_eq!(quote("plain"), "'plain'");
+        assert_eq!(quote("it's"), "\"it's\"");
+        assert_eq!(quote("it's \"x\""), "'it\\'s \"x\"'");
+        assert_eq!(quote("a\\b\nc"), "'a\\\\b\\\nc'");
+    }
+}
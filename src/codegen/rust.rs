@@ -0,0 +1,777 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+
+use super::{
+    BlockHandler,
+    GenCtx,
+    Generator,
+    Order,
+    prefix_lines,
+};
+use super::super::{
+    Block,
+    CodegenError,
+    FieldValue,
+    Program,
+};
+
+/// Generates a Rust program from Blockly's standard logic, loop, math, text,
+/// variable and procedure blocks. Register handlers with `on` for custom
+/// blocks, or to replace a built-in one.
+///
+/// Blockly variables become fields of a `Vars` struct, typed as `f64`,
+/// `String` or `bool` from their declared type or else from the first value
+/// assigned to them. Procedures become functions taking `&mut Vars`, and the
+/// top-level stacks become `main`.
+///
+/// ```
+/// use blockly_parser::codegen::{generate, RustGenerator};
+/// use blockly_parser::program_from_xml;
+///
+/// let generator = RustGenerator::new()
+///     .on("led_on", |block, _ctx| Ok(format!("led_on({});\n", block.field_str("TIME")?)));
+///
+/// let program = program_from_xml(
+///     r#"<xml><block type="led_on"><field name="TIME">300</field></block></xml>"#
+/// ).unwrap();
+/// assert!(generate(&program, &generator).unwrap().contains("    led_on(300);\n"));
+/// ```
+#[derive(Default)]
+pub struct RustGenerator {
+    overrides: HashMap<String, BlockHandler>,
+    state: RefCell<State>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Type {
+    Number,
+    Text,
+    Boolean,
+}
+
+#[derive(Default)]
+struct State {
+    // By Blockly name, in the order they were declared
+    variables: IndexMap<String, Type>,
+    // By lowercased name, as procedure names are case-insensitive
+    procedures: HashMap<String, Signature>,
+    // The parameters of the procedure being generated
+    parameters: Vec<(String, Type)>,
+    functions: Vec<String>,
+    uses_range: bool,
+}
+
+// Operator precedence in Rust: lower binds tighter
+mod order {
+    use super::Order;
+
+    pub const ATOMIC: Order = Order::ATOMIC;
+    /// Function and method calls, and the receiver of a method call.
+    pub const CALL: Order = Order(1.0);
+    pub const UNARY: Order = Order(2.0);
+    pub const CAST: Order = Order(3.0);
+    pub const MULTIPLICATIVE: Order = Order(4.0);
+    pub const ADDITIVE: Order = Order(5.0);
+    pub const COMPARISON: Order = Order(6.0);
+    pub const AND: Order = Order(7.0);
+    pub const OR: Order = Order(8.0);
+    /// An `if` used as a value.
+    pub const CONDITIONAL: Order = Order(9.0);
+    pub const NONE: Order = Order::NONE;
+
+    pub const OVERRIDES: &[(Order, Order)] = &[(CALL, CALL), (AND, AND), (OR, OR)];
+}
+
+struct Signature {
+    name: String,
+    parameters: Vec<(String, Type)>,
+    returns: Option<Type>,
+}
+
+impl RustGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generate blocks of `block_type` with `handler` instead of the
+    /// built-in code.
+    pub fn on<T, F>(mut self, block_type: T, handler: F) -> Self
+        where T: Into<String>,
+              F: Fn(&Block, &mut GenCtx) -> Result<String, CodegenError> + 'static
+    {
+        self.overrides.insert(block_type.into(), Box::new(handler));
+        self
+    }
+
+    // Statements are given `order::NONE`
+    fn builtin(&self, block: &Block, ctx: &mut GenCtx) -> Result<(String, Order), CodegenError> {
+        let code = match block.block_type.as_str() {
+            // Logic
+            "controls_if" => {
+                let branches = block.mutation.as_ref()
+                    .and_then(|mutation| mutation.attribute("elseif"))
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(0) + 1;
+                let mut code = String::new();
+                for branch in 0..branches {
+                    if branch > 0 {
+                        code.push_str("} else ");
+                    }
+                    let condition = value(ctx, block, &format!("IF{}", branch), order::NONE, "false")?;
+                    let body = ctx.statement_to_code(block, &format!("DO{}", branch))?;
+                    code.push_str(&format!("if {} {{\n{}", condition, body));
+                }
+                if block.statements.contains_key("ELSE") {
+                    code.push_str(&format!("}} else {{\n{}", ctx.statement_to_code(block, "ELSE")?));
+                }
+                code.push_str("}\n");
+                (code, order::NONE)
+            },
+            "logic_compare" => {
+                let operator = match block.field_str("OP")? {
+                    "EQ" => "==",
+                    "NEQ" => "!=",
+                    "LT" => "<",
+                    "LTE" => "<=",
+                    "GT" => ">",
+                    "GTE" => ">=",
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                let a = value(ctx, block, "A", order::COMPARISON, "0.0")?;
+                let b = value(ctx, block, "B", order::COMPARISON, "0.0")?;
+                (format!("{} {} {}", a, operator, b), order::COMPARISON)
+            },
+            "logic_operation" => {
+                let (operator, order) = match block.field_str("OP")? {
+                    "AND" => ("&&", order::AND),
+                    "OR" => ("||", order::OR),
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                let a = value(ctx, block, "A", order, "false")?;
+                let b = value(ctx, block, "B", order, "false")?;
+                (format!("{} {} {}", a, operator, b), order)
+            },
+            "logic_negate" => (format!("!{}", value(ctx, block, "BOOL", order::UNARY, "false")?), order::UNARY),
+            "logic_boolean" => match block.field_str("BOOL")? {
+                "TRUE" => ("true".to_string(), order::ATOMIC),
+                _ => ("false".to_string(), order::ATOMIC),
+            },
+            "logic_ternary" => {
+                let kind = self.expression_type(block.values.get("THEN").map(|input| input.block()));
+                let code = format!(
+                    "if {} {{ {} }} else {{ {} }}",
+                    value(ctx, block, "IF", order::NONE, "false")?,
+                    value(ctx, block, "THEN", order::NONE, kind.default_value())?,
+                    value(ctx, block, "ELSE", order::NONE, kind.default_value())?
+                );
+                (code, order::CONDITIONAL)
+            },
+
+            // Loops
+            "controls_repeat_ext" => {
+                let times = value(ctx, block, "TIMES", order::CAST, "0.0")?;
+                (format!("for _ in 0..{} as i64 {{\n{}}}\n", times, ctx.statement_to_code(block, "DO")?), order::NONE)
+            },
+            "controls_repeat" => {
+                let times = block.field_i64("TIMES")?;
+                (format!("for _ in 0..{} {{\n{}}}\n", times, ctx.statement_to_code(block, "DO")?), order::NONE)
+            },
+            "controls_whileUntil" => {
+                let condition = match block.field_str("MODE")? {
+                    "UNTIL" => format!("!{}", value(ctx, block, "BOOL", order::UNARY, "false")?),
+                    _ => value(ctx, block, "BOOL", order::NONE, "false")?,
+                };
+                (format!("while {} {{\n{}}}\n", condition, ctx.statement_to_code(block, "DO")?), order::NONE)
+            },
+            "controls_for" => {
+                self.state.borrow_mut().uses_range = true;
+                let code = format!(
+                    "for value in blockly_range({}, {}, {}) {{\n{}{}}}\n",
+                    value(ctx, block, "FROM", order::NONE, "0.0")?,
+                    value(ctx, block, "TO", order::NONE, "0.0")?,
+                    value(ctx, block, "BY", order::NONE, "1.0")?,
+                    prefix_lines(&format!("{} = value;\n", self.variable(block.field_str("VAR")?)), ctx.indent()),
+                    ctx.statement_to_code(block, "DO")?
+                );
+                (code, order::NONE)
+            },
+            "controls_flow_statements" => match block.field_str("FLOW")? {
+                "BREAK" => ("break;\n".to_string(), order::NONE),
+                "CONTINUE" => ("continue;\n".to_string(), order::NONE),
+                flow => return Err(unknown_option(block, "FLOW", flow)),
+            },
+
+            // Math
+            "math_number" => {
+                let number = block.field_f64("NUM")?;
+                (literal(number), if number < 0.0 { order::UNARY } else { order::ATOMIC })
+            },
+            "math_arithmetic" => {
+                let (operator, order) = match block.field_str("OP")? {
+                    "ADD" => (" + ", order::ADDITIVE),
+                    "MINUS" => (" - ", order::ADDITIVE),
+                    "MULTIPLY" => (" * ", order::MULTIPLICATIVE),
+                    "DIVIDE" => (" / ", order::MULTIPLICATIVE),
+                    "POWER" => ("", order::NONE),
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                let a = value(ctx, block, "A", order, "0.0")?;
+                let b = value(ctx, block, "B", order, "0.0")?;
+                if operator.is_empty() {
+                    (format!("f64::powf({}, {})", a, b), order::CALL)
+                } else {
+                    (format!("{}{}{}", a, operator, b), order)
+                }
+            },
+            "math_single" => {
+                let operator = block.field_str("OP")?;
+                if operator == "NEG" {
+                    return Ok((format!("-{}", value(ctx, block, "NUM", order::UNARY, "0.0")?), order::UNARY));
+                }
+                let number = value(ctx, block, "NUM", order::NONE, "0.0")?;
+                let code = match operator {
+                    "ROOT" => format!("f64::sqrt({})", number),
+                    "ABS" => format!("f64::abs({})", number),
+                    "LN" => format!("f64::ln({})", number),
+                    "LOG10" => format!("f64::log10({})", number),
+                    "EXP" => format!("f64::exp({})", number),
+                    "POW10" => format!("f64::powf(10.0, {})", number),
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                (code, order::CALL)
+            },
+            "math_trig" => {
+                let number = value(ctx, block, "NUM", order::NONE, "0.0")?;
+                let code = match block.field_str("OP")? {
+                    "SIN" => format!("f64::sin(f64::to_radians({}))", number),
+                    "COS" => format!("f64::cos(f64::to_radians({}))", number),
+                    "TAN" => format!("f64::tan(f64::to_radians({}))", number),
+                    "ASIN" => format!("f64::to_degrees(f64::asin({}))", number),
+                    "ACOS" => format!("f64::to_degrees(f64::acos({}))", number),
+                    "ATAN" => format!("f64::to_degrees(f64::atan({}))", number),
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                (code, order::CALL)
+            },
+            "math_constant" => match block.field_str("CONSTANT")? {
+                "PI" => ("std::f64::consts::PI".to_string(), order::ATOMIC),
+                "E" => ("std::f64::consts::E".to_string(), order::ATOMIC),
+                "GOLDEN_RATIO" => ("(1.0 + f64::sqrt(5.0)) / 2.0".to_string(), order::MULTIPLICATIVE),
+                "SQRT2" => ("std::f64::consts::SQRT_2".to_string(), order::ATOMIC),
+                "SQRT1_2" => ("std::f64::consts::FRAC_1_SQRT_2".to_string(), order::ATOMIC),
+                "INFINITY" => ("f64::INFINITY".to_string(), order::ATOMIC),
+                constant => return Err(unknown_option(block, "CONSTANT", constant)),
+            },
+            "math_number_property" => {
+                let property = block.field_str("PROPERTY")?;
+                let inner = match property {
+                    "POSITIVE" | "NEGATIVE" => order::COMPARISON,
+                    _ => order::MULTIPLICATIVE,
+                };
+                let number = value(ctx, block, "NUMBER_TO_CHECK", inner, "0.0")?;
+                let code = match property {
+                    "EVEN" => format!("{} % 2.0 == 0.0", number),
+                    "ODD" => format!("f64::abs({} % 2.0) == 1.0", number),
+                    "WHOLE" => format!("{} % 1.0 == 0.0", number),
+                    "POSITIVE" => format!("{} > 0.0", number),
+                    "NEGATIVE" => format!("{} < 0.0", number),
+                    "DIVISIBLE_BY" => {
+                        let divisor = value(ctx, block, "DIVISOR", order::MULTIPLICATIVE, "0.0")?;
+                        format!("{} % {} == 0.0", number, divisor)
+                    },
+                    property => return Err(unknown_option(block, "PROPERTY", property)),
+                };
+                (code, order::COMPARISON)
+            },
+            "math_round" => {
+                let function = match block.field_str("OP")? {
+                    "ROUND" => "round",
+                    "ROUNDUP" => "ceil",
+                    "ROUNDDOWN" => "floor",
+                    op => return Err(unknown_option(block, "OP", op)),
+                };
+                (format!("f64::{}({})", function, value(ctx, block, "NUM", order::NONE, "0.0")?), order::CALL)
+            },
+            "math_modulo" => {
+                let dividend = value(ctx, block, "DIVIDEND", order::MULTIPLICATIVE, "0.0")?;
+                let divisor = value(ctx, block, "DIVISOR", order::MULTIPLICATIVE, "0.0")?;
+                (format!("{} % {}", dividend, divisor), order::MULTIPLICATIVE)
+            },
+            "math_constrain" => {
+                let code = format!(
+                    "f64::min(f64::max({}, {}), {})",
+                    value(ctx, block, "VALUE", order::NONE, "0.0")?,
+                    value(ctx, block, "LOW", order::NONE, "0.0")?,
+                    value(ctx, block, "HIGH", order::NONE, "0.0")?
+                );
+                (code, order::CALL)
+            },
+            "math_change" => {
+                let delta = value(ctx, block, "DELTA", order::NONE, "0.0")?;
+                (format!("{} += {};\n", self.variable(block.field_str("VAR")?), delta), order::NONE)
+            },
+
+            // Text
+            "text" => (format!("String::from({:?})", block.field_str("TEXT").unwrap_or("")), order::CALL),
+            "text_join" => {
+                let items = block.mutation.as_ref()
+                    .and_then(|mutation| mutation.attribute("items"))
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(2);
+                let mut values = Vec::new();
+                for item in 0..items {
+                    values.push(value(ctx, block, &format!("ADD{}", item), order::NONE, "\"\"")?);
+                }
+                (format!("format!(\"{}\", {})", "{}".repeat(items), values.join(", ")), order::CALL)
+            },
+            "text_append" => {
+                let text = value(ctx, block, "TEXT", order::CALL, "\"\"")?;
+                (format!("{}.push_str(&{}.to_string());\n", self.variable(block.field_str("VAR")?), text), order::NONE)
+            },
+            "text_length" => (format!("{}.chars().count() as f64", value(ctx, block, "VALUE", order::CALL, "\"\"")?), order::CAST),
+            "text_isEmpty" => (format!("{}.is_empty()", value(ctx, block, "VALUE", order::CALL, "\"\"")?), order::CALL),
+            "text_print" => (format!("println!(\"{{}}\", {});\n", value(ctx, block, "TEXT", order::NONE, "\"\"")?), order::NONE),
+
+            // Variables
+            "variables_get" => {
+                let name = block.field_str("VAR")?;
+                let variable = self.variable(name);
+                if self.variable_type(name) == Type::Text {
+                    (format!("{}.clone()", variable), order::CALL)
+                } else {
+                    (variable, order::ATOMIC)
+                }
+            },
+            "variables_set" => {
+                let name = block.field_str("VAR")?;
+                let kind = self.variable_type(name);
+                let assigned = value(ctx, block, "VALUE", order::NONE, kind.default_value())?;
+                (format!("{} = {};\n", self.variable(name), assigned), order::NONE)
+            },
+
+            // Procedures
+            "procedures_defnoreturn" | "procedures_defreturn" => {
+                self.define_procedure(block, ctx)?;
+                (String::new(), order::NONE)
+            },
+            "procedures_callnoreturn" | "procedures_callreturn" => {
+                let name = block.mutation.as_ref()
+                    .and_then(|mutation| mutation.attribute("name"))
+                    .ok_or_else(|| CodegenError::Custom(format!("call block {:?} names no procedure", block.id)))?;
+                let (function, parameters) = match self.state.borrow().procedures.get(&name.to_lowercase()) {
+                    Some(signature) => (signature.name.clone(), signature.parameters.clone()),
+                    None => return Err(CodegenError::Custom(format!("procedure `{}` is not defined", name))),
+                };
+                let mut arguments = vec!["vars".to_string()];
+                for (index, &(_, kind)) in parameters.iter().enumerate() {
+                    arguments.push(value(ctx, block, &format!("ARG{}", index), order::NONE, kind.default_value())?);
+                }
+                let call = format!("{}({})", function, arguments.join(", "));
+                if block.block_type == "procedures_callnoreturn" {
+                    (format!("{};\n", call), order::NONE)
+                } else {
+                    (call, order::CALL)
+                }
+            },
+            "procedures_ifreturn" => {
+                let returns = block.mutation.as_ref()
+                    .and_then(|mutation| mutation.attribute("value")) != Some("0");
+                let statement = if returns {
+                    let kind = self.expression_type(block.values.get("VALUE").map(|input| input.block()));
+                    format!("return {};\n", value(ctx, block, "VALUE", order::NONE, kind.default_value())?)
+                } else {
+                    "return;\n".to_string()
+                };
+                let code = format!(
+                    "if {} {{\n{}}}\n",
+                    value(ctx, block, "CONDITION", order::NONE, "false")?,
+                    prefix_lines(&statement, ctx.indent())
+                );
+                (code, order::NONE)
+            },
+
+            _ => {
+                return Err(CodegenError::UnsupportedBlock {
+                    block_type: block.block_type.clone(),
+                    id: block.id.clone(),
+                });
+            }
+        };
+        Ok(code)
+    }
+
+    // Procedure definitions are collected and written out by `finish`
+    fn define_procedure(&self, block: &Block, ctx: &mut GenCtx) -> Result<(), CodegenError> {
+        let name = block.field_str("NAME")?;
+        let (function, parameters, returns) = match self.state.borrow().procedures.get(&name.to_lowercase()) {
+            Some(signature) => (signature.name.clone(), signature.parameters.clone(), signature.returns),
+            None => return Ok(()),
+        };
+
+        let outer = self.state.borrow_mut().parameters.clone();
+        self.state.borrow_mut().parameters = parameters.clone();
+        let body = ctx.statement_to_code(block, "STACK");
+        let result = match returns {
+            Some(kind) => value(ctx, block, "RETURN", order::NONE, kind.default_value()).map(Some),
+            None => Ok(None),
+        };
+        self.state.borrow_mut().parameters = outer;
+        let (body, result) = (body?, result?);
+
+        let mut arguments = vec!["vars: &mut Vars".to_string()];
+        for &(ref name, kind) in parameters.iter() {
+            arguments.push(format!("mut {}: {}", identifier(name), kind.name()));
+        }
+        let mut code = format!("fn {}({})", function, arguments.join(", "));
+        if let Some(kind) = returns {
+            code.push_str(&format!(" -> {}", kind.name()));
+        }
+        code.push_str(" {\n");
+        code.push_str(&body);
+        if let Some(result) = result {
+            code.push_str(&prefix_lines(&format!("{}\n", result), ctx.indent()));
+        }
+        code.push_str("}\n");
+        self.state.borrow_mut().functions.push(code);
+        Ok(())
+    }
+
+    // Parameters of the procedure being generated are locals; everything
+    // else lives in `Vars`
+    fn variable(&self, name: &str) -> String {
+        if self.state.borrow().parameters.iter().any(|(parameter, _)| parameter == name) {
+            identifier(name)
+        } else {
+            format!("vars.{}", identifier(name))
+        }
+    }
+
+    fn variable_type(&self, name: &str) -> Type {
+        let state = self.state.borrow();
+        state.parameters.iter()
+            .find(|(parameter, _)| parameter == name)
+            .map(|&(_, kind)| kind)
+            .or_else(|| state.variables.get(name).cloned())
+            .unwrap_or(Type::Number)
+    }
+
+    // The type of the value a block produces, by block type
+    fn expression_type(&self, block: Option<&Block>) -> Type {
+        let block = match block {
+            Some(block) => block,
+            None => return Type::Number,
+        };
+        match block.block_type.as_str() {
+            "logic_boolean" | "logic_compare" | "logic_operation" | "logic_negate"
+                | "math_number_property" | "text_isEmpty" => Type::Boolean,
+            "text" | "text_join" => Type::Text,
+            "logic_ternary" => self.expression_type(block.values.get("THEN").map(|input| input.block())),
+            "variables_get" => match block.fields.get("VAR") {
+                Some(FieldValue::SimpleField(name)) => self.variable_type(name),
+                _ => Type::Number,
+            },
+            "procedures_callreturn" => block.mutation.as_ref()
+                .and_then(|mutation| mutation.attribute("name"))
+                .and_then(|name| self.state.borrow().procedures.get(&name.to_lowercase()).and_then(|signature| signature.returns))
+                .unwrap_or(Type::Number),
+            _ => Type::Number,
+        }
+    }
+
+    fn infer_variables(&self, program: &Program) {
+        let mut declared: IndexMap<String, Option<Type>> = program.variables.iter()
+            .map(|variable| (variable.name.clone(), variable.var_type.as_deref().and_then(Type::from_blockly)))
+            .collect();
+        for block in program.iter_blocks() {
+            let name = match block.fields.get("VAR") {
+                Some(FieldValue::SimpleField(name)) => name,
+                _ => continue,
+            };
+            let assigned = match block.block_type.as_str() {
+                "variables_set" => Some(self.expression_type(block.values.get("VALUE").map(|input| input.block()))),
+                "text_append" => Some(Type::Text),
+                "math_change" | "controls_for" => Some(Type::Number),
+                "variables_get" => None,
+                _ => continue,
+            };
+            let entry = declared.entry(name.clone()).or_insert(None);
+            if entry.is_none() {
+                *entry = assigned;
+            }
+        }
+        self.state.borrow_mut().variables = declared.into_iter()
+            .map(|(name, kind)| (name, kind.unwrap_or(Type::Number)))
+            .collect();
+    }
+
+    fn infer_procedures(&self, program: &Program) {
+        let procedures = program.procedures();
+        for procedure in procedures.definitions() {
+            let call = procedures.calls().iter().find(|call| {
+                procedures.resolve(call).is_some_and(|called| called.name == procedure.name)
+            });
+            let parameters = procedure.parameters.iter()
+                .enumerate()
+                .map(|(index, name)| {
+                    let argument = call.and_then(|call| call.values.get(&format!("ARG{}", index)));
+                    (name.to_string(), self.expression_type(argument.map(|input| input.block())))
+                })
+                .collect();
+            let returns = if procedure.returns {
+                Some(self.expression_type(procedure.definition.values.get("RETURN").map(|input| input.block())))
+            } else {
+                None
+            };
+            self.state.borrow_mut().procedures.insert(procedure.name.to_lowercase(), Signature {
+                name: identifier(procedure.name),
+                parameters,
+                returns,
+            });
+        }
+    }
+}
+
+impl Generator for RustGenerator {
+    fn generate_block(&self, block: &Block, ctx: &mut GenCtx) -> Result<String, CodegenError> {
+        match self.overrides.get(&block.block_type) {
+            Some(handler) => handler(block, ctx),
+            None => self.builtin(block, ctx).map(|(code, _)| code),
+        }
+    }
+
+    // Handlers' values may be anything, so they are treated as binding loosest
+    fn generate_expression(&self, block: &Block, ctx: &mut GenCtx) -> Result<(String, Order), CodegenError> {
+        match self.overrides.get(&block.block_type) {
+            Some(handler) => Ok((handler(block, ctx)?, order::NONE)),
+            None => self.builtin(block, ctx),
+        }
+    }
+
+    fn order_overrides(&self) -> &[(Order, Order)] {
+        order::OVERRIDES
+    }
+
+    fn prepare(&self, program: &Program, ctx: &mut GenCtx) -> Result<(), CodegenError> {
+        *self.state.borrow_mut() = State::default();
+        ctx.set_indent("    ");
+        self.infer_variables(program);
+        self.infer_procedures(program);
+        Ok(())
+    }
+
+    fn finish(&self, _program: &Program, code: String) -> Result<String, CodegenError> {
+        let state = self.state.replace(State::default());
+        let mut out = String::from("#[derive(Default)]\nstruct Vars {\n");
+        for (name, kind) in state.variables.iter() {
+            out.push_str(&format!("    {}: {},\n", identifier(name), kind.name()));
+        }
+        out.push_str("}\n");
+        if state.uses_range {
+            out.push('\n');
+            out.push_str(RANGE);
+        }
+        for function in state.functions.iter() {
+            out.push('\n');
+            out.push_str(function);
+        }
+        out.push_str("\nfn main() {\n    let mut vars = Vars::default();\n    let vars = &mut vars;\n");
+        for line in code.lines().filter(|line| !line.trim().is_empty()) {
+            out.push_str("    ");
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push_str("}\n");
+        Ok(out)
+    }
+}
+
+// Counts like Blockly's `controls_for`: down when `from` is above `to`, by
+// the size of `step`
+const RANGE: &str = "\
+fn blockly_range(from: f64, to: f64, step: f64) -> impl Iterator<Item = f64> {
+    let step = step.abs();
+    let up = from <= to;
+    std::iter::successors(Some(from), move |&i| Some(if up { i + step } else { i - step }))
+        .take_while(move |&i| if up { i <= to } else { i >= to })
+}
+";
+
+impl Type {
+    fn from_blockly(var_type: &str) -> Option<Self> {
+        match var_type {
+            "Number" => Some(Type::Number),
+            "String" => Some(Type::Text),
+            "Boolean" => Some(Type::Boolean),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Type::Number => "f64",
+            Type::Text => "String",
+            Type::Boolean => "bool",
+        }
+    }
+
+    fn default_value(self) -> &'static str {
+        match self {
+            Type::Number => "0.0",
+            Type::Text => "String::new()",
+            Type::Boolean => "false",
+        }
+    }
+}
+
+// An empty input produces `default`
+fn value(ctx: &mut GenCtx, block: &Block, name: &str, outer: Order, default: &str) -> Result<String, CodegenError> {
+    let code = ctx.value_to_code_with_order(block, name, outer)?;
+    if code.is_empty() {
+        Ok(default.to_string())
+    } else {
+        Ok(code)
+    }
+}
+
+fn literal(number: f64) -> String {
+    if number.is_nan() {
+        "f64::NAN".to_string()
+    } else if number.is_infinite() {
+        if number > 0.0 { "f64::INFINITY" } else { "f64::NEG_INFINITY" }.to_string()
+    } else {
+        format!("{:?}", number)
+    }
+}
+
+fn unknown_option(block: &Block, field: &str, option: &str) -> CodegenError {
+    CodegenError::Custom(format!("block {:?} has unknown {} `{}`", block.id, field, option))
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn", "for", "if",
+    "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "static",
+    "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while", "async", "await", "dyn",
+    "main", "value", "vars",
+];
+
+// Blockly names may hold spaces and punctuation
+fn identifier(name: &str) -> String {
+    let mut identifier: String = name.chars()
+        .map(|ch| if ch.is_alphanumeric() || ch == '_' { ch } else { '_' })
+        .collect();
+    if identifier.is_empty() || identifier.starts_with(|ch: char| ch.is_numeric()) {
+        identifier.insert(0, '_');
+    }
+    if KEYWORDS.contains(&identifier.as_str()) {
+        identifier.push('_');
+    }
+    identifier
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::generate;
+    use super::super::super::program_from_xml;
+
+    #[test]
+    fn test_rust_generator() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables>
+                    <variable id="c">count</variable>
+                    <variable id="m">my message</variable>
+                </variables>
+                <block type="procedures_defreturn" id="def">
+                    <mutation><arg name="x"></arg></mutation>
+                    <field name="NAME">double</field>
+                    <value name="RETURN">
+                        <block type="math_arithmetic" id="mul">
+                            <field name="OP">MULTIPLY</field>
+                            <value name="A"><block type="variables_get" id="x"><field name="VAR">x</field></block></value>
+                            <value name="B"><block type="math_number" id="two"><field name="NUM">2</field></block></value>
+                        </block>
+                    </value>
+                </block>
+                <block type="variables_set" id="set">
+                    <field name="VAR" id="m">my message</field>
+                    <value name="VALUE"><block type="text" id="hi"><field name="TEXT">Hi "you"</field></block></value>
+                    <next>
+                        <block type="controls_for" id="for">
+                            <field name="VAR" id="c">count</field>
+                            <value name="FROM"><block type="math_number" id="one"><field name="NUM">1</field></block></value>
+                            <value name="TO"><block type="math_number" id="three"><field name="NUM">3</field></block></value>
+                            <statement name="DO">
+                                <block type="controls_if" id="if">
+                                    <mutation else="1"></mutation>
+                                    <value name="IF0">
+                                        <block type="logic_compare" id="cmp">
+                                            <field name="OP">GT</field>
+                                            <value name="A">
+                                                <block type="procedures_callreturn" id="call">
+                                                    <mutation name="double"><arg name="x"></arg></mutation>
+                                                    <value name="ARG0"><block type="variables_get" id="get"><field name="VAR">count</field></block></value>
+                                                </block>
+                                            </value>
+                                            <value name="B"><block type="math_number" id="four"><field name="NUM">4</field></block></value>
+                                        </block>
+                                    </value>
+                                    <statement name="DO0">
+                                        <block type="text_print" id="print">
+                                            <value name="TEXT"><block type="variables_get" id="msg"><field name="VAR">my message</field></block></value>
+                                        </block>
+                                    </statement>
+                                    <statement name="ELSE">
+                                        <block type="controls_flow_statements" id="continue"><field name="FLOW">CONTINUE</field></block>
+                                    </statement>
+                                </block>
+                            </statement>
+                        </block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let code = generate(&program, &RustGenerator::new()).unwrap();
+        assert_eq!(code, format!("\
+#[derive(Default)]
+struct Vars {{
+    count: f64,
+    my_message: String,
+    x: f64,
+}}
+
+{}
+fn double(vars: &mut Vars, mut x: f64) -> f64 {{
+    x * 2.0
+}}
+
+fn main() {{
+    let mut vars = Vars::default();
+    let vars = &mut vars;
+    vars.my_message = String::from(\"Hi \\\"you\\\"\");
+    for value in blockly_range(1.0, 3.0, 1.0) {{
+        vars.count = value;
+        if double(vars, vars.count) > 4.0 {{
+            println!(\"{{}}\", vars.my_message.clone());
+        }} else {{
+            continue;
+        }}
+    }}
+}}
+", RANGE));
+    }
+
+    #[test]
+    fn test_rust_generator_overrides() {
+        let program = program_from_xml(r#"<xml><block type="led_on" id="a"><next><block type="buzz" id="b"></block></next></block></xml>"#).unwrap();
+        let generator = RustGenerator::new().on("led_on", |_block, _ctx| Ok("led_on();\n".to_string()));
+        assert_eq!(generate(&program, &generator), Err(CodegenError::UnsupportedBlock {
+            block_type: "buzz".to_string(),
+            id: "b".to_string(),
+        }));
+    }
+}
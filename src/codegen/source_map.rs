@@ -0,0 +1,160 @@
+// Block code is wrapped in these while generating, so the lines it ends up
+// on can be found after the generator has added prologues or moved
+// definitions around. They are private-use characters, which no generator
+// should produce itself.
+const START: char = '\u{E000}';
+const ID_END: char = '\u{E001}';
+const END: char = '\u{E002}';
+
+/// The lines of generated code each statement block produced, so an error
+/// at some line can be traced back to the block in the editor.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct CodeMap {
+    ranges: Vec<CodeRange>,
+}
+
+/// Lines `start_line..=end_line` of the generated code came from the block
+/// with id `block_id`. Lines are numbered from 1.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct CodeRange {
+    pub block_id: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl CodeMap {
+    /// Every range, ordered by where it starts. A block's range covers the
+    /// ranges of the statements nested in it.
+    pub fn ranges(&self) -> &[CodeRange] {
+        &self.ranges
+    }
+
+    /// The id of the innermost block that produced `line`.
+    pub fn block_at(&self, line: usize) -> Option<&str> {
+        self.ranges.iter()
+            .filter(|range| range.start_line <= line && line <= range.end_line)
+            .min_by_key(|range| range.end_line - range.start_line)
+            .map(|range| range.block_id.as_str())
+    }
+
+    /// The first range produced by the block with id `block_id`.
+    pub fn range(&self, block_id: &str) -> Option<&CodeRange> {
+        self.ranges.iter().find(|range| range.block_id == block_id)
+    }
+}
+
+/// Wrap the code of block number `index`, keeping its final newline last.
+pub(crate) fn mark(code: String, index: usize) -> String {
+    let (body, newline) = match code.strip_suffix('\n') {
+        Some(body) => (body, "\n"),
+        None => (code.as_str(), ""),
+    };
+    format!("{}{}{}{}{}{}", START, index, ID_END, body, END, newline)
+}
+
+/// Remove the marks from finished code, and turn them into ranges of the
+/// blocks in `block_ids`.
+pub(crate) fn unmark(code: &str, block_ids: &[String]) -> (String, CodeMap) {
+    let mut unmarked = String::with_capacity(code.len());
+    let mut ranges = Vec::new();
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut line = 1;
+    let mut chars = code.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            START => {
+                let index: String = chars.by_ref().take_while(|&ch| ch != ID_END).collect();
+                if let Ok(index) = index.parse() {
+                    open.push((index, line));
+                }
+            },
+            END => {
+                if let Some((index, start_line)) = open.pop() {
+                    ranges.push((index, start_line, line));
+                }
+            },
+            _ => {
+                if ch == '\n' {
+                    line += 1;
+                }
+                unmarked.push(ch);
+            },
+        }
+    }
+    // A generator that dropped an end mark leaves its block running to the end
+    let last_line = line - usize::from(unmarked.ends_with('\n'));
+    ranges.extend(open.into_iter().map(|(index, start_line)| (index, start_line, last_line)));
+
+    ranges.sort_by_key(|&(_, start_line, end_line)| (start_line, ::std::cmp::Reverse(end_line)));
+    let ranges = ranges.into_iter()
+        .filter_map(|(index, start_line, end_line)| block_ids.get(index).map(|block_id| CodeRange {
+            block_id: block_id.clone(),
+            start_line,
+            end_line,
+        }))
+        .collect();
+    (unmarked, CodeMap { ranges })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        generate_with_source_map,
+        DispatchGenerator,
+        JavaScriptGenerator,
+    };
+    use super::super::super::{
+        program_from_xml,
+        TraversalOptions,
+    };
+
+    #[test]
+    fn test_source_map() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="on"><next><block type="led_off" id="off"></block></next></block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let generator = DispatchGenerator::new()
+            .on("main_loop", |block, ctx| Ok(format!("loop {{\n{}}}\n", ctx.statement_to_code(block, "BODY")?)))
+            .on("led_on", |_block, _ctx| Ok("led_on();\nsleep();\n".to_string()))
+            .on("led_off", |_block, _ctx| Ok("led_off();\n".to_string()));
+
+        let (code, map) = generate_with_source_map(&program, &generator, &TraversalOptions::default()).unwrap();
+        assert_eq!(code, "loop {\n  led_on();\n  sleep();\n  led_off();\n}\n");
+        assert_eq!(map.ranges(), &[
+            CodeRange { block_id: "main".to_string(), start_line: 1, end_line: 5 },
+            CodeRange { block_id: "on".to_string(), start_line: 2, end_line: 3 },
+            CodeRange { block_id: "off".to_string(), start_line: 4, end_line: 4 },
+        ]);
+        assert_eq!(map.block_at(3), Some("on"));
+        assert_eq!(map.block_at(5), Some("main"));
+        assert_eq!(map.block_at(6), None);
+        assert_eq!(map.range("off").map(|range| range.start_line), Some(4));
+    }
+
+    #[test]
+    fn test_source_map_through_definitions() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="text_print" id="top"></block>
+                <block type="procedures_defnoreturn" id="def">
+                    <field name="NAME">greet</field>
+                    <statement name="STACK"><block type="text_print" id="inner"></block></statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let (code, map) = generate_with_source_map(&program, &JavaScriptGenerator::new(), &TraversalOptions::default()).unwrap();
+        assert_eq!(code, "function greet() {\n  window.alert('');\n}\n\n\nwindow.alert('');\n");
+        assert_eq!(map.block_at(2), Some("inner"));
+        assert_eq!(map.block_at(6), Some("top"));
+    }
+}
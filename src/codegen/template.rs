@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use super::{GenCtx, Generator};
+use super::super::{
+    Block,
+    CodegenError,
+};
+
+/// A generator driven by one template per block type, for simple targets
+/// that need no Rust code of their own.
+///
+/// In a template, `{field:NAME}` is replaced by the value of a field,
+/// `{value:NAME}` by the code for a value input and `{statement:NAME}` by the
+/// indented code for a statement input. `{{` and `}}` stand for literal
+/// braces. As with any generator, statement templates end with a newline.
+///
+/// ```
+/// use blockly_parser::codegen::{generate, TemplateGenerator};
+/// use blockly_parser::program_from_xml;
+///
+/// let generator = TemplateGenerator::from_json(r#"{
+///     "repeat": "repeat {field:TIMES} {{\n{statement:DO}}}\n",
+///     "led_on": "led_on({value:TIME});\n",
+///     "number": "{field:NUM}"
+/// }"#).unwrap();
+///
+/// let program = program_from_xml(r#"
+///     <xml>
+///         <block type="repeat">
+///             <field name="TIMES">3</field>
+///             <statement name="DO">
+///                 <block type="led_on">
+///                     <value name="TIME"><block type="number"><field name="NUM">300</field></block></value>
+///                 </block>
+///             </statement>
+///         </block>
+///     </xml>
+/// "#).unwrap();
+/// assert_eq!(generate(&program, &generator).unwrap(), "repeat 3 {\n  led_on(300);\n}\n");
+/// ```
+#[derive(Default, Debug)]
+pub struct TemplateGenerator {
+    templates: HashMap<String, Vec<Segment>>,
+}
+
+#[derive(PartialEq, Debug)]
+enum Segment {
+    Text(String),
+    Field(String),
+    Value(String),
+    Statement(String),
+}
+
+impl TemplateGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A generator with the templates of a JSON object, keyed by block type.
+    pub fn from_json(json: &str) -> Result<Self, CodegenError> {
+        let templates: HashMap<String, String> = serde_json::from_str(json)
+            .map_err(|error| CodegenError::Custom(format!("invalid templates: {}", error)))?;
+        Self::from_templates(templates)
+    }
+
+    /// A generator with the templates of a TOML table, keyed by block type.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, CodegenError> {
+        let templates: HashMap<String, String> = toml::from_str(text)
+            .map_err(|error| CodegenError::Custom(format!("invalid templates: {}", error)))?;
+        Self::from_templates(templates)
+    }
+
+    pub fn from_templates<I, T, U>(templates: I) -> Result<Self, CodegenError>
+        where I: IntoIterator<Item = (T, U)>,
+              T: Into<String>,
+              U: AsRef<str>
+    {
+        let mut generator = Self::new();
+        for (block_type, template) in templates {
+            generator.insert(block_type, template.as_ref())?;
+        }
+        Ok(generator)
+    }
+
+    /// Use `template` for blocks of type `block_type`, replacing any earlier
+    /// one. Fails if the template has a malformed placeholder.
+    pub fn insert<T: Into<String>>(&mut self, block_type: T, template: &str) -> Result<(), CodegenError> {
+        let block_type = block_type.into();
+        let segments = parse(template)
+            .map_err(|message| CodegenError::Custom(format!("template for `{}`: {}", block_type, message)))?;
+        self.templates.insert(block_type, segments);
+        Ok(())
+    }
+}
+
+impl Generator for TemplateGenerator {
+    fn generate_block(&self, block: &Block, ctx: &mut GenCtx) -> Result<String, CodegenError> {
+        let segments = self.templates.get(&block.block_type)
+            .ok_or_else(|| CodegenError::UnsupportedBlock {
+                block_type: block.block_type.clone(),
+                id: block.id.clone(),
+            })?;
+        let mut code = String::new();
+        for segment in segments {
+            match *segment {
+                Segment::Text(ref text) => code.push_str(text),
+                Segment::Field(ref name) => code.push_str(block.field_str(name)?),
+                Segment::Value(ref name) => code.push_str(&ctx.value_to_code(block, name)?),
+                Segment::Statement(ref name) => code.push_str(&ctx.statement_to_code(block, name)?),
+            }
+        }
+        Ok(code)
+    }
+}
+
+fn parse(template: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(index) = rest.find(['{', '}']) {
+        text.push_str(&rest[..index]);
+        let brace = &rest[index..index + 1];
+        rest = &rest[index + 1..];
+        if rest.starts_with(brace) {
+            text.push_str(brace);
+            rest = &rest[1..];
+            continue;
+        }
+        if brace == "}" {
+            return Err("unmatched `}`, write `}}` for a literal brace".to_string());
+        }
+        let end = rest.find('}').ok_or_else(|| "unclosed `{`".to_string())?;
+        let placeholder = &rest[..end];
+        rest = &rest[end + 1..];
+        let segment = match placeholder.split_once(':') {
+            Some(("field", name)) => Segment::Field(name.to_string()),
+            Some(("value", name)) => Segment::Value(name.to_string()),
+            Some(("statement", name)) => Segment::Statement(name.to_string()),
+            _ => return Err(format!("unknown placeholder `{{{}}}`", placeholder)),
+        };
+        if !text.is_empty() {
+            segments.push(Segment::Text(::std::mem::take(&mut text)));
+        }
+        segments.push(segment);
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_template() {
+        assert_eq!(parse("if {value:IF} {{\n{statement:DO}}}\n"), Ok(vec![
+            Segment::Text("if ".to_string()),
+            Segment::Value("IF".to_string()),
+            Segment::Text(" {\n".to_string()),
+            Segment::Statement("DO".to_string()),
+            Segment::Text("}\n".to_string()),
+        ]));
+        assert_eq!(parse("{field:TIME}"), Ok(vec![Segment::Field("TIME".to_string())]));
+        assert!(parse("{input:A}").is_err());
+        assert!(parse("wait {field:TIME").is_err());
+        assert!(parse("}").is_err());
+
+        let error = TemplateGenerator::from_json(r#"{"led_on": "led_on({TIME});\n"}"#).unwrap_err();
+        assert_eq!(error, CodegenError::Custom("template for `led_on`: unknown placeholder `{TIME}`".to_string()));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_from_toml() {
+        use super::super::generate;
+        use super::super::super::program_from_xml;
+
+        let generator = TemplateGenerator::from_toml(r#"
+            led_on = "led_on({field:TIME});\n"
+            led_off = "led_off({field:TIME});\n"
+        "#).unwrap();
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="led_on">
+                    <field name="TIME">300</field>
+                    <next><block type="led_off"><field name="TIME">100</field></block></next>
+                </block>
+            </xml>
+        "#).unwrap();
+        assert_eq!(generate(&program, &generator).unwrap(), "led_on(300);\nled_off(100);\n");
+    }
+}
@@ -0,0 +1,208 @@
+use indexmap::IndexMap;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    InputContent,
+    MapHasher,
+    Mutation,
+    RawElement,
+    RawNode,
+};
+
+impl Program {
+    /// Whether two programs have the same top-level stacks, ignoring block ids,
+    /// positions and the order of the stacks on the workspace. Variables and
+    /// comments are not compared.
+    pub fn eq_ignore_ids(&self, other: &Program) -> bool {
+        if self.groups.len() != other.groups.len() {
+            return false;
+        }
+        let mut unmatched: Vec<&StatementBody> = other.groups.iter().collect();
+        for group in self.groups.iter() {
+            match unmatched.iter().position(|candidate| group.eq_ignore_ids(candidate)) {
+                Some(index) => { unmatched.swap_remove(index); },
+                None => return false
+            }
+        }
+        true
+    }
+}
+
+impl StatementBody {
+    pub fn eq_ignore_ids(&self, other: &StatementBody) -> bool {
+        self.shadow == other.shadow
+            && self.blocks.len() == other.blocks.len()
+            && self.blocks.iter()
+                .zip(other.blocks.iter())
+                .all(|(a, b)| a.eq_ignore_ids(b))
+    }
+}
+
+impl Block {
+    /// Whether two blocks have the same type, fields, mutation, disabled state
+    /// and inputs, recursively. Ids, positions, comments and the other
+    /// editing flags are ignored, as are id attributes in the mutation, such
+    /// as the variable ids of procedure parameters.
+    pub fn eq_ignore_ids(&self, other: &Block) -> bool {
+        let mutations_match = match (&self.mutation, &other.mutation) {
+            (Some(a), Some(b)) => a.eq_ignore_ids(b),
+            (None, None) => true,
+            _ => false
+        };
+        if self.block_type != other.block_type
+            || !mutations_match
+            || self.flags.disabled != other.flags.disabled
+            || self.fields.len() != other.fields.len()
+            || self.values.len() != other.values.len()
+            || self.statements.len() != other.statements.len() {
+            return false;
+        }
+
+        let fields_match = self.fields.iter().all(|(name, value)| {
+            match other.fields.get(name) {
+                Some(other_value) => value.eq_ignore_ids(other_value),
+                None => false
+            }
+        });
+        let values_match = self.values.iter().all(|(name, input)| {
+            match other.values.get(name) {
+                Some(other_input) => input.eq_ignore_ids(other_input),
+                None => false
+            }
+        });
+        let statements_match = self.statements.iter().all(|(name, body)| {
+            match other.statements.get(name) {
+                Some(other_body) => body.eq_ignore_ids(other_body),
+                None => false
+            }
+        });
+
+        fields_match && values_match && statements_match
+    }
+}
+
+impl FieldValue {
+    pub fn eq_ignore_ids(&self, other: &FieldValue) -> bool {
+        match (self, other) {
+            (FieldValue::SimpleField(a), FieldValue::SimpleField(b)) => a == b,
+            (FieldValue::ExpressionField(a), FieldValue::ExpressionField(b)) => a.eq_ignore_ids(b),
+            _ => false
+        }
+    }
+}
+
+impl InputContent {
+    pub fn eq_ignore_ids(&self, other: &InputContent) -> bool {
+        self.is_shadow() == other.is_shadow() && self.block().eq_ignore_ids(other.block())
+    }
+}
+
+impl Mutation {
+    pub fn eq_ignore_ids(&self, other: &Mutation) -> bool {
+        attributes_eq_ignore_ids(&self.attributes, &other.attributes)
+            && self.children.len() == other.children.len()
+            && self.children.iter()
+                .zip(other.children.iter())
+                .all(|(a, b)| element_eq_ignore_ids(a, b))
+    }
+}
+
+/// Whether a mutation attribute holds an id: `varid` on procedure
+/// parameters, or `id` on their JSON form.
+pub(crate) fn is_id_attribute(name: &str) -> bool {
+    name == "id" || name == "varid"
+}
+
+fn attributes_eq_ignore_ids(a: &IndexMap<String, String, MapHasher>, b: &IndexMap<String, String, MapHasher>) -> bool {
+    let kept = |attributes: &IndexMap<String, String, MapHasher>| {
+        attributes.iter().filter(|(name, _)| !is_id_attribute(name)).count()
+    };
+    kept(a) == kept(b)
+        && a.iter()
+            .filter(|(name, _)| !is_id_attribute(name))
+            .all(|(name, value)| b.get(name) == Some(value))
+}
+
+fn element_eq_ignore_ids(a: &RawElement, b: &RawElement) -> bool {
+    a.name == b.name
+        && a.namespace == b.namespace
+        && attributes_eq_ignore_ids(&a.attributes, &b.attributes)
+        && a.children.len() == b.children.len()
+        && a.children.iter()
+            .zip(b.children.iter())
+            .all(|(a, b)| match (a, b) {
+                (RawNode::Element(a), RawNode::Element(b)) => element_eq_ignore_ids(a, b),
+                (RawNode::Text(a), RawNode::Text(b)) => a == b,
+                _ => false
+            })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_eq_ignore_ids() {
+        let solution = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="led_off" id="x1" x="300" y="10"></block>
+                <block type="main_loop" id="a1" x="10" y="10">
+                    <statement name="BODY">
+                        <block type="led_on" id="a2">
+                            <field name="TIME">300</field>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let student = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="b1" x="80" y="40">
+                    <statement name="BODY">
+                        <block type="led_on" id="b2">
+                            <field name="TIME">300</field>
+                        </block>
+                    </statement>
+                </block>
+                <block type="led_off" id="y1" x="50" y="400"></block>
+            </xml>
+        "#).unwrap();
+        let wrong_time = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="b1">
+                    <statement name="BODY">
+                        <block type="led_on" id="b2">
+                            <field name="TIME">500</field>
+                        </block>
+                    </statement>
+                </block>
+                <block type="led_off" id="y1"></block>
+            </xml>
+        "#).unwrap();
+
+        assert!(solution.eq_ignore_ids(&student));
+        assert!(student.eq_ignore_ids(&solution));
+        assert!(!solution.eq_ignore_ids(&wrong_time));
+        assert!(solution.groups[1].blocks[0].eq_ignore_ids(&student.groups[0].blocks[0]));
+        assert!(!solution.groups[0].blocks[0].eq_ignore_ids(&student.groups[0].blocks[0]));
+    }
+
+    #[test]
+    fn test_eq_ignore_ids_in_mutations() {
+        let procedure = |varid: &str, arg: &str| program_from_xml(&format!(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="procedures_defnoreturn" id="{}">
+                    <mutation><arg name="{}" varid="{}"></arg></mutation>
+                    <field name="NAME">blink</field>
+                </block>
+            </xml>
+        "#, varid, arg, varid)).unwrap();
+
+        assert!(procedure("v1", "count").eq_ignore_ids(&procedure("v2", "count")));
+        assert!(!procedure("v1", "count").eq_ignore_ids(&procedure("v1", "total")));
+    }
+}
@@ -0,0 +1,73 @@
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::read::{
+    GzDecoder,
+    ZlibDecoder,
+};
+use flate2::write::GzEncoder;
+
+use super::{
+    Program,
+    ParseError,
+    program_from_reader,
+    program_to_xml,
+};
+
+/// Parse a gzip-compressed XML workspace. Corrupt data is reported as
+/// `ParseError::Io`, like other read failures.
+pub fn program_from_gzip(bytes: &[u8]) -> Result<Program, ParseError> {
+    program_from_reader(GzDecoder::new(bytes))
+}
+
+/// Parse a zlib-compressed XML workspace, as sent with HTTP's
+/// `Content-Encoding: deflate`.
+pub fn program_from_deflate(bytes: &[u8]) -> Result<Program, ParseError> {
+    program_from_reader(ZlibDecoder::new(bytes))
+}
+
+/// Write the program as gzip-compressed XML, the reverse of
+/// `program_from_gzip`.
+pub fn program_to_gzip(program: &Program) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(program_to_xml(program).as_bytes()).expect("writing to a Vec cannot fail");
+    encoder.finish().expect("writing to a Vec cannot fail")
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use super::super::program_from_xml;
+
+    const XML: &str = r#"
+        <xml xmlns="https://developers.google.com/blockly/xml">
+            <block type="main_loop" id="main">
+                <statement name="BODY">
+                    <block type="led_on" id="on"><field name="TIME">300</field></block>
+                </statement>
+            </block>
+        </xml>
+    "#;
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let program = program_from_xml(XML).unwrap();
+        let compressed = program_to_gzip(&program);
+        let decompressed = program_from_gzip(&compressed).unwrap();
+        assert_eq!(decompressed.groups, program.groups);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(XML.as_bytes()).unwrap();
+        assert_eq!(program_from_deflate(&encoder.finish().unwrap()).unwrap().groups, program.groups);
+    }
+
+    #[test]
+    fn test_corrupt_gzip() {
+        match program_from_gzip(b"not gzip") {
+            Err(ParseError::Io { .. }) => {},
+            other => panic!("expected a read error, got {:?}", other),
+        }
+    }
+}
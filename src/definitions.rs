@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+
+use serde_json::{
+    Map,
+    Value,
+};
+
+use super::ParseError;
+use super::json::{
+    parse_json,
+    as_object,
+    as_array,
+    get_string,
+    require_string,
+};
+
+/// The shape of a block type, loaded from Blockly's JSON block definition
+/// format (`Blockly.defineBlocksWithJsonArray`).
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BlockDefinition {
+    pub block_type: String,
+    /// Fields that are saved with the workspace, in message order.
+    /// Plain labels and images are left out.
+    pub fields: Vec<FieldDefinition>,
+    /// Named inputs, in message order. Dummy inputs are left out.
+    pub inputs: Vec<InputDefinition>,
+    pub output: Option<Connection>,
+    pub previous_statement: Option<Connection>,
+    pub next_statement: Option<Connection>,
+}
+
+/// A connection point and the types it accepts. An empty `check` accepts anything.
+#[derive(PartialEq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Connection {
+    pub check: Vec<String>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct InputDefinition {
+    pub name: String,
+    pub kind: InputKind,
+    pub check: Vec<String>,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum InputKind {
+    Value,
+    Statement,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct FieldDefinition {
+    pub name: String,
+    pub kind: FieldKind,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum FieldKind {
+    Text,
+    Number { min: Option<f64>, max: Option<f64>, precision: Option<f64> },
+    Dropdown(Vec<DropdownOption>),
+    Checkbox,
+    Variable { types: Vec<String> },
+    Colour,
+    Angle,
+    Label,
+    /// A field type this crate does not know, e.g. from a plugin.
+    Other(String),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DropdownOption {
+    /// The text shown to the user, or the `alt` text of an image option.
+    pub label: String,
+    /// The value saved in the workspace.
+    pub value: String,
+}
+
+impl BlockDefinition {
+    pub fn field(&self, name: &str) -> Option<&FieldDefinition> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    pub fn input(&self, name: &str) -> Option<&InputDefinition> {
+        self.inputs.iter().find(|input| input.name == name)
+    }
+
+    pub fn is_statement_input(&self, name: &str) -> bool {
+        self.input(name).map(|input| input.kind == InputKind::Statement).unwrap_or(false)
+    }
+}
+
+/// Block definitions by block type.
+#[derive(Debug, Default, Clone)]
+pub struct BlockRegistry {
+    definitions: HashMap<String, BlockDefinition>,
+}
+
+impl BlockRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load definitions in the formats accepted by `definitions_from_json`.
+    pub fn from_json(json: &str) -> Result<Self, ParseError> {
+        let mut registry = Self::new();
+        for definition in definitions_from_json(json)? {
+            registry.register(definition);
+        }
+        Ok(registry)
+    }
+
+    /// Add a definition, replacing any earlier one for the same block type.
+    pub fn register(&mut self, definition: BlockDefinition) {
+        self.definitions.insert(definition.block_type.clone(), definition);
+    }
+
+    pub fn get(&self, block_type: &str) -> Option<&BlockDefinition> {
+        self.definitions.get(block_type)
+    }
+
+    /// Every definition, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &BlockDefinition> {
+        self.definitions.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+}
+
+/// Load block definitions from JSON: either an array of definitions, as
+/// passed to `Blockly.defineBlocksWithJsonArray`, or a single definition.
+pub fn definitions_from_json(json: &str) -> Result<Vec<BlockDefinition>, ParseError> {
+    let root = parse_json(json)?;
+    match *root {
+        Value::Array(ref definitions) => definitions.iter().map(definition_from_json).collect(),
+        _ => Ok(vec![definition_from_json(&root)?])
+    }
+}
+
+fn definition_from_json(value: &Value) -> Result<BlockDefinition, ParseError> {
+    let object = as_object(value, "block definition")?;
+
+    let mut definition = BlockDefinition {
+        block_type: require_string(object, "block definition", "type")?,
+        fields: Vec::new(),
+        inputs: Vec::new(),
+        output: connection_from_json(object, "output")?,
+        previous_statement: connection_from_json(object, "previousStatement")?,
+        next_statement: connection_from_json(object, "nextStatement")?,
+    };
+
+    // Arguments are numbered alongside their messages: `message0`/`args0`, `message1`/`args1`, ...
+    let mut index = 0;
+    while object.contains_key(&format!("message{}", index)) {
+        let key = format!("args{}", index);
+        if let Some(args) = object.get(&key) {
+            for arg in as_array(args, &key)? {
+                add_arg(&mut definition, as_object(arg, &key)?)?;
+            }
+        }
+        index += 1;
+    }
+
+    Ok(definition)
+}
+
+fn add_arg(definition: &mut BlockDefinition, arg: &Map<String, Value>) -> Result<(), ParseError> {
+    let arg_type = require_string(arg, "argument", "type")?;
+    let input_kind = match arg_type.as_str() {
+        "input_value" => Some(InputKind::Value),
+        "input_statement" => Some(InputKind::Statement),
+        _ => None
+    };
+    if let Some(kind) = input_kind {
+        definition.inputs.push(InputDefinition {
+            name: require_string(arg, &arg_type, "name")?,
+            kind,
+            check: get_check(arg, "check")?,
+        });
+        return Ok(());
+    }
+
+    if let Some(kind) = field_kind_from_json(&arg_type, arg)? {
+        definition.fields.push(FieldDefinition {
+            name: require_string(arg, &arg_type, "name")?,
+            kind,
+        });
+    }
+    Ok(())
+}
+
+// `None` for arguments that are neither inputs nor saved fields
+fn field_kind_from_json(arg_type: &str, arg: &Map<String, Value>) -> Result<Option<FieldKind>, ParseError> {
+    let kind = match arg_type {
+        "input_dummy" | "input_end_row" | "field_label" | "field_image" => return Ok(None),
+        "field_input" | "field_multilinetext" => FieldKind::Text,
+        "field_number" => FieldKind::Number {
+            min: get_number(arg, "min")?,
+            max: get_number(arg, "max")?,
+            precision: get_number(arg, "precision")?,
+        },
+        "field_dropdown" => FieldKind::Dropdown(dropdown_options_from_json(arg)?),
+        "field_checkbox" => FieldKind::Checkbox,
+        "field_variable" => FieldKind::Variable { types: get_check(arg, "variableTypes")? },
+        "field_colour" => FieldKind::Colour,
+        "field_angle" => FieldKind::Angle,
+        "field_label_serializable" => FieldKind::Label,
+        _ => {
+            // Blockly falls back to `alt` when a field type is not registered
+            if let Some(alt) = arg.get("alt") {
+                let alt = as_object(alt, "alt")?;
+                let alt_type = require_string(alt, "argument", "type")?;
+                return field_kind_from_json(&alt_type, alt);
+            }
+            FieldKind::Other(arg_type.to_string())
+        }
+    };
+    Ok(Some(kind))
+}
+
+fn dropdown_options_from_json(arg: &Map<String, Value>) -> Result<Vec<DropdownOption>, ParseError> {
+    let options = match arg.get("options") {
+        Some(options) => as_array(options, "options")?,
+        None => return Ok(Vec::new())
+    };
+    options.iter()
+        .map(|option| {
+            let pair = as_array(option, "dropdown option")?;
+            let (label, value) = match (pair.first(), pair.get(1)) {
+                (Some(label), Some(Value::String(value))) => (label, value),
+                _ => return Err(ParseError::UnexpectedStructure(format!("invalid dropdown option: {}", option)))
+            };
+            let label = match *label {
+                Value::String(ref text) => text.clone(),
+                Value::Object(ref image) => get_string(image, "alt").unwrap_or_default(),
+                _ => return Err(ParseError::UnexpectedStructure(format!("invalid dropdown option: {}", option)))
+            };
+            Ok(DropdownOption {
+                label,
+                value: value.clone(),
+            })
+        })
+        .collect()
+}
+
+// `"output": null` means an output that accepts anything, so presence of the key is what matters
+fn connection_from_json(object: &Map<String, Value>, key: &str) -> Result<Option<Connection>, ParseError> {
+    if !object.contains_key(key) {
+        return Ok(None);
+    }
+    Ok(Some(Connection {
+        check: get_check(object, key)?,
+    }))
+}
+
+// A type check is `null`, a single type name, or an array of them
+fn get_check(object: &Map<String, Value>, key: &str) -> Result<Vec<String>, ParseError> {
+    match object.get(key) {
+        None | Some(&Value::Null) => Ok(Vec::new()),
+        Some(Value::String(check)) => Ok(vec![check.clone()]),
+        Some(value) => {
+            as_array(value, key)?
+                .iter()
+                .map(|check| {
+                    check.as_str()
+                        .map(|check| check.to_string())
+                        .ok_or_else(|| ParseError::UnexpectedStructure(format!("expected {} to hold strings", key)))
+                })
+                .collect()
+        }
+    }
+}
+
+fn get_number(object: &Map<String, Value>, key: &str) -> Result<Option<f64>, ParseError> {
+    match object.get(key) {
+        Some(value) => {
+            value.as_f64()
+                .map(Some)
+                .ok_or_else(|| ParseError::UnexpectedStructure(format!("expected {} to be a number", key)))
+        },
+        None => Ok(None)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_definitions_from_json() {
+        let json: &str = r#"
+            [
+                {
+                    "type": "inner_loop",
+                    "message0": "repeat %1 times",
+                    "args0": [
+                        { "type": "field_number", "name": "COUNT", "value": 3, "min": 0, "precision": 1 }
+                    ],
+                    "message1": "do %1",
+                    "args1": [
+                        { "type": "input_statement", "name": "BODY" }
+                    ],
+                    "previousStatement": null,
+                    "nextStatement": null
+                },
+                {
+                    "type": "led_colour",
+                    "message0": "%1 colour %2 %3",
+                    "args0": [
+                        { "type": "field_image", "src": "led.png", "width": 15, "height": 15 },
+                        {
+                            "type": "field_dropdown",
+                            "name": "COLOUR",
+                            "options": [["red", "RED"], [{ "src": "green.png", "alt": "green" }, "GREEN"]]
+                        },
+                        { "type": "input_value", "name": "BRIGHTNESS", "check": "Number" }
+                    ],
+                    "output": ["Colour", "String"]
+                }
+            ]
+        "#;
+        let definitions = definitions_from_json(json).unwrap();
+        assert_eq!(definitions.len(), 2);
+
+        let inner_loop = &definitions[0];
+        assert_eq!(inner_loop.block_type, "inner_loop");
+        assert_eq!(inner_loop.field("COUNT").unwrap().kind, FieldKind::Number {
+            min: Some(0.0),
+            max: None,
+            precision: Some(1.0),
+        });
+        assert!(inner_loop.is_statement_input("BODY"));
+        assert_eq!(inner_loop.previous_statement, Some(Connection::default()));
+        assert_eq!(inner_loop.output, None);
+
+        let led_colour = &definitions[1];
+        assert_eq!(led_colour.fields.len(), 1);
+        assert_eq!(led_colour.fields[0].kind, FieldKind::Dropdown(vec![
+            DropdownOption { label: "red".to_string(), value: "RED".to_string() },
+            DropdownOption { label: "green".to_string(), value: "GREEN".to_string() },
+        ]));
+        assert_eq!(led_colour.input("BRIGHTNESS"), Some(&InputDefinition {
+            name: "BRIGHTNESS".to_string(),
+            kind: InputKind::Value,
+            check: vec!["Number".to_string()],
+        }));
+        assert!(!led_colour.is_statement_input("BRIGHTNESS"));
+        assert_eq!(led_colour.output.as_ref().unwrap().check, vec!["Colour", "String"]);
+    }
+
+    #[test]
+    fn test_single_definition_and_alt_fallback() {
+        let json: &str = r#"
+            {
+                "type": "pick_angle",
+                "message0": "%1",
+                "args0": [
+                    { "type": "field_slider", "name": "DEGREES", "alt": { "type": "field_angle", "name": "DEGREES" } },
+                    { "type": "field_rainbow", "name": "HUE" }
+                ]
+            }
+        "#;
+        let definitions = definitions_from_json(json).unwrap();
+        assert_eq!(definitions[0].fields, vec![
+            FieldDefinition { name: "DEGREES".to_string(), kind: FieldKind::Angle },
+            FieldDefinition { name: "HUE".to_string(), kind: FieldKind::Other("field_rainbow".to_string()) },
+        ]);
+
+        assert_eq!(
+            definitions_from_json(r#"{ "message0": "" }"#).unwrap_err(),
+            ParseError::MissingAttribute { element: "block definition".to_string(), attribute: "type".to_string(), span: None }
+        );
+    }
+}
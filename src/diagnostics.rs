@@ -0,0 +1,174 @@
+use std::fmt;
+use std::mem;
+use std::slice;
+
+use super::{
+    Program,
+    SourceMap,
+    StatementBody,
+};
+
+/// A recoverable problem found while parsing. Parsing carries on past it, so
+/// the program is still usable, but the input may not say what its author
+/// meant.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Warning {
+    /// A `<field>` has no text. `block` is the id of the block holding it.
+    EmptyField { block: String, field: String },
+    /// A `<block>` or `<variable>` has no `id` attribute.
+    MissingId { element: String },
+    /// An element the parser does not understand. It is skipped, or kept in
+    /// `Program::extras` or `Block::extra_elements`.
+    UnknownElement { parent: String, element: String },
+    /// A block has two fields with the same name; the last one is kept.
+    DuplicateField { block: String, field: String },
+    /// A block in a value input has a `<next>` chain, handled as
+    /// `ParserOptions::value_next` says.
+    NextOnValueBlock { block: String },
+    /// A block has an `x` or a `y` attribute but not both, so it has no
+    /// position.
+    PartialPosition { block: String },
+    /// A value or statement input holds both a block and a shadow. The block
+    /// is kept and the shadow is dropped.
+    DroppedShadow { block: String, input: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::EmptyField { ref block, ref field } => {
+                write!(f, "field `{}` of block {:?} is empty", field, block)
+            },
+            Warning::MissingId { ref element } => {
+                write!(f, "<{}> element has no `id` attribute", element)
+            },
+            Warning::UnknownElement { ref parent, ref element } => {
+                write!(f, "unknown <{}> element inside <{}>", element, parent)
+            },
+            Warning::DuplicateField { ref block, ref field } => {
+                write!(f, "block {:?} has more than one `{}` field", block, field)
+            },
+            Warning::NextOnValueBlock { ref block } => {
+                write!(f, "block {:?} is in a value input but has a next block", block)
+            },
+            Warning::PartialPosition { ref block } => {
+                write!(f, "block {:?} has only one of `x` and `y`, so its position is dropped", block)
+            },
+            Warning::DroppedShadow { ref block, ref input } => {
+                write!(f, "dropped the shadow under the block in input `{}` of block {:?}", input, block)
+            },
+        }
+    }
+}
+
+/// The warnings from one parse, in document order.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Diagnostics {
+    warnings: Vec<Warning>,
+    // Chains cut off value blocks by `ValueNext::Detach`, waiting to be
+    // added to the program as top-level stacks.
+    detached: Vec<StatementBody>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn push(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+
+    pub(crate) fn detach(&mut self, stack: StatementBody) {
+        self.detached.push(stack);
+    }
+
+    pub(crate) fn take_detached(&mut self) -> Vec<StatementBody> {
+        mem::take(&mut self.detached)
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Warning> {
+        self.warnings.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.warnings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<Warning> {
+        self.warnings
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Warning;
+    type IntoIter = slice::Iter<'a, Warning>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Warning;
+    type IntoIter = ::std::vec::IntoIter<Warning>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.warnings.into_iter()
+    }
+}
+
+/// A parsed program with the warnings found along the way.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ParseOutcome {
+    pub program: Program,
+    pub warnings: Diagnostics,
+    /// Where each block is in the source.
+    pub spans: SourceMap,
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        ParserOptions,
+        program_from_xml_with_diagnostics,
+    };
+
+    #[test]
+    fn test_warnings() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable>count</variable></variables>
+                <block type="text_print" id="a">
+                    <field name="TEXT"></field>
+                    <field name="TEXT">hi</field>
+                    <sparkle></sparkle>
+                    <next><block type="led_off"></block></next>
+                </block>
+            </xml>
+        "#;
+        let outcome = program_from_xml_with_diagnostics(xml, &ParserOptions::default()).unwrap();
+        assert_eq!(outcome.program.groups[0].blocks.len(), 2);
+        assert_eq!(outcome.warnings.into_vec(), vec![
+            Warning::MissingId { element: "variable".to_string() },
+            Warning::EmptyField { block: "a".to_string(), field: "TEXT".to_string() },
+            Warning::DuplicateField { block: "a".to_string(), field: "TEXT".to_string() },
+            Warning::UnknownElement { parent: "block".to_string(), element: "sparkle".to_string() },
+            Warning::MissingId { element: "block".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_no_warnings() {
+        let xml: &str = r#"<xml><block type="led_on" id="a"><field name="TIME">300</field></block></xml>"#;
+        let outcome = program_from_xml_with_diagnostics(xml, &ParserOptions::default()).unwrap();
+        assert!(outcome.warnings.is_empty());
+        assert_eq!(outcome.warnings.iter().count(), 0);
+    }
+}
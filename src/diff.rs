@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+};
+
+/// Where a block is connected. Parents are referred to by block id.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Attachment {
+    /// The first block of a top-level stack.
+    TopLevel,
+    /// Below another block in a stack.
+    Next { previous: String },
+    /// The first block in a statement input.
+    Statement { parent: String, input: String },
+    Value { parent: String, input: String },
+    Field { parent: String, field: String },
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Change {
+    BlockAdded { id: String, block_type: String, attachment: Attachment },
+    BlockRemoved { id: String, block_type: String, attachment: Attachment },
+    /// The block is connected somewhere else. `from` refers to parents by their
+    /// ids in the new program where those blocks still exist.
+    BlockMoved { id: String, from: Attachment, to: Attachment },
+    /// A field's text changed, or the field was added (`old` is `None`) or
+    /// removed (`new` is `None`). Fields holding nested blocks are not compared.
+    FieldChanged { id: String, field: String, old: Option<String>, new: Option<String> },
+}
+
+impl Program {
+    pub fn diff(&self, new: &Program) -> Vec<Change> {
+        diff(self, new)
+    }
+}
+
+/// Compare two versions of a workspace. Blocks are matched by id; blocks
+/// whose id is missing or changed are matched by type and position in the
+/// tree instead. Changes for the new program are listed in document order,
+/// followed by removals.
+pub fn diff(old: &Program, new: &Program) -> Vec<Change> {
+    let old_entries = flatten(old);
+    let new_entries = flatten(new);
+
+    // Index of the matching entry in `new_entries`, per old entry
+    let mut matches: Vec<Option<usize>> = vec![None; old_entries.len()];
+    let mut matched_new = vec![false; new_entries.len()];
+
+    let mut new_by_id: HashMap<&str, usize> = HashMap::new();
+    for (index, entry) in new_entries.iter().enumerate() {
+        if !entry.block.id.is_empty() {
+            new_by_id.entry(entry.block.id.as_str()).or_insert(index);
+        }
+    }
+    for (old_index, entry) in old_entries.iter().enumerate() {
+        if let Some(&new_index) = new_by_id.get(entry.block.id.as_str()) {
+            if !matched_new[new_index] && new_entries[new_index].block.block_type == entry.block.block_type {
+                matches[old_index] = Some(new_index);
+                matched_new[new_index] = true;
+            }
+        }
+    }
+
+    // Structural fallback for blocks whose ids did not line up
+    for (old_index, entry) in old_entries.iter().enumerate() {
+        if matches[old_index].is_some() {
+            continue;
+        }
+        let candidate = new_entries.iter()
+            .enumerate()
+            .position(|(new_index, new_entry)| {
+                !matched_new[new_index]
+                    && new_entry.path == entry.path
+                    && new_entry.block.block_type == entry.block.block_type
+            });
+        if let Some(new_index) = candidate {
+            matches[old_index] = Some(new_index);
+            matched_new[new_index] = true;
+        }
+    }
+
+    let mut renamed: HashMap<&str, &str> = HashMap::new();
+    for (old_index, new_index) in matches.iter().enumerate() {
+        if let Some(new_index) = *new_index {
+            renamed.insert(old_entries[old_index].block.id.as_str(), new_entries[new_index].block.id.as_str());
+        }
+    }
+
+    let mut old_by_new: Vec<Option<usize>> = vec![None; new_entries.len()];
+    for (old_index, new_index) in matches.iter().enumerate() {
+        if let Some(new_index) = *new_index {
+            old_by_new[new_index] = Some(old_index);
+        }
+    }
+
+    let mut changes = Vec::new();
+    for (new_index, new_entry) in new_entries.iter().enumerate() {
+        let old_entry = match old_by_new[new_index] {
+            Some(old_index) => &old_entries[old_index],
+            None => {
+                changes.push(Change::BlockAdded {
+                    id: new_entry.block.id.clone(),
+                    block_type: new_entry.block.block_type.clone(),
+                    attachment: new_entry.attachment.clone(),
+                });
+                continue;
+            }
+        };
+
+        let from = rename_attachment(&old_entry.attachment, &renamed);
+        if from != new_entry.attachment {
+            changes.push(Change::BlockMoved {
+                id: new_entry.block.id.clone(),
+                from,
+                to: new_entry.attachment.clone(),
+            });
+        }
+
+        diff_fields(old_entry.block, new_entry.block, &mut changes);
+    }
+
+    for (old_index, old_entry) in old_entries.iter().enumerate() {
+        if matches[old_index].is_none() {
+            changes.push(Change::BlockRemoved {
+                id: old_entry.block.id.clone(),
+                block_type: old_entry.block.block_type.clone(),
+                attachment: old_entry.attachment.clone(),
+            });
+        }
+    }
+
+    changes
+}
+
+fn diff_fields(old: &Block, new: &Block, changes: &mut Vec<Change>) {
+    let mut names: Vec<&String> = old.fields.keys().chain(new.fields.keys()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let old_text = simple_field_text(old.fields.get(name));
+        let new_text = simple_field_text(new.fields.get(name));
+        if old_text != new_text {
+            changes.push(Change::FieldChanged {
+                id: new.id.clone(),
+                field: name.clone(),
+                old: old_text,
+                new: new_text,
+            });
+        }
+    }
+}
+
+fn simple_field_text(value: Option<&FieldValue>) -> Option<String> {
+    match value {
+        Some(FieldValue::SimpleField(text)) => Some(text.clone()),
+        _ => None
+    }
+}
+
+fn rename_attachment(attachment: &Attachment, renamed: &HashMap<&str, &str>) -> Attachment {
+    let rename = |id: &String| -> String {
+        renamed.get(id.as_str()).map(|new_id| new_id.to_string()).unwrap_or_else(|| id.clone())
+    };
+    match *attachment {
+        Attachment::TopLevel => Attachment::TopLevel,
+        Attachment::Next { ref previous } => Attachment::Next { previous: rename(previous) },
+        Attachment::Statement { ref parent, ref input } => {
+            Attachment::Statement { parent: rename(parent), input: input.clone() }
+        },
+        Attachment::Value { ref parent, ref input } => {
+            Attachment::Value { parent: rename(parent), input: input.clone() }
+        },
+        Attachment::Field { ref parent, ref field } => {
+            Attachment::Field { parent: rename(parent), field: field.clone() }
+        },
+    }
+}
+
+pub(crate) struct Entry<'a> {
+    pub(crate) block: &'a Block,
+    pub(crate) attachment: Attachment,
+    /// Whether the block is a `<shadow>`.
+    pub(crate) shadow: bool,
+    // Position in the tree, e.g. `0/BODY/2`, for structural matching
+    path: String,
+}
+
+/// Every block in the program with where it is attached, in document order.
+pub(crate) fn flatten(program: &Program) -> Vec<Entry<'_>> {
+    let mut entries = Vec::new();
+    for (index, group) in program.groups.iter().enumerate() {
+        flatten_stack(group, Attachment::TopLevel, &index.to_string(), &mut entries);
+    }
+    entries
+}
+
+fn flatten_stack<'a>(body: &'a StatementBody, first: Attachment, path: &str, entries: &mut Vec<Entry<'a>>) {
+    let mut attachment = first;
+    for (index, block) in body.blocks.iter().enumerate() {
+        let block_path = format!("{}/{}", path, index);
+        flatten_block(block, attachment, body.shadow, block_path, entries);
+        attachment = Attachment::Next { previous: block.id.clone() };
+    }
+}
+
+fn flatten_block<'a>(block: &'a Block, attachment: Attachment, shadow: bool, path: String, entries: &mut Vec<Entry<'a>>) {
+    entries.push(Entry {
+        block,
+        attachment,
+        shadow,
+        path: path.clone(),
+    });
+
+    let mut field_names: Vec<&String> = block.fields.keys().collect();
+    field_names.sort();
+    for name in field_names {
+        if let FieldValue::ExpressionField(ref expression) = block.fields[name] {
+            let attachment = Attachment::Field { parent: block.id.clone(), field: name.clone() };
+            flatten_block(expression, attachment, false, format!("{}/{}", path, name), entries);
+        }
+    }
+
+    let mut value_names: Vec<&String> = block.values.keys().collect();
+    value_names.sort();
+    for name in value_names {
+        let attachment = Attachment::Value { parent: block.id.clone(), input: name.clone() };
+        let input = &block.values[name];
+        flatten_block(input.block(), attachment, input.is_shadow(), format!("{}/{}", path, name), entries);
+    }
+
+    let mut statement_names: Vec<&String> = block.statements.keys().collect();
+    statement_names.sort();
+    for name in statement_names {
+        let attachment = Attachment::Statement { parent: block.id.clone(), input: name.clone() };
+        flatten_stack(&block.statements[name], attachment, &format!("{}/{}", path, name), entries);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_diff() {
+        let old = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <field name="TIME">300</field>
+                            <next>
+                                <block type="led_off" id="off">
+                                    <field name="TIME">100</field>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="buzzer" id="buzz"></block>
+            </xml>
+        "#).unwrap();
+        let new = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_off" id="off">
+                            <field name="TIME">100</field>
+                            <next>
+                                <block type="led_on" id="on">
+                                    <field name="TIME">500</field>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="wait" id="wait"></block>
+            </xml>
+        "#).unwrap();
+
+        assert_eq!(old.diff(&new), vec![
+            Change::BlockMoved {
+                id: "off".to_string(),
+                from: Attachment::Next { previous: "on".to_string() },
+                to: Attachment::Statement { parent: "main".to_string(), input: "BODY".to_string() },
+            },
+            Change::BlockMoved {
+                id: "on".to_string(),
+                from: Attachment::Statement { parent: "main".to_string(), input: "BODY".to_string() },
+                to: Attachment::Next { previous: "off".to_string() },
+            },
+            Change::FieldChanged {
+                id: "on".to_string(),
+                field: "TIME".to_string(),
+                old: Some("300".to_string()),
+                new: Some("500".to_string()),
+            },
+            Change::BlockAdded {
+                id: "wait".to_string(),
+                block_type: "wait".to_string(),
+                attachment: Attachment::TopLevel,
+            },
+            Change::BlockRemoved {
+                id: "buzz".to_string(),
+                block_type: "buzzer".to_string(),
+                attachment: Attachment::TopLevel,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_diff_structural_fallback() {
+        let old = program_from_xml(r#"
+            <xml><block type="led_on" id="a"><field name="TIME">300</field></block></xml>
+        "#).unwrap();
+        let new = program_from_xml(r#"
+            <xml><block type="led_on" id="b"><field name="TIME">200</field></block></xml>
+        "#).unwrap();
+
+        assert_eq!(diff(&old, &new), vec![
+            Change::FieldChanged {
+                id: "b".to_string(),
+                field: "TIME".to_string(),
+                old: Some("300".to_string()),
+                new: Some("200".to_string()),
+            },
+        ]);
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+
+use super::{
+    Program,
+    Block,
+    FieldValue,
+};
+
+// Blockly's ids are 20 characters of punctuation soup, so only the start is
+// shown unless the alternate flag is given
+const SHORT_ID_LENGTH: usize = 4;
+
+/// One line: the block's type, id and fields, then what is in its inputs,
+/// e.g. `led_on#^3xb… {TIME=300} (BRIGHTNESS: math_number)`. With `{:#}` the
+/// whole id is shown.
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.block_type)?;
+        if !self.id.is_empty() {
+            match self.id.char_indices().nth(SHORT_ID_LENGTH) {
+                Some((end, _)) if !f.alternate() => write!(f, "#{}…", &self.id[..end])?,
+                _ => write!(f, "#{}", self.id)?,
+            }
+        }
+
+        if !self.fields.is_empty() {
+            f.write_str(" {")?;
+            for (index, (name, value)) in self.fields.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                match *value {
+                    FieldValue::SimpleField(ref text) if needs_quotes(text) => write!(f, "{}={:?}", name, text)?,
+                    FieldValue::SimpleField(ref text) => write!(f, "{}={}", name, text)?,
+                    FieldValue::ExpressionField(ref inner) => write!(f, "{}={}", name, inner.block_type)?,
+                }
+            }
+            f.write_str("}")?;
+        }
+
+        if !self.values.is_empty() || !self.statements.is_empty() {
+            f.write_str(" (")?;
+            let values = self.values.iter()
+                .map(|(name, input)| format!("{}: {}", name, input.block().block_type));
+            let statements = self.statements.iter()
+                .map(|(name, body)| match body.blocks.len() {
+                    1 => format!("{}: {}", name, body.blocks[0].block_type),
+                    count => format!("{}: {} blocks", name, count),
+                });
+            for (index, input) in values.chain(statements).enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                f.write_str(&input)?;
+            }
+            f.write_str(")")?;
+        }
+
+        if self.flags.disabled {
+            f.write_str(" [disabled]")?;
+        }
+        Ok(())
+    }
+}
+
+/// One line counting the program's blocks, stacks and variables, with the
+/// type of the first block in each stack, e.g.
+/// `4 blocks in 2 stacks (main_loop, buzzer), 1 variable`.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let blocks = self.iter_blocks().count();
+        write!(f, "{} in {}", plural(blocks, "block"), plural(self.groups.len(), "stack"))?;
+        if !self.groups.is_empty() {
+            let firsts: Vec<&str> = self.groups.iter()
+                .filter_map(|group| group.blocks.first())
+                .map(|block| block.block_type.as_str())
+                .collect();
+            write!(f, " ({})", firsts.join(", "))?;
+        }
+        if !self.variables.is_empty() {
+            write!(f, ", {}", plural(self.variables.len(), "variable"))?;
+        }
+        if !self.comments.is_empty() {
+            write!(f, ", {}", plural(self.comments.len(), "comment"))?;
+        }
+        Ok(())
+    }
+}
+
+fn needs_quotes(text: &str) -> bool {
+    text.is_empty() || text.chars().any(|c| c.is_whitespace() || c.is_control() || ",{}=\"".contains(c))
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_display() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">count</variable></variables>
+                <block type="main_loop" id="[.)/fqUYv92(mzb{?:~u" x="50" y="50">
+                    <statement name="BODY">
+                        <block type="led_on" id="^3xb.m4E9i0;3$R10(=5">
+                            <field name="TIME">300</field>
+                            <value name="BRIGHTNESS"><shadow type="math_number" id="n"><field name="NUM">80</field></shadow></value>
+                            <next><block type="text_print" id="p" disabled="true"><field name="TEXT">hi there</field></block></next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="buzzer"></block>
+            </xml>
+        "#).unwrap();
+        let main_loop = &program.groups[0].blocks[0];
+        let body = &main_loop.statements["BODY"].blocks;
+
+        assert_eq!(main_loop.to_string(), "main_loop#[.)/… (BODY: 2 blocks)");
+        assert_eq!(body[0].to_string(), "led_on#^3xb… {TIME=300} (BRIGHTNESS: math_number)");
+        assert_eq!(format!("{:#}", body[0]), "led_on#^3xb.m4E9i0;3$R10(=5 {TIME=300} (BRIGHTNESS: math_number)");
+        assert_eq!(body[1].to_string(), r#"text_print#p {TEXT="hi there"} [disabled]"#);
+        assert_eq!(program.to_string(), "5 blocks in 2 stacks (main_loop, buzzer), 1 variable");
+    }
+}
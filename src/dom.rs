@@ -0,0 +1,233 @@
+use std::borrow::Cow;
+
+use sxd_document::dom::{
+    Element,
+    ChildOfElement,
+};
+
+use super::{
+    RawElement,
+    Span,
+};
+use super::raw::RawNode;
+
+/// The parts of an XML element the parser reads. Implemented for
+/// sxd-document's DOM and for `RawElement` trees, so alternative XML backends
+/// share the same parsing code. Element names are local names, without a
+/// prefix. Attribute names are as written: an unprefixed name only matches an
+/// attribute in no namespace, and namespaced attributes are named by their
+/// prefix, e.g. `xml:space`.
+pub(crate) trait XmlElement: Copy {
+    fn name(&self) -> &str;
+    /// The namespace URI, or `None` for no namespace.
+    fn namespace(&self) -> Option<&str>;
+    /// The name to show in error messages.
+    fn display_name(&self) -> String {
+        self.name().to_string()
+    }
+    /// Where the element is in the source, if the backend knows.
+    fn span(&self) -> Option<Span> {
+        None
+    }
+    fn attribute(&self, name: &str) -> Option<&str>;
+    /// Includes an `xmlns:` declaration for each prefix the attributes use,
+    /// even if it was declared on an ancestor, so kept attributes can be
+    /// written back out on their own.
+    fn attributes(&self) -> Vec<(Cow<'_, str>, &str)>;
+    fn child_elements(&self) -> Vec<Self>;
+    /// The concatenated text of the element's direct text children.
+    fn text(&self) -> String;
+    fn to_raw(&self) -> RawElement;
+}
+
+impl<'d> XmlElement for Element<'d> {
+    fn name(&self) -> &str {
+        Element::name(self).local_part()
+    }
+
+    fn namespace(&self) -> Option<&str> {
+        Element::name(self).namespace_uri()
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        Element::attributes(self)
+            .into_iter()
+            .find(|attribute| {
+                let qname = attribute.name();
+                match (qname.namespace_uri(), attribute.preferred_prefix()) {
+                    (None, _) => qname.local_part() == name,
+                    (Some(XML_NAMESPACE), _) => name.strip_prefix("xml:") == Some(qname.local_part()),
+                    (Some(_), Some(prefix)) => name.strip_prefix(prefix).and_then(|rest| rest.strip_prefix(':')) == Some(qname.local_part()),
+                    (Some(_), None) => false,
+                }
+            })
+            .map(|attribute| attribute.value())
+    }
+
+    fn attributes(&self) -> Vec<(Cow<'_, str>, &str)> {
+        let mut attributes: Vec<(Cow<str>, &str)> = Vec::new();
+        for attribute in Element::attributes(self) {
+            let qname = attribute.name();
+            let local = qname.local_part();
+            match (qname.namespace_uri(), attribute.preferred_prefix()) {
+                (Some(XML_NAMESPACE), _) => attributes.push((format!("xml:{}", local).into(), attribute.value())),
+                (Some(namespace), Some(prefix)) => {
+                    let declaration = format!("xmlns:{}", prefix);
+                    if !attributes.iter().any(|(name, _)| *name == declaration) {
+                        attributes.push((declaration.into(), namespace));
+                    }
+                    attributes.push((format!("{}:{}", prefix, local).into(), attribute.value()));
+                },
+                _ => attributes.push((local.into(), attribute.value())),
+            }
+        }
+        attributes
+    }
+
+    fn child_elements(&self) -> Vec<Self> {
+        self.children()
+            .into_iter()
+            .filter_map(|child| {
+                if let ChildOfElement::Element(el) = child {
+                    return Some(el);
+                }
+                None
+            })
+            .collect()
+    }
+
+    fn text(&self) -> String {
+        self.children()
+            .into_iter()
+            .filter_map(|child| {
+                if let ChildOfElement::Text(text_node) = child {
+                    return Some(text_node.text());
+                }
+                None
+            })
+            .collect()
+    }
+
+    fn to_raw(&self) -> RawElement {
+        RawElement::new(*self)
+    }
+}
+
+impl XmlElement for &RawElement {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    // Backends that build `RawElement` trees do not resolve namespaces
+    fn namespace(&self) -> Option<&str> {
+        None
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        RawElement::attribute(self, name)
+    }
+
+    fn attributes(&self) -> Vec<(Cow<'_, str>, &str)> {
+        self.attributes.iter()
+            .map(|(name, value)| (Cow::from(name.as_str()), value.as_str()))
+            .collect()
+    }
+
+    fn child_elements(&self) -> Vec<Self> {
+        self.children.iter()
+            .filter_map(|child| {
+                match *child {
+                    RawNode::Element(ref el) => Some(el),
+                    RawNode::Text(_) => None,
+                }
+            })
+            .collect()
+    }
+
+    fn text(&self) -> String {
+        RawElement::text(self)
+    }
+
+    fn to_raw(&self) -> RawElement {
+        (*self).clone()
+    }
+}
+
+/// The namespace of the `xml` prefix, bound without a declaration.
+pub(crate) const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// An element read through `ParserOptions::namespaces`. Elements in other
+/// namespaces have an empty name, so they match nothing and are handled like
+/// any other unknown element.
+#[derive(Clone, Copy)]
+pub(crate) struct Scoped<'o, E> {
+    element: E,
+    namespaces: Option<&'o [String]>,
+}
+
+impl<'o, E: XmlElement> Scoped<'o, E> {
+    pub(crate) fn new(element: E, namespaces: Option<&'o [String]>) -> Self {
+        Self {
+            element,
+            namespaces,
+        }
+    }
+
+    fn is_accepted(&self) -> bool {
+        match self.namespaces {
+            Some(namespaces) => {
+                let namespace = self.element.namespace().unwrap_or("");
+                namespaces.iter().any(|accepted| accepted == namespace)
+            },
+            None => true
+        }
+    }
+}
+
+impl<'o, E: XmlElement> XmlElement for Scoped<'o, E> {
+    fn name(&self) -> &str {
+        if self.is_accepted() {
+            self.element.name()
+        } else {
+            ""
+        }
+    }
+
+    fn namespace(&self) -> Option<&str> {
+        self.element.namespace()
+    }
+
+    fn display_name(&self) -> String {
+        match self.element.namespace() {
+            Some(namespace) if !self.is_accepted() => format!("{{{}}}{}", namespace, self.element.name()),
+            _ => self.element.display_name(),
+        }
+    }
+
+    fn span(&self) -> Option<Span> {
+        self.element.span()
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.element.attribute(name)
+    }
+
+    fn attributes(&self) -> Vec<(Cow<'_, str>, &str)> {
+        self.element.attributes()
+    }
+
+    fn child_elements(&self) -> Vec<Self> {
+        self.element.child_elements()
+            .into_iter()
+            .map(|child| Scoped::new(child, self.namespaces))
+            .collect()
+    }
+
+    fn text(&self) -> String {
+        self.element.text()
+    }
+
+    fn to_raw(&self) -> RawElement {
+        self.element.to_raw()
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    InputContent,
+};
+
+impl Program {
+    /// Render the program as a Graphviz `digraph`. Each block is a node
+    /// labeled with its type and text fields; edges are labeled with the
+    /// input or field name, or `next`. Shadow blocks are drawn dashed.
+    ///
+    /// Nodes are numbered in document order, since block ids may be missing
+    /// or repeated.
+    pub fn to_dot(&self) -> String {
+        let mut writer = DotWriter {
+            out: String::from("digraph program {\n    node [shape=box];\n"),
+            next_node: 0,
+        };
+        for group in &self.groups {
+            writer.stack(group, None);
+        }
+        writer.out.push_str("}\n");
+        writer.out
+    }
+}
+
+struct DotWriter {
+    out: String,
+    next_node: usize,
+}
+
+impl DotWriter {
+    // Write the blocks of a stack, linking the first to `parent` with its label
+    fn stack(&mut self, body: &StatementBody, parent: Option<(usize, &str)>) {
+        let mut previous = parent;
+        for block in &body.blocks {
+            let node = self.block(block, body.shadow);
+            if let Some((from, label)) = previous {
+                self.edge(from, node, label);
+            }
+            previous = Some((node, "next"));
+        }
+    }
+
+    fn block(&mut self, block: &Block, shadow: bool) -> usize {
+        let node = self.next_node;
+        self.next_node += 1;
+
+        let mut label = block.block_type.clone();
+        let mut names: Vec<&String> = block.fields.keys().collect();
+        names.sort();
+        for name in &names {
+            if let FieldValue::SimpleField(ref value) = block.fields[*name] {
+                label.push_str(&format!("\n{} = {}", name, value));
+            }
+        }
+        let style = if shadow { ", style=dashed" } else { "" };
+        writeln!(self.out, "    n{} [label=\"{}\"{}];", node, escape(&label), style).unwrap();
+
+        for name in names {
+            if let FieldValue::ExpressionField(ref inner) = block.fields[name] {
+                let child = self.block(inner, false);
+                self.edge(node, child, name);
+            }
+        }
+        let mut values: Vec<_> = block.values.iter().collect();
+        values.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, content) in values {
+            let child = match *content {
+                InputContent::Block(ref inner) => self.block(inner, false),
+                InputContent::Shadow(ref inner) => self.block(inner, true),
+            };
+            self.edge(node, child, name);
+        }
+        let mut statements: Vec<_> = block.statements.iter().collect();
+        statements.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, body) in statements {
+            self.stack(body, Some((node, name)));
+        }
+        node
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: &str) {
+        writeln!(self.out, "    n{} -> n{} [label=\"{}\"];", from, to, escape(label)).unwrap();
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_to_dot() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <field name="LABEL">say "hi"</field>
+                            <value name="BRIGHTNESS">
+                                <shadow type="math_number" id="num"><field name="NUM">80</field></shadow>
+                            </value>
+                            <next>
+                                <block type="led_off" id="off"></block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        assert_eq!(program.to_dot(), r#"digraph program {
+    node [shape=box];
+    n0 [label="main_loop"];
+    n1 [label="led_on\nLABEL = say \"hi\""];
+    n2 [label="math_number\nNUM = 80", style=dashed];
+    n1 -> n2 [label="BRIGHTNESS"];
+    n0 -> n1 [label="BODY"];
+    n3 [label="led_off"];
+    n1 -> n3 [label="next"];
+}
+"#);
+    }
+}
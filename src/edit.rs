@@ -0,0 +1,301 @@
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    InputContent,
+};
+use super::iter::child_blocks_mut;
+
+impl Program {
+    /// Remove the block with this id, along with everything nested inside it.
+    /// Blocks below it in a stack move up to take its place, and top-level
+    /// stacks left empty are removed.
+    pub fn remove_block_by_id(&mut self, id: &str) -> Option<Block> {
+        self.remove(id, false).and_then(|mut blocks| blocks.pop())
+    }
+
+    /// Rename the variable with this id, along with the variable fields that
+    /// refer to it. Returns false if there is no such variable.
+    pub fn rename_variable<N: Into<String>>(&mut self, id: &str, name: N) -> bool {
+        let name = name.into();
+        match self.variables.iter_mut().find(|variable| variable.id == id) {
+            Some(variable) => variable.name = name.clone(),
+            None => return false,
+        }
+        let mut pending: Vec<&mut Block> = self.groups.iter_mut()
+            .flat_map(|group| group.blocks.iter_mut())
+            .collect();
+        while let Some(block) = pending.pop() {
+            for (field, variable) in block.field_variables.iter() {
+                if variable.id == id {
+                    block.fields.insert(field.clone(), FieldValue::SimpleField(name.clone()));
+                }
+            }
+            pending.extend(child_blocks_mut(block));
+        }
+        true
+    }
+
+    /// Remove the block with this id together with the blocks below it in
+    /// its stack, as Blockly does when a block is dragged away.
+    pub(crate) fn detach_stack(&mut self, id: &str) -> Option<StatementBody> {
+        self.remove(id, true).map(|blocks| StatementBody {
+            blocks,
+            shadow: false,
+        })
+    }
+
+    fn remove(&mut self, id: &str, with_next: bool) -> Option<Vec<Block>> {
+        for index in 0..self.groups.len() {
+            if let Some(removed) = remove_from_stack(&mut self.groups[index], id, with_next) {
+                if self.groups[index].blocks.is_empty() {
+                    self.groups.remove(index);
+                }
+                return Some(removed);
+            }
+        }
+        None
+    }
+
+    /// The stack holding the block with this id, and the block's index in it.
+    pub(crate) fn find_stack_mut(&mut self, id: &str) -> Option<(&mut StatementBody, usize)> {
+        self.groups.iter_mut().find_map(|group| find_in_stack_mut(group, id))
+    }
+}
+
+impl StatementBody {
+    /// Insert a block into the stack at `index`, shifting the blocks below it down.
+    ///
+    /// Panics if `index` is greater than the number of blocks, like `Vec::insert`.
+    pub fn insert_block(&mut self, index: usize, block: Block) {
+        self.blocks.insert(index, block);
+    }
+
+    /// Remove and return the block at `index`; the blocks below it move up.
+    ///
+    /// Panics if `index` is out of bounds, like `Vec::remove`.
+    pub fn remove_block(&mut self, index: usize) -> Block {
+        self.blocks.remove(index)
+    }
+}
+
+impl Block {
+    /// Detach the stack in a statement input.
+    pub fn remove_statement(&mut self, name: &str) -> Option<StatementBody> {
+        self.statements.shift_remove(name)
+    }
+
+    /// Set a field, returning its previous value.
+    pub fn replace_field<N: Into<String>>(&mut self, name: N, value: FieldValue) -> Option<FieldValue> {
+        self.fields.insert(name.into(), value)
+    }
+}
+
+fn find_in_stack_mut<'a>(stack: &'a mut StatementBody, id: &str) -> Option<(&'a mut StatementBody, usize)> {
+    if let Some(index) = stack.blocks.iter().position(|block| block.id == id) {
+        return Some((stack, index));
+    }
+    stack.blocks.iter_mut()
+        .flat_map(statements_mut)
+        .find_map(|inner| find_in_stack_mut(inner, id))
+}
+
+// The block's statement inputs, and those of blocks in its fields and value
+// inputs at any depth
+fn statements_mut(block: &mut Block) -> Vec<&mut StatementBody> {
+    let mut stacks = Vec::new();
+    let mut pending = vec![block];
+    while let Some(inner) = pending.pop() {
+        for field in inner.fields.values_mut() {
+            if let FieldValue::ExpressionField(ref mut expression) = *field {
+                pending.push(&mut **expression);
+            }
+        }
+        for input in inner.values.values_mut() {
+            pending.push(input.block_mut());
+        }
+        stacks.extend(inner.statements.values_mut());
+    }
+    stacks
+}
+
+// The removed block, followed by the blocks below it when `with_next` is set
+fn remove_from_stack(stack: &mut StatementBody, id: &str, with_next: bool) -> Option<Vec<Block>> {
+    if let Some(index) = stack.blocks.iter().position(|block| block.id == id) {
+        if with_next {
+            return Some(stack.blocks.split_off(index));
+        }
+        return Some(vec![stack.blocks.remove(index)]);
+    }
+    for block in stack.blocks.iter_mut() {
+        if let Some(removed) = remove_from_block(block, id, with_next) {
+            return Some(removed);
+        }
+    }
+    None
+}
+
+fn remove_from_block(block: &mut Block, id: &str, with_next: bool) -> Option<Vec<Block>> {
+    let field_name = block.fields.iter()
+        .find(|&(_, value)| match value {
+            FieldValue::ExpressionField(expression) => expression.id == id,
+            FieldValue::SimpleField(_) => false,
+        })
+        .map(|(name, _)| name.clone());
+    if let Some(name) = field_name {
+        if let Some(FieldValue::ExpressionField(expression)) = block.fields.shift_remove(&name) {
+            return Some(vec![*expression]);
+        }
+    }
+
+    let value_name = block.values.iter()
+        .find(|&(_, input)| input.block().id == id)
+        .map(|(name, _)| name.clone());
+    if let Some(name) = value_name {
+        return block.values.shift_remove(&name).map(|input| match input {
+            InputContent::Block(removed) | InputContent::Shadow(removed) => vec![removed],
+        });
+    }
+
+    let mut emptied = None;
+    let mut found = None;
+    for (name, stack) in block.statements.iter_mut() {
+        if let Some(removed) = remove_from_stack(stack, id, with_next) {
+            if stack.blocks.is_empty() {
+                emptied = Some(name.clone());
+            }
+            found = Some(removed);
+            break;
+        }
+    }
+    if let Some(name) = emptied {
+        block.statements.shift_remove(&name);
+    }
+    if found.is_some() {
+        return found;
+    }
+
+    for field in block.fields.values_mut() {
+        if let FieldValue::ExpressionField(ref mut expression) = *field {
+            if let Some(removed) = remove_from_block(expression, id, with_next) {
+                return Some(removed);
+            }
+        }
+    }
+    for input in block.values.values_mut() {
+        if let Some(removed) = remove_from_block(input.block_mut(), id, with_next) {
+            return Some(removed);
+        }
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        program_from_xml,
+        program_to_xml,
+        BlockBuilder,
+        FieldVariable,
+    };
+
+    const XML: &str = r#"
+        <xml xmlns="https://developers.google.com/blockly/xml">
+            <block type="main_loop" id="main">
+                <statement name="BODY">
+                    <block type="led_on" id="on">
+                        <value name="BRIGHTNESS">
+                            <block type="math_number" id="num">
+                                <field name="NUM">80</field>
+                            </block>
+                        </value>
+                        <next>
+                            <block type="led_off" id="off"></block>
+                        </next>
+                    </block>
+                </statement>
+            </block>
+            <block type="buzzer" id="buzz"></block>
+        </xml>
+    "#;
+
+    #[test]
+    fn test_remove_block_by_id() {
+        let mut program = program_from_xml(XML).unwrap();
+
+        let removed = program.remove_block_by_id("num").unwrap();
+        assert_eq!(removed.block_type, "math_number");
+        assert!(program.find_block_by_id("on").unwrap().values.is_empty());
+
+        let removed = program.remove_block_by_id("on").unwrap();
+        assert_eq!(removed.block_type, "led_on");
+        let main_loop_block = &program.groups[0].blocks[0];
+        assert_eq!(main_loop_block.statements["BODY"].blocks[0].id, "off");
+
+        program.remove_block_by_id("off").unwrap();
+        assert!(program.groups[0].blocks[0].statements.is_empty());
+
+        program.remove_block_by_id("buzz").unwrap();
+        assert_eq!(program.groups.len(), 1);
+        assert!(program.remove_block_by_id("buzz").is_none());
+    }
+
+    #[test]
+    fn test_edit_blocks() {
+        let mut program = program_from_xml(XML).unwrap();
+        let main_loop_block = &mut program.groups[0].blocks[0];
+
+        let mut body = main_loop_block.remove_statement("BODY").unwrap();
+        assert!(main_loop_block.remove_statement("BODY").is_none());
+
+        body.insert_block(1, BlockBuilder::new("wait").id("wait").build());
+        let types: Vec<&str> = body.blocks.iter().map(|block| block.block_type.as_str()).collect();
+        assert_eq!(types, vec!["led_on", "wait", "led_off"]);
+        assert_eq!(body.remove_block(0).id, "on");
+
+        let wait_block = &mut body.blocks[0];
+        assert_eq!(wait_block.replace_field("TIME", FieldValue::SimpleField("100".to_string())), None);
+        assert_eq!(
+            wait_block.replace_field("TIME", FieldValue::SimpleField("200".to_string())),
+            Some(FieldValue::SimpleField("100".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_rename_variable() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables>
+                    <variable id="v1" type="Number">count</variable>
+                </variables>
+                <block type="variables_set" id="set">
+                    <field name="VAR" id="v1" variabletype="Number">count</field>
+                    <value name="VALUE">
+                        <block type="variables_get" id="get">
+                            <field name="VAR" id="v1" variabletype="Number">count</field>
+                        </block>
+                    </value>
+                </block>
+            </xml>
+        "#;
+        let mut program = program_from_xml(xml).unwrap();
+        let set_block = &program.groups[0].blocks[0];
+        assert_eq!(set_block.field_variables["VAR"], FieldVariable {
+            id: "v1".to_string(),
+            var_type: Some("Number".to_string()),
+        });
+
+        assert!(program.rename_variable("v1", "total"));
+        assert!(!program.rename_variable("v2", "other"));
+        assert_eq!(program.variables[0].name, "total");
+        let set_block = &program.groups[0].blocks[0];
+        assert_eq!(set_block.field_str("VAR"), Ok("total"));
+        assert_eq!(set_block.values["VALUE"].block().field_str("VAR"), Ok("total"));
+
+        let written = program_to_xml(&program);
+        assert!(written.contains(r#"<field name="VAR" id="v1" variabletype="Number">total</field>"#));
+    }
+}
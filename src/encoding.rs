@@ -0,0 +1,131 @@
+use base64::Engine;
+use base64::engine::general_purpose::{
+    STANDARD_PAD_INDIFFERENT,
+    URL_SAFE_PAD_INDIFFERENT,
+};
+
+use super::{
+    Program,
+    DecodeError,
+    program_from_json,
+    program_from_xml,
+};
+
+/// Decode a base64 workspace, in the standard or URL-safe alphabet with or
+/// without padding, and parse it. JSON is recognized by its opening `{`;
+/// anything else is parsed as XML.
+pub fn program_from_base64(encoded: &str) -> Result<Program, DecodeError> {
+    let bytes = decode_base64(encoded)?;
+    program_from_bytes(bytes)
+}
+
+/// Parse a `data:` URL holding a workspace, e.g.
+/// `data:text/xml;base64,PHhtbD48L3htbD4=` or `data:,%3Cxml%3E%3C%2Fxml%3E`.
+/// Media types containing `json` are parsed as JSON, others as XML.
+pub fn program_from_data_url(url: &str) -> Result<Program, DecodeError> {
+    let rest = url.trim()
+        .strip_prefix("data:")
+        .ok_or_else(|| DecodeError::InvalidDataUrl("missing `data:` scheme".to_string()))?;
+    let comma = rest.find(',')
+        .ok_or_else(|| DecodeError::InvalidDataUrl("missing `,` before the data".to_string()))?;
+    let (header, payload) = (&rest[..comma], &rest[comma + 1..]);
+
+    let mut parameters = header.split(';');
+    let media_type = parameters.next().unwrap_or("").to_ascii_lowercase();
+    let is_base64 = parameters.any(|parameter| parameter.eq_ignore_ascii_case("base64"));
+
+    let payload = percent_decode(payload)?;
+    let bytes = if is_base64 {
+        decode_base64(&String::from_utf8(payload).map_err(|_| DecodeError::Utf8)?)?
+    } else {
+        payload
+    };
+    let text = String::from_utf8(bytes).map_err(|_| DecodeError::Utf8)?;
+    let program = if media_type.contains("json") {
+        program_from_json(&text)?
+    } else {
+        program_from_xml(&text)?
+    };
+    Ok(program)
+}
+
+fn decode_base64(encoded: &str) -> Result<Vec<u8>, DecodeError> {
+    let encoded: String = encoded.chars().filter(|ch| !ch.is_whitespace()).collect();
+    let engine = if encoded.contains('-') || encoded.contains('_') {
+        URL_SAFE_PAD_INDIFFERENT
+    } else {
+        STANDARD_PAD_INDIFFERENT
+    };
+    engine.decode(encoded).map_err(|error| DecodeError::Base64(error.to_string()))
+}
+
+fn percent_decode(text: &str) -> Result<Vec<u8>, DecodeError> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = text.get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| DecodeError::InvalidDataUrl(format!("invalid percent escape at byte {}", index)))?;
+            decoded.push(hex);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    Ok(decoded)
+}
+
+fn program_from_bytes(bytes: Vec<u8>) -> Result<Program, DecodeError> {
+    let text = String::from_utf8(bytes).map_err(|_| DecodeError::Utf8)?;
+    let program = if text.trim_start().starts_with('{') {
+        program_from_json(&text)?
+    } else {
+        program_from_xml(&text)?
+    };
+    Ok(program)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::ParseError;
+
+    #[test]
+    fn test_program_from_base64() {
+        let xml = r#"<xml><block type="led_on" id="a"></block></xml>"#;
+        let encoded = STANDARD_PAD_INDIFFERENT.encode(xml);
+        assert_eq!(program_from_base64(&encoded).unwrap().groups[0].blocks[0].id, "a");
+
+        let json = r#"{"blocks": {"blocks": [{"type": "led_on", "id": "b"}]}}"#;
+        let encoded = URL_SAFE_PAD_INDIFFERENT.encode(json);
+        assert_eq!(program_from_base64(encoded.trim_end_matches('=')).unwrap().groups[0].blocks[0].id, "b");
+
+        match program_from_base64("not base64!") {
+            Err(DecodeError::Base64(_)) => {},
+            other => panic!("expected a base64 error, got {:?}", other),
+        }
+        let encoded = STANDARD_PAD_INDIFFERENT.encode("<workspace/>");
+        assert_eq!(program_from_base64(&encoded).unwrap_err(), DecodeError::Parse(ParseError::MissingRootElement));
+    }
+
+    #[test]
+    fn test_program_from_data_url() {
+        let program = program_from_data_url("data:text/xml;base64,PHhtbD48YmxvY2sgdHlwZT0ibGVkX29uIi8+PC94bWw+").unwrap();
+        assert_eq!(program.groups[0].blocks[0].block_type, "led_on");
+
+        let program = program_from_data_url("data:,%3Cxml%3E%3Cblock%20type%3D%22led_off%22%2F%3E%3C%2Fxml%3E").unwrap();
+        assert_eq!(program.groups[0].blocks[0].block_type, "led_off");
+
+        let program = program_from_data_url(r#"data:application/json,{"blocks":{"blocks":[{"type":"wait"}]}}"#).unwrap();
+        assert_eq!(program.groups[0].blocks[0].block_type, "wait");
+
+        match program_from_data_url("https://example.com/workspace.xml") {
+            Err(DecodeError::InvalidDataUrl(_)) => {},
+            other => panic!("expected an invalid URL error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use super::Span;
+use super::interpreter::Limit;
+
+#[derive(PartialEq, Debug)]
+pub enum ParseError {
+    /// The input is not well-formed XML. `position` is a byte offset into the input.
+    Xml { position: usize, message: String },
+    /// The input is not valid JSON.
+    Json { line: usize, column: usize, message: String },
+    /// The input could not be read. Invalid UTF-8 is reported with `io::ErrorKind::InvalidData`.
+    Io { kind: io::ErrorKind, message: String },
+    /// Elements are nested deeper than `ParserOptions::max_depth` allows.
+    TooDeep { max_depth: usize },
+    /// The document has no root `<xml>` element.
+    MissingRootElement,
+    /// An element is missing an attribute the parser requires, e.g. `type` on a `<block>`.
+    MissingAttribute { element: String, attribute: String, span: Option<Span> },
+    /// An attribute holds a value the parser cannot interpret, e.g. a non-numeric `x`.
+    InvalidAttribute { element: String, attribute: String, value: String, span: Option<Span> },
+    /// An element appears somewhere the parser does not expect it. Only
+    /// reported by strict parsing, or where skipping it would lose data.
+    UnexpectedElement { parent: String, element: String, span: Option<Span> },
+    /// The document is well-formed XML but not shaped like a Blockly workspace.
+    UnexpectedStructure(String),
+    /// A field's text is not what `ParserOptions` requires, e.g. a malformed
+    /// colour in one of `colour_fields`.
+    InvalidField { field: String, value: String, expected: &'static str, span: Option<Span> },
+}
+
+impl ParseError {
+    /// Where in the input the error is. Spans of elements are only known
+    /// when parsing with `program_from_xml_with_diagnostics`; XML syntax
+    /// errors give an empty span at their position.
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            ParseError::Xml { position, .. } => Some(Span { start: position, end: position }),
+            ParseError::MissingAttribute { span, .. } |
+            ParseError::InvalidAttribute { span, .. } |
+            ParseError::UnexpectedElement { span, .. } |
+            ParseError::InvalidField { span, .. } => span,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Xml { position, ref message } => {
+                write!(f, "invalid XML at byte {}: {}", position, message)
+            },
+            ParseError::Json { line, column, ref message } => {
+                write!(f, "invalid JSON at line {} column {}: {}", line, column, message)
+            },
+            ParseError::Io { ref message, .. } => {
+                write!(f, "could not read input: {}", message)
+            },
+            ParseError::TooDeep { max_depth } => {
+                write!(f, "elements are nested more than {} levels deep", max_depth)
+            },
+            ParseError::MissingRootElement => {
+                write!(f, "missing root <xml> element")
+            },
+            ParseError::MissingAttribute { ref element, ref attribute, .. } => {
+                write!(f, "<{}> element is missing the `{}` attribute", element, attribute)
+            },
+            ParseError::InvalidAttribute { ref element, ref attribute, ref value, .. } => {
+                write!(f, "<{}> element has an invalid `{}` attribute: {:?}", element, attribute, value)
+            },
+            ParseError::UnexpectedElement { ref parent, ref element, .. } => {
+                write!(f, "unexpected <{}> element inside <{}>", element, parent)
+            },
+            ParseError::UnexpectedStructure(ref message) => {
+                write!(f, "unexpected structure: {}", message)
+            },
+            ParseError::InvalidField { ref field, ref value, expected, .. } => {
+                write!(f, "field `{}` has value {:?}, expected {}", field, value, expected)
+            },
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(error: io::Error) -> Self {
+        ParseError::Io {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum FieldError {
+    /// The block has no field with this name.
+    Missing(String),
+    /// The field holds a nested block rather than a plain value.
+    NotSimple(String),
+    /// The field's text could not be converted to the requested type.
+    Invalid { field: String, value: String, expected: &'static str },
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FieldError::Missing(ref field) => {
+                write!(f, "missing field `{}`", field)
+            },
+            FieldError::NotSimple(ref field) => {
+                write!(f, "field `{}` holds a block, not a value", field)
+            },
+            FieldError::Invalid { ref field, ref value, expected } => {
+                write!(f, "field `{}` has value {:?}, expected {}", field, value, expected)
+            },
+        }
+    }
+}
+
+impl Error for FieldError {}
+
+#[derive(PartialEq, Debug)]
+pub enum FromBlockError {
+    WrongBlockType { expected: String, found: String },
+    Field(FieldError),
+}
+
+impl fmt::Display for FromBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FromBlockError::WrongBlockType { ref expected, ref found } => {
+                write!(f, "expected a `{}` block, found `{}`", expected, found)
+            },
+            FromBlockError::Field(ref error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for FromBlockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            FromBlockError::WrongBlockType { .. } => None,
+            FromBlockError::Field(ref error) => Some(error),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum CodegenError {
+    /// The generator has no code for this block type.
+    UnsupportedBlock { block_type: String, id: String },
+    /// A field the generator needs is missing or malformed.
+    Field(FieldError),
+    /// Any other problem reported by a generator.
+    Custom(String),
+}
+
+impl From<FieldError> for CodegenError {
+    fn from(error: FieldError) -> Self {
+        CodegenError::Field(error)
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodegenError::UnsupportedBlock { ref block_type, ref id } => {
+                write!(f, "no code generator for `{}` block (id {:?})", block_type, id)
+            },
+            CodegenError::Field(ref error) => error.fmt(f),
+            CodegenError::Custom(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for CodegenError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            CodegenError::Field(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Returned by the interpreter's standard blocks when a block cannot run.
+#[derive(PartialEq, Debug)]
+pub enum EvalError {
+    /// Neither the standard blocks nor the embedder handle this block type.
+    UnsupportedBlock { block_type: String, id: String },
+    /// A field the block needs is missing or malformed.
+    Field(FieldError),
+    /// Any other problem reported by an embedder's blocks.
+    Custom(String),
+}
+
+impl From<FieldError> for EvalError {
+    fn from(error: FieldError) -> Self {
+        EvalError::Field(error)
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvalError::UnsupportedBlock { ref block_type, ref id } => {
+                write!(f, "cannot run `{}` block (id {:?})", block_type, id)
+            },
+            EvalError::Field(ref error) => error.fmt(f),
+            EvalError::Custom(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for EvalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            EvalError::Field(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Returned by `Runner::try_run` when a run goes over its `Limits`.
+#[derive(PartialEq, Eq, Debug)]
+pub enum RunError {
+    LimitExceeded(Limit),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunError::LimitExceeded(Limit::Steps(steps)) => write!(f, "ran more than {} blocks", steps),
+            RunError::LimitExceeded(Limit::Iterations(passes)) => write!(f, "a loop ran more than {} times", passes),
+            RunError::LimitExceeded(Limit::Timeout(timeout)) => write!(f, "ran for longer than {:?}", timeout),
+        }
+    }
+}
+
+impl Error for RunError {}
+
+/// Returned by `Runner::restore` when a snapshot does not fit the program.
+#[derive(PartialEq, Eq, Debug)]
+pub struct SnapshotError {
+    /// The index of the first frame that does not fit.
+    pub frame: usize,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "snapshot frame {} does not fit the program", self.frame)
+    }
+}
+
+impl Error for SnapshotError {}
+
+/// Returned by `Program::check_unique_ids` when blocks share an id.
+#[derive(PartialEq, Debug)]
+pub struct DuplicateIdError {
+    /// Each id used by more than one block, in the order first repeated.
+    pub ids: Vec<String>,
+}
+
+impl fmt::Display for DuplicateIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "duplicate block ids: {:?}", self.ids)
+    }
+}
+
+impl Error for DuplicateIdError {}
+
+/// Returned by `Program::merge` when the blocks of a version cannot be
+/// matched by id.
+#[derive(PartialEq, Debug)]
+pub enum MergeError {
+    /// A block has no id.
+    MissingId { block_type: String },
+    DuplicateIds(DuplicateIdError),
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MergeError::MissingId { ref block_type } => write!(f, "a `{}` block has no id", block_type),
+            MergeError::DuplicateIds(ref error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for MergeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            MergeError::MissingId { .. } => None,
+            MergeError::DuplicateIds(ref error) => Some(error),
+        }
+    }
+}
+
+/// Returned when an `IdGenerator` keeps returning ids that are already used.
+#[derive(PartialEq, Debug)]
+pub struct RepeatedIdError {
+    /// The used id it returned last.
+    pub id: String,
+}
+
+impl fmt::Display for RepeatedIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "id generator keeps returning ids that are already used, such as {:?}", self.id)
+    }
+}
+
+impl Error for RepeatedIdError {}
+
+/// Returned by `Program::apply_event` when an event does not fit the program.
+#[derive(PartialEq, Debug)]
+pub enum EventError {
+    /// No block has the id the event refers to.
+    UnknownBlock(String),
+    UnknownVariable(String),
+    /// A stack of several blocks was moved into a value input, which holds
+    /// one block.
+    StackInValueInput { block: String, input: String },
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EventError::UnknownBlock(ref id) => write!(f, "no block with id {:?}", id),
+            EventError::UnknownVariable(ref id) => write!(f, "no variable with id {:?}", id),
+            EventError::StackInValueInput { ref block, ref input } => {
+                write!(f, "cannot move a stack of blocks into value input {:?} of block {:?}", input, block)
+            },
+        }
+    }
+}
+
+impl Error for EventError {}
+
+/// Returned by `Program::patch_block_xml`.
+#[derive(PartialEq, Debug)]
+pub enum PatchError {
+    Parse(ParseError),
+    /// No block has the id being patched.
+    UnknownBlock(String),
+}
+
+impl From<ParseError> for PatchError {
+    fn from(error: ParseError) -> Self {
+        PatchError::Parse(error)
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::Parse(ref error) => error.fmt(f),
+            PatchError::UnknownBlock(ref id) => write!(f, "no block with id {:?}", id),
+        }
+    }
+}
+
+impl Error for PatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PatchError::Parse(ref error) => Some(error),
+            PatchError::UnknownBlock(_) => None,
+        }
+    }
+}
+
+/// Returned by the `encoding` feature's decoding helpers. Failures to decode
+/// the text are kept apart from failures to parse the decoded workspace.
+#[cfg(feature = "encoding")]
+#[derive(PartialEq, Debug)]
+pub enum DecodeError {
+    /// The text is not valid base64.
+    Base64(String),
+    /// The URL is not a well-formed `data:` URL.
+    InvalidDataUrl(String),
+    /// The decoded bytes are not UTF-8.
+    Utf8,
+    Parse(ParseError),
+}
+
+#[cfg(feature = "encoding")]
+impl From<ParseError> for DecodeError {
+    fn from(error: ParseError) -> Self {
+        DecodeError::Parse(error)
+    }
+}
+
+#[cfg(feature = "encoding")]
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Base64(ref message) => write!(f, "invalid base64: {}", message),
+            DecodeError::InvalidDataUrl(ref message) => write!(f, "invalid data URL: {}", message),
+            DecodeError::Utf8 => write!(f, "decoded workspace is not valid UTF-8"),
+            DecodeError::Parse(ref error) => error.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "encoding")]
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DecodeError::Parse(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use serde_json::Value;
+
+use super::{
+    BlockRegistry,
+    Diagnostics,
+    Program,
+    StatementBody,
+    BlockComment,
+    FieldValue,
+    InputContent,
+    Mutation,
+    Variable,
+    FieldVariable,
+    ParseError,
+    EventError,
+    program_from_xml,
+};
+use super::migrations::is_variable_field;
+use super::xml::round_coordinate;
+use super::json::{
+    as_array,
+    as_object,
+    get_string,
+    is_statement_input_name,
+    mutation_from_json,
+    parse_json,
+    require_string,
+    scalar_to_string,
+    statement_body_from_json,
+};
+
+/// A workspace change reported by Blockly's event system, as serialized by
+/// `event.toJson()`. Blocks are referred to by id.
+#[derive(PartialEq, Debug)]
+pub enum Event {
+    /// A new top-level block, with the blocks nested inside and below it.
+    Create { block_id: String, blocks: StatementBody },
+    /// `ids` lists the deleted block and everything deleted with it.
+    Delete { block_id: String, ids: Vec<String> },
+    Change { block_id: String, change: BlockChange },
+    /// The block and the blocks below it in its stack were moved.
+    Move { block_id: String, to: MoveTarget },
+    VarCreate { id: String, name: String, var_type: Option<String> },
+    VarDelete { id: String },
+    VarRename { id: String, name: String },
+    /// UI events, and changes to state the parser does not model.
+    Other { event_type: String },
+}
+
+#[derive(PartialEq, Debug)]
+pub enum BlockChange {
+    Field { name: String, value: String },
+    /// The comment text, or `None` when the comment was removed.
+    Comment(Option<String>),
+    Collapsed(bool),
+    Disabled(bool),
+    Mutation(Option<Mutation>),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum MoveTarget {
+    TopLevel { position: Option<(i32, i32)> },
+    /// Below another block in a stack.
+    Next { parent: String },
+    Input { parent: String, input: String },
+}
+
+/// Parse events serialized with `event.toJson()`, either a single event
+/// object or an array of them.
+///
+/// Created blocks are read from the event's `xml`, or from its `json` when
+/// there is no XML. Variable fields in `json` name their variable by id,
+/// since the workspace's variables are not part of the event.
+pub fn events_from_json(json: &str) -> Result<Vec<Event>, ParseError> {
+    let root = parse_json(json)?;
+    match *root {
+        Value::Array(_) => as_array(&root, "events")?.iter().map(event_from_json).collect(),
+        _ => Ok(vec![event_from_json(&root)?]),
+    }
+}
+
+fn event_from_json(value: &Value) -> Result<Event, ParseError> {
+    let object = as_object(value, "event")?;
+    let event_type = require_string(object, "event", "type")?;
+    let event = match event_type.as_str() {
+        "create" => {
+            let blocks = match (get_string(object, "xml"), object.get("json")) {
+                (Some(xml), _) => {
+                    let program = program_from_xml(&format!("<xml>{}</xml>", xml))?;
+                    program.groups.into_iter().next().ok_or_else(|| {
+                        ParseError::UnexpectedStructure("create event without a block".to_string())
+                    })?
+                },
+                (None, Some(json)) => statement_body_from_json(json, &[], &BlockRegistry::new(), &mut Diagnostics::new())?,
+                (None, None) => return Err(missing("create", "xml")),
+            };
+            Event::Create {
+                block_id: require_string(object, "create event", "blockId")?,
+                blocks,
+            }
+        },
+        "delete" => {
+            let block_id = require_string(object, "delete event", "blockId")?;
+            let ids = match object.get("ids") {
+                Some(ids) => as_array(ids, "ids")?.iter().map(scalar_to_string).collect(),
+                None => vec![block_id.clone()],
+            };
+            Event::Delete { block_id, ids }
+        },
+        "change" => {
+            let new_value = object.get("newValue").unwrap_or(&Value::Null);
+            let change = match require_string(object, "change event", "element")?.as_str() {
+                "field" => BlockChange::Field {
+                    name: require_string(object, "change event", "name")?,
+                    value: scalar_to_string(new_value),
+                },
+                "comment" => BlockChange::Comment(match *new_value {
+                    Value::Null => None,
+                    ref text => Some(scalar_to_string(text)),
+                }),
+                "collapsed" => BlockChange::Collapsed(new_value.as_bool().unwrap_or(false)),
+                "disabled" => BlockChange::Disabled(new_value.as_bool().unwrap_or(false)),
+                "mutation" => BlockChange::Mutation(change_mutation(new_value)?),
+                _ => return Ok(Event::Other { event_type }),
+            };
+            Event::Change {
+                block_id: require_string(object, "change event", "blockId")?,
+                change,
+            }
+        },
+        "move" => {
+            let to = match (get_string(object, "newParentId"), get_string(object, "newInputName")) {
+                (Some(parent), Some(input)) => MoveTarget::Input { parent, input },
+                (Some(parent), None) => MoveTarget::Next { parent },
+                (None, _) => MoveTarget::TopLevel {
+                    position: match get_string(object, "newCoordinate") {
+                        Some(coordinate) => Some(parse_coordinate(&coordinate)?),
+                        None => None,
+                    },
+                },
+            };
+            Event::Move {
+                block_id: require_string(object, "move event", "blockId")?,
+                to,
+            }
+        },
+        "var_create" => Event::VarCreate {
+            id: require_string(object, "var_create event", "varId")?,
+            name: require_string(object, "var_create event", "varName")?,
+            var_type: get_string(object, "varType").filter(|var_type| !var_type.is_empty()),
+        },
+        "var_delete" => Event::VarDelete {
+            id: require_string(object, "var_delete event", "varId")?,
+        },
+        "var_rename" => Event::VarRename {
+            id: require_string(object, "var_rename event", "varId")?,
+            name: require_string(object, "var_rename event", "newName")?,
+        },
+        _ => Event::Other { event_type },
+    };
+    Ok(event)
+}
+
+// Mutations are sent as `mutationToDom` XML, or as JSON text for blocks that
+// save their extra state as JSON
+fn change_mutation(value: &Value) -> Result<Option<Mutation>, ParseError> {
+    let text = match *value {
+        Value::String(ref text) if !text.trim().is_empty() => text.trim(),
+        _ => return Ok(None),
+    };
+    if text.starts_with('<') {
+        mutation_from_json(value).map(Some)
+    } else {
+        mutation_from_json(&*parse_json(text)?).map(Some)
+    }
+}
+
+// Blockly writes coordinates as "x,y"
+fn parse_coordinate(text: &str) -> Result<(i32, i32), ParseError> {
+    let invalid = || ParseError::UnexpectedStructure(format!("invalid coordinate: {:?}", text));
+    let mut parts = text.split(',').map(|part| part.trim().parse::<f64>().ok().and_then(round_coordinate));
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Some(x)), Some(Some(y)), None) => Ok((x, y)),
+        _ => Err(invalid()),
+    }
+}
+
+fn missing(event_type: &str, key: &str) -> ParseError {
+    ParseError::MissingAttribute {
+        element: format!("{} event", event_type),
+        attribute: key.to_string(),
+        span: None,
+    }
+}
+
+impl Program {
+    /// Apply events in order, stopping at the first that does not fit.
+    pub fn apply_events<I: IntoIterator<Item = Event>>(&mut self, events: I) -> Result<(), EventError> {
+        for event in events {
+            self.apply_event(event)?;
+        }
+        Ok(())
+    }
+
+    /// Update the program the way Blockly updated the workspace the event
+    /// came from. Renaming a variable does not change the text of the
+    /// variable fields that use it.
+    pub fn apply_event(&mut self, event: Event) -> Result<(), EventError> {
+        match event {
+            Event::Create { blocks, .. } => {
+                self.groups.push(blocks);
+            },
+            Event::Delete { block_id, ids } => {
+                self.remove_block_by_id(&block_id).ok_or(EventError::UnknownBlock(block_id))?;
+                for id in ids {
+                    self.remove_block_by_id(&id);
+                }
+            },
+            Event::Change { block_id, change } => {
+                // Looked up before the block borrows the program
+                let variable = match change {
+                    BlockChange::Field { ref value, .. } => self.variables.iter().find(|variable| variable.id == *value).cloned(),
+                    _ => None,
+                };
+                let block = match self.find_block_by_id_mut(&block_id) {
+                    Some(block) => block,
+                    None => return Err(EventError::UnknownBlock(block_id)),
+                };
+                match change {
+                    // Variable fields report the id of the new variable
+                    BlockChange::Field { name, value } => if is_variable_field(block, &name) {
+                        let variable = variable.ok_or(EventError::UnknownVariable(value))?;
+                        block.fields.insert(name.clone(), FieldValue::SimpleField(variable.name));
+                        block.field_variables.insert(name, FieldVariable {
+                            id: variable.id,
+                            var_type: variable.var_type,
+                        });
+                    } else {
+                        block.fields.insert(name, FieldValue::SimpleField(value));
+                    },
+                    BlockChange::Comment(None) => block.comment = None,
+                    BlockChange::Comment(Some(text)) => match block.comment {
+                        Some(ref mut comment) => comment.text = text,
+                        None => {
+                            block.comment = Some(BlockComment {
+                                text,
+                                pinned: false,
+                                width: None,
+                                height: None,
+                            });
+                        }
+                    },
+                    BlockChange::Collapsed(collapsed) => block.flags.collapsed = collapsed,
+                    BlockChange::Disabled(disabled) => block.flags.disabled = disabled,
+                    BlockChange::Mutation(mutation) => block.mutation = mutation,
+                }
+            },
+            Event::Move { block_id, to } => {
+                if let MoveTarget::Next { ref parent } | MoveTarget::Input { ref parent, .. } = to {
+                    if self.find_block_by_id(parent).is_none() {
+                        return Err(EventError::UnknownBlock(parent.clone()));
+                    }
+                }
+                let stack = self.detach_stack(&block_id).ok_or(EventError::UnknownBlock(block_id))?;
+                // The stack goes back on the workspace if it does not fit
+                if let Err((stack, error)) = self.attach_stack(stack, &to) {
+                    self.groups.push(stack);
+                    return Err(error);
+                }
+            },
+            Event::VarCreate { id, name, var_type } => {
+                self.variables.push(Variable {
+                    name,
+                    id,
+                    var_type,
+                });
+            },
+            Event::VarDelete { id } => {
+                match self.variables.iter().position(|variable| variable.id == id) {
+                    Some(index) => { self.variables.remove(index); },
+                    None => return Err(EventError::UnknownVariable(id)),
+                }
+            },
+            Event::VarRename { id, name } => {
+                if !self.rename_variable(&id, name) {
+                    return Err(EventError::UnknownVariable(id));
+                }
+            },
+            Event::Other { .. } => {},
+        }
+        Ok(())
+    }
+
+    // Gives the stack back if its new parent is not in the program, which
+    // happens for events that attach a stack inside itself, or if the stack
+    // does not fit the input
+    fn attach_stack(&mut self, mut stack: StatementBody, to: &MoveTarget) -> Result<(), (StatementBody, EventError)> {
+        stack.blocks[0].position = None;
+        match *to {
+            MoveTarget::TopLevel { position } => {
+                stack.blocks[0].position = position;
+                self.groups.push(stack);
+            },
+            MoveTarget::Next { ref parent } => {
+                let (body, index) = match self.find_stack_mut(parent) {
+                    Some(found) => found,
+                    None => return Err((stack, EventError::UnknownBlock(parent.clone()))),
+                };
+                let below = body.blocks.split_off(index + 1);
+                body.blocks.extend(stack.blocks);
+                body.blocks.extend(below);
+            },
+            MoveTarget::Input { ref parent, ref input } => {
+                let block = match self.find_block_by_id_mut(parent) {
+                    Some(block) => block,
+                    None => return Err((stack, EventError::UnknownBlock(parent.clone()))),
+                };
+                // Events do not say what kind of input it is
+                let is_statement = block.statements.contains_key(input)
+                    || (!block.values.contains_key(input) && (stack.blocks.len() > 1 || is_statement_input_name(input)));
+                if is_statement {
+                    let body = block.statements.entry(input.clone()).or_insert_with(|| StatementBody {
+                        blocks: Vec::new(),
+                        shadow: false,
+                    });
+                    if body.shadow {
+                        body.blocks.clear();
+                        body.shadow = false;
+                    }
+                    let below = std::mem::replace(&mut body.blocks, stack.blocks);
+                    body.blocks.extend(below);
+                } else if stack.blocks.len() > 1 {
+                    return Err((stack, EventError::StackInValueInput {
+                        block: parent.clone(),
+                        input: input.clone(),
+                    }));
+                } else {
+                    let first = stack.blocks.remove(0);
+                    // A block already in the input is bumped onto the workspace
+                    if let Some(InputContent::Block(displaced)) = block.values.insert(input.clone(), InputContent::Block(first)) {
+                        self.groups.push(StatementBody {
+                            blocks: vec![displaced],
+                            shadow: false,
+                        });
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ids(program: &Program) -> Vec<&str> {
+        program.iter_blocks().map(|block| block.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_events_from_json() {
+        let events = events_from_json(r#"[
+            { "type": "create", "blockId": "a", "xml": "<block xmlns=\"https://developers.google.com/blockly/xml\" type=\"led_on\" id=\"a\" x=\"10\" y=\"20\"><field name=\"TIME\">300</field></block>", "ids": ["a"] },
+            { "type": "change", "blockId": "a", "element": "field", "name": "TIME", "oldValue": "300", "newValue": 500 },
+            { "type": "change", "blockId": "a", "element": "mutation", "newValue": "<mutation items=\"2\"></mutation>" },
+            { "type": "move", "blockId": "a", "newCoordinate": "15.4,25" },
+            { "type": "move", "blockId": "a", "newParentId": "b", "newInputName": "DO" },
+            { "type": "selected", "newElementId": "a" }
+        ]"#).unwrap();
+
+        assert_eq!(events.len(), 6);
+        match events[0] {
+            Event::Create { ref block_id, ref blocks } => {
+                assert_eq!(block_id, "a");
+                assert_eq!(blocks.blocks[0].position, Some((10, 20)));
+            },
+            ref other => panic!("expected a create event, got {:?}", other),
+        }
+        assert_eq!(events[1], Event::Change {
+            block_id: "a".to_string(),
+            change: BlockChange::Field { name: "TIME".to_string(), value: "500".to_string() },
+        });
+        match events[2] {
+            Event::Change { change: BlockChange::Mutation(Some(ref mutation)), .. } => assert_eq!(mutation.attribute("items"), Some("2")),
+            ref other => panic!("expected a mutation change, got {:?}", other),
+        }
+        assert_eq!(events[3], Event::Move { block_id: "a".to_string(), to: MoveTarget::TopLevel { position: Some((15, 25)) } });
+        assert_eq!(events[4], Event::Move {
+            block_id: "a".to_string(),
+            to: MoveTarget::Input { parent: "b".to_string(), input: "DO".to_string() },
+        });
+        assert_eq!(events[5], Event::Other { event_type: "selected".to_string() });
+
+        assert_eq!(events_from_json(r#"{ "type": "move", "blockId": "a", "newCoordinate": "x" }"#).unwrap_err(),
+            ParseError::UnexpectedStructure("invalid coordinate: \"x\"".to_string()));
+        assert_eq!(events_from_json(r#"{ "type": "move", "blockId": "a", "newCoordinate": "NaN,1e400" }"#).unwrap_err(),
+            ParseError::UnexpectedStructure("invalid coordinate: \"NaN,1e400\"".to_string()));
+    }
+
+    #[test]
+    fn test_apply_events() {
+        let mut program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="main" x="0" y="0">
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <next><block type="led_off" id="off"></block></next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let events = events_from_json(r#"[
+            { "type": "create", "blockId": "wait", "xml": "<block type=\"wait\" id=\"wait\" x=\"50\" y=\"50\"></block>" },
+            { "type": "move", "blockId": "wait", "newParentId": "on" },
+            { "type": "change", "blockId": "on", "element": "disabled", "newValue": true },
+            { "type": "change", "blockId": "off", "element": "comment", "newValue": "lights out" },
+            { "type": "move", "blockId": "off", "newCoordinate": "100,100" },
+            { "type": "delete", "blockId": "on", "ids": ["on"] },
+            { "type": "var_create", "varId": "v1", "varName": "count", "varType": "" },
+            { "type": "var_rename", "varId": "v1", "newName": "total" }
+        ]"#).unwrap();
+        program.apply_events(events).unwrap();
+
+        assert_eq!(ids(&program), vec!["main", "wait", "off"]);
+        assert_eq!(program.groups.len(), 2);
+        assert_eq!(program.groups[1].blocks[0].position, Some((100, 100)));
+        assert_eq!(program.groups[1].blocks[0].comment.as_ref().unwrap().text, "lights out");
+        assert_eq!(program.variables[0].name, "total");
+
+        let unknown = events_from_json(r#"{ "type": "move", "blockId": "wait", "newParentId": "gone", "newInputName": "BODY" }"#).unwrap();
+        assert_eq!(program.apply_events(unknown), Err(EventError::UnknownBlock("gone".to_string())));
+        assert_eq!(ids(&program), vec!["main", "wait", "off"]);
+    }
+
+    #[test]
+    fn test_apply_variable_field_change() {
+        let mut program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables>
+                    <variable id="v1">count</variable>
+                    <variable id="v2" type="Number">total</variable>
+                </variables>
+                <block type="variables_set" id="set">
+                    <field name="VAR" id="v1">count</field>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let events = events_from_json(r#"{ "type": "change", "blockId": "set", "element": "field", "name": "VAR", "oldValue": "v1", "newValue": "v2" }"#).unwrap();
+        program.apply_events(events).unwrap();
+        let block = &program.groups[0].blocks[0];
+        assert_eq!(block.fields["VAR"], FieldValue::SimpleField("total".to_string()));
+        assert_eq!(block.field_variables["VAR"], FieldVariable { id: "v2".to_string(), var_type: Some("Number".to_string()) });
+
+        let unknown = events_from_json(r#"{ "type": "change", "blockId": "set", "element": "field", "name": "VAR", "newValue": "v3" }"#).unwrap();
+        assert_eq!(program.apply_events(unknown), Err(EventError::UnknownVariable("v3".to_string())));
+        assert_eq!(program.groups[0].blocks[0].field_variables["VAR"].id, "v2");
+    }
+
+    #[test]
+    fn test_apply_move_into_value_input() {
+        let mut program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="controls_if" id="if" x="0" y="0"></block>
+                <block type="logic_boolean" id="true" x="50" y="0"></block>
+                <block type="led_on" id="on" x="100" y="0">
+                    <next><block type="led_off" id="off"></block></next>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let events = events_from_json(r#"{ "type": "move", "blockId": "true", "newParentId": "if", "newInputName": "IF0" }"#).unwrap();
+        program.apply_events(events).unwrap();
+        assert_eq!(program.groups.len(), 2);
+        match program.groups[0].blocks[0].values["IF0"] {
+            InputContent::Block(ref block) => assert_eq!(block.id, "true"),
+            ref other => panic!("expected a block, got {:?}", other),
+        }
+
+        let stack = events_from_json(r#"{ "type": "move", "blockId": "on", "newParentId": "if", "newInputName": "IF0" }"#).unwrap();
+        assert_eq!(program.apply_events(stack), Err(EventError::StackInValueInput {
+            block: "if".to_string(),
+            input: "IF0".to_string(),
+        }));
+        assert_eq!(ids(&program), vec!["if", "true", "on", "off"]);
+    }
+}
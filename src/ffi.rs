@@ -0,0 +1,216 @@
+//! A C interface, declared in `include/blockly_parser.h`. Build a library
+//! to link against with
+//! `cargo rustc --lib --release --features ffi --crate-type staticlib`
+//! (or `cdylib`).
+//!
+//! Programs and strings returned by these functions are owned by the caller
+//! and must be released with `blockly_program_free` and `blockly_string_free`.
+
+use std::any::Any;
+use std::ffi::{
+    CStr,
+    CString,
+};
+use std::os::raw::c_char;
+use std::panic::{
+    self,
+    AssertUnwindSafe,
+};
+use std::ptr;
+
+use super::{
+    Program,
+    Block,
+    FieldValue,
+    ParseError,
+    program_from_xml,
+};
+
+/// Parse a workspace from NUL-terminated XML. Returns null if it cannot be
+/// parsed, or if parsing panics, and then stores the error message in
+/// `*error` unless `error` is null.
+///
+/// # Safety
+///
+/// `xml` must be a valid NUL-terminated string, and `error` null or valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn blockly_parse(xml: *const c_char, error: *mut *mut c_char) -> *mut Program {
+    // Unwinding into C is undefined behavior, so a panic becomes an error
+    let result = match to_str(xml) {
+        Some(xml) => panic::catch_unwind(|| program_from_xml(xml))
+            .unwrap_or_else(|payload| Err(panic_message(payload)))
+            .map_err(|error| error.to_string()),
+        None => Err("XML is null or not UTF-8".to_string()),
+    };
+    match result {
+        Ok(program) => Box::into_raw(Box::new(program)),
+        Err(message) => {
+            if !error.is_null() {
+                *error = to_c_string(&message);
+            }
+            ptr::null_mut()
+        },
+    }
+}
+
+/// # Safety
+///
+/// `program` must be null or returned by `blockly_parse`, and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn blockly_program_free(program: *mut Program) {
+    if !program.is_null() {
+        drop(Box::from_raw(program));
+    }
+}
+
+/// The number of blocks in the program, counting nested and shadow blocks.
+///
+/// # Safety
+///
+/// `program` must be null or a live program from `blockly_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn blockly_block_count(program: *const Program) -> usize {
+    match program.as_ref() {
+        Some(program) => program.iter_blocks().count(),
+        None => 0,
+    }
+}
+
+/// The text of field `field` on the block at `path`, written as `BlockPath`
+/// displays it, e.g. `0/1/BODY/2/VALUE`. Returns null if there is no such
+/// block or field, or if looking it up panics.
+///
+/// A step whose name is a number is read as a position, so statement inputs
+/// with numeric names cannot be reached.
+///
+/// # Safety
+///
+/// `program` must be null or a live program from `blockly_parse`; `path` and
+/// `field` must be null or valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn blockly_field(program: *const Program, path: *const c_char, field: *const c_char) -> *mut c_char {
+    let (program, path, field) = match (program.as_ref(), to_str(path), to_str(field)) {
+        (Some(program), Some(path), Some(field)) => (program, path, field),
+        _ => return ptr::null_mut(),
+    };
+    let text = panic::catch_unwind(AssertUnwindSafe(|| {
+        match block_at(program, path).and_then(|block| block.fields.get(field)) {
+            Some(FieldValue::SimpleField(text)) => Some(to_c_string(text)),
+            _ => None,
+        }
+    }));
+    text.ok().flatten().unwrap_or(ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `string` must be null or returned by one of these functions, and not yet
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn blockly_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> ParseError {
+    let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown error".to_string());
+    ParseError::UnexpectedStructure(format!("panicked while parsing: {}", message))
+}
+
+unsafe fn to_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+    CStr::from_ptr(string).to_str().ok()
+}
+
+// Interior NULs cannot be passed to C, so the string is cut at the first one
+fn to_c_string(text: &str) -> *mut c_char {
+    let end = text.find('\0').unwrap_or(text.len());
+    CString::new(&text[..end]).unwrap_or_default().into_raw()
+}
+
+// The steps of a displayed `BlockPath` do not say which kind of input they
+// follow: a statement input is followed by a position, and a name alone is a
+// value input, or else a field holding a block.
+fn block_at<'a>(program: &'a Program, path: &str) -> Option<&'a Block> {
+    let mut parts = path.split('/').peekable();
+    let group = parts.next()?.parse::<usize>().ok()?;
+    let index = parts.next()?.parse::<usize>().ok()?;
+    let mut block = program.groups.get(group)?.blocks.get(index)?;
+    while let Some(name) = parts.next() {
+        let position = parts.peek().and_then(|part| part.parse::<usize>().ok());
+        block = match position {
+            Some(position) => {
+                parts.next();
+                block.statements.get(name)?.blocks.get(position)?
+            },
+            None => match (block.values.get(name), block.fields.get(name)) {
+                (Some(input), _) => input.block(),
+                (None, Some(FieldValue::ExpressionField(inner))) => inner,
+                _ => return None,
+            },
+        };
+    }
+    Some(block)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ffi() {
+        let xml = CString::new(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="a">
+                    <statement name="BODY">
+                        <block type="led_on" id="b">
+                            <field name="TIME">300</field>
+                            <value name="BRIGHTNESS"><shadow type="math_number" id="c"><field name="NUM">80</field></shadow></value>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        unsafe {
+            let program = blockly_parse(xml.as_ptr(), ptr::null_mut());
+            assert!(!program.is_null());
+            assert_eq!(blockly_block_count(program), 3);
+
+            for (path, field, expected) in [("0/0/BODY/0", "TIME", Some("300")), ("0/0/BODY/0/BRIGHTNESS", "NUM", Some("80")), ("0/0/BODY/1", "TIME", None)] {
+                let path = CString::new(path).unwrap();
+                let field = CString::new(field).unwrap();
+                let text = blockly_field(program, path.as_ptr(), field.as_ptr());
+                assert_eq!(text.as_ref().map(|_| CStr::from_ptr(text).to_str().unwrap()), expected);
+                blockly_string_free(text);
+            }
+            blockly_program_free(program);
+
+            let broken = CString::new("<xml><block></xml>").unwrap();
+            let mut error = ptr::null_mut();
+            assert!(blockly_parse(broken.as_ptr(), &mut error).is_null());
+            assert!(!CStr::from_ptr(error).to_str().unwrap().is_empty());
+            blockly_string_free(error);
+        }
+    }
+
+    #[test]
+    fn test_header_declares_every_function() {
+        let header = include_str!("../include/blockly_parser.h");
+        let functions: Vec<&str> = include_str!("ffi.rs")
+            .lines()
+            .filter_map(|line| line.strip_prefix("pub unsafe extern \"C\" fn "))
+            .map(|rest| &rest[..rest.find('(').unwrap()])
+            .collect();
+        assert_eq!(functions.len(), 5);
+        for function in functions {
+            assert!(header.contains(&format!(" {}(", function)) || header.contains(&format!("*{}(", function)), "{} is not declared", function);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use super::{
+    Block,
+    FieldValue,
+    FieldError,
+    Rgb,
+};
+
+impl Block {
+    pub fn field_str(&self, name: &str) -> Result<&str, FieldError> {
+        match self.fields.get(name) {
+            Some(FieldValue::SimpleField(value)) => Ok(value),
+            Some(FieldValue::ExpressionField(_)) => Err(FieldError::NotSimple(name.to_string())),
+            None => Err(FieldError::Missing(name.to_string())),
+        }
+    }
+
+    /// Parse a field's text with `FromStr`, ignoring surrounding whitespace.
+    pub fn field_parse<T: FromStr>(&self, name: &str, expected: &'static str) -> Result<T, FieldError> {
+        let value = self.field_str(name)?;
+        value.trim().parse::<T>().map_err(|_| {
+            FieldError::Invalid {
+                field: name.to_string(),
+                value: value.to_string(),
+                expected,
+            }
+        })
+    }
+
+    pub fn field_i64(&self, name: &str) -> Result<i64, FieldError> {
+        self.field_parse(name, "an integer")
+    }
+
+    pub fn field_f64(&self, name: &str) -> Result<f64, FieldError> {
+        self.field_parse(name, "a number")
+    }
+
+    /// Checkbox fields are saved as `TRUE` or `FALSE`.
+    pub fn field_bool(&self, name: &str) -> Result<bool, FieldError> {
+        let value = self.field_str(name)?;
+        match value.trim() {
+            "TRUE" | "true" => Ok(true),
+            "FALSE" | "false" => Ok(false),
+            _ => Err(FieldError::Invalid {
+                field: name.to_string(),
+                value: value.to_string(),
+                expected: "TRUE or FALSE",
+            })
+        }
+    }
+
+    /// Colour fields are saved as `#rrggbb`.
+    pub fn field_colour(&self, name: &str) -> Result<Rgb, FieldError> {
+        let value = self.field_str(name)?;
+        Rgb::from_hex(value).ok_or_else(|| FieldError::Invalid {
+            field: name.to_string(),
+            value: value.to_string(),
+            expected: "a colour like #ff0000",
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::BlockBuilder;
+
+    #[test]
+    fn test_typed_field_accessors() {
+        let block = BlockBuilder::new("led_on")
+            .field("TIME", "300")
+            .field("BRIGHTNESS", "0.5")
+            .field("BLINK", "TRUE")
+            .field("LABEL", "kitchen")
+            .build();
+
+        assert_eq!(block.field_str("LABEL"), Ok("kitchen"));
+        assert_eq!(block.field_i64("TIME"), Ok(300));
+        assert_eq!(block.field_f64("BRIGHTNESS"), Ok(0.5));
+        assert_eq!(block.field_bool("BLINK"), Ok(true));
+        assert_eq!(block.field_parse::<u8>("TIME", "a byte"), Err(FieldError::Invalid {
+            field: "TIME".to_string(),
+            value: "300".to_string(),
+            expected: "a byte",
+        }));
+        assert_eq!(block.field_i64("LABEL"), Err(FieldError::Invalid {
+            field: "LABEL".to_string(),
+            value: "kitchen".to_string(),
+            expected: "an integer",
+        }));
+        assert_eq!(block.field_i64("COLOR"), Err(FieldError::Missing("COLOR".to_string())));
+    }
+}
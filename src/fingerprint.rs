@@ -0,0 +1,248 @@
+use indexmap::IndexMap;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    Mutation,
+    RawElement,
+    RawNode,
+};
+use super::compare::is_id_attribute;
+
+// 64-bit FNV-1a, which unlike `std`'s hasher is the same on every platform and
+// Rust version
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0100_0000_01b3;
+
+pub(crate) struct Fnv(pub(crate) u64);
+
+impl Fnv {
+    pub(crate) fn new() -> Self {
+        Fnv(OFFSET_BASIS)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(PRIME);
+        }
+    }
+
+    // Length-prefixed, so that ("ab", "c") and ("a", "bc") differ
+    pub(crate) fn str(&mut self, text: &str) {
+        self.u64(text.len() as u64);
+        self.bytes(text.as_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn tag(&mut self, tag: u8) {
+        self.bytes(&[tag]);
+    }
+}
+
+impl Program {
+    /// A hash of the program's structure that stays the same across runs,
+    /// platforms and crate versions with the same major version. Programs that
+    /// are `eq_ignore_ids` have the same fingerprint: block ids, positions,
+    /// comments, variables and the order of the top-level stacks do not
+    /// affect it.
+    pub fn fingerprint(&self) -> u64 {
+        let mut stacks: Vec<u64> = self.groups.iter()
+            .map(|group| {
+                let mut hasher = Fnv::new();
+                hash_body(&mut hasher, group);
+                hasher.0
+            })
+            .collect();
+        stacks.sort_unstable();
+
+        let mut hasher = Fnv::new();
+        hasher.u64(stacks.len() as u64);
+        for stack in stacks {
+            hasher.u64(stack);
+        }
+        hasher.0
+    }
+}
+
+fn hash_body(hasher: &mut Fnv, body: &StatementBody) {
+    hasher.tag(body.shadow as u8);
+    hasher.u64(body.blocks.len() as u64);
+    for block in &body.blocks {
+        hash_block(hasher, block);
+    }
+}
+
+fn hash_block(hasher: &mut Fnv, block: &Block) {
+    hasher.str(&block.block_type);
+    hasher.tag(block.flags.disabled as u8);
+    match block.mutation {
+        Some(ref mutation) => {
+            hasher.tag(1);
+            hash_mutation(hasher, mutation);
+        },
+        None => hasher.tag(0),
+    }
+
+    hasher.u64(block.fields.len() as u64);
+    for (name, value) in sorted(&block.fields) {
+        hasher.str(name);
+        match *value {
+            FieldValue::SimpleField(ref text) => {
+                hasher.tag(0);
+                hasher.str(text);
+            },
+            FieldValue::ExpressionField(ref inner) => {
+                hasher.tag(1);
+                hash_block(hasher, inner);
+            },
+        }
+    }
+
+    hasher.u64(block.values.len() as u64);
+    for (name, input) in sorted(&block.values) {
+        hasher.str(name);
+        hasher.tag(input.is_shadow() as u8);
+        hash_block(hasher, input.block());
+    }
+
+    hasher.u64(block.statements.len() as u64);
+    for (name, body) in sorted(&block.statements) {
+        hasher.str(name);
+        hash_body(hasher, body);
+    }
+}
+
+fn hash_mutation(hasher: &mut Fnv, mutation: &Mutation) {
+    hash_attributes(hasher, &mutation.attributes);
+    hasher.u64(mutation.children.len() as u64);
+    for child in &mutation.children {
+        hash_element(hasher, child);
+    }
+}
+
+fn hash_element(hasher: &mut Fnv, element: &RawElement) {
+    hasher.str(&element.name);
+    hash_attributes(hasher, &element.attributes);
+    hash_nodes(hasher, &element.children);
+}
+
+// Id attributes are left out, like block ids
+fn hash_attributes<S>(hasher: &mut Fnv, attributes: &IndexMap<String, String, S>) {
+    let attributes: Vec<_> = sorted(attributes).into_iter()
+        .filter(|(name, _)| !is_id_attribute(name))
+        .collect();
+    hasher.u64(attributes.len() as u64);
+    for (name, value) in attributes {
+        hasher.str(name);
+        hasher.str(value);
+    }
+}
+
+fn hash_nodes(hasher: &mut Fnv, nodes: &[RawNode]) {
+    hasher.u64(nodes.len() as u64);
+    for node in nodes {
+        match *node {
+            RawNode::Element(ref element) => {
+                hasher.tag(0);
+                hash_element(hasher, element);
+            },
+            RawNode::Text(ref text) => {
+                hasher.tag(1);
+                hasher.str(text);
+            },
+        }
+    }
+}
+
+pub(crate) fn sorted<V, S>(map: &IndexMap<String, V, S>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<(&String, &V)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_fingerprint() {
+        let solution = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="led_off" id="x1" x="300" y="10"></block>
+                <block type="main_loop" id="a1" x="10" y="10">
+                    <statement name="BODY">
+                        <block type="led_on" id="a2">
+                            <field name="TIME">300</field>
+                            <field name="COLOR">red</field>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let student = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">count</variable></variables>
+                <block type="main_loop" id="b1" x="80" y="40">
+                    <statement name="BODY">
+                        <block type="led_on" id="b2">
+                            <field name="COLOR">red</field>
+                            <field name="TIME">300</field>
+                        </block>
+                    </statement>
+                </block>
+                <block type="led_off" id="y1" x="50" y="400"></block>
+            </xml>
+        "#).unwrap();
+        let wrong_time = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="b1">
+                    <statement name="BODY">
+                        <block type="led_on" id="b2">
+                            <field name="TIME">500</field>
+                            <field name="COLOR">red</field>
+                        </block>
+                    </statement>
+                </block>
+                <block type="led_off" id="y1"></block>
+            </xml>
+        "#).unwrap();
+
+        assert_eq!(solution.fingerprint(), student.fingerprint());
+        assert_ne!(solution.fingerprint(), wrong_time.fingerprint());
+        // Pinned so that an accidental change to the hash shows up
+        let empty = program_from_xml("<xml></xml>").unwrap();
+        assert_eq!(empty.fingerprint(), 0xa8c7_f832_281a_39c5);
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_variable_ids() {
+        let procedure = |varid: &str| program_from_xml(&format!(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="{0}">count</variable></variables>
+                <block type="procedures_defreturn">
+                    <mutation><arg name="count" varid="{0}"></arg></mutation>
+                    <field name="NAME">double</field>
+                    <value name="RETURN"><block type="variables_get"><field name="VAR" id="{0}">count</field></block></value>
+                </block>
+            </xml>
+        "#, varid)).unwrap();
+        let renamed = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="procedures_defreturn">
+                    <mutation><arg name="total" varid="v1"></arg></mutation>
+                    <field name="NAME">double</field>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        assert_eq!(procedure("v1").fingerprint(), procedure("k#Q9@zT").fingerprint());
+        assert_ne!(procedure("v1").fingerprint(), renamed.fingerprint());
+    }
+}
@@ -0,0 +1,144 @@
+use super::{
+    Diagnostics,
+    Program,
+    ParseError,
+    ParserOptions,
+    check_depth,
+    program_from_element,
+    stack_from_element,
+};
+use super::dom::{
+    Scoped,
+    XmlElement,
+};
+
+const OPEN: &str = "<fragments>";
+const CLOSE: &str = "</fragments>";
+
+/// Parse a string holding any number of root elements one after the other,
+/// each either an `<xml>` workspace or a bare `<block>` or `<shadow>` stack,
+/// as copied to the clipboard. Each root becomes one program; a bare block
+/// becomes a program with a single stack. XML declarations before each root
+/// are allowed.
+///
+/// ```
+/// use blockly_parser::programs_from_xml_multi;
+///
+/// let programs = programs_from_xml_multi(r#"
+///     <block type="led_on" id="a"></block>
+///     <xml><block type="led_off" id="b"></block></xml>
+/// "#).unwrap();
+/// assert_eq!(programs.len(), 2);
+/// assert_eq!(programs[0].groups[0].blocks[0].block_type, "led_on");
+/// ```
+pub fn programs_from_xml_multi(xml: &str) -> Result<Vec<Program>, ParseError> {
+    programs_from_xml_multi_with_options(xml, &ParserOptions::default())
+}
+
+pub fn programs_from_xml_multi_with_options(xml: &str, options: &ParserOptions) -> Result<Vec<Program>, ParseError> {
+    if let Some(max_depth) = options.max_depth {
+        check_depth(xml, max_depth)?;
+    }
+    // The roots are parsed as children of one synthetic element. Declarations
+    // are blanked rather than removed so error positions stay right.
+    let wrapped = format!("{}{}{}", OPEN, blank_declarations(xml), CLOSE);
+    parse_fragments(&wrapped, options).map_err(|error| match error {
+        ParseError::Xml { position, message } => ParseError::Xml {
+            position: position.saturating_sub(OPEN.len()).min(xml.len()),
+            message,
+        },
+        other => other,
+    })
+}
+
+#[cfg(not(feature = "quick-xml-backend"))]
+fn parse_fragments(wrapped: &str, options: &ParserOptions) -> Result<Vec<Program>, ParseError> {
+    let package = super::parse_package(wrapped)?;
+    let document = package.as_document();
+    let root = document.root()
+        .children()
+        .into_iter()
+        .find_map(|child| child.element())
+        .ok_or(ParseError::MissingRootElement)?;
+    programs_from_roots(root.child_elements(), options)
+}
+
+#[cfg(feature = "quick-xml-backend")]
+fn parse_fragments(wrapped: &str, options: &ParserOptions) -> Result<Vec<Program>, ParseError> {
+    let tree = super::quick::parse_root(wrapped)?;
+    programs_from_roots(XmlElement::child_elements(&tree.root()), options)
+}
+
+fn programs_from_roots<E: XmlElement>(roots: Vec<E>, options: &ParserOptions) -> Result<Vec<Program>, ParseError> {
+    roots.into_iter()
+        .map(|root| {
+            match Scoped::new(root, options.namespaces.as_deref()).name() {
+                "block" | "shadow" => {
+                    let mut program = Program::new();
+                    program.groups.push(stack_from_element(root, options, &mut Diagnostics::new())?);
+                    Ok(program)
+                },
+                _ => program_from_element(root, options, &mut Diagnostics::new()),
+            }
+        })
+        .collect()
+}
+
+// Replace each `<?xml ...?>` declaration with spaces of the same length
+fn blank_declarations(xml: &str) -> String {
+    let mut blanked = String::with_capacity(xml.len());
+    let mut rest = xml;
+    while let Some(start) = rest.find("<?xml") {
+        let end = match rest[start..].find("?>") {
+            Some(end) => start + end + 2,
+            None => break,
+        };
+        blanked.push_str(&rest[..start]);
+        blanked.push_str(&" ".repeat(end - start));
+        rest = &rest[end..];
+    }
+    blanked.push_str(rest);
+    blanked
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_programs_from_xml_multi() {
+        let programs = programs_from_xml_multi(r#"<?xml version="1.0"?>
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">count</variable></variables>
+                <block type="main_loop" id="main"></block>
+            </xml>
+            <?xml version="1.0"?>
+            <block type="led_on" id="on">
+                <next><block type="led_off" id="off"></block></next>
+            </block>
+            <shadow type="math_number" id="num"><field name="NUM">1</field></shadow>
+        "#).unwrap();
+
+        assert_eq!(programs.len(), 3);
+        assert_eq!(programs[0].variables.len(), 1);
+        assert_eq!(programs[0].groups[0].blocks[0].id, "main");
+        assert_eq!(programs[1].groups[0].blocks.len(), 2);
+        assert!(programs[2].groups[0].shadow);
+        assert_eq!(programs_from_xml_multi("  ").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_programs_from_xml_multi_errors() {
+        assert_eq!(
+            programs_from_xml_multi(r#"<block type="a"></block><comment>text</comment>"#).unwrap_err(),
+            ParseError::MissingRootElement
+        );
+        // The quick-xml backend ignores the unmatched end tag instead
+        #[cfg(not(feature = "quick-xml-backend"))]
+        match programs_from_xml_multi(r#"<block type="a"></blok>"#) {
+            Err(ParseError::Xml { position, .. }) => assert!(position <= 23),
+            other => panic!("expected an XML error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use super::{
+    Block,
+    FieldError,
+    FromBlockError,
+};
+
+/// Conversion from a parsed block into a user-defined type.
+///
+/// With the `derive` feature, `#[derive(FromBlock)]` implements this for
+/// structs whose fields map onto block fields:
+///
+/// ```ignore
+/// #[derive(FromBlock)]
+/// #[blockly(block_type = "led_on")]
+/// struct LedOn {
+///     #[blockly(field = "TIME")]
+///     time: u32,
+/// }
+/// ```
+///
+/// The block type defaults to the struct name in snake case and each field
+/// name defaults to the struct field's name in upper case.
+pub trait FromBlock: Sized {
+    fn from_block(block: &Block) -> Result<Self, FromBlockError>;
+}
+
+/// Conversion from one named field of a block.
+pub trait FromField: Sized {
+    fn from_field(block: &Block, name: &str) -> Result<Self, FieldError>;
+}
+
+impl FromField for String {
+    fn from_field(block: &Block, name: &str) -> Result<Self, FieldError> {
+        block.field_str(name).map(|value| value.to_string())
+    }
+}
+
+impl FromField for bool {
+    fn from_field(block: &Block, name: &str) -> Result<Self, FieldError> {
+        block.field_bool(name)
+    }
+}
+
+/// A missing field becomes `None`; a present but invalid field is still an error.
+impl<T: FromField> FromField for Option<T> {
+    fn from_field(block: &Block, name: &str) -> Result<Self, FieldError> {
+        match T::from_field(block, name) {
+            Ok(value) => Ok(Some(value)),
+            Err(FieldError::Missing(_)) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+macro_rules! impl_from_field_parse {
+    ($($t:ty => $expected:expr),*) => {
+        $(
+            impl FromField for $t {
+                fn from_field(block: &Block, name: &str) -> Result<Self, FieldError> {
+                    block.field_parse(name, $expected)
+                }
+            }
+        )*
+    }
+}
+
+impl_from_field_parse! {
+    i8 => "an integer",
+    i16 => "an integer",
+    i32 => "an integer",
+    i64 => "an integer",
+    isize => "an integer",
+    u8 => "a non-negative integer",
+    u16 => "a non-negative integer",
+    u32 => "a non-negative integer",
+    u64 => "a non-negative integer",
+    usize => "a non-negative integer",
+    f32 => "a number",
+    f64 => "a number"
+}
+
+/// Check a block's type before converting it, for use by `FromBlock` implementations.
+pub fn expect_block_type(block: &Block, expected: &str) -> Result<(), FromBlockError> {
+    if block.block_type == expected {
+        Ok(())
+    } else {
+        Err(FromBlockError::WrongBlockType {
+            expected: expected.to_string(),
+            found: block.block_type.clone(),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::BlockBuilder;
+
+    struct LedOn {
+        time: u32,
+        label: Option<String>,
+    }
+
+    impl FromBlock for LedOn {
+        fn from_block(block: &Block) -> Result<Self, FromBlockError> {
+            expect_block_type(block, "led_on")?;
+            Ok(LedOn {
+                time: FromField::from_field(block, "TIME").map_err(FromBlockError::Field)?,
+                label: FromField::from_field(block, "LABEL").map_err(FromBlockError::Field)?,
+            })
+        }
+    }
+
+    #[test]
+    fn test_from_block() {
+        let led_on = LedOn::from_block(&BlockBuilder::new("led_on").field("TIME", "300").build()).unwrap();
+        assert_eq!(led_on.time, 300);
+        assert_eq!(led_on.label, None);
+
+        let wrong_type = LedOn::from_block(&BlockBuilder::new("led_off").build());
+        assert_eq!(wrong_type.err(), Some(FromBlockError::WrongBlockType {
+            expected: "led_on".to_string(),
+            found: "led_off".to_string(),
+        }));
+
+        let negative = LedOn::from_block(&BlockBuilder::new("led_on").field("TIME", "-1").build());
+        assert_eq!(negative.err(), Some(FromBlockError::Field(FieldError::Invalid {
+            field: "TIME".to_string(),
+            value: "-1".to_string(),
+            expected: "a non-negative integer",
+        })));
+    }
+}
@@ -0,0 +1,378 @@
+//! Compare a student's program against a solution, for auto-grading.
+//!
+//! Unlike `diff`, blocks are never matched by id, since two people's
+//! programs share none. Stacks are aligned by block type instead, and the
+//! result is phrased as feedback on the student's program.
+
+use std::fmt;
+use std::mem;
+use std::slice;
+
+use indexmap::IndexMap;
+
+use super::{
+    Program,
+    Block,
+    FieldValue,
+    MapHasher,
+};
+
+/// What `compare` treats as a match.
+#[derive(PartialEq, Debug, Clone)]
+pub struct GradingOptions {
+    /// Fields not compared, e.g. `VAR` when students name variables themselves.
+    pub ignore_fields: Vec<String>,
+    /// Numeric fields within this of each other are equal; `10` and `10.0`
+    /// always are.
+    pub numeric_tolerance: f64,
+    /// The share of credit, from 0 to 1, for blocks that are right but in the
+    /// wrong place.
+    pub misplaced_credit: f64,
+}
+
+impl Default for GradingOptions {
+    fn default() -> Self {
+        Self {
+            ignore_fields: Vec::new(),
+            numeric_tolerance: 0.0,
+            misplaced_credit: 0.5,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct GradeReport {
+    /// From 0, nothing in common, to 1, the same program.
+    pub score: f64,
+    /// Field mismatches in solution order, then misplaced, missing and
+    /// extra blocks.
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// One difference from the solution. Locations are paths of inputs, e.g.
+/// `workspace > controls_repeat_ext.DO`.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Mismatch {
+    /// A solution block the student does not have. `blocks` counts it and
+    /// the blocks inside it.
+    MissingBlock { location: String, block_type: String, blocks: usize },
+    /// A block the solution does not have.
+    ExtraBlock { location: String, block_type: String, blocks: usize },
+    /// `found` is `None` when the student's block lacks the field.
+    WrongField { location: String, block_type: String, field: String, expected: String, found: Option<String> },
+    /// A block that is in the solution, but in another input or stack,
+    /// e.g. inside a loop it should follow.
+    WrongNesting { block_type: String, expected: String, found: String },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mismatch::MissingBlock { ref location, ref block_type, blocks } => {
+                write!(f, "missing a `{}` block in {}", block_type, location)?;
+                if blocks > 1 {
+                    write!(f, " (with {} blocks inside it)", blocks - 1)?;
+                }
+                Ok(())
+            },
+            Mismatch::ExtraBlock { ref location, ref block_type, .. } => {
+                write!(f, "unexpected `{}` block in {}", block_type, location)
+            },
+            Mismatch::WrongField { ref location, ref block_type, ref field, ref expected, ref found } => {
+                match *found {
+                    Some(ref found) => write!(f, "`{}` block in {}: {} should be {:?}, not {:?}", block_type, location, field, expected, found),
+                    None => write!(f, "`{}` block in {}: {} should be {:?}", block_type, location, field, expected),
+                }
+            },
+            Mismatch::WrongNesting { ref block_type, ref expected, ref found } => {
+                write!(f, "`{}` block should be in {}, not {}", block_type, expected, found)
+            },
+        }
+    }
+}
+
+impl Program {
+    pub fn grade(&self, solution: &Program, options: &GradingOptions) -> GradeReport {
+        compare(self, solution, options)
+    }
+}
+
+/// Grade `student` against `solution`. Each solution block the student has
+/// earns half credit for being there and half for its fields; misplaced
+/// blocks earn `misplaced_credit` of that. The score divides the credit by
+/// the block count of the larger program, so extra blocks cost as well.
+pub fn compare(student: &Program, solution: &Program, options: &GradingOptions) -> GradeReport {
+    let mut grader = Grader {
+        options,
+        credit: 0.0,
+        weight: 1.0,
+        mismatches: Vec::new(),
+        missing: Vec::new(),
+        extra: Vec::new(),
+    };
+
+    // Top-level stacks can be anywhere, so pair them by their first block
+    let mut used = vec![false; student.groups.len()];
+    for group in &solution.groups {
+        let first_type = group.blocks.first().map(|block| block.block_type.as_str());
+        let paired = student.groups.iter()
+            .enumerate()
+            .position(|(index, other)| !used[index] && other.blocks.first().map(|block| block.block_type.as_str()) == first_type);
+        match paired {
+            Some(index) => {
+                used[index] = true;
+                grader.align(&group.blocks, &student.groups[index].blocks, "workspace");
+            },
+            None => grader.align(&group.blocks, &[], "workspace"),
+        }
+    }
+    for (index, group) in student.groups.iter().enumerate() {
+        if !used[index] {
+            grader.align(&[], &group.blocks, "workspace");
+        }
+    }
+    grader.place_unmatched();
+
+    let total = count_blocks(solution).max(count_blocks(student));
+    let score = if total == 0 { 1.0 } else { (grader.credit / total as f64).clamp(0.0, 1.0) };
+    GradeReport {
+        score,
+        mismatches: grader.mismatches,
+    }
+}
+
+struct Grader<'a> {
+    options: &'a GradingOptions,
+    credit: f64,
+    // Scales credit while comparing misplaced blocks
+    weight: f64,
+    mismatches: Vec<Mismatch>,
+    missing: Vec<Unmatched<'a>>,
+    extra: Vec<Unmatched<'a>>,
+}
+
+struct Unmatched<'a> {
+    block: &'a Block,
+    location: String,
+}
+
+impl<'a> Grader<'a> {
+    // Pair the blocks of two stacks by their longest common sequence of types
+    fn align(&mut self, solution: &'a [Block], student: &'a [Block], location: &str) {
+        let (n, m) = (solution.len(), student.len());
+        let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[i][j] = if solution[i].block_type == student[j].block_type {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && solution[i].block_type == student[j].block_type {
+                self.compare_blocks(&solution[i], &student[j], location);
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lengths[i + 1][j] >= lengths[i][j + 1]) {
+                self.missing.push(Unmatched { block: &solution[i], location: location.to_string() });
+                i += 1;
+            } else {
+                self.extra.push(Unmatched { block: &student[j], location: location.to_string() });
+                j += 1;
+            }
+        }
+    }
+
+    fn compare_blocks(&mut self, solution: &'a Block, student: &'a Block, location: &str) {
+        let mut compared = 0;
+        let mut correct = 0;
+        for (name, value) in &solution.fields {
+            if self.options.ignore_fields.contains(name) {
+                continue;
+            }
+            let expected = match *value {
+                FieldValue::SimpleField(ref expected) => expected,
+                FieldValue::ExpressionField(_) => continue,
+            };
+            compared += 1;
+            let found = match student.fields.get(name) {
+                Some(FieldValue::SimpleField(found)) => Some(found),
+                _ => None,
+            };
+            if found.is_some_and(|found| self.fields_match(expected, found)) {
+                correct += 1;
+            } else {
+                self.mismatches.push(Mismatch::WrongField {
+                    location: location.to_string(),
+                    block_type: solution.block_type.clone(),
+                    field: name.clone(),
+                    expected: expected.clone(),
+                    found: found.cloned(),
+                });
+            }
+        }
+        let field_share = if compared == 0 { 1.0 } else { correct as f64 / compared as f64 };
+        self.credit += self.weight * (0.5 + 0.5 * field_share);
+
+        for (name, solution_field, student_field) in union(&solution.fields, &student.fields) {
+            let inner = format!("{} > {}.{}", location, solution.block_type, name);
+            self.align(expression(solution_field), expression(student_field), &inner);
+        }
+        for (name, solution_input, student_input) in union(&solution.values, &student.values) {
+            let inner = format!("{} > {}.{}", location, solution.block_type, name);
+            let solution_blocks = solution_input.map_or(&[][..], |input| slice::from_ref(input.block()));
+            let student_blocks = student_input.map_or(&[][..], |input| slice::from_ref(input.block()));
+            self.align(solution_blocks, student_blocks, &inner);
+        }
+        for (name, solution_body, student_body) in union(&solution.statements, &student.statements) {
+            let inner = format!("{} > {}.{}", location, solution.block_type, name);
+            let solution_blocks = solution_body.map_or(&[][..], |body| &body.blocks[..]);
+            let student_blocks = student_body.map_or(&[][..], |body| &body.blocks[..]);
+            self.align(solution_blocks, student_blocks, &inner);
+        }
+    }
+
+    // Pair missing blocks with extra blocks of the same type, which are
+    // misplaced rather than absent. Comparing a pair can turn up more.
+    fn place_unmatched(&mut self) {
+        let misplaced_credit = self.options.misplaced_credit;
+        let mut missing_blocks = Vec::new();
+        let mut extra_blocks = Vec::new();
+        loop {
+            let missing = mem::take(&mut self.missing);
+            let mut extra: Vec<Option<Unmatched>> = mem::take(&mut self.extra).into_iter().map(Some).collect();
+            if missing.is_empty() && extra.is_empty() {
+                break;
+            }
+            for unmatched in missing {
+                let paired = extra.iter()
+                    .position(|other| other.as_ref().is_some_and(|other| other.block.block_type == unmatched.block.block_type));
+                match paired.and_then(|index| extra[index].take()) {
+                    Some(other) => {
+                        self.mismatches.push(Mismatch::WrongNesting {
+                            block_type: unmatched.block.block_type.clone(),
+                            expected: unmatched.location.clone(),
+                            found: other.location.clone(),
+                        });
+                        self.weight = misplaced_credit;
+                        self.compare_blocks(unmatched.block, other.block, &unmatched.location);
+                        self.weight = 1.0;
+                    },
+                    None => missing_blocks.push(unmatched),
+                }
+            }
+            extra_blocks.extend(extra.into_iter().flatten());
+        }
+
+        for unmatched in missing_blocks {
+            self.mismatches.push(Mismatch::MissingBlock {
+                location: unmatched.location,
+                block_type: unmatched.block.block_type.clone(),
+                blocks: 1 + unmatched.block.descendants().count(),
+            });
+        }
+        for unmatched in extra_blocks {
+            self.mismatches.push(Mismatch::ExtraBlock {
+                location: unmatched.location,
+                block_type: unmatched.block.block_type.clone(),
+                blocks: 1 + unmatched.block.descendants().count(),
+            });
+        }
+    }
+
+    fn fields_match(&self, expected: &str, found: &str) -> bool {
+        if expected == found {
+            return true;
+        }
+        match (expected.trim().parse::<f64>(), found.trim().parse::<f64>()) {
+            (Ok(expected), Ok(found)) => (expected - found).abs() <= self.options.numeric_tolerance,
+            _ => false,
+        }
+    }
+}
+
+// Names in either map, the solution's first, with each side's entry
+fn union<'a, V>(solution: &'a IndexMap<String, V, MapHasher>, student: &'a IndexMap<String, V, MapHasher>) -> Vec<(&'a str, Option<&'a V>, Option<&'a V>)> {
+    let mut names: Vec<&str> = solution.keys().map(|name| name.as_str()).collect();
+    names.extend(student.keys().map(|name| name.as_str()).filter(|name| !solution.contains_key(*name)));
+    names.into_iter()
+        .map(|name| (name, solution.get(name), student.get(name)))
+        .collect()
+}
+
+fn expression(field: Option<&FieldValue>) -> &[Block] {
+    match field {
+        Some(FieldValue::ExpressionField(block)) => slice::from_ref(&**block),
+        _ => &[],
+    }
+}
+
+fn count_blocks(program: &Program) -> usize {
+    program.iter_blocks().count()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    const SOLUTION: &str = r#"
+        <xml>
+            <block type="controls_repeat_ext" id="a">
+                <value name="TIMES"><shadow type="math_number" id="b"><field name="NUM">10</field></shadow></value>
+                <statement name="DO">
+                    <block type="led_on" id="c"><next><block type="wait" id="d"><field name="MS">500</field></block></next></block>
+                </statement>
+            </block>
+        </xml>
+    "#;
+
+    #[test]
+    fn test_compare_identical() {
+        let solution = program_from_xml(SOLUTION).unwrap();
+        let student = program_from_xml(&SOLUTION.replace("id=\"", "id=\"student-").replace(">10<", ">10.0<")).unwrap();
+        let report = compare(&student, &solution, &GradingOptions::default());
+        assert_eq!(report.score, 1.0);
+        assert!(report.mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_compare_mismatches() {
+        let solution = program_from_xml(SOLUTION).unwrap();
+        let student = program_from_xml(r#"
+            <xml>
+                <block type="controls_repeat_ext" id="1">
+                    <value name="TIMES"><shadow type="math_number" id="2"><field name="NUM">5</field></shadow></value>
+                    <statement name="DO"><block type="led_on" id="3"></block></statement>
+                    <next>
+                        <block type="wait" id="4"><field name="MS">500</field><next><block type="text_print" id="5"></block></next></block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+        let report = student.grade(&solution, &GradingOptions::default());
+        assert_eq!(report.mismatches, [
+            Mismatch::WrongField {
+                location: "workspace > controls_repeat_ext.TIMES".to_string(),
+                block_type: "math_number".to_string(),
+                field: "NUM".to_string(),
+                expected: "10".to_string(),
+                found: Some("5".to_string()),
+            },
+            Mismatch::WrongNesting {
+                block_type: "wait".to_string(),
+                expected: "workspace > controls_repeat_ext.DO".to_string(),
+                found: "workspace".to_string(),
+            },
+            Mismatch::ExtraBlock { location: "workspace".to_string(), block_type: "text_print".to_string(), blocks: 1 },
+        ]);
+        assert_eq!(report.mismatches[0].to_string(), r#"`math_number` block in workspace > controls_repeat_ext.TIMES: NUM should be "10", not "5""#);
+        // Repeat and LED full, the number half, the wait half of full, out of 5 blocks
+        assert!((report.score - (1.0 + 1.0 + 0.5 + 0.5) / 5.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,86 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::{
+    BuildHasher,
+    Hash,
+    Hasher,
+};
+
+use indexmap::IndexMap;
+
+use super::{
+    Block,
+    Mutation,
+    RawElement,
+};
+
+// `IndexMap` has no `Hash` and compares equal regardless of order, so the types
+// holding one hash their entries sorted by key. This agrees with the derived
+// `PartialEq`, which ignores order.
+
+impl Hash for Block {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.block_type.hash(state);
+        self.id.hash(state);
+        hash_sorted(&self.fields, state);
+        hash_sorted(&self.statements, state);
+        hash_sorted(&self.values, state);
+        self.mutation.hash(state);
+        self.position.hash(state);
+        self.flags.hash(state);
+        self.comment.hash(state);
+        self.data.hash(state);
+        hash_sorted(&self.extra_attributes, state);
+        self.extra_elements.hash(state);
+        hash_sorted(&self.field_variables, state);
+        self.preserve_space.hash(state);
+    }
+}
+
+impl Hash for Mutation {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_sorted(&self.attributes, state);
+        self.children.hash(state);
+    }
+}
+
+impl Hash for RawElement {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        hash_sorted(&self.attributes, state);
+        self.children.hash(state);
+    }
+}
+
+fn hash_sorted<V: Hash, S: BuildHasher, H: Hasher>(map: &IndexMap<String, V, S>, state: &mut H) {
+    let mut entries: Vec<(&String, &V)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries.hash(state);
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_programs_in_sets() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="led_on" id="a">
+                    <mutation items="2" mode="fast"></mutation>
+                    <field name="TIME">300</field>
+                    <field name="COLOR">red</field>
+                </block>
+            </xml>
+        "#;
+        let mut seen = HashSet::new();
+        assert!(seen.insert(program_from_xml(xml).unwrap()));
+        // Parsed again, the maps may iterate in a different order
+        assert!(!seen.insert(program_from_xml(xml).unwrap()));
+        assert!(seen.insert(Default::default()));
+        assert_eq!(seen.len(), 2);
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::hash::{
+    BuildHasher,
+    Hasher,
+};
+
+use super::{
+    Program,
+    DuplicateIdError,
+    RepeatedIdError,
+};
+use super::iter::child_blocks_mut;
+
+/// The characters Blockly draws ids from (`Blockly.utils.idGenerator.genUid`).
+const SOUP: &[u8] = b"!#$%()*+,-./:;=?@[]^_`{|}~ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+const ID_LENGTH: usize = 20;
+
+impl Program {
+    /// Give every block a fresh Blockly-style id, e.g. after duplicating a
+    /// template project. Variable ids are left alone.
+    pub fn regenerate_ids(&mut self) {
+        let mut ids = SoupIdGenerator::new();
+        let mut used = HashSet::new();
+        let mut pending: Vec<_> = self.groups.iter_mut()
+            .flat_map(|group| group.blocks.iter_mut())
+            .collect();
+        while let Some(block) = pending.pop() {
+            block.id = random_unused_id(&mut ids, &mut used);
+            pending.extend(child_blocks_mut(block));
+        }
+    }
+
+    /// Like `regenerate_ids`, taking ids from `ids`. Blocks keep the ids
+    /// given so far if `ids` keeps repeating used ones.
+    pub fn regenerate_ids_with<G: IdGenerator>(&mut self, ids: &mut G) -> Result<(), RepeatedIdError> {
+        let mut used = HashSet::new();
+        let mut pending: Vec<_> = self.groups.iter_mut()
+            .flat_map(|group| group.blocks.iter_mut())
+            .collect();
+        while let Some(block) = pending.pop() {
+            block.id = unused_id(ids, &mut used)?;
+            pending.extend(child_blocks_mut(block));
+        }
+        Ok(())
+    }
+
+    /// Give the blocks and variables that have no id one from `ids`.
+    pub fn fill_missing_ids<G: IdGenerator>(&mut self, ids: &mut G) -> Result<(), RepeatedIdError> {
+        let mut used: HashSet<String> = self.iter_blocks().map(|block| block.id.clone()).collect();
+        let mut pending: Vec<_> = self.groups.iter_mut()
+            .flat_map(|group| group.blocks.iter_mut())
+            .collect();
+        while let Some(block) = pending.pop() {
+            if block.id.is_empty() {
+                block.id = unused_id(ids, &mut used)?;
+            }
+            pending.extend(child_blocks_mut(block));
+        }
+
+        let mut used: HashSet<String> = self.variables.iter().map(|variable| variable.id.clone()).collect();
+        for variable in &mut self.variables {
+            if variable.id.is_empty() {
+                variable.id = unused_id(ids, &mut used)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that no two blocks share an id. Blocks without an id are ignored.
+    pub fn check_unique_ids(&self) -> Result<(), DuplicateIdError> {
+        let mut seen = HashSet::new();
+        let mut duplicates: Vec<String> = Vec::new();
+        for block in self.iter_blocks() {
+            if block.id.is_empty() {
+                continue;
+            }
+            if !seen.insert(block.id.as_str()) && !duplicates.contains(&block.id) {
+                duplicates.push(block.id.clone());
+            }
+        }
+        if duplicates.is_empty() {
+            Ok(())
+        } else {
+            Err(DuplicateIdError {
+                ids: duplicates
+            })
+        }
+    }
+}
+
+/// A source of ids for new blocks and variables.
+///
+/// Ids the program already uses are skipped, so a generator must keep coming
+/// up with new ones: the methods taking one return a `RepeatedIdError` if
+/// it returns too many used ids in a row.
+pub trait IdGenerator {
+    fn next_id(&mut self) -> String;
+}
+
+/// Blockly's own ids (`Blockly.utils.idGenerator.genUid`): 20 characters
+/// drawn from the same soup of letters, digits and punctuation.
+///
+/// `new` seeds each generator differently. `seeded` gives the same ids
+/// every run, for reproducible tests and snapshots.
+#[derive(Debug, Clone)]
+pub struct SoupIdGenerator {
+    state: u64,
+}
+
+impl SoupIdGenerator {
+    // SplitMix64, seeded from the standard library's per-process random
+    // keys. Ids only need to be unique, not unpredictable.
+    pub fn new() -> Self {
+        Self::seeded(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            state: seed,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Default for SoupIdGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdGenerator for SoupIdGenerator {
+    fn next_id(&mut self) -> String {
+        (0..ID_LENGTH)
+            .map(|_| SOUP[(self.next_u64() % SOUP.len() as u64) as usize] as char)
+            .collect()
+    }
+}
+
+// How many ids in a row an `IdGenerator` may return that are already used
+const MAX_ID_ATTEMPTS: usize = 1000;
+
+// An id from `ids` not already in `used`, which it is then added to
+fn unused_id<G: IdGenerator>(ids: &mut G, used: &mut HashSet<String>) -> Result<String, RepeatedIdError> {
+    let mut id = String::new();
+    for _ in 0..MAX_ID_ATTEMPTS {
+        id = ids.next_id();
+        if used.insert(id.clone()) {
+            return Ok(id);
+        }
+    }
+    Err(RepeatedIdError {
+        id,
+    })
+}
+
+// Like `unused_id`, for random ids, which only repeat by chance
+pub(crate) fn random_unused_id(ids: &mut SoupIdGenerator, used: &mut HashSet<String>) -> String {
+    loop {
+        let id = ids.next_id();
+        if used.insert(id.clone()) {
+            return id;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    const XML: &str = r#"
+        <xml xmlns="https://developers.google.com/blockly/xml">
+            <block type="main_loop" id="main">
+                <statement name="BODY">
+                    <block type="led_on" id="on">
+                        <next>
+                            <block type="led_off" id="on"></block>
+                        </next>
+                    </block>
+                </statement>
+            </block>
+            <block type="buzzer" id="main"></block>
+            <block type="buzzer" id="main"></block>
+        </xml>
+    "#;
+
+    #[test]
+    fn test_check_unique_ids() {
+        let program = program_from_xml(XML).unwrap();
+        assert_eq!(program.check_unique_ids(), Err(DuplicateIdError {
+            ids: vec!["on".to_string(), "main".to_string()],
+        }));
+    }
+
+    #[test]
+    fn test_regenerate_ids() {
+        let mut program = program_from_xml(XML).unwrap();
+        program.regenerate_ids();
+        assert_eq!(program.check_unique_ids(), Ok(()));
+        for block in program.iter_blocks() {
+            assert_eq!(block.id.len(), ID_LENGTH);
+            assert!(block.id.bytes().all(|byte| SOUP.contains(&byte)));
+        }
+    }
+
+    #[test]
+    fn test_seeded_ids() {
+        let mut first = program_from_xml(XML).unwrap();
+        let mut second = first.clone();
+        first.regenerate_ids_with(&mut SoupIdGenerator::seeded(7)).unwrap();
+        second.regenerate_ids_with(&mut SoupIdGenerator::seeded(7)).unwrap();
+        assert_eq!(first, second);
+        assert_ne!(SoupIdGenerator::seeded(7).next_id(), SoupIdGenerator::seeded(8).next_id());
+    }
+
+    struct Constant;
+
+    impl IdGenerator for Constant {
+        fn next_id(&mut self) -> String {
+            "same".to_string()
+        }
+    }
+
+    #[test]
+    fn test_repeating_generator() {
+        let mut program = program_from_xml(XML).unwrap();
+        assert_eq!(program.regenerate_ids_with(&mut Constant), Err(RepeatedIdError {
+            id: "same".to_string(),
+        }));
+        assert_eq!(program.fill_missing_ids(&mut Constant), Ok(()));
+
+        let mut program = program_from_xml(r#"<xml><block type="a"></block><block type="b"></block></xml>"#).unwrap();
+        assert_eq!(program.fill_missing_ids(&mut Constant), Err(RepeatedIdError {
+            id: "same".to_string(),
+        }));
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use super::{
+    Program,
+    Block,
+};
+use super::iter::child_blocks_mut;
+
+/// A lookup table from block id to block, for resolving many ids against the
+/// same program without walking the tree each time.
+pub struct BlockIndex<'a> {
+    blocks: HashMap<&'a str, &'a Block>,
+}
+
+impl<'a> BlockIndex<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let mut blocks = HashMap::new();
+        for block in program.iter_blocks() {
+            // Keep the first block if ids collide, matching find_block_by_id.
+            blocks.entry(block.id.as_str()).or_insert(block);
+        }
+        Self {
+            blocks
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&'a Block> {
+        self.blocks.get(id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl Program {
+    pub fn find_block_by_id(&self, id: &str) -> Option<&Block> {
+        self.iter_blocks().find(|block| block.id == id)
+    }
+
+    pub fn find_block_by_id_mut(&mut self, id: &str) -> Option<&mut Block> {
+        for group in self.groups.iter_mut() {
+            for block in group.blocks.iter_mut() {
+                if let Some(found) = find_in_block_mut(block, id) {
+                    return Some(found);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn index(&self) -> BlockIndex<'_> {
+        BlockIndex::new(self)
+    }
+}
+
+fn find_in_block_mut<'a>(block: &'a mut Block, id: &str) -> Option<&'a mut Block> {
+    if block.id == id {
+        return Some(block);
+    }
+    for child in child_blocks_mut(block) {
+        if let Some(found) = find_in_block_mut(child, id) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{
+        program_from_xml,
+        FieldValue,
+    };
+
+    const XML: &str = r#"
+        <xml xmlns="https://developers.google.com/blockly/xml">
+            <block type="main_loop" id="[.)/fqUYv92(mzb{?:~u">
+                <statement name="BODY">
+                    <block type="led_on" id="^3xb.m4E9i0;3$R10(=5">
+                        <field name="TIME">300</field>
+                        <next>
+                            <block type="led_off" id="HX4*sB9=gbJtq$Y{ke6b">
+                                <field name="TIME">100</field>
+                            </block>
+                        </next>
+                    </block>
+                </statement>
+            </block>
+        </xml>
+    "#;
+
+    #[test]
+    fn test_find_block_by_id() {
+        let mut program = program_from_xml(XML).unwrap();
+
+        let led_off = program.find_block_by_id("HX4*sB9=gbJtq$Y{ke6b").unwrap();
+        assert_eq!(led_off.block_type, "led_off");
+        assert!(program.find_block_by_id("missing").is_none());
+
+        {
+            let led_on = program.find_block_by_id_mut("^3xb.m4E9i0;3$R10(=5").unwrap();
+            led_on.fields.insert("TIME".to_string(), FieldValue::SimpleField("500".to_string()));
+        }
+        let led_on = program.find_block_by_id("^3xb.m4E9i0;3$R10(=5").unwrap();
+        assert_eq!(led_on.fields.get("TIME"), Some(&FieldValue::SimpleField("500".to_string())));
+    }
+
+    #[test]
+    fn test_block_index() {
+        let program = program_from_xml(XML).unwrap();
+        let index = program.index();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get("[.)/fqUYv92(mzb{?:~u").unwrap().block_type, "main_loop");
+        assert!(index.get("missing").is_none());
+    }
+}
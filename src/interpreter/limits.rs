@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+/// Bounds on a `Runner`'s run, so an untrusted program cannot hang its host.
+/// Every limit is off by default.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Limits {
+    /// The most blocks to run, counting each pass of a loop with an empty
+    /// body as one.
+    pub max_steps: Option<u64>,
+    /// The most passes any one loop may make each time it runs.
+    pub max_iterations: Option<usize>,
+    /// How long the run may take, from its first step. Needs a clock; see
+    /// `Runner::clock`.
+    pub timeout: Option<Duration>,
+}
+
+/// The limit a run went over, with its value.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum Limit {
+    Steps(u64),
+    Iterations(usize),
+    Timeout(Duration),
+}
+
+/// The time, for `Limits::timeout`. Supplied by the caller, since not every
+/// host has `std::time::Instant`, and so tests can control time.
+pub trait Clock {
+    /// The time since some fixed point, such as the start of the process.
+    fn now(&self) -> Duration;
+}
+
+impl<F: Fn() -> Duration> Clock for F {
+    fn now(&self) -> Duration {
+        self()
+    }
+}
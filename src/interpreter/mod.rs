@@ -0,0 +1,372 @@
+//! Run a program by walking its blocks and handing each one to an
+//! `Executor`, which performs the block's effect and decides where to go
+//! next. `run_async` does the same for an `AsyncExecutor` whose handlers
+//! await, e.g. hardware I/O or timers.
+//!
+//! `Standard` is an `Executor` for Blockly's standard blocks, with variables
+//! in an `Environment`, so embedders only write their own blocks. A `Runner`
+//! runs a program one block at a time, and can be paused and resumed, or
+//! held to `Limits` so an untrusted program cannot hang its host. Its
+//! `Snapshot` can be saved to resume the run in a later process, and an
+//! `Observer` can watch it.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+};
+
+mod limits;
+mod observer;
+mod runner;
+mod snapshot;
+mod standard;
+mod value;
+
+pub use self::limits::{
+    Clock,
+    Limit,
+    Limits,
+};
+pub use self::observer::Observer;
+pub use self::runner::{
+    RunControl,
+    RunState,
+    Runner,
+};
+pub use self::snapshot::{
+    FrameSnapshot,
+    Resumable,
+    Snapshot,
+    StandardState,
+};
+pub use self::standard::{
+    evaluate,
+    evaluate_input,
+    CustomBlocks,
+    Standard,
+};
+pub use self::value::{
+    Environment,
+    Value,
+};
+
+/// What the runtime does after a block has executed.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ControlFlow {
+    /// Move on to the next block in the stack.
+    Next,
+    /// Run the named statement input once, then move on, e.g. for the branch
+    /// taken by a conditional. A missing input is treated as empty.
+    Enter(String),
+    /// Run the named statement input, then ask `Executor::next_iteration`
+    /// whether to run it again.
+    Loop(String),
+    /// Leave the innermost enclosing loop.
+    Break,
+    /// Stop the whole run.
+    Stop,
+}
+
+/// How a run ended.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Outcome {
+    Finished,
+    /// An executor returned `ControlFlow::Stop`.
+    Stopped,
+}
+
+pub trait Executor {
+    fn execute(&mut self, block: &Block) -> ControlFlow;
+
+    /// Called after each pass through a loop's body, with the number of
+    /// passes completed so far. The default runs the body once.
+    fn next_iteration(&mut self, _block: &Block, _completed: usize) -> bool {
+        false
+    }
+
+    /// The executor's variables, if it keeps them in an `Environment`, so an
+    /// `Observer` can see them change.
+    fn environment(&mut self) -> Option<&mut Environment> {
+        None
+    }
+}
+
+/// An `Executor` whose handlers are async. The returned futures must be
+/// `Send`, so runs can be spawned on multi-threaded runtimes such as tokio.
+pub trait AsyncExecutor: Send {
+    fn execute(&mut self, block: &Block) -> impl Future<Output = ControlFlow> + Send;
+
+    /// See `Executor::next_iteration`.
+    fn next_iteration(&mut self, _block: &Block, _completed: usize) -> impl Future<Output = bool> + Send {
+        async { false }
+    }
+}
+
+// How a stack was left
+enum Exit {
+    Finished,
+    Break,
+    Stop,
+}
+
+/// Run every top-level stack in order.
+pub fn run<E: Executor>(program: &Program, executor: &mut E) -> Outcome {
+    for group in &program.groups {
+        if let Exit::Stop = run_body(group, executor) {
+            return Outcome::Stopped;
+        }
+    }
+    Outcome::Finished
+}
+
+/// Run a single stack, such as one of `Program::groups` or a statement input.
+pub fn run_stack<E: Executor>(body: &StatementBody, executor: &mut E) -> Outcome {
+    match run_body(body, executor) {
+        Exit::Stop => Outcome::Stopped,
+        _ => Outcome::Finished,
+    }
+}
+
+fn run_body<E: Executor>(body: &StatementBody, executor: &mut E) -> Exit {
+    for block in &body.blocks {
+        match executor.execute(block) {
+            ControlFlow::Next => {},
+            ControlFlow::Enter(name) => {
+                if let Some(inner) = block.statements.get(&name) {
+                    match run_body(inner, executor) {
+                        Exit::Finished => {},
+                        exit => return exit,
+                    }
+                }
+            },
+            ControlFlow::Loop(name) => {
+                let mut completed = 0;
+                loop {
+                    if let Some(inner) = block.statements.get(&name) {
+                        match run_body(inner, executor) {
+                            Exit::Finished => {},
+                            Exit::Break => break,
+                            Exit::Stop => return Exit::Stop,
+                        }
+                    }
+                    completed += 1;
+                    if !executor.next_iteration(block, completed) {
+                        break;
+                    }
+                }
+            },
+            ControlFlow::Break => return Exit::Break,
+            ControlFlow::Stop => return Exit::Stop,
+        }
+    }
+    Exit::Finished
+}
+
+/// Run every top-level stack in order, awaiting each block's handler.
+pub async fn run_async<E: AsyncExecutor>(program: &Program, executor: &mut E) -> Outcome {
+    for group in &program.groups {
+        if let Exit::Stop = run_body_async(group, executor).await {
+            return Outcome::Stopped;
+        }
+    }
+    Outcome::Finished
+}
+
+/// Run a single stack, awaiting each block's handler.
+pub async fn run_stack_async<E: AsyncExecutor>(body: &StatementBody, executor: &mut E) -> Outcome {
+    match run_body_async(body, executor).await {
+        Exit::Stop => Outcome::Stopped,
+        _ => Outcome::Finished,
+    }
+}
+
+// Boxed, since the future refers to itself for nested statements
+fn run_body_async<'a, E: AsyncExecutor>(body: &'a StatementBody, executor: &'a mut E) -> Pin<Box<dyn Future<Output = Exit> + Send + 'a>> {
+    Box::pin(async move {
+        for block in &body.blocks {
+            match executor.execute(block).await {
+                ControlFlow::Next => {},
+                ControlFlow::Enter(name) => {
+                    if let Some(inner) = block.statements.get(&name) {
+                        match run_body_async(inner, executor).await {
+                            Exit::Finished => {},
+                            exit => return exit,
+                        }
+                    }
+                },
+                ControlFlow::Loop(name) => {
+                    let mut completed = 0;
+                    loop {
+                        if let Some(inner) = block.statements.get(&name) {
+                            match run_body_async(inner, executor).await {
+                                Exit::Finished => {},
+                                Exit::Break => break,
+                                Exit::Stop => return Exit::Stop,
+                            }
+                        }
+                        completed += 1;
+                        if !executor.next_iteration(block, completed).await {
+                            break;
+                        }
+                    }
+                },
+                ControlFlow::Break => return Exit::Break,
+                ControlFlow::Stop => return Exit::Stop,
+            }
+        }
+        Exit::Finished
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    // Records what the LED would do instead of driving hardware
+    struct Led {
+        log: Vec<String>,
+    }
+
+    impl Executor for Led {
+        fn execute(&mut self, block: &Block) -> ControlFlow {
+            match block.block_type.as_str() {
+                "main_loop" => ControlFlow::Enter("BODY".to_string()),
+                "inner_loop" => ControlFlow::Loop("BODY".to_string()),
+                "led_on" | "led_off" => {
+                    self.log.push(format!("{} {}", block.block_type, block.field_str("TIME").unwrap()));
+                    ControlFlow::Next
+                },
+                "stop" => ControlFlow::Stop,
+                "break" => ControlFlow::Break,
+                _ => ControlFlow::Next,
+            }
+        }
+
+        fn next_iteration(&mut self, block: &Block, completed: usize) -> bool {
+            (completed as i64) < block.field_i64("COUNT").unwrap()
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="inner_loop" id="loop">
+                            <field name="COUNT">2</field>
+                            <statement name="BODY">
+                                <block type="led_on" id="on"><field name="TIME">300</field>
+                                    <next><block type="led_off" id="off"><field name="TIME">100</field></block></next>
+                                </block>
+                            </statement>
+                            <next>
+                                <block type="led_on" id="last"><field name="TIME">50</field></block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let mut led = Led { log: Vec::new() };
+        assert_eq!(run(&program, &mut led), Outcome::Finished);
+        assert_eq!(led.log, vec!["led_on 300", "led_off 100", "led_on 300", "led_off 100", "led_on 50"]);
+    }
+
+    // Handlers that await, polled to completion without a runtime
+    struct AsyncLed {
+        log: Vec<String>,
+    }
+
+    impl AsyncExecutor for AsyncLed {
+        async fn execute(&mut self, block: &Block) -> ControlFlow {
+            let time = block.field_i64("TIME").unwrap_or(0);
+            Ready.await;
+            self.log.push(format!("{} {}", block.block_type, time));
+            match block.block_type.as_str() {
+                "inner_loop" => ControlFlow::Loop("BODY".to_string()),
+                _ => ControlFlow::Next,
+            }
+        }
+
+        async fn next_iteration(&mut self, block: &Block, completed: usize) -> bool {
+            (completed as i64) < block.field_i64("COUNT").unwrap()
+        }
+    }
+
+    // Pending once before completing, like a timer
+    struct Ready;
+
+    impl Future for Ready {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, context: &mut std::task::Context) -> std::task::Poll<()> {
+            context.waker().wake_by_ref();
+            std::task::Poll::Ready(())
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_async() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="inner_loop" id="loop">
+                    <field name="COUNT">2</field>
+                    <statement name="BODY">
+                        <block type="led_on" id="on"><field name="TIME">300</field></block>
+                    </statement>
+                    <next>
+                        <block type="led_off" id="off"><field name="TIME">100</field></block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let mut led = AsyncLed { log: Vec::new() };
+        assert_eq!(block_on(run_async(&program, &mut led)), Outcome::Finished);
+        assert_eq!(led.log, vec!["inner_loop 0", "led_on 300", "led_on 300", "led_off 100"]);
+    }
+
+    #[test]
+    fn test_break_and_stop() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="inner_loop" id="loop">
+                    <field name="COUNT">5</field>
+                    <statement name="BODY">
+                        <block type="led_on" id="on"><field name="TIME">1</field>
+                            <next><block type="break" id="break"></block></next>
+                        </block>
+                    </statement>
+                    <next>
+                        <block type="stop" id="stop">
+                            <next><block type="led_off" id="off"><field name="TIME">2</field></block></next>
+                        </block>
+                    </next>
+                </block>
+                <block type="led_off" id="never"><field name="TIME">3</field></block>
+            </xml>
+        "#).unwrap();
+
+        let mut led = Led { log: Vec::new() };
+        assert_eq!(run(&program, &mut led), Outcome::Stopped);
+        assert_eq!(led.log, vec!["led_on 1"]);
+    }
+}
@@ -0,0 +1,32 @@
+use super::Value;
+use super::super::Block;
+
+/// Watches a `Runner`'s run, e.g. for tracing or live telemetry, without
+/// changing its executor. Every method does nothing by default.
+pub trait Observer {
+    /// Called just before a block runs.
+    fn on_block_enter(&mut self, _block: &Block) {}
+
+    /// Called once a block is done: straight after it runs, or for a block
+    /// with a statement input running, once the input has been left.
+    fn on_block_exit(&mut self, _block: &Block) {}
+
+    /// Called after a step sets a variable to a new value, with its old one.
+    /// Only for executors that expose their `Environment`; see
+    /// `Executor::environment`.
+    fn on_variable_change(&mut self, _name: &str, _old: Option<&Value>, _new: &Value) {}
+}
+
+impl<O: Observer + ?Sized> Observer for &mut O {
+    fn on_block_enter(&mut self, block: &Block) {
+        (**self).on_block_enter(block)
+    }
+
+    fn on_block_exit(&mut self, block: &Block) {
+        (**self).on_block_exit(block)
+    }
+
+    fn on_variable_change(&mut self, name: &str, old: Option<&Value>, new: &Value) {
+        (**self).on_variable_change(name, old, new)
+    }
+}
@@ -0,0 +1,747 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+
+use super::{
+    Clock,
+    ControlFlow,
+    Environment,
+    Executor,
+    FrameSnapshot,
+    Limit,
+    Limits,
+    Observer,
+    Resumable,
+    Snapshot,
+};
+use super::super::{
+    Program,
+    Block,
+    RunError,
+    SnapshotError,
+};
+
+/// Where a `Runner` is in its run.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum RunState {
+    /// There are blocks left to run.
+    Running,
+    /// There are blocks left to run, but `Runner::run` will not run them
+    /// until the run is resumed.
+    Paused,
+    /// Every block has run.
+    Finished,
+    /// An executor returned `ControlFlow::Stop`.
+    Stopped,
+    Cancelled,
+    /// The run went over one of its `Limits`; see `Runner::exceeded`.
+    LimitExceeded,
+}
+
+const RUNNING: u8 = 0;
+const PAUSED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// Pauses, resumes or cancels a `Runner` from elsewhere, e.g. a UI thread
+/// or one of the run's own blocks. Clones control the same run.
+#[derive(Debug, Clone, Default)]
+pub struct RunControl {
+    state: Arc<AtomicU8>,
+}
+
+impl RunControl {
+    pub fn pause(&self) {
+        let _ = self.state.compare_exchange(RUNNING, PAUSED, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        let _ = self.state.compare_exchange(PAUSED, RUNNING, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    /// End the run for good. A cancelled run cannot be resumed.
+    pub fn cancel(&self) {
+        self.state.store(CANCELLED, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == PAUSED
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == CANCELLED
+    }
+}
+
+type Highlight<'p> = Box<dyn FnMut(&Block) + 'p>;
+
+// A stack being run, and how far it has got
+struct Frame<'p> {
+    // The statement input being run and its block, or `None` for a
+    // top-level stack
+    statement: Option<String>,
+    owner: Option<&'p Block>,
+    blocks: &'p [Block],
+    index: usize,
+    // For a loop's body, the passes completed
+    passes: Option<usize>,
+}
+
+/// Runs a program one block at a time, like `run` but resumable, e.g. for a
+/// debugger that highlights each block as it runs.
+///
+/// ```
+/// use blockly_parser::interpreter::{ControlFlow, Executor, RunState, Runner};
+/// use blockly_parser::{program_from_xml, Block};
+///
+/// struct Led;
+///
+/// impl Executor for Led {
+///     fn execute(&mut self, _block: &Block) -> ControlFlow {
+///         ControlFlow::Next
+///     }
+/// }
+///
+/// let program = program_from_xml(r#"
+///     <xml><block type="led_on" id="on"><next><block type="led_off" id="off"></block></next></block></xml>
+/// "#).unwrap();
+/// let mut runner = Runner::new(&program, Led);
+/// assert_eq!(runner.current_block().map(|block| block.id.as_str()), Some("on"));
+/// assert_eq!(runner.step(), RunState::Running);
+/// assert_eq!(runner.current_block().map(|block| block.id.as_str()), Some("off"));
+/// assert_eq!(runner.step(), RunState::Finished);
+/// ```
+pub struct Runner<'p, E> {
+    program: &'p Program,
+    executor: E,
+    // The next top-level stack to start
+    next_group: usize,
+    frames: Vec<Frame<'p>>,
+    stopped: bool,
+    control: RunControl,
+    highlight: Option<Highlight<'p>>,
+    observer: Option<Box<dyn Observer + 'p>>,
+    limits: Limits,
+    clock: Option<Box<dyn Clock + 'p>>,
+    steps: u64,
+    // When the first step ran, by the clock
+    started: Option<Duration>,
+    exceeded: Option<Limit>,
+}
+
+impl<'p, E: Executor> Runner<'p, E> {
+    pub fn new(program: &'p Program, executor: E) -> Self {
+        let mut runner = Self::unsettled(program, executor);
+        runner.settle();
+        runner
+    }
+
+    fn unsettled(program: &'p Program, executor: E) -> Self {
+        Self {
+            program,
+            executor,
+            next_group: 0,
+            frames: Vec::new(),
+            stopped: false,
+            control: RunControl::default(),
+            highlight: None,
+            observer: None,
+            limits: Limits::default(),
+            clock: None,
+            steps: 0,
+            started: None,
+            exceeded: None,
+        }
+    }
+
+    /// Call `highlight` with each block just before it runs.
+    pub fn on_highlight<F: FnMut(&Block) + 'p>(mut self, highlight: F) -> Self {
+        self.highlight = Some(Box::new(highlight));
+        self
+    }
+
+    /// Tell `observer` about each block and variable change. Pass a `&mut`
+    /// to look at the observer after the run.
+    pub fn observe<O: Observer + 'p>(mut self, observer: O) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    /// End the run with `RunState::LimitExceeded` once it goes over `limits`.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The clock for `Limits::timeout`, which is not enforced without one,
+    /// e.g. `move || start.elapsed()` for a `std::time::Instant` `start`.
+    pub fn clock<C: Clock + 'p>(mut self, clock: C) -> Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    /// The limit the run went over, if any.
+    pub fn exceeded(&self) -> Option<Limit> {
+        self.exceeded
+    }
+
+    /// A handle for pausing, resuming or cancelling the run.
+    pub fn control(&self) -> RunControl {
+        self.control.clone()
+    }
+
+    pub fn state(&self) -> RunState {
+        if self.control.is_cancelled() {
+            RunState::Cancelled
+        } else if self.exceeded.is_some() {
+            RunState::LimitExceeded
+        } else if self.stopped {
+            RunState::Stopped
+        } else if self.frames.is_empty() {
+            RunState::Finished
+        } else if self.control.is_paused() {
+            RunState::Paused
+        } else {
+            RunState::Running
+        }
+    }
+
+    /// The block the next `step` will run.
+    pub fn current_block(&self) -> Option<&'p Block> {
+        match self.state() {
+            RunState::Running | RunState::Paused => {
+                self.frames.last().map(|frame| &frame.blocks[frame.index])
+            },
+            _ => None,
+        }
+    }
+
+    /// Run the current block, even while paused, so a paused run can be
+    /// stepped through.
+    pub fn step(&mut self) -> RunState {
+        let block = match self.current_block() {
+            Some(block) => block,
+            None => return self.state(),
+        };
+        if let Some(limit) = self.check_limits() {
+            self.exceed(limit);
+            return self.state();
+        }
+        self.steps += 1;
+        if let Some(ref mut highlight) = self.highlight {
+            highlight(block);
+        }
+        // The variables before the step, to find the ones it changes
+        let before = match self.observer {
+            Some(_) => self.executor.environment().map(|env| env.clone()),
+            None => None,
+        };
+        if let Some(ref mut observer) = self.observer {
+            observer.on_block_enter(block);
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            frame.index += 1;
+        }
+        let depth = self.frames.len();
+        match self.executor.execute(block) {
+            ControlFlow::Next => {},
+            ControlFlow::Enter(name) => {
+                if let Some(inner) = block.statements.get(&name) {
+                    self.frames.push(Frame { statement: Some(name), owner: Some(block), blocks: &inner.blocks, index: 0, passes: None });
+                }
+            },
+            ControlFlow::Loop(name) => {
+                let blocks = match block.statements.get(&name) {
+                    Some(inner) => &inner.blocks[..],
+                    None => &[],
+                };
+                self.frames.push(Frame { statement: Some(name), owner: Some(block), blocks, index: 0, passes: Some(0) });
+            },
+            ControlFlow::Break => {
+                self.exit(block);
+                // Leave the innermost loop, or else the top-level stack
+                while let Some(frame) = self.pop_frame() {
+                    if frame.passes.is_some() {
+                        break;
+                    }
+                }
+            },
+            ControlFlow::Stop => {
+                self.exit(block);
+                self.stopped = true;
+                while self.pop_frame().is_some() {}
+            },
+        }
+        if self.frames.len() == depth {
+            self.exit(block);
+        }
+        self.settle();
+        if let Some(before) = before {
+            self.report_changes(&before);
+        }
+        self.state()
+    }
+
+    fn exit(&mut self, block: &Block) {
+        if let Some(ref mut observer) = self.observer {
+            observer.on_block_exit(block);
+        }
+    }
+
+    // Leave the innermost frame, which is done with its block
+    fn pop_frame(&mut self) -> Option<Frame<'p>> {
+        let frame = self.frames.pop()?;
+        if let Some(owner) = frame.owner {
+            self.exit(owner);
+        }
+        Some(frame)
+    }
+
+    fn report_changes(&mut self, before: &Environment) {
+        let observer = match self.observer {
+            Some(ref mut observer) => observer,
+            None => return,
+        };
+        let env = match self.executor.environment() {
+            Some(env) => env,
+            None => return,
+        };
+        for (name, value) in env.variables() {
+            let old = before.get(name);
+            if old != Some(value) {
+                observer.on_variable_change(name, old, value);
+            }
+        }
+    }
+
+    /// Step until the run ends, or is paused or cancelled.
+    pub fn run(&mut self) -> RunState {
+        loop {
+            match self.state() {
+                RunState::Running => self.step(),
+                state => return state,
+            };
+        }
+    }
+
+    /// Save the run's progress and the executor's state. Limits and the
+    /// highlight callback are not saved, and a timeout starts over.
+    pub fn snapshot(&mut self) -> Snapshot<E::State> where E: Resumable {
+        let frames = self.frames.iter()
+            .map(|frame| FrameSnapshot {
+                statement: frame.statement.clone(),
+                index: frame.index,
+                passes: frame.passes,
+            })
+            .collect();
+        Snapshot {
+            next_group: self.next_group,
+            frames,
+            steps: self.steps,
+            stopped: self.stopped,
+            exceeded: self.exceeded,
+            state: self.executor.save(),
+        }
+    }
+
+    /// Resume a run from `snapshot`, which must have been taken from a run
+    /// of the same program.
+    pub fn restore(program: &'p Program, executor: E, snapshot: Snapshot<E::State>) -> Result<Self, SnapshotError> where E: Resumable {
+        let mut runner = Self::unsettled(program, executor);
+        runner.next_group = snapshot.next_group;
+        runner.steps = snapshot.steps;
+        runner.stopped = snapshot.stopped;
+        runner.exceeded = snapshot.exceeded;
+        for (index, saved) in snapshot.frames.into_iter().enumerate() {
+            let frame = runner.restore_frame(saved).ok_or(SnapshotError { frame: index })?;
+            runner.frames.push(frame);
+        }
+        runner.executor.load(snapshot.state);
+        runner.settle();
+        Ok(runner)
+    }
+
+    fn restore_frame(&self, saved: FrameSnapshot) -> Option<Frame<'p>> {
+        let frame = match (self.frames.last(), saved.statement) {
+            (None, None) if saved.passes.is_none() => {
+                let group = self.program.groups.get(self.next_group.checked_sub(1)?)?;
+                Frame { statement: None, owner: None, blocks: &group.blocks, index: saved.index, passes: None }
+            },
+            // The block that ran this frame is the last one its parent ran
+            (Some(parent), Some(name)) => {
+                let block = parent.blocks.get(parent.index.checked_sub(1)?)?;
+                let blocks = match (block.statements.get(&name), saved.passes) {
+                    (Some(inner), _) => &inner.blocks[..],
+                    (None, Some(_)) => &[],
+                    (None, None) => return None,
+                };
+                Frame { statement: Some(name), owner: Some(block), blocks, index: saved.index, passes: saved.passes }
+            },
+            _ => return None,
+        };
+        if frame.index > frame.blocks.len() {
+            return None;
+        }
+        Some(frame)
+    }
+
+    /// Like `run`, but going over a limit is an error.
+    pub fn try_run(&mut self) -> Result<RunState, RunError> {
+        match self.run() {
+            RunState::LimitExceeded => match self.exceeded {
+                Some(limit) => Err(RunError::LimitExceeded(limit)),
+                None => Ok(RunState::LimitExceeded),
+            },
+            state => Ok(state),
+        }
+    }
+
+    // The limit running one more block would go over
+    fn check_limits(&mut self) -> Option<Limit> {
+        if let Some(max) = self.limits.max_steps {
+            if self.steps >= max {
+                return Some(Limit::Steps(max));
+            }
+        }
+        if let (Some(timeout), Some(clock)) = (self.limits.timeout, self.clock.as_ref()) {
+            let now = clock.now();
+            let started = *self.started.get_or_insert(now);
+            if now.saturating_sub(started) > timeout {
+                return Some(Limit::Timeout(timeout));
+            }
+        }
+        None
+    }
+
+    fn exceed(&mut self, limit: Limit) {
+        self.exceeded = Some(limit);
+        while self.pop_frame().is_some() {}
+    }
+
+    // Leave finished stacks and start loops' next passes, until a block is
+    // ready to run or the run has finished
+    fn settle(&mut self) {
+        if self.stopped || self.exceeded.is_some() {
+            return;
+        }
+        loop {
+            let (owner, passes, finished) = match self.frames.last_mut() {
+                Some(frame) => (frame.owner, frame.passes, frame.index >= frame.blocks.len()),
+                None => {
+                    let group = match self.program.groups.get(self.next_group) {
+                        Some(group) => group,
+                        None => return,
+                    };
+                    self.next_group += 1;
+                    self.frames.push(Frame { statement: None, owner: None, blocks: &group.blocks, index: 0, passes: None });
+                    continue;
+                },
+            };
+            if !finished {
+                return;
+            }
+            match (owner, passes) {
+                (Some(block), Some(completed)) if self.executor.next_iteration(block, completed + 1) => {
+                    if let Some(max) = self.limits.max_iterations {
+                        if completed + 1 >= max {
+                            self.exceed(Limit::Iterations(max));
+                            return;
+                        }
+                    }
+                    // A pass over an empty body runs no blocks, so counts as
+                    // a step itself
+                    if self.frames.last().is_some_and(|frame| frame.blocks.is_empty()) {
+                        if let Some(limit) = self.check_limits() {
+                            self.exceed(limit);
+                            return;
+                        }
+                        self.steps += 1;
+                    }
+                    if let Some(frame) = self.frames.last_mut() {
+                        frame.index = 0;
+                        frame.passes = Some(completed + 1);
+                    }
+                },
+                _ => {
+                    self.pop_frame();
+                },
+            }
+        }
+    }
+
+    pub fn executor(&self) -> &E {
+        &self.executor
+    }
+
+    pub fn executor_mut(&mut self) -> &mut E {
+        &mut self.executor
+    }
+
+    pub fn into_executor(self) -> E {
+        self.executor
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+    use super::super::{
+        CustomBlocks,
+        Environment,
+        Standard,
+        Value,
+    };
+    use super::super::super::{
+        program_from_xml,
+        EvalError,
+    };
+
+    // Logs each LED block, pausing the run at `pause` blocks
+    struct Led {
+        log: Vec<String>,
+        control: Option<RunControl>,
+    }
+
+    impl Executor for Led {
+        fn execute(&mut self, block: &Block) -> ControlFlow {
+            match block.block_type.as_str() {
+                "inner_loop" => ControlFlow::Loop("BODY".to_string()),
+                "pause" => {
+                    if let Some(ref control) = self.control {
+                        control.pause();
+                    }
+                    ControlFlow::Next
+                },
+                "break" => ControlFlow::Break,
+                "stop" => ControlFlow::Stop,
+                _ => {
+                    self.log.push(block.id.clone());
+                    ControlFlow::Next
+                },
+            }
+        }
+
+        fn next_iteration(&mut self, block: &Block, completed: usize) -> bool {
+            (completed as i64) < block.field_i64("COUNT").unwrap()
+        }
+    }
+
+    const XML: &str = r#"
+        <xml>
+            <block type="inner_loop" id="loop">
+                <field name="COUNT">2</field>
+                <statement name="BODY">
+                    <block type="led_on" id="on"><next><block type="pause" id="pause"></block></next></block>
+                </statement>
+                <next><block type="led_off" id="off"></block></next>
+            </block>
+            <block type="led_off" id="last"></block>
+        </xml>
+    "#;
+
+    #[test]
+    fn test_runner_steps() {
+        let program = program_from_xml(XML).unwrap();
+        let highlighted = RefCell::new(Vec::new());
+        let mut runner = Runner::new(&program, Led { log: Vec::new(), control: None })
+            .on_highlight(|block| highlighted.borrow_mut().push(block.id.clone()));
+        let mut states = Vec::new();
+        while runner.state() == RunState::Running {
+            states.push(runner.step());
+        }
+        assert_eq!(states.last(), Some(&RunState::Finished));
+        assert_eq!(states.len(), 7);
+        assert_eq!(runner.current_block(), None);
+        assert_eq!(runner.into_executor().log, vec!["on", "on", "off", "last"]);
+        assert_eq!(*highlighted.borrow(), vec!["loop", "on", "pause", "on", "pause", "off", "last"]);
+    }
+
+    #[test]
+    fn test_runner_pause_and_cancel() {
+        let program = program_from_xml(XML).unwrap();
+        let mut runner = Runner::new(&program, Led { log: Vec::new(), control: None });
+        let control = runner.control();
+        runner.executor_mut().control = Some(control.clone());
+
+        assert_eq!(runner.run(), RunState::Paused);
+        assert_eq!(runner.executor().log, vec!["on"]);
+        assert_eq!(runner.current_block().map(|block| block.id.as_str()), Some("on"));
+
+        // Stepping while paused runs a single block
+        assert_eq!(runner.step(), RunState::Paused);
+        assert_eq!(runner.executor().log, vec!["on", "on"]);
+
+        control.resume();
+        assert_eq!(runner.run(), RunState::Paused);
+        control.cancel();
+        control.resume();
+        assert_eq!(runner.run(), RunState::Cancelled);
+        assert_eq!(runner.step(), RunState::Cancelled);
+        assert_eq!(runner.executor().log, vec!["on", "on"]);
+    }
+
+    #[test]
+    fn test_runner_limits() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="inner_loop" id="loop">
+                    <field name="COUNT">1000000</field>
+                    <statement name="BODY"><block type="led_on" id="on"></block></statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let led = || Led { log: Vec::new(), control: None };
+
+        let limits = Limits { max_iterations: Some(3), ..Limits::default() };
+        let mut runner = Runner::new(&program, led()).limits(limits);
+        assert_eq!(runner.try_run(), Err(RunError::LimitExceeded(Limit::Iterations(3))));
+        assert_eq!(runner.state(), RunState::LimitExceeded);
+        assert_eq!(runner.executor().log.len(), 3);
+
+        let limits = Limits { max_steps: Some(10), ..Limits::default() };
+        let mut runner = Runner::new(&program, led()).limits(limits);
+        assert_eq!(runner.run(), RunState::LimitExceeded);
+        assert_eq!(runner.exceeded(), Some(Limit::Steps(10)));
+        assert_eq!(runner.executor().log.len(), 9);
+
+        // Each block takes a second, by this clock
+        let now = Cell::new(Duration::from_secs(0));
+        let limits = Limits { timeout: Some(Duration::from_secs(5)), ..Limits::default() };
+        let mut runner = Runner::new(&program, led())
+            .limits(limits)
+            .clock(|| {
+                now.set(now.get() + Duration::from_secs(1));
+                now.get()
+            });
+        assert_eq!(runner.try_run(), Err(RunError::LimitExceeded(Limit::Timeout(Duration::from_secs(5)))));
+        assert_eq!(runner.executor().log.len(), 5);
+    }
+
+    #[test]
+    fn test_runner_limits_empty_loop() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="controls_whileUntil" id="loop">
+                    <field name="MODE">WHILE</field>
+                    <value name="BOOL"><block type="logic_boolean"><field name="BOOL">TRUE</field></block></value>
+                    <statement name="DO"></statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let limits = Limits { max_steps: Some(100), ..Limits::default() };
+        let mut runner = Runner::new(&program, Standard::new(Board::default())).limits(limits);
+        assert_eq!(runner.try_run(), Err(RunError::LimitExceeded(Limit::Steps(100))));
+
+        let now = Cell::new(Duration::from_secs(0));
+        let limits = Limits { timeout: Some(Duration::from_secs(5)), ..Limits::default() };
+        let mut runner = Runner::new(&program, Standard::new(Board::default()))
+            .limits(limits)
+            .clock(|| {
+                now.set(now.get() + Duration::from_secs(1));
+                now.get()
+            });
+        assert_eq!(runner.try_run(), Err(RunError::LimitExceeded(Limit::Timeout(Duration::from_secs(5)))));
+    }
+
+    // Counts LED blinks in a variable
+    #[derive(Default)]
+    struct Board {
+        env: Environment,
+    }
+
+    impl CustomBlocks for Board {
+        fn environment(&mut self) -> &mut Environment {
+            &mut self.env
+        }
+
+        fn execute(&mut self, _block: &Block) -> Result<ControlFlow, EvalError> {
+            let blinks = self.env.get("blinks").map_or(0.0, Value::to_number);
+            self.env.set("blinks", Value::Number(blinks + 1.0));
+            Ok(ControlFlow::Next)
+        }
+    }
+
+    #[test]
+    fn test_runner_snapshot() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="controls_repeat" id="outer">
+                    <field name="TIMES">3</field>
+                    <statement name="DO">
+                        <block type="controls_repeat" id="inner">
+                            <field name="TIMES">2</field>
+                            <statement name="DO"><block type="blink"></block></statement>
+                        </block>
+                    </statement>
+                </block>
+                <block type="blink"></block>
+            </xml>
+        "#).unwrap();
+        let mut runner = Runner::new(&program, Standard::new(Board::default()));
+        for _ in 0..5 {
+            runner.step();
+        }
+        let snapshot = runner.snapshot();
+        assert_eq!(snapshot.frames.len(), 3);
+        assert_eq!(snapshot.frames[1], FrameSnapshot { statement: Some("DO".to_string()), index: 1, passes: Some(1) });
+        assert_eq!(snapshot.state.environment.get("blinks"), Some(&Value::Number(2.0)));
+
+        // The restored run picks up where the first left off
+        let mut restored = Runner::restore(&program, Standard::new(Board::default()), snapshot.clone()).unwrap();
+        assert_eq!(restored.current_block().map(|block| block.block_type.as_str()), Some("blink"));
+        assert_eq!(restored.run(), RunState::Finished);
+        assert_eq!(restored.executor_mut().blocks().env.get("blinks"), Some(&Value::Number(7.0)));
+
+        let other = program_from_xml("<xml><block type=\"blink\"></block></xml>").unwrap();
+        let result = Runner::restore(&other, Standard::new(Board::default()), snapshot);
+        assert_eq!(result.err(), Some(SnapshotError { frame: 1 }));
+    }
+
+    #[derive(Default)]
+    struct Trace {
+        events: Vec<String>,
+    }
+
+    impl Observer for Trace {
+        fn on_block_enter(&mut self, block: &Block) {
+            self.events.push(format!("enter {}", block.id));
+        }
+
+        fn on_block_exit(&mut self, block: &Block) {
+            self.events.push(format!("exit {}", block.id));
+        }
+
+        fn on_variable_change(&mut self, name: &str, old: Option<&Value>, new: &Value) {
+            let old = old.map_or("unset".to_string(), Value::to_string);
+            self.events.push(format!("{}: {} -> {}", name, old, new));
+        }
+    }
+
+    #[test]
+    fn test_runner_observer() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="controls_repeat" id="loop">
+                    <field name="TIMES">2</field>
+                    <statement name="DO">
+                        <block type="blink" id="blink"></block>
+                    </statement>
+                    <next><block type="controls_flow_statements" id="break"><field name="FLOW">BREAK</field></block></next>
+                </block>
+            </xml>
+        "#).unwrap();
+        let mut trace = Trace::default();
+        let mut runner = Runner::new(&program, Standard::new(Board::default())).observe(&mut trace);
+        assert_eq!(runner.run(), RunState::Finished);
+        drop(runner);
+        assert_eq!(trace.events, vec![
+            "enter loop",
+            "enter blink", "exit blink", "blinks: unset -> 1",
+            "enter blink", "exit blink", "exit loop", "blinks: 1 -> 2",
+            "enter break", "exit break",
+        ]);
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use super::{
+    Environment,
+    Executor,
+    Limit,
+};
+
+/// A `Runner`'s progress through a program, from `Runner::snapshot`, for
+/// resuming the run later, e.g. after the process restarts. `S` is the
+/// executor's own state, such as its variables.
+///
+/// A snapshot only fits the program it was taken from.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Snapshot<S> {
+    /// The next top-level stack to start.
+    pub next_group: usize,
+    /// The stacks being run, outermost first.
+    pub frames: Vec<FrameSnapshot>,
+    /// The blocks run so far, for `Limits::max_steps`.
+    pub steps: u64,
+    pub stopped: bool,
+    pub exceeded: Option<Limit>,
+    pub state: S,
+}
+
+/// A stack being run, and how far it has got.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct FrameSnapshot {
+    /// The statement input of the enclosing frame's last run block, or
+    /// `None` for a top-level stack.
+    pub statement: Option<String>,
+    /// The index of the next block to run.
+    pub index: usize,
+    /// For a loop's body, the passes completed.
+    pub passes: Option<usize>,
+}
+
+/// An `Executor` whose state can be saved with a `Runner`'s snapshot.
+pub trait Resumable: Executor {
+    type State;
+
+    fn save(&mut self) -> Self::State;
+
+    /// Replace the executor's state with a saved one.
+    fn load(&mut self, state: Self::State);
+}
+
+/// The state of a `Standard` executor.
+#[derive(PartialEq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct StandardState {
+    pub environment: Environment,
+    /// Passes to run of the counted loops that have started, by block id.
+    pub loop_counts: HashMap<String, usize>,
+}
@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::f64::consts;
+
+use super::{
+    ControlFlow,
+    Environment,
+    Executor,
+    Resumable,
+    StandardState,
+    Value,
+};
+use super::super::{
+    Block,
+    EvalError,
+};
+
+/// An embedder's own blocks, run by `Standard` alongside Blockly's standard
+/// blocks. The embedder owns the variable environment, so its blocks can
+/// read and set variables.
+pub trait CustomBlocks {
+    fn environment(&mut self) -> &mut Environment;
+
+    /// Run a statement block that is not one of the standard blocks.
+    fn execute(&mut self, block: &Block) -> Result<ControlFlow, EvalError> {
+        Err(unsupported(block))
+    }
+
+    /// Evaluate a value block that is not one of the standard blocks. Use
+    /// `evaluate_input` for its inputs.
+    fn evaluate(&mut self, block: &Block) -> Result<Value, EvalError> {
+        Err(unsupported(block))
+    }
+
+    /// See `Executor::next_iteration`, for custom loop blocks.
+    fn next_iteration(&mut self, _block: &Block, _completed: usize) -> Result<bool, EvalError> {
+        Ok(false)
+    }
+}
+
+fn unsupported(block: &Block) -> EvalError {
+    EvalError::UnsupportedBlock {
+        block_type: block.block_type.clone(),
+        id: block.id.clone(),
+    }
+}
+
+/// The value of a value block, standard or custom.
+///
+/// The standard value blocks are `math_number`, `math_arithmetic`,
+/// `math_single`, `math_modulo`, `math_constant`, `logic_boolean`,
+/// `logic_null`, `logic_compare`, `logic_operation`, `logic_negate`,
+/// `logic_ternary`, `text`, `text_join`, `text_length`, `text_isEmpty` and
+/// `variables_get`.
+pub fn evaluate<B: CustomBlocks + ?Sized>(blocks: &mut B, block: &Block) -> Result<Value, EvalError> {
+    let value = match block.block_type.as_str() {
+        "math_number" => Value::Number(block.field_f64("NUM")?),
+        "math_arithmetic" => {
+            let a = evaluate_input(blocks, block, "A", Value::Number(0.0))?.to_number();
+            let b = evaluate_input(blocks, block, "B", Value::Number(0.0))?.to_number();
+            Value::Number(match block.field_str("OP")? {
+                "ADD" => a + b,
+                "MINUS" => a - b,
+                "MULTIPLY" => a * b,
+                "DIVIDE" => a / b,
+                "POWER" => a.powf(b),
+                op => return Err(unknown_option(block, "OP", op)),
+            })
+        },
+        "math_single" => {
+            let number = evaluate_input(blocks, block, "NUM", Value::Number(0.0))?.to_number();
+            Value::Number(match block.field_str("OP")? {
+                "ROOT" => number.sqrt(),
+                "ABS" => number.abs(),
+                "NEG" => -number,
+                "LN" => number.ln(),
+                "LOG10" => number.log10(),
+                "EXP" => number.exp(),
+                "POW10" => 10f64.powf(number),
+                op => return Err(unknown_option(block, "OP", op)),
+            })
+        },
+        "math_modulo" => {
+            let dividend = evaluate_input(blocks, block, "DIVIDEND", Value::Number(0.0))?.to_number();
+            let divisor = evaluate_input(blocks, block, "DIVISOR", Value::Number(0.0))?.to_number();
+            Value::Number(dividend % divisor)
+        },
+        "math_constant" => Value::Number(match block.field_str("CONSTANT")? {
+            "PI" => consts::PI,
+            "E" => consts::E,
+            "GOLDEN_RATIO" => (1.0 + 5f64.sqrt()) / 2.0,
+            "SQRT2" => consts::SQRT_2,
+            "SQRT1_2" => consts::FRAC_1_SQRT_2,
+            "INFINITY" => f64::INFINITY,
+            constant => return Err(unknown_option(block, "CONSTANT", constant)),
+        }),
+        "logic_boolean" => Value::Boolean(block.field_str("BOOL")? == "TRUE"),
+        "logic_null" => Value::Null,
+        "logic_compare" => {
+            let a = evaluate_input(blocks, block, "A", Value::Number(0.0))?;
+            let b = evaluate_input(blocks, block, "B", Value::Number(0.0))?;
+            let ordering = a.compare(&b);
+            Value::Boolean(match block.field_str("OP")? {
+                "EQ" => ordering.is_some_and(|ordering| ordering.is_eq()),
+                "NEQ" => ordering.is_none_or(|ordering| ordering.is_ne()),
+                "LT" => ordering.is_some_and(|ordering| ordering.is_lt()),
+                "LTE" => ordering.is_some_and(|ordering| ordering.is_le()),
+                "GT" => ordering.is_some_and(|ordering| ordering.is_gt()),
+                "GTE" => ordering.is_some_and(|ordering| ordering.is_ge()),
+                op => return Err(unknown_option(block, "OP", op)),
+            })
+        },
+        // Like JavaScript's `&&` and `||`, the result is one of the operands
+        "logic_operation" => {
+            let a = evaluate_input(blocks, block, "A", Value::Boolean(false))?;
+            match block.field_str("OP")? {
+                "AND" if !a.is_truthy() => a,
+                "OR" if a.is_truthy() => a,
+                "AND" | "OR" => evaluate_input(blocks, block, "B", Value::Boolean(false))?,
+                op => return Err(unknown_option(block, "OP", op)),
+            }
+        },
+        "logic_negate" => Value::Boolean(!evaluate_input(blocks, block, "BOOL", Value::Boolean(true))?.is_truthy()),
+        "logic_ternary" => {
+            let condition = evaluate_input(blocks, block, "IF", Value::Boolean(false))?;
+            let branch = if condition.is_truthy() { "THEN" } else { "ELSE" };
+            evaluate_input(blocks, block, branch, Value::Null)?
+        },
+        "text" => Value::Text(block.field_str("TEXT").unwrap_or("").to_string()),
+        "text_join" => {
+            let items = block.mutation.as_ref()
+                .and_then(|mutation| mutation.attribute("items"))
+                .and_then(|count| count.parse::<usize>().ok())
+                .unwrap_or(2);
+            let mut text = String::new();
+            for item in 0..items {
+                text.push_str(&evaluate_input(blocks, block, &format!("ADD{}", item), Value::from(""))?.to_string());
+            }
+            Value::Text(text)
+        },
+        // Measured in UTF-16 code units, like JavaScript's `length`
+        "text_length" => {
+            let text = evaluate_input(blocks, block, "VALUE", Value::from(""))?.to_string();
+            Value::Number(text.encode_utf16().count() as f64)
+        },
+        "text_isEmpty" => Value::Boolean(evaluate_input(blocks, block, "VALUE", Value::from(""))?.to_string().is_empty()),
+        "variables_get" => {
+            let name = block.field_str("VAR")?;
+            blocks.environment().get(name).cloned().unwrap_or(Value::Null)
+        },
+        _ => blocks.evaluate(block)?,
+    };
+    Ok(value)
+}
+
+/// The value of the value input `name` of `block`, or `default` if the
+/// input is empty.
+pub fn evaluate_input<B: CustomBlocks + ?Sized>(blocks: &mut B, block: &Block, name: &str, default: Value) -> Result<Value, EvalError> {
+    match block.values.get(name) {
+        Some(input) => evaluate(blocks, input.block()),
+        None => Ok(default),
+    }
+}
+
+fn unknown_option(block: &Block, field: &str, option: &str) -> EvalError {
+    EvalError::Custom(format!("block {:?} has unknown {} `{}`", block.id, field, option))
+}
+
+/// An `Executor` that runs Blockly's standard statement blocks itself and
+/// hands every other block to `B`.
+///
+/// The standard statement blocks are `variables_set`, `math_change`,
+/// `text_append`, `controls_if`, `controls_repeat`, `controls_repeat_ext`,
+/// `controls_whileUntil` and the `break` option of
+/// `controls_flow_statements`. The first error stops the run; see `error`.
+pub struct Standard<B> {
+    blocks: B,
+    // Passes left to run of counted loops, by block id
+    counts: HashMap<String, usize>,
+    error: Option<EvalError>,
+}
+
+impl<B: CustomBlocks> Standard<B> {
+    pub fn new(blocks: B) -> Self {
+        Self {
+            blocks,
+            counts: HashMap::new(),
+            error: None,
+        }
+    }
+
+    pub fn blocks(&self) -> &B {
+        &self.blocks
+    }
+
+    pub fn blocks_mut(&mut self) -> &mut B {
+        &mut self.blocks
+    }
+
+    pub fn into_inner(self) -> B {
+        self.blocks
+    }
+
+    /// The error that stopped the run, if any.
+    pub fn error(&self) -> Option<&EvalError> {
+        self.error.as_ref()
+    }
+
+    fn try_execute(&mut self, block: &Block) -> Result<ControlFlow, EvalError> {
+        let blocks = &mut self.blocks;
+        let flow = match block.block_type.as_str() {
+            "variables_set" => {
+                let value = evaluate_input(blocks, block, "VALUE", Value::Number(0.0))?;
+                blocks.environment().set(block.field_str("VAR")?, value);
+                ControlFlow::Next
+            },
+            "math_change" => {
+                let delta = evaluate_input(blocks, block, "DELTA", Value::Number(0.0))?.to_number();
+                let name = block.field_str("VAR")?;
+                let env = blocks.environment();
+                let number = match env.get(name) {
+                    Some(&Value::Number(number)) => number,
+                    _ => 0.0,
+                };
+                env.set(name, Value::Number(number + delta));
+                ControlFlow::Next
+            },
+            "text_append" => {
+                let text = evaluate_input(blocks, block, "TEXT", Value::from(""))?;
+                let name = block.field_str("VAR")?;
+                let env = blocks.environment();
+                let joined = match env.get(name) {
+                    Some(&Value::Null) | None => text.to_string(),
+                    Some(value) => format!("{}{}", value, text),
+                };
+                env.set(name, Value::Text(joined));
+                ControlFlow::Next
+            },
+            "controls_if" => {
+                let branches = block.mutation.as_ref()
+                    .and_then(|mutation| mutation.attribute("elseif"))
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(0) + 1;
+                for branch in 0..branches {
+                    if evaluate_input(blocks, block, &format!("IF{}", branch), Value::Boolean(false))?.is_truthy() {
+                        return Ok(ControlFlow::Enter(format!("DO{}", branch)));
+                    }
+                }
+                ControlFlow::Enter("ELSE".to_string())
+            },
+            "controls_repeat" | "controls_repeat_ext" => {
+                let times = match block.block_type.as_str() {
+                    "controls_repeat" => block.field_f64("TIMES")?,
+                    _ => evaluate_input(blocks, block, "TIMES", Value::Number(0.0))?.to_number(),
+                };
+                // Blockly's loops run while the counter is below the count
+                let passes = if times > 0.0 { times.ceil() as usize } else { 0 };
+                if passes == 0 {
+                    return Ok(ControlFlow::Next);
+                }
+                self.counts.insert(block.id.clone(), passes);
+                ControlFlow::Loop("DO".to_string())
+            },
+            "controls_whileUntil" => {
+                if !self.condition_holds(block)? {
+                    return Ok(ControlFlow::Next);
+                }
+                ControlFlow::Loop("DO".to_string())
+            },
+            "controls_flow_statements" if block.field_str("FLOW")? == "BREAK" => ControlFlow::Break,
+            _ => blocks.execute(block)?,
+        };
+        Ok(flow)
+    }
+
+    // Whether a `controls_whileUntil` loop runs its body again
+    fn condition_holds(&mut self, block: &Block) -> Result<bool, EvalError> {
+        let condition = evaluate_input(&mut self.blocks, block, "BOOL", Value::Boolean(false))?.is_truthy();
+        Ok(match block.field_str("MODE")? {
+            "UNTIL" => !condition,
+            _ => condition,
+        })
+    }
+
+    fn try_next_iteration(&mut self, block: &Block, completed: usize) -> Result<bool, EvalError> {
+        match block.block_type.as_str() {
+            "controls_repeat" | "controls_repeat_ext" => {
+                Ok(self.counts.get(&block.id).is_some_and(|&passes| completed < passes))
+            },
+            "controls_whileUntil" => self.condition_holds(block),
+            _ => self.blocks.next_iteration(block, completed),
+        }
+    }
+}
+
+impl<B: CustomBlocks> Executor for Standard<B> {
+    fn execute(&mut self, block: &Block) -> ControlFlow {
+        if self.error.is_some() {
+            return ControlFlow::Stop;
+        }
+        match self.try_execute(block) {
+            Ok(flow) => flow,
+            Err(error) => {
+                self.error = Some(error);
+                ControlFlow::Stop
+            },
+        }
+    }
+
+    fn next_iteration(&mut self, block: &Block, completed: usize) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+        match self.try_next_iteration(block, completed) {
+            Ok(again) => again,
+            Err(error) => {
+                self.error = Some(error);
+                false
+            },
+        }
+    }
+
+    fn environment(&mut self) -> Option<&mut Environment> {
+        Some(self.blocks.environment())
+    }
+}
+
+impl<B: CustomBlocks> Resumable for Standard<B> {
+    type State = StandardState;
+
+    fn save(&mut self) -> StandardState {
+        StandardState {
+            environment: self.blocks.environment().clone(),
+            loop_counts: self.counts.clone(),
+        }
+    }
+
+    fn load(&mut self, state: StandardState) {
+        *self.blocks.environment() = state.environment;
+        self.counts = state.loop_counts;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        run,
+        Outcome,
+    };
+    use super::super::super::program_from_xml;
+
+    // An LED that logs its brightness, and a sensor that always reads 7
+    #[derive(Default)]
+    struct Board {
+        env: Environment,
+        log: Vec<String>,
+    }
+
+    impl CustomBlocks for Board {
+        fn environment(&mut self) -> &mut Environment {
+            &mut self.env
+        }
+
+        fn execute(&mut self, block: &Block) -> Result<ControlFlow, EvalError> {
+            match block.block_type.as_str() {
+                "led_on" => {
+                    let brightness = evaluate_input(self, block, "BRIGHTNESS", Value::Number(100.0))?;
+                    self.log.push(format!("led_on {}", brightness));
+                    Ok(ControlFlow::Next)
+                },
+                _ => Err(unsupported(block)),
+            }
+        }
+
+        fn evaluate(&mut self, block: &Block) -> Result<Value, EvalError> {
+            match block.block_type.as_str() {
+                "sensor" => Ok(Value::Number(7.0)),
+                _ => Err(unsupported(block)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_standard_blocks() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="variables_set">
+                    <field name="VAR">level</field>
+                    <value name="VALUE"><block type="sensor"></block></value>
+                    <next>
+                        <block type="controls_repeat_ext">
+                            <value name="TIMES"><shadow type="math_number"><field name="NUM">3</field></shadow></value>
+                            <statement name="DO">
+                                <block type="math_change">
+                                    <field name="VAR">level</field>
+                                    <value name="DELTA"><block type="math_number"><field name="NUM">10</field></block></value>
+                                    <next>
+                                        <block type="led_on">
+                                            <value name="BRIGHTNESS">
+                                                <block type="math_arithmetic">
+                                                    <field name="OP">MULTIPLY</field>
+                                                    <value name="A"><block type="variables_get"><field name="VAR">level</field></block></value>
+                                                    <value name="B"><block type="math_number"><field name="NUM">2</field></block></value>
+                                                </block>
+                                            </value>
+                                        </block>
+                                    </next>
+                                </block>
+                            </statement>
+                            <next>
+                                <block type="controls_if">
+                                    <value name="IF0">
+                                        <block type="logic_compare">
+                                            <field name="OP">GT</field>
+                                            <value name="A"><block type="variables_get"><field name="VAR">level</field></block></value>
+                                            <value name="B"><block type="math_number"><field name="NUM">30</field></block></value>
+                                        </block>
+                                    </value>
+                                    <statement name="DO0"><block type="led_on"></block></statement>
+                                </block>
+                            </next>
+                        </block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let mut executor = Standard::new(Board::default());
+        assert_eq!(run(&program, &mut executor), Outcome::Finished);
+        assert_eq!(executor.error(), None);
+        let board = executor.into_inner();
+        assert_eq!(board.log, vec!["led_on 34", "led_on 54", "led_on 74", "led_on 100"]);
+        assert_eq!(board.env.get("level"), Some(&Value::Number(37.0)));
+    }
+
+    #[test]
+    fn test_standard_error() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="buzzer" id="buzz"><next><block type="led_on"></block></next></block>
+            </xml>
+        "#).unwrap();
+        let mut executor = Standard::new(Board::default());
+        assert_eq!(run(&program, &mut executor), Outcome::Stopped);
+        assert_eq!(executor.error(), Some(&EvalError::UnsupportedBlock {
+            block_type: "buzzer".to_string(),
+            id: "buzz".to_string(),
+        }));
+        assert!(executor.blocks().log.is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use indexmap::IndexMap;
+
+use super::super::codegen::blockly::{
+    format_number,
+    parse_number,
+};
+
+/// A value of a Blockly program, with JavaScript's conversions between
+/// types, since that is where Blockly programs usually run.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    Boolean(bool),
+    /// An unset variable or `logic_null`.
+    Null,
+}
+
+impl Value {
+    /// JavaScript's `Number(value)`.
+    pub fn to_number(&self) -> f64 {
+        match *self {
+            Value::Number(number) => number,
+            Value::Text(ref text) => parse_number(text),
+            Value::Boolean(boolean) => if boolean { 1.0 } else { 0.0 },
+            Value::Null => 0.0,
+        }
+    }
+
+    /// JavaScript's truthiness: `0`, `NaN`, `""`, `false` and null are false.
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            Value::Number(number) => number != 0.0 && !number.is_nan(),
+            Value::Text(ref text) => !text.is_empty(),
+            Value::Boolean(boolean) => boolean,
+            Value::Null => false,
+        }
+    }
+
+    /// How JavaScript's `==` and `<` compare two values: texts by their
+    /// characters, anything else as numbers. `None` if either is `NaN`.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+            (Value::Null, Value::Null) => Some(Ordering::Equal),
+            _ => self.to_number().partial_cmp(&other.to_number()),
+        }
+    }
+}
+
+/// JavaScript's `String(value)`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Number(number) => write!(f, "{}", format_number(number)),
+            Value::Text(ref text) => write!(f, "{}", text),
+            Value::Boolean(boolean) => write!(f, "{}", boolean),
+            Value::Null => write!(f, "null"),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(number: f64) -> Self {
+        Value::Number(number)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(boolean: bool) -> Self {
+        Value::Boolean(boolean)
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(text: &'a str) -> Self {
+        Value::Text(text.to_string())
+    }
+}
+
+/// Variables by name, in a stack of scopes. The outermost scope holds the
+/// workspace's variables; embedders push a scope for each procedure call,
+/// for its parameters.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Environment {
+    scopes: Vec<IndexMap<String, Value>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self { scopes: vec![IndexMap::new()] }
+    }
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value of a variable in the innermost scope that has it.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Assign to a variable in the innermost scope that has it, or else
+    /// create it in the outermost scope, as Blockly's variables are global.
+    pub fn set<T: Into<String>>(&mut self, name: T, value: Value) {
+        let name = name.into();
+        let scope = self.scopes.iter().rposition(|scope| scope.contains_key(&name)).unwrap_or(0);
+        self.scopes[scope].insert(name, value);
+    }
+
+    /// Create a variable in the innermost scope, hiding any outer one with
+    /// the same name.
+    pub fn define<T: Into<String>>(&mut self, name: T, value: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.into(), value);
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(IndexMap::new());
+    }
+
+    /// Drop the innermost scope and its variables. The outermost scope is
+    /// never dropped.
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// The variables in scope, leaving out those hidden by an inner scope.
+    pub fn variables(&self) -> IndexMap<&str, &Value> {
+        let mut variables = IndexMap::new();
+        for scope in self.scopes.iter().rev() {
+            for (name, value) in scope {
+                variables.entry(name.as_str()).or_insert(value);
+            }
+        }
+        variables
+    }
+
+    /// The variables of the outermost scope, in the order they were created.
+    pub fn globals(&self) -> &IndexMap<String, Value> {
+        &self.scopes[0]
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_value_conversions() {
+        assert_eq!(Value::from(" 2.5 ").to_number(), 2.5);
+        assert!(Value::from("two").to_number().is_nan());
+        assert!(!Value::Number(0.0).is_truthy() && Value::from("0").is_truthy());
+        assert_eq!(Value::from("10").compare(&Value::Number(9.0)), Some(Ordering::Greater));
+        assert_eq!(Value::from("10").compare(&Value::from("9")), Some(Ordering::Less));
+        assert_eq!(Value::Number(3.0).to_string(), "3");
+        assert_eq!(Value::Boolean(true).to_string(), "true");
+    }
+
+    #[test]
+    fn test_environment() {
+        let mut env = Environment::new();
+        env.set("count", Value::Number(1.0));
+        env.push_scope();
+        env.define("x", Value::Number(2.0));
+        env.set("count", Value::Number(3.0));
+        env.set("total", Value::Number(4.0));
+        assert_eq!(env.get("x"), Some(&Value::Number(2.0)));
+        env.pop_scope();
+        env.pop_scope();
+        assert_eq!(env.get("x"), None);
+        assert_eq!(env.globals().keys().collect::<Vec<_>>(), ["count", "total"]);
+        assert_eq!(env.get("count"), Some(&Value::Number(3.0)));
+    }
+}
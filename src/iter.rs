@@ -0,0 +1,187 @@
+use super::{
+    Program,
+    Block,
+    FieldValue,
+    TraversalOptions,
+};
+
+/// A depth-first, pre-order iterator over blocks. Each block is yielded before
+/// the blocks nested in its fields, value inputs and statement inputs, and a
+/// stack is walked from top to bottom.
+pub struct Blocks<'a> {
+    stack: Vec<&'a Block>,
+    options: TraversalOptions,
+}
+
+impl<'a> Blocks<'a> {
+    fn new(mut roots: Vec<&'a Block>, options: TraversalOptions) -> Self {
+        roots.reverse();
+        Self {
+            stack: roots,
+            options,
+        }
+    }
+
+    fn children(&self, block: &'a Block) -> Vec<&'a Block> {
+        if !self.options.enters(block) {
+            return Vec::new();
+        }
+        let mut children = Vec::new();
+        for field in block.fields.values() {
+            if let FieldValue::ExpressionField(ref expression) = *field {
+                if self.options.includes(expression) {
+                    children.push(&**expression);
+                }
+            }
+        }
+        for input in block.values.values() {
+            if self.options.includes(input.block()) {
+                children.push(input.block());
+            }
+        }
+        for statement in block.statements.values() {
+            children.extend(self.options.stack(&statement.blocks));
+        }
+        children
+    }
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = &'a Block;
+
+    fn next(&mut self) -> Option<&'a Block> {
+        let block = self.stack.pop()?;
+        let mut children = self.children(block);
+        children.reverse();
+        self.stack.extend(children);
+        Some(block)
+    }
+}
+
+impl Program {
+    pub fn iter_blocks(&self) -> Blocks<'_> {
+        self.iter_blocks_with(&TraversalOptions::default())
+    }
+
+    /// Like `iter_blocks`, skipping disabled blocks or the insides of
+    /// collapsed blocks as `options` say.
+    pub fn iter_blocks_with(&self, options: &TraversalOptions) -> Blocks<'_> {
+        let roots = self.groups.iter()
+            .flat_map(|group| options.stack(&group.blocks))
+            .collect();
+        Blocks::new(roots, *options)
+    }
+}
+
+impl Block {
+    /// All blocks nested inside this one, not including the block itself.
+    pub fn descendants(&self) -> Blocks<'_> {
+        Blocks::new(child_blocks(self), TraversalOptions::default())
+    }
+}
+
+/// The blocks directly attached to a block's inputs, in the order
+/// fields, values, statements.
+pub(crate) fn child_blocks(block: &Block) -> Vec<&Block> {
+    let mut children = Vec::new();
+    for field in block.fields.values() {
+        if let FieldValue::ExpressionField(ref expression) = *field {
+            children.push(&**expression);
+        }
+    }
+    for input in block.values.values() {
+        children.push(input.block());
+    }
+    for statement in block.statements.values() {
+        children.extend(statement.blocks.iter());
+    }
+    children
+}
+
+pub(crate) fn child_blocks_mut(block: &mut Block) -> Vec<&mut Block> {
+    let mut children = Vec::new();
+    for field in block.fields.values_mut() {
+        if let FieldValue::ExpressionField(ref mut expression) = *field {
+            children.push(&mut **expression);
+        }
+    }
+    for input in block.values.values_mut() {
+        children.push(input.block_mut());
+    }
+    for statement in block.statements.values_mut() {
+        children.extend(statement.blocks.iter_mut());
+    }
+    children
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{
+        program_from_xml,
+        DisabledBlocks,
+        TraversalOptions,
+    };
+
+    #[test]
+    fn test_iter_blocks() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="1">
+                    <statement name="BODY">
+                        <block type="inner_loop" id="2">
+                            <value name="COUNT">
+                                <block type="math_number" id="3">
+                                    <field name="NUM">3</field>
+                                </block>
+                            </value>
+                            <next>
+                                <block type="led_on" id="4"></block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="led_off" id="5"></block>
+            </xml>
+        "#;
+        let program = program_from_xml(xml).unwrap();
+
+        let ids: Vec<&str> = program.iter_blocks().map(|block| block.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "2", "3", "4", "5"]);
+
+        assert_eq!(program.iter_blocks().filter(|block| block.block_type.starts_with("led")).count(), 2);
+
+        let main_loop_block = &program.groups[0].blocks[0];
+        let descendant_ids: Vec<&str> = main_loop_block.descendants().map(|block| block.id.as_str()).collect();
+        assert_eq!(descendant_ids, vec!["2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_iter_blocks_with() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="1" collapsed="true">
+                    <statement name="BODY">
+                        <block type="inner_loop" id="2" disabled="true">
+                            <value name="COUNT">
+                                <block type="math_number" id="3"></block>
+                            </value>
+                            <next>
+                                <block type="led_on" id="4"></block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="led_off" id="5" disabled="true"></block>
+            </xml>
+        "#;
+        let program = program_from_xml(xml).unwrap();
+        let ids = |options: TraversalOptions| -> Vec<String> {
+            program.iter_blocks_with(&options).map(|block| block.id.clone()).collect()
+        };
+
+        assert_eq!(ids(TraversalOptions::skip_disabled()), vec!["1", "4"]);
+        assert_eq!(ids(TraversalOptions { disabled: DisabledBlocks::SkipWithNext, ..TraversalOptions::default() }), vec!["1"]);
+        assert_eq!(ids(TraversalOptions { enter_collapsed: false, ..TraversalOptions::default() }), vec!["1", "5"]);
+    }
+}
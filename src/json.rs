@@ -0,0 +1,915 @@
+use std::mem;
+use std::ops::Deref;
+
+use indexmap::IndexMap;
+
+use serde_json::{
+    Map,
+    Value,
+};
+use sxd_document::dom::ChildOfRoot;
+
+use super::{
+    BlockRegistry,
+    InputKind,
+    Program,
+    StatementBody,
+    Block,
+    BlockComment,
+    FieldValue,
+    FieldVariable,
+    InputContent,
+    Mutation,
+    Variable,
+    WorkspaceComment,
+    RawElement,
+    RawNode,
+    Diagnostics,
+    ParseError,
+    ParseOutcome,
+    SourceMap,
+    Warning,
+    parse_package,
+};
+use super::xml::round_coordinate;
+use super::limits::{
+    check_depth,
+    DEFAULT_MAX_DEPTH,
+};
+
+// Blockly's JSON format does not say whether an input is a value or a statement
+// input, so without a definition statement inputs are recognized by their
+// conventional names.
+const STATEMENT_INPUT_NAMES: &[&str] = &["DO", "ELSE", "STACK", "BODY", "SUBSTACK"];
+
+/// Parse a workspace saved with Blockly's JSON serialization system
+/// (`Blockly.serialization.workspaces.save`).
+///
+/// Inputs are mapped onto `Block::statements` when the connected block has a
+/// `next` chain or the input has a conventional statement name (`DO`, `DO0`,
+/// `ELSE`, `STACK`, `BODY`, `SUBSTACK`), and onto `Block::values` otherwise.
+pub fn program_from_json(json: &str) -> Result<Program, ParseError> {
+    program_from_json_with_registry(json, &BlockRegistry::new())
+}
+
+/// Like `program_from_json`, but inputs of block types defined in `registry`
+/// are mapped by the kind of input their definition gives.
+pub fn program_from_json_with_registry(json: &str, registry: &BlockRegistry) -> Result<Program, ParseError> {
+    parse_program(json, registry, &mut Diagnostics::new())
+}
+
+/// Like `program_from_json_with_registry`, but also reports the recoverable
+/// problems found along the way. The spans are empty, as they only cover XML.
+pub fn program_from_json_with_diagnostics(json: &str, registry: &BlockRegistry) -> Result<ParseOutcome, ParseError> {
+    let mut warnings = Diagnostics::new();
+    let program = parse_program(json, registry, &mut warnings)?;
+    Ok(ParseOutcome {
+        program,
+        warnings,
+        spans: SourceMap::default(),
+    })
+}
+
+fn parse_program(json: &str, registry: &BlockRegistry, diagnostics: &mut Diagnostics) -> Result<Program, ParseError> {
+    let root = parse_json(json)?;
+    let root = as_object(&root, "workspace")?;
+
+    let mut program = Program::new();
+
+    if let Some(variables) = root.get("variables") {
+        for variable in as_array(variables, "variables")? {
+            program.variables.push(variable_from_json(variable)?);
+        }
+    }
+
+    if let Some(comments) = root.get("workspaceComments") {
+        for comment in as_array(comments, "workspaceComments")? {
+            program.comments.push(workspace_comment_from_json(comment)?);
+        }
+    }
+
+    if let Some(blocks) = root.get("blocks") {
+        let blocks = as_object(blocks, "blocks")?;
+        if let Some(top_blocks) = blocks.get("blocks") {
+            for block in as_array(top_blocks, "blocks")? {
+                let group = statement_body_from_json(block, &program.variables, registry, diagnostics)?;
+                program.groups.push(group);
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+fn variable_from_json(value: &Value) -> Result<Variable, ParseError> {
+    let variable = as_object(value, "variable")?;
+    let var_type = get_string(variable, "type")
+        .and_then(|var_type| if var_type.is_empty() { None } else { Some(var_type) });
+    Ok(Variable {
+        name: require_string(variable, "variable", "name")?,
+        id: get_string(variable, "id").unwrap_or_default(),
+        var_type,
+    })
+}
+
+fn workspace_comment_from_json(value: &Value) -> Result<WorkspaceComment, ParseError> {
+    let comment = as_object(value, "workspace comment")?;
+    let position = match (comment.get("x"), comment.get("y")) {
+        (Some(x), Some(y)) => Some((as_coordinate(x, "x")?, as_coordinate(y, "y")?)),
+        _ => None
+    };
+    Ok(WorkspaceComment {
+        id: get_string(comment, "id").unwrap_or_default(),
+        text: get_string(comment, "text").unwrap_or_default(),
+        position,
+        width: get_coordinate(comment, "width")?,
+        height: get_coordinate(comment, "height")?,
+    })
+}
+
+pub(crate) fn statement_body_from_json(first_block: &Value, variables: &[Variable], registry: &BlockRegistry, diagnostics: &mut Diagnostics) -> Result<StatementBody, ParseError> {
+    let mut blocks = Vec::new();
+    let mut block_value = first_block;
+    loop {
+        blocks.push(block_from_json(block_value, variables, registry, diagnostics)?);
+        match get_next_block(block_value)? {
+            Some(next_block) => { block_value = next_block; },
+            None => break
+        }
+    }
+    Ok(StatementBody {
+        blocks,
+        shadow: false,
+    })
+}
+
+pub(crate) fn block_from_json(value: &Value, variables: &[Variable], registry: &BlockRegistry, diagnostics: &mut Diagnostics) -> Result<Block, ParseError> {
+    let object = as_object(value, "block")?;
+
+    let mut block = Block::new(require_string(object, "block", "type")?);
+    block.id = get_string(object, "id").unwrap_or_default();
+
+    if let (Some(x), Some(y)) = (object.get("x"), object.get("y")) {
+        block.position = Some((as_coordinate(x, "x")?, as_coordinate(y, "y")?));
+    }
+
+    block.flags.collapsed = get_bool(object, "collapsed")?.unwrap_or(false);
+    block.flags.deletable = get_bool(object, "deletable")?.unwrap_or(true);
+    block.flags.movable = get_bool(object, "movable")?.unwrap_or(true);
+    block.flags.editable = get_bool(object, "editable")?.unwrap_or(true);
+    block.flags.disabled = !get_bool(object, "enabled")?.unwrap_or(true);
+    if let Some(Value::Array(reasons)) = object.get("disabledReasons") {
+        block.flags.disabled = block.flags.disabled || !reasons.is_empty();
+    }
+
+    if let Some(extra_state) = object.get("extraState") {
+        block.mutation = Some(mutation_from_json(extra_state)?);
+    }
+
+    if let Some(comment) = object.get("icons").and_then(|icons| icons.get("comment")) {
+        block.comment = Some(comment_from_json(comment)?);
+    }
+
+    block.data = get_string(object, "data");
+
+    if let Some(fields) = object.get("fields") {
+        for (name, field) in as_object(fields, "fields")? {
+            let (value, variable) = field_from_json(field, variables)?;
+            block.fields.insert(name.clone(), value);
+            if let Some(variable) = variable {
+                block.field_variables.insert(name.clone(), variable);
+            }
+        }
+    }
+
+    if let Some(inputs) = object.get("inputs") {
+        for (name, input) in as_object(inputs, "inputs")? {
+            let input = as_object(input, "input")?;
+            let (content, shadow) = match (input.get("block"), input.get("shadow")) {
+                (Some(content), Some(_)) => {
+                    diagnostics.push(Warning::DroppedShadow { block: block.id.clone(), input: name.clone() });
+                    (content, false)
+                },
+                (Some(block), None) => (block, false),
+                (None, Some(shadow)) => (shadow, true),
+                (None, None) => continue
+            };
+
+            let kind = registry.get(&block.block_type)
+                .and_then(|definition| definition.input(name))
+                .map(|input| input.kind);
+            let is_statement = match kind {
+                Some(kind) => kind == InputKind::Statement,
+                None => is_statement_input(name, content),
+            };
+            if is_statement {
+                let mut body = statement_body_from_json(content, variables, registry, diagnostics)?;
+                body.shadow = shadow;
+                block.statements.insert(name.clone(), body);
+            } else {
+                let input_block = block_from_json(content, variables, registry, diagnostics)?;
+                let input_content = if shadow {
+                    InputContent::Shadow(input_block)
+                } else {
+                    InputContent::Block(input_block)
+                };
+                block.values.insert(name.clone(), input_content);
+            }
+        }
+    }
+
+    Ok(block)
+}
+
+fn comment_from_json(value: &Value) -> Result<BlockComment, ParseError> {
+    let comment = as_object(value, "comment")?;
+    Ok(BlockComment {
+        text: get_string(comment, "text").unwrap_or_default(),
+        pinned: get_bool(comment, "pinned")?.unwrap_or(false),
+        width: get_coordinate(comment, "width")?,
+        height: get_coordinate(comment, "height")?,
+    })
+}
+
+fn is_statement_input(name: &str, content: &Value) -> bool {
+    if content.get("next").is_some() {
+        return true;
+    }
+    is_statement_input_name(name)
+}
+
+pub(crate) fn is_statement_input_name(name: &str) -> bool {
+    let base_name = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    STATEMENT_INPUT_NAMES.contains(&base_name)
+}
+
+fn get_next_block(block: &Value) -> Result<Option<&Value>, ParseError> {
+    match block.get("next") {
+        Some(next) => {
+            let next = as_object(next, "next")?;
+            Ok(next.get("block").or_else(|| next.get("shadow")))
+        },
+        None => Ok(None)
+    }
+}
+
+fn field_from_json(value: &Value, variables: &[Variable]) -> Result<(FieldValue, Option<FieldVariable>), ParseError> {
+    match *value {
+        Value::String(ref text) => Ok((FieldValue::SimpleField(text.clone()), None)),
+        Value::Number(ref number) => Ok((FieldValue::SimpleField(number.to_string()), None)),
+        Value::Bool(checked) => {
+            let text = if checked { "TRUE" } else { "FALSE" };
+            Ok((FieldValue::SimpleField(text.to_string()), None))
+        },
+        Value::Object(ref object) => {
+            // Variable fields are saved as a reference into the `variables` list.
+            let id = require_string(object, "field", "id")?;
+            let variable = variables.iter().find(|variable| variable.id == id);
+            let name = variable
+                .map(|variable| variable.name.clone())
+                .or_else(|| get_string(object, "name"))
+                .unwrap_or_else(|| id.clone());
+            let var_type = variable
+                .and_then(|variable| variable.var_type.clone())
+                .or_else(|| get_string(object, "type").filter(|var_type| !var_type.is_empty()));
+            Ok((FieldValue::SimpleField(name), Some(FieldVariable { id, var_type })))
+        },
+        _ => Err(ParseError::UnexpectedStructure(format!("unsupported field value: {}", value)))
+    }
+}
+
+/// Blocks with legacy `mutationToDom` hooks save their extra state as an XML
+/// string; newer blocks save a JSON object, whose scalar members become
+/// mutation attributes and whose nested arrays and objects become child elements.
+pub(crate) fn mutation_from_json(value: &Value) -> Result<Mutation, ParseError> {
+    match *value {
+        Value::String(ref xml) if xml.trim_start().starts_with('<') => {
+            check_depth(xml, DEFAULT_MAX_DEPTH)?;
+            let package = parse_package(xml)?;
+            let document = package.as_document();
+            let mutation_el = document.root()
+                .children()
+                .iter()
+                .filter_map(|child| {
+                    if let &ChildOfRoot::Element(el) = child {
+                        return Some(el);
+                    }
+                    None
+                })
+                .next();
+            match mutation_el {
+                Some(el) => Ok(Mutation::from_element(el)),
+                None => Err(ParseError::UnexpectedStructure("empty extraState".to_string()))
+            }
+        },
+        Value::Object(ref object) => {
+            let raw = raw_element_from_json("mutation", object);
+            let children = raw.children
+                .into_iter()
+                .filter_map(|child| {
+                    match child {
+                        RawNode::Element(el) => Some(el),
+                        RawNode::Text(_) => None,
+                    }
+                })
+                .collect();
+            Ok(Mutation {
+                attributes: raw.attributes,
+                children,
+            })
+        },
+        ref scalar => {
+            let mut attributes = IndexMap::default();
+            attributes.insert("value".to_string(), scalar_to_string(scalar));
+            Ok(Mutation {
+                attributes,
+                children: Vec::new(),
+            })
+        }
+    }
+}
+
+fn raw_element_from_json(name: &str, object: &Map<String, Value>) -> RawElement {
+    let mut element = RawElement {
+        name: name.to_string(),
+        namespace: None,
+        prefix: None,
+        attributes: IndexMap::default(),
+        children: Vec::new(),
+    };
+    for (key, value) in object {
+        match *value {
+            Value::Object(ref child) => {
+                element.children.push(RawNode::Element(raw_element_from_json(key, child)));
+            },
+            Value::Array(ref items) => {
+                for item in items {
+                    let child = match *item {
+                        Value::Object(ref item_object) => raw_element_from_json(key, item_object),
+                        ref scalar => RawElement {
+                            name: key.clone(),
+                            namespace: None,
+                            prefix: None,
+                            attributes: IndexMap::default(),
+                            children: vec![RawNode::Text(scalar_to_string(scalar))],
+                        }
+                    };
+                    element.children.push(RawNode::Element(child));
+                }
+            },
+            ref scalar => {
+                element.attributes.insert(key.clone(), scalar_to_string(scalar));
+            }
+        }
+    }
+    element
+}
+
+pub(crate) fn scalar_to_string(value: &Value) -> String {
+    match *value {
+        Value::String(ref text) => text.clone(),
+        Value::Null => "".to_string(),
+        ref other => other.to_string(),
+    }
+}
+
+// JSON value utilities
+
+/// A parsed JSON document. Each block in a stack nests two levels inside the
+/// one above it, too deep for serde_json's recursive parser and for the
+/// recursive drop of `Value`, so it is read and taken apart with explicit
+/// stacks.
+pub(crate) struct Json(Value);
+
+impl Deref for Json {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl Drop for Json {
+    fn drop(&mut self) {
+        let mut pending = vec![mem::take(&mut self.0)];
+        while let Some(value) = pending.pop() {
+            match value {
+                Value::Array(items) => pending.extend(items),
+                Value::Object(object) => pending.extend(object.into_iter().map(|(_, item)| item)),
+                _ => {},
+            }
+        }
+    }
+}
+
+// An array or object still being read
+struct Container {
+    array: Option<Vec<Value>>,
+    object: Map<String, Value>,
+    // The key of the member being read
+    key: String,
+    is_next: bool,
+    counted: bool,
+}
+
+/// Parse a JSON document, rejecting arrays and objects nested deeper than
+/// `DEFAULT_MAX_DEPTH`. As with `check_depth`, `next` objects and the
+/// `block` or `shadow` directly inside them are not counted.
+pub(crate) fn parse_json(json: &str) -> Result<Json, ParseError> {
+    let mut reader = JsonReader { text: json, pos: 0 };
+    let mut open: Vec<Container> = Vec::new();
+    let mut depth = 0;
+
+    let root = 'values: loop {
+        reader.skip_whitespace();
+        let mut value = match reader.peek() {
+            Some(b'[') | Some(b'{') => {
+                let is_array = reader.peek() == Some(b'[');
+                reader.pos += 1;
+                let key = open.last().filter(|parent| parent.array.is_none()).map(|parent| parent.key.as_str());
+                let in_next = open.last().is_some_and(|parent| parent.is_next);
+                let is_next = key == Some("next");
+                let in_chain = is_next || (in_next && matches!(key, Some("block") | Some("shadow")));
+                let counted = !in_chain;
+                if counted {
+                    depth += 1;
+                    if depth > DEFAULT_MAX_DEPTH {
+                        return Err(ParseError::TooDeep { max_depth: DEFAULT_MAX_DEPTH });
+                    }
+                }
+                let mut container = Container {
+                    array: if is_array { Some(Vec::new()) } else { None },
+                    object: Map::new(),
+                    key: String::new(),
+                    is_next,
+                    counted,
+                };
+                reader.skip_whitespace();
+                if reader.peek() == Some(if is_array { b']' } else { b'}' }) {
+                    reader.pos += 1;
+                    if counted {
+                        depth -= 1;
+                    }
+                    container.into_value()
+                } else {
+                    if !is_array {
+                        container.key = reader.key()?;
+                    }
+                    open.push(container);
+                    continue;
+                }
+            },
+            Some(b'"') => Value::String(reader.string()?),
+            Some(b't') => reader.literal("true", Value::Bool(true))?,
+            Some(b'f') => reader.literal("false", Value::Bool(false))?,
+            Some(b'n') => reader.literal("null", Value::Null)?,
+            Some(b'-') | Some(b'0'..=b'9') => reader.number()?,
+            Some(_) => return Err(reader.error("expected value")),
+            None => return Err(reader.error("EOF while parsing a value")),
+        };
+
+        // Add the value to its container, then finish every container it
+        // closes
+        loop {
+            let parent = match open.last_mut() {
+                Some(parent) => parent,
+                None => break 'values value,
+            };
+            match parent.array {
+                Some(ref mut items) => items.push(value),
+                None => { parent.object.insert(mem::take(&mut parent.key), value); },
+            }
+            let close = if parent.array.is_some() { b']' } else { b'}' };
+            reader.skip_whitespace();
+            match reader.peek() {
+                Some(b',') => {
+                    reader.pos += 1;
+                    if parent.array.is_none() {
+                        parent.key = reader.key()?;
+                    }
+                    continue 'values;
+                },
+                Some(byte) if byte == close => {
+                    reader.pos += 1;
+                    let container = open.pop().expect("open container");
+                    if container.counted {
+                        depth -= 1;
+                    }
+                    value = container.into_value();
+                },
+                Some(_) if close == b']' => return Err(reader.error("expected `,` or `]`")),
+                Some(_) => return Err(reader.error("expected `,` or `}`")),
+                None => return Err(reader.error("EOF while parsing a list or an object")),
+            }
+        }
+    };
+
+    let root = Json(root);
+    reader.skip_whitespace();
+    if reader.pos < json.len() {
+        return Err(reader.error("trailing characters"));
+    }
+    Ok(root)
+}
+
+impl Container {
+    fn into_value(self) -> Value {
+        match self.array {
+            Some(items) => Value::Array(items),
+            None => Value::Object(self.object),
+        }
+    }
+}
+
+struct JsonReader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    // An object member's key and the colon after it
+    fn key(&mut self) -> Result<String, ParseError> {
+        self.skip_whitespace();
+        if self.peek() != Some(b'"') {
+            return Err(self.error("key must be a string"));
+        }
+        let key = self.string()?;
+        self.skip_whitespace();
+        if self.peek() != Some(b':') {
+            return Err(self.error("expected `:`"));
+        }
+        self.pos += 1;
+        Ok(key)
+    }
+
+    // Strings and numbers are short, so serde_json reads them once their end
+    // is found
+    fn string(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        let bytes = self.text.as_bytes();
+        let mut end = start + 1;
+        loop {
+            match bytes.get(end) {
+                Some(b'"') => break,
+                Some(b'\\') => end += 2,
+                Some(_) => end += 1,
+                None => {
+                    self.pos = bytes.len();
+                    return Err(self.error("EOF while parsing a string"));
+                },
+            }
+        }
+        self.pos = end + 1;
+        serde_json::from_str(&self.text[start..self.pos]).map_err(|error| self.scalar_error(start, error))
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9') | Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e') | Some(b'E')) {
+            self.pos += 1;
+        }
+        serde_json::from_str(&self.text[start..self.pos]).map_err(|error| self.scalar_error(start, error))
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, ParseError> {
+        if !self.text[self.pos..].starts_with(word) {
+            return Err(self.error("expected value"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        self.error_at(self.pos, message.to_string())
+    }
+
+    fn scalar_error(&self, start: usize, error: serde_json::Error) -> ParseError {
+        let message = error.to_string();
+        // serde_json adds the position in the token, which is replaced with
+        // the position in the document
+        let message = match message.find(" at line ") {
+            Some(index) => message[..index].to_string(),
+            None => message,
+        };
+        self.error_at(start + error.column().saturating_sub(1), message)
+    }
+
+    fn error_at(&self, pos: usize, message: String) -> ParseError {
+        let before = &self.text.as_bytes()[..pos.min(self.text.len())];
+        let line_start = before.iter().rposition(|&byte| byte == b'\n').map_or(0, |index| index + 1);
+        ParseError::Json {
+            line: 1 + before.iter().filter(|&&byte| byte == b'\n').count(),
+            column: pos - line_start + 1,
+            message,
+        }
+    }
+}
+
+pub(crate) fn as_object<'a>(value: &'a Value, what: &str) -> Result<&'a Map<String, Value>, ParseError> {
+    value.as_object()
+        .ok_or_else(|| ParseError::UnexpectedStructure(format!("expected {} to be an object", what)))
+}
+
+pub(crate) fn as_array<'a>(value: &'a Value, what: &str) -> Result<&'a Vec<Value>, ParseError> {
+    value.as_array()
+        .ok_or_else(|| ParseError::UnexpectedStructure(format!("expected {} to be an array", what)))
+}
+
+fn as_coordinate(value: &Value, what: &str) -> Result<i32, ParseError> {
+    let coordinate = value.as_f64()
+        .ok_or_else(|| ParseError::UnexpectedStructure(format!("expected {} to be a number", what)))?;
+    round_coordinate(coordinate)
+        .ok_or_else(|| ParseError::UnexpectedStructure(format!("{} is out of range: {}", what, coordinate)))
+}
+
+fn get_coordinate(object: &Map<String, Value>, key: &str) -> Result<Option<i32>, ParseError> {
+    match object.get(key) {
+        Some(value) => as_coordinate(value, key).map(Some),
+        None => Ok(None)
+    }
+}
+
+pub(crate) fn get_string(object: &Map<String, Value>, key: &str) -> Option<String> {
+    object.get(key)
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string())
+}
+
+pub(crate) fn require_string(object: &Map<String, Value>, element: &str, key: &str) -> Result<String, ParseError> {
+    get_string(object, key).ok_or_else(|| {
+        ParseError::MissingAttribute {
+            element: element.to_string(),
+            attribute: key.to_string(),
+            span: None,
+        }
+    })
+}
+
+pub(crate) fn get_bool(object: &Map<String, Value>, key: &str) -> Result<Option<bool>, ParseError> {
+    match object.get(key) {
+        Some(value) => {
+            value.as_bool()
+                .map(Some)
+                .ok_or_else(|| ParseError::UnexpectedStructure(format!("expected {} to be a boolean", key)))
+        },
+        None => Ok(None)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_program_from_json() {
+        let json: &str = r#"
+            {
+                "blocks": {
+                    "languageVersion": 0,
+                    "blocks": [
+                        {
+                            "type": "main_loop",
+                            "id": "[.)/fqUYv92(mzb{?:~u",
+                            "x": 50,
+                            "y": 50,
+                            "deletable": false,
+                            "data": "pin 3",
+                            "icons": {
+                                "comment": { "text": "Runs forever", "pinned": false, "height": 80, "width": 160 }
+                            },
+                            "inputs": {
+                                "BODY": {
+                                    "block": {
+                                        "type": "led_on",
+                                        "id": "^3xb.m4E9i0;3$R10(=5",
+                                        "fields": { "TIME": 300 },
+                                        "next": {
+                                            "block": {
+                                                "type": "variables_set",
+                                                "id": "HX4*sB9=gbJtq$Y{ke6b",
+                                                "fields": { "VAR": { "id": "v1" } },
+                                                "inputs": {
+                                                    "VALUE": {
+                                                        "shadow": {
+                                                            "type": "math_number",
+                                                            "id": "n1",
+                                                            "fields": { "NUM": 1.5 }
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        },
+                        {
+                            "type": "controls_if",
+                            "id": "if1",
+                            "extraState": { "elseIfCount": 1, "hasElse": true }
+                        },
+                        {
+                            "type": "lists_create_with",
+                            "id": "list1",
+                            "extraState": "<mutation items=\"3\"></mutation>"
+                        }
+                    ]
+                },
+                "variables": [
+                    { "name": "count", "id": "v1" }
+                ],
+                "workspaceComments": [
+                    { "id": "c1", "text": "Blink", "x": 10, "y": 20, "width": 200, "height": 100 }
+                ]
+            }
+        "#;
+
+        let program = program_from_json(json).unwrap();
+        assert_eq!(program.variables.len(), 1);
+        assert_eq!(program.variables[0].var_type, None);
+        assert_eq!(program.groups.len(), 3);
+        assert_eq!(program.comments.len(), 1);
+        assert_eq!(program.comments[0].text, "Blink");
+        assert_eq!(program.comments[0].position, Some((10, 20)));
+
+        let main_loop_block = &program.groups[0].blocks[0];
+        assert_eq!(main_loop_block.block_type, "main_loop");
+        assert_eq!(main_loop_block.position, Some((50, 50)));
+        assert!(!main_loop_block.flags.deletable);
+        assert_eq!(main_loop_block.data, Some("pin 3".to_string()));
+        assert_eq!(main_loop_block.comment, Some(BlockComment {
+            text: "Runs forever".to_string(),
+            pinned: false,
+            width: Some(160),
+            height: Some(80),
+        }));
+
+        let body = main_loop_block.statements.get("BODY").unwrap();
+        assert_eq!(body.blocks.len(), 2);
+        assert_eq!(body.blocks[0].fields.get("TIME"), Some(&FieldValue::SimpleField("300".to_string())));
+
+        let set_block = &body.blocks[1];
+        assert_eq!(set_block.fields.get("VAR"), Some(&FieldValue::SimpleField("count".to_string())));
+        assert_eq!(set_block.field_variables["VAR"].id, "v1");
+        let value = set_block.values.get("VALUE").unwrap();
+        assert!(value.is_shadow());
+        assert_eq!(value.block().fields.get("NUM"), Some(&FieldValue::SimpleField("1.5".to_string())));
+
+        let if_mutation = program.groups[1].blocks[0].mutation.as_ref().unwrap();
+        assert_eq!(if_mutation.attribute("elseIfCount"), Some("1"));
+        assert_eq!(if_mutation.attribute("hasElse"), Some("true"));
+
+        let list_mutation = program.groups[2].blocks[0].mutation.as_ref().unwrap();
+        assert_eq!(list_mutation.attribute("items"), Some("3"));
+    }
+
+    #[test]
+    fn test_program_from_json_errors() {
+        match program_from_json("{\"blocks\": ") {
+            Err(ParseError::Json { line: 1, .. }) => {},
+            other => panic!("Expected JSON error, got {:?}", other),
+        }
+
+        assert_eq!(
+            program_from_json(r#"{"blocks": {"blocks": [{"id": "abc"}]}}"#).unwrap_err(),
+            ParseError::MissingAttribute {
+                element: "block".to_string(),
+                attribute: "type".to_string(),
+                span: None,
+            }
+        );
+
+        assert_eq!(
+            program_from_json(r#"{"blocks": {"blocks": [{"type": "led_on", "x": 1e12, "y": 0}]}}"#).unwrap_err(),
+            ParseError::UnexpectedStructure("x is out of range: 1000000000000".to_string())
+        );
+
+        assert_eq!(program_from_json("{}").unwrap().groups.len(), 0);
+
+        for (json, message) in [
+            ("{} {}", "trailing characters"),
+            ("{\"a\" 1}", "expected `:`"),
+            ("[1 2]", "expected `,` or `]`"),
+            ("{1: 2}", "key must be a string"),
+            ("\n  [tru]", "expected value"),
+        ] {
+            match parse_json(json) {
+                Err(ParseError::Json { message: ref error, .. }) => assert_eq!(error, message, "{}", json),
+                Err(error) => panic!("Expected JSON error for {}, got {:?}", json, error),
+                Ok(_) => panic!("Expected JSON error for {}", json),
+            }
+        }
+        assert!(matches!(parse_json("\n  [tru]"), Err(ParseError::Json { line: 2, column: 4, .. })));
+        let nested = format!("{}{}", "[".repeat(DEFAULT_MAX_DEPTH + 1), "]".repeat(DEFAULT_MAX_DEPTH + 1));
+        assert!(matches!(parse_json(&nested), Err(ParseError::TooDeep { .. })));
+    }
+
+    // A stack of `length` blocks, each nested in the previous one's `next`
+    pub(crate) fn long_stack_json(length: usize) -> String {
+        let mut json = String::from(r#"{"blocks": {"blocks": ["#);
+        for index in 0..length {
+            if index > 0 {
+                json.push_str(r#", "next": {"block": "#);
+            }
+            json.push_str(&format!(r#"{{"type": "led_on", "id": "b{}", "fields": {{"TIME": "{}"}}"#, index, index));
+        }
+        for index in 0..length {
+            json.push('}');
+            if index + 1 < length {
+                json.push('}');
+            }
+        }
+        json.push_str("]}}");
+        json
+    }
+
+    #[test]
+    fn test_program_from_json_long_stack() {
+        let program = program_from_json(&long_stack_json(5000)).unwrap();
+        assert_eq!(program.groups[0].blocks.len(), 5000);
+        assert_eq!(program.groups[0].blocks[4999].field_str("TIME"), Ok("4999"));
+    }
+
+    #[test]
+    fn test_program_from_json_with_registry() {
+        let json = r#"
+            {
+                "blocks": {
+                    "blocks": [
+                        {
+                            "type": "forever",
+                            "inputs": {
+                                "LOOP_BODY": { "block": { "type": "led_on" } },
+                                "DO": { "block": { "type": "math_number" } }
+                            }
+                        }
+                    ]
+                }
+            }
+        "#;
+        let registry = BlockRegistry::from_json(r#"[
+            {
+                "type": "forever",
+                "message0": "forever %1 %2",
+                "args0": [
+                    { "type": "input_statement", "name": "LOOP_BODY" },
+                    { "type": "input_value", "name": "DO" }
+                ]
+            }
+        ]"#).unwrap();
+
+        let guessed = &program_from_json(json).unwrap().groups[0].blocks[0];
+        assert!(guessed.values.contains_key("LOOP_BODY"));
+        assert!(guessed.statements.contains_key("DO"));
+
+        let defined = &program_from_json_with_registry(json, &registry).unwrap().groups[0].blocks[0];
+        assert_eq!(defined.statements["LOOP_BODY"].blocks[0].block_type, "led_on");
+        assert_eq!(defined.values["DO"].block().block_type, "math_number");
+    }
+
+    #[test]
+    fn test_program_from_json_with_diagnostics() {
+        let json = r#"
+            {
+                "blocks": {
+                    "blocks": [
+                        {
+                            "type": "controls_if",
+                            "id": "a",
+                            "inputs": {
+                                "IF0": {
+                                    "shadow": { "type": "logic_boolean", "id": "b" },
+                                    "block": { "type": "logic_compare", "id": "c" }
+                                },
+                                "DO0": {
+                                    "shadow": { "type": "led_on", "id": "d" },
+                                    "block": { "type": "led_off", "id": "e" }
+                                }
+                            }
+                        }
+                    ]
+                }
+            }
+        "#;
+        let outcome = program_from_json_with_diagnostics(json, &BlockRegistry::new()).unwrap();
+        assert_eq!(outcome.warnings.into_vec(), [
+            Warning::DroppedShadow { block: "a".to_string(), input: "DO0".to_string() },
+            Warning::DroppedShadow { block: "a".to_string(), input: "IF0".to_string() },
+        ]);
+        let block = &outcome.program.groups[0].blocks[0];
+        assert_eq!(block.values["IF0"].block().id, "c");
+        assert_eq!(block.statements["DO0"].blocks[0].id, "e");
+        assert_eq!(outcome.program, program_from_json(json).unwrap());
+    }
+}
@@ -0,0 +1,317 @@
+use serde_json::{
+    Map,
+    Value,
+};
+
+use super::{
+    BlockRegistry,
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    InputContent,
+    ParseError,
+    program_from_json,
+    program_from_json_with_registry,
+    program_from_xml,
+    program_to_xml,
+};
+use super::writer::write_mutation;
+
+/// Serialize a program in Blockly's JSON format, as loaded by
+/// `Blockly.serialization.workspaces.load`.
+///
+/// Mutations are saved as an XML string under `extraState`, the way Blockly
+/// saves blocks with legacy `mutationToDom` hooks. The JSON format has no
+/// place for expression fields, `Program::extras`, or anything kept by
+/// `ParserOptions::preserve_unknown`, so those are left out.
+pub fn program_to_json(program: &Program) -> String {
+    let mut root = Map::new();
+    if !program.groups.is_empty() {
+        let mut blocks = Map::new();
+        blocks.insert("languageVersion".to_string(), Value::from(0));
+        let top_blocks = program.groups.iter()
+            .filter_map(|group| stack_to_json(group, program))
+            .collect();
+        blocks.insert("blocks".to_string(), Value::Array(top_blocks));
+        root.insert("blocks".to_string(), Value::Object(blocks));
+    }
+    if !program.variables.is_empty() {
+        let variables = program.variables.iter()
+            .map(|variable| {
+                let mut object = Map::new();
+                object.insert("name".to_string(), Value::from(variable.name.as_str()));
+                object.insert("id".to_string(), Value::from(variable.id.as_str()));
+                if let Some(ref var_type) = variable.var_type {
+                    object.insert("type".to_string(), Value::from(var_type.as_str()));
+                }
+                Value::Object(object)
+            })
+            .collect();
+        root.insert("variables".to_string(), Value::Array(variables));
+    }
+    if !program.comments.is_empty() {
+        let comments = program.comments.iter()
+            .map(|comment| {
+                let mut object = Map::new();
+                object.insert("id".to_string(), Value::from(comment.id.as_str()));
+                object.insert("text".to_string(), Value::from(comment.text.as_str()));
+                if let Some((x, y)) = comment.position {
+                    object.insert("x".to_string(), Value::from(x));
+                    object.insert("y".to_string(), Value::from(y));
+                }
+                insert_size(&mut object, comment.width, comment.height);
+                Value::Object(object)
+            })
+            .collect();
+        root.insert("workspaceComments".to_string(), Value::Array(comments));
+    }
+    Value::Object(root).to_string()
+}
+
+/// Convert a workspace from Blockly XML to Blockly's JSON format. See
+/// `program_to_json` for what the JSON format cannot hold.
+pub fn xml_to_json(xml: &str) -> Result<String, ParseError> {
+    Ok(program_to_json(&program_from_xml(xml)?))
+}
+
+/// Convert a workspace from Blockly's JSON format to Blockly XML. See
+/// `program_from_json` for how inputs are told apart: a statement input with
+/// an unconventional name holding a single block, or only a shadow, becomes
+/// a `<value>`. Use `json_to_xml_with_registry` to keep such inputs.
+pub fn json_to_xml(json: &str) -> Result<String, ParseError> {
+    Ok(program_to_xml(&program_from_json(json)?))
+}
+
+/// Like `json_to_xml`, but inputs of block types defined in `registry` are
+/// written as the kind of input their definition gives.
+pub fn json_to_xml_with_registry(json: &str, registry: &BlockRegistry) -> Result<String, ParseError> {
+    Ok(program_to_xml(&program_from_json_with_registry(json, registry)?))
+}
+
+// The JSON format nests each block in a stack under the previous one's `next`
+fn stack_to_json(body: &StatementBody, program: &Program) -> Option<Value> {
+    let key = if body.shadow { "shadow" } else { "block" };
+    body.blocks.iter().rev().fold(None, |next, block| {
+        let mut object = block_to_json(block, program);
+        if let Some(next) = next {
+            let mut connection = Map::new();
+            connection.insert(key.to_string(), next);
+            object.insert("next".to_string(), Value::Object(connection));
+        }
+        Some(Value::Object(object))
+    })
+}
+
+fn block_to_json(block: &Block, program: &Program) -> Map<String, Value> {
+    let mut object = Map::new();
+    object.insert("type".to_string(), Value::from(block.block_type.as_str()));
+    if !block.id.is_empty() {
+        object.insert("id".to_string(), Value::from(block.id.as_str()));
+    }
+    if let Some((x, y)) = block.position {
+        object.insert("x".to_string(), Value::from(x));
+        object.insert("y".to_string(), Value::from(y));
+    }
+
+    let flags = &block.flags;
+    for &(key, value, default) in &[
+        ("collapsed", flags.collapsed, false),
+        ("enabled", !flags.disabled, true),
+        ("deletable", flags.deletable, true),
+        ("movable", flags.movable, true),
+        ("editable", flags.editable, true),
+    ] {
+        if value != default {
+            object.insert(key.to_string(), Value::from(value));
+        }
+    }
+
+    if let Some(ref mutation) = block.mutation {
+        let mut xml = String::new();
+        write_mutation(&mut xml, mutation);
+        object.insert("extraState".to_string(), Value::from(xml));
+    }
+
+    if let Some(ref comment) = block.comment {
+        let mut json = Map::new();
+        json.insert("text".to_string(), Value::from(comment.text.as_str()));
+        json.insert("pinned".to_string(), Value::from(comment.pinned));
+        insert_size(&mut json, comment.width, comment.height);
+        let mut icons = Map::new();
+        icons.insert("comment".to_string(), Value::Object(json));
+        object.insert("icons".to_string(), Value::Object(icons));
+    }
+
+    if let Some(ref data) = block.data {
+        object.insert("data".to_string(), Value::from(data.as_str()));
+    }
+
+    let mut fields = Map::new();
+    for (name, value) in &block.fields {
+        let text = match *value {
+            FieldValue::SimpleField(ref text) => text,
+            FieldValue::ExpressionField(_) => continue,
+        };
+        let json = match block.field_variables.get(name) {
+            Some(variable) => {
+                // Variables declared on the workspace are found by id, so only
+                // undeclared ones need their name and type
+                let mut reference = Map::new();
+                reference.insert("id".to_string(), Value::from(variable.id.as_str()));
+                if !program.variables.iter().any(|declared| declared.id == variable.id) {
+                    reference.insert("name".to_string(), Value::from(text.as_str()));
+                    if let Some(ref var_type) = variable.var_type {
+                        reference.insert("type".to_string(), Value::from(var_type.as_str()));
+                    }
+                }
+                Value::Object(reference)
+            },
+            None => Value::from(text.as_str()),
+        };
+        fields.insert(name.clone(), json);
+    }
+    if !fields.is_empty() {
+        object.insert("fields".to_string(), Value::Object(fields));
+    }
+
+    let mut inputs = Map::new();
+    for (name, content) in &block.values {
+        let (key, input_block) = match *content {
+            InputContent::Block(ref input_block) => ("block", input_block),
+            InputContent::Shadow(ref input_block) => ("shadow", input_block),
+        };
+        let mut input = Map::new();
+        input.insert(key.to_string(), Value::Object(block_to_json(input_block, program)));
+        inputs.insert(name.clone(), Value::Object(input));
+    }
+    for (name, body) in &block.statements {
+        if let Some(first) = stack_to_json(body, program) {
+            let key = if body.shadow { "shadow" } else { "block" };
+            let mut input = Map::new();
+            input.insert(key.to_string(), first);
+            inputs.insert(name.clone(), Value::Object(input));
+        }
+    }
+    if !inputs.is_empty() {
+        object.insert("inputs".to_string(), Value::Object(inputs));
+    }
+
+    object
+}
+
+fn insert_size(object: &mut Map<String, Value>, width: Option<i32>, height: Option<i32>) {
+    if let Some(width) = width {
+        object.insert("width".to_string(), Value::from(width));
+    }
+    if let Some(height) = height {
+        object.insert("height".to_string(), Value::from(height));
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_xml_json_round_trip() {
+        let xml = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">count</variable><variable type="Colour" id="v2">shade</variable></variables>
+                <block type="controls_repeat_ext" id="a" x="10" y="20" collapsed="true" deletable="false">
+                    <comment pinned="true" h="80" w="160">Blink</comment>
+                    <data>pin 3</data>
+                    <value name="TIMES"><shadow type="math_number" id="b"><field name="NUM">10</field></shadow></value>
+                    <statement name="DO">
+                        <block type="variables_set" id="c" disabled="true">
+                            <field name="VAR" id="v1">count</field>
+                            <value name="VALUE"><block type="math_number" id="d"><field name="NUM">1</field></block></value>
+                            <next><block type="lists_create_with" id="e"><mutation items="2"></mutation></block></next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="led_on" id="f" x="100" y="20"></block>
+                <comment id="g" x="1" y="2" w="200" h="100">Notes</comment>
+            </xml>
+        "#;
+        let program = program_from_xml(xml).unwrap();
+        let json = xml_to_json(xml).unwrap();
+        assert_eq!(program_from_json(&json).unwrap(), program);
+        assert_eq!(program_from_xml(&json_to_xml(&json).unwrap()).unwrap(), program);
+    }
+
+    #[test]
+    fn test_json_xml_round_trip() {
+        let json = r#"{
+            "blocks": {
+                "languageVersion": 0,
+                "blocks": [{
+                    "type": "main_loop", "id": "a", "x": 50, "y": 50, "editable": false,
+                    "inputs": {
+                        "BODY": {"block": {
+                            "type": "variables_set", "id": "b",
+                            "fields": {"VAR": {"id": "v1"}},
+                            "inputs": {"VALUE": {"shadow": {"type": "math_number", "id": "c", "fields": {"NUM": "1.5"}}}},
+                            "next": {"block": {"type": "led_on", "id": "d", "extraState": "<mutation pin=\"3\"></mutation>"}}
+                        }}
+                    }
+                }]
+            },
+            "variables": [{"name": "count", "id": "v1"}],
+            "workspaceComments": [{"id": "e", "text": "Blink", "x": 10, "y": 20}]
+        }"#;
+        let expected: Value = serde_json::from_str(json).unwrap();
+        let xml = json_to_xml(json).unwrap();
+        let converted: Value = serde_json::from_str(&xml_to_json(&xml).unwrap()).unwrap();
+        assert_eq!(converted, expected);
+    }
+
+    #[test]
+    fn test_json_to_xml_long_stack() {
+        let mut program = Program::new();
+        program.groups.push(StatementBody {
+            blocks: (0..500)
+                .map(|index| {
+                    let mut block = Block::new("led_on");
+                    block.id = format!("b{}", index);
+                    block
+                })
+                .collect(),
+            shadow: false,
+        });
+        let xml = json_to_xml(&program_to_json(&program)).unwrap();
+        assert_eq!(program_from_xml(&xml).unwrap(), program);
+    }
+
+    #[test]
+    fn test_json_to_xml_with_registry() {
+        let xml = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="forever" id="a">
+                    <statement name="LOOP_BODY"><block type="led_on" id="b"></block></statement>
+                    <statement name="SETUP"><shadow type="led_off" id="c"></shadow></statement>
+                </block>
+            </xml>
+        "#;
+        let registry = BlockRegistry::from_json(r#"[
+            {
+                "type": "forever",
+                "message0": "forever %1 setup %2",
+                "args0": [
+                    { "type": "input_statement", "name": "LOOP_BODY" },
+                    { "type": "input_statement", "name": "SETUP" }
+                ]
+            }
+        ]"#).unwrap();
+        let program = program_from_xml(xml).unwrap();
+        let json = xml_to_json(xml).unwrap();
+
+        // Without definitions the inputs are taken for value inputs
+        let guessed = program_from_xml(&json_to_xml(&json).unwrap()).unwrap();
+        assert!(guessed.groups[0].blocks[0].statements.is_empty());
+
+        let converted = json_to_xml_with_registry(&json, &registry).unwrap();
+        assert_eq!(program_from_xml(&converted).unwrap(), program);
+    }
+}
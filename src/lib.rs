@@ -1,362 +1,449 @@
-extern crate sxd_document;
-
-use std::collections::HashMap;
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use sxd_document::{
-    parser,
-    Package,
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate sxd_document;
+#[cfg(feature = "std")]
+extern crate serde_json;
+extern crate indexmap;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "derive")]
+extern crate blockly_parser_derive;
+#[cfg(any(feature = "stream", feature = "quick-xml-backend"))]
+extern crate quick_xml;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "wasm")]
+extern crate serde_wasm_bindgen;
+#[cfg(feature = "encoding")]
+extern crate base64;
+#[cfg(feature = "compression")]
+extern crate flate2;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "toml")]
+extern crate toml;
+#[cfg(feature = "python")]
+extern crate pyo3;
+
+#[cfg(feature = "std")]
+pub mod codegen;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod grade;
+#[cfg(feature = "std")]
+pub mod interpreter;
+#[cfg(feature = "std")]
+pub mod opt;
+#[cfg(feature = "std")]
+pub mod tree;
+
+#[cfg(feature = "std")]
+mod batch;
+#[cfg(feature = "std")]
+mod borrowed;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod bundle;
+mod bytecode;
+#[cfg(feature = "std")]
+mod compare;
+#[cfg(feature = "compression")]
+mod compression;
+#[cfg(feature = "std")]
+mod definitions;
+#[cfg(feature = "std")]
+mod diagnostics;
+#[cfg(feature = "std")]
+mod display;
+#[cfg(feature = "std")]
+mod dom;
+#[cfg(feature = "std")]
+mod dot;
+#[cfg(feature = "std")]
+mod edit;
+#[cfg(feature = "encoding")]
+mod encoding;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "std")]
+mod events;
+#[cfg(feature = "std")]
+mod fields;
+#[cfg(feature = "std")]
+mod fingerprint;
+#[cfg(feature = "std")]
+mod fragments;
+mod hash;
+#[cfg(feature = "std")]
+mod ids;
+#[cfg(feature = "std")]
+mod from_block;
+#[cfg(feature = "std")]
+mod index;
+#[cfg(feature = "std")]
+mod iter;
+#[cfg(feature = "std")]
+mod json;
+#[cfg(feature = "std")]
+mod json_writer;
+#[cfg(feature = "std")]
+mod limits;
+#[cfg(feature = "std")]
+mod merge;
+#[cfg(feature = "std")]
+mod migrations;
+mod model;
+#[cfg(feature = "std")]
+mod normalize;
+#[cfg(feature = "std")]
+mod options;
+#[cfg(feature = "std")]
+mod orphans;
+#[cfg(feature = "std")]
+mod path;
+#[cfg(feature = "std")]
+mod patch;
+mod plugins;
+#[cfg(feature = "std")]
+mod policy;
+#[cfg(feature = "std")]
+mod procedures;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "std")]
+mod query;
+#[cfg(feature = "quick-xml-backend")]
+mod quick;
+mod raw;
+#[cfg(feature = "std")]
+mod render;
+#[cfg(feature = "std")]
+mod repair;
+#[cfg(feature = "std")]
+mod sanitize;
+#[cfg(feature = "std")]
+mod schema;
+#[cfg(feature = "std")]
+mod shadows;
+#[cfg(feature = "std")]
+mod similarity;
+#[cfg(feature = "std")]
+mod span;
+#[cfg(feature = "std")]
+mod stats;
+#[cfg(feature = "std")]
+mod steps;
+#[cfg(feature = "stream")]
+mod stream;
+#[cfg(feature = "std")]
+mod toolbox;
+#[cfg(feature = "std")]
+mod traversal;
+#[cfg(feature = "std")]
+mod typed;
+#[cfg(feature = "std")]
+mod validate;
+#[cfg(feature = "std")]
+mod visitor;
+#[cfg(feature = "std")]
+mod workspace;
+#[cfg(feature = "std")]
+mod writer;
+#[cfg(feature = "std")]
+mod xml;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+/// The map type used for fields, inputs and attributes, which keeps them in
+/// document order.
+pub use indexmap::IndexMap;
+#[cfg(feature = "std")]
+pub use batch::{
+    parse_batch,
+    parse_batch_with_options,
 };
-use sxd_document::dom::{
-    Document,
-    ChildOfRoot,
-    Element,
-    ChildOfElement,
+#[cfg(feature = "std")]
+pub use borrowed::{
+    XmlDocument,
+    ProgramRef,
+    VariableRef,
+    StatementBodyRef,
+    BlockRef,
+    BlockRefs,
+    InputContentRef,
+    FieldValueRef,
+};
+#[cfg(feature = "std")]
+pub use builder::{
+    BlockBuilder,
+    ProgramBuilder,
+};
+#[cfg(feature = "std")]
+pub use bundle::{
+    Bundle,
+    BundleEntry,
+    BundleSource,
+    bundle_from_file,
+    bundle_from_jsonl,
+    bundle_from_reader,
+};
+pub use bytecode::{
+    Bytecode,
+    BytecodeError,
+    Instr,
+};
+#[cfg(feature = "std")]
+pub use definitions::{
+    BlockDefinition,
+    BlockRegistry,
+    Connection,
+    DropdownOption,
+    FieldDefinition,
+    FieldKind,
+    InputDefinition,
+    InputKind,
+    definitions_from_json,
+};
+#[cfg(feature = "std")]
+pub use diagnostics::{
+    Diagnostics,
+    ParseOutcome,
+    Warning,
+};
+#[cfg(feature = "std")]
+pub use error::{
+    ParseError,
+    FieldError,
+    FromBlockError,
+    CodegenError,
+    EvalError,
+    RunError,
+    SnapshotError,
+    DuplicateIdError,
+    MergeError,
+    RepeatedIdError,
+    EventError,
+    PatchError,
+};
+#[cfg(feature = "std")]
+pub use events::{
+    BlockChange,
+    Event,
+    MoveTarget,
+    events_from_json,
+};
+#[cfg(feature = "std")]
+pub use fragments::{
+    programs_from_xml_multi,
+    programs_from_xml_multi_with_options,
+};
+#[cfg(feature = "std")]
+pub use from_block::{
+    FromBlock,
+    FromField,
+    expect_block_type,
+};
+#[cfg(feature = "derive")]
+pub use blockly_parser_derive::FromBlock;
+#[cfg(feature = "std")]
+pub use ids::{
+    IdGenerator,
+    SoupIdGenerator,
+};
+#[cfg(feature = "std")]
+pub use index::BlockIndex;
+#[cfg(feature = "std")]
+pub use iter::Blocks;
+#[cfg(feature = "std")]
+pub use json::{
+    program_from_json,
+    program_from_json_with_diagnostics,
+    program_from_json_with_registry,
+};
+#[cfg(feature = "std")]
+pub use json_writer::{
+    json_to_xml,
+    json_to_xml_with_registry,
+    program_to_json,
+    xml_to_json,
+};
+#[cfg(feature = "std")]
+use limits::check_depth;
+#[cfg(feature = "std")]
+use typed::check_colour_field;
+#[cfg(feature = "std")]
+pub use limits::DEFAULT_MAX_DEPTH;
+#[cfg(feature = "std")]
+pub use merge::{
+    Conflict,
+    ConflictKind,
+    MergeResult,
+};
+#[cfg(feature = "std")]
+pub use migrations::{
+    Migration,
+    MigrationOutcome,
+    program_from_xml_migrate,
+};
+pub use model::{
+    Program,
+    Variable,
+    WorkspaceComment,
+    StatementBody,
+    Block,
+    BlockFlags,
+    FieldVariable,
+    BlockComment,
+    Mutation,
+    InputContent,
+    FieldValue,
+    MapHasher,
+    FnvHasher,
+};
+#[cfg(feature = "std")]
+pub use options::{
+    ParserOptions,
+    ValueNext,
+    BLOCKLY_NAMESPACE,
+    XHTML_NAMESPACE,
+};
+#[cfg(feature = "std")]
+pub use path::{
+    BlockPath,
+    PathStep,
+};
+pub use plugins::{
+    Extensions,
+    UnknownElementHandler,
+};
+#[cfg(feature = "std")]
+pub use policy::{
+    Policy,
+    Violation,
+};
+#[cfg(feature = "std")]
+pub use procedures::{
+    Procedure,
+    Procedures,
+};
+#[cfg(feature = "std")]
+pub use query::{
+    Query,
+    QueryMatch,
+};
+pub use raw::{
+    RawElement,
+    RawNode,
+};
+#[cfg(feature = "std")]
+pub use repair::{
+    Repair,
+    RepairOutcome,
+    program_from_xml_repair,
+};
+#[cfg(feature = "std")]
+pub use sanitize::SanitizeOptions;
+#[cfg(feature = "std")]
+pub use similarity::{
+    Signature,
+    similar_pairs,
+};
+#[cfg(feature = "std")]
+pub use span::{
+    SourceMap,
+    Span,
+};
+#[cfg(feature = "std")]
+pub use stats::ProgramStats;
+#[cfg(feature = "std")]
+pub use steps::{
+    LoopCount,
+    LoopRules,
+    Steps,
+};
+#[cfg(feature = "stream")]
+pub use stream::{
+    BlockEvent,
+    BlockEventReader,
+};
+#[cfg(feature = "std")]
+pub use toolbox::{
+    Toolbox,
+    ToolboxCategory,
+    ToolboxItem,
+    ToolboxKind,
+    toolbox_from_json,
+    toolbox_from_xml,
+};
+#[cfg(feature = "std")]
+pub use traversal::{
+    DisabledBlocks,
+    TraversalOptions,
+};
+#[cfg(feature = "std")]
+pub use typed::{
+    Date,
+    Rgb,
+    TypedField,
+};
+#[cfg(feature = "std")]
+pub use validate::ValidationIssue;
+#[cfg(feature = "std")]
+pub use visitor::Visitor;
+#[cfg(feature = "std")]
+pub use workspace::{
+    BlockData,
+    BlockId,
+    Link,
+    Workspace,
+};
+#[cfg(feature = "std")]
+pub use writer::{
+    program_to_xml,
+    program_to_xml_with_ids,
+};
+#[cfg(feature = "wasm")]
+pub use wasm::parse_workspace;
+#[cfg(feature = "encoding")]
+pub use encoding::{
+    program_from_base64,
+    program_from_data_url,
+};
+#[cfg(feature = "encoding")]
+pub use error::DecodeError;
+#[cfg(feature = "compression")]
+pub use compression::{
+    program_from_deflate,
+    program_from_gzip,
+    program_to_gzip,
+};
+#[cfg(feature = "std")]
+pub use xml::{
+    program_from_xml,
+    program_from_reader,
+    program_from_file,
+    program_from_xml_with_options,
+    program_from_xml_with_diagnostics,
+};
+#[cfg(feature = "std")]
+use xml::{
+    check_input_children,
+    get_attribute,
+    get_child_element,
+    get_next_block_element,
+    get_xml_element,
+    missing_attribute,
+    parse_bool,
+    parse_coordinate,
+    parse_package,
+    program_from_element,
+    stack_from_element,
+    stack_from_xml,
+    unexpected_element,
 };
-
-#[derive(Debug)]
-pub struct Program {
-    pub groups: Vec<StatementBody>
-}
-
-#[derive(PartialEq, Debug)]
-pub struct StatementBody {
-    pub blocks: Vec<Block>
-}
-
-#[derive(PartialEq, Debug)]
-pub struct Block {
-    pub block_type: String,
-    pub id: String,
-    pub fields: HashMap<String, FieldValue>,
-    pub statements: HashMap<String, StatementBody>,
-}
-
-#[derive(PartialEq, Debug)]
-pub enum FieldValue {
-    SimpleField(String),
-    ExpressionField(Block),
-}
-
-impl Program {
-    pub fn new() -> Self {
-        Self {
-            groups: Vec::new()
-        }
-    }
-}
-
-impl StatementBody {
-    fn new(first_block: Option<Element>) -> Self {
-        let mut blocks = Vec::new();
-        if let Some(el) = first_block {
-            // Create each block, put them into the statement body
-            let mut block_el: Element;
-            block_el = el;
-            loop {
-                blocks.push(Block::new(block_el));
-                if let Some(next_block) = get_next_block_element(&block_el) {
-                    block_el = next_block;
-                } else {
-                    break;
-                }
-            }
-        }
-        Self {
-            blocks
-        }
-    }
-}
-
-impl Block {
-    fn new(block_el: Element) -> Self {
-        let mut block = Self {
-            block_type: "".to_string(),
-            id: "".to_string(),
-            fields: HashMap::new(),
-            statements: HashMap::new()
-        };
-
-        for attribute in block_el.attributes().iter() {
-            let name = attribute.name().local_part();
-            let value = attribute.value().to_string();
-            match name {
-                "type" => { block.block_type = value; },
-                "id" => { block.id = value; },
-                _ => {}
-            }
-        }
-
-        for child in block_el.children().iter() {
-            if let &ChildOfElement::Element(child_el) = child {
-                let child_name = child_el.name().local_part();
-                match child_name {
-                    "statement" => {
-                        let statement_el = child_el;
-                        let statement_name = get_attribute(statement_el, "name").unwrap();
-                        let statement_body = StatementBody::new(get_first_child_element(statement_el));
-                        block.statements.insert(statement_name, statement_body);
-                    },
-                    "field" => {
-                        let field_el = child_el;
-                        let field_name = get_attribute(field_el, "name").unwrap();
-                        let field_value = FieldValue::new(field_el);
-                        block.fields.insert(field_name, field_value);
-                    },
-                    _ => {}
-                }
-            }
-        }
-
-        block
-    }
-}
-
-impl FieldValue {
-    fn new(field_el: Element) -> Self {
-        for child in field_el.children().iter() {
-            match child {
-                &ChildOfElement::Text(text_node) => {
-                    let value = text_node.text().to_string();
-                    return FieldValue::SimpleField(value);
-                },
-                _ => panic!("TODO: Implement expression fields")
-            }
-        }
-        panic!("Expected child nodes for field");
-    }
-}
-
-// Utilities for creating Blockly data structures
-
-pub fn program_from_xml(xml: &str) -> Program {
-    let mut program = Program::new();
-
-    let package: Package = parser::parse(xml).expect("Failed to parse XML!");
-    let document: Document = package.as_document();
-
-    let xml_element = get_xml_element(document).expect("Failed to find XML element!");
-
-    for child in xml_element.children().iter() {
-        if let &ChildOfElement::Element(el) = child {
-            let element_name = el.name().local_part();
-            match element_name {
-                "block" => {
-                    program.groups.push(StatementBody::new(Some(el)));
-                },
-                // TODO: handle `variables`
-                _ => {}
-            }
-        }
-    }
-
-    program
-}
-
-fn get_next_block_element<'b>(block_el: &Element<'b>) -> Option<Element<'b>> {
-    let next_el: Option<Element> = block_el.children()
-        .iter()
-        .filter_map(|child| {
-            if let &ChildOfElement::Element(el) = child {
-                if el.name().local_part() == "next" {
-                    return Some(el);
-                }
-            }
-            None
-        })
-        .next();
-
-    if let Some(next_el) = next_el {
-        let next_block_el: Option<Element> = next_el.children()
-            .iter()
-            .filter_map(|&child| {
-                if let ChildOfElement::Element(el) = child {
-                    if el.name().local_part() == "block" {
-                        return Some(el);
-                    }
-                }
-                None
-            })
-            .next();
-        return next_block_el;
-    }
-
-    None
-}
-
-// General DOM utilities
-
-fn get_xml_element(document: Document) -> Option<Element> {
-    document.root()
-        .children()
-        .iter()
-        .filter_map(|child| {
-            if let &ChildOfRoot::Element(el) = child {
-                if el.name().local_part() == "xml" {
-                    return Some(el);
-                }
-            }
-            None
-        })
-        .next()
-}
-
-fn get_first_child_element(element: Element) -> Option<Element> {
-    element.children()
-        .iter()
-        .filter_map(|child| {
-            if let &ChildOfElement::Element(el) = child {
-                return Some(el);
-            }
-            None
-        })
-        .next()
-}
-
-fn get_attribute(element: Element, attribute_name: &str) -> Option<String> {
-    element.attributes()
-        .iter()
-        .filter_map(|attribute| {
-            let name = attribute.name().local_part();
-            if name == attribute_name {
-                let value = attribute.value().to_string();
-                return Some(value);
-            }
-            None
-        })
-        .next()
-}
-
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    fn get_fragment_root(package: &Package) -> Option<Element> {
-        package.as_document()
-            .root()
-            .children()
-            .iter()
-            .filter_map(|child| {
-                if let &ChildOfRoot::Element(el) = child {
-                    return Some(el);
-                }
-                None
-            })
-            .next()
-    }
-
-    #[test]
-    fn test_new_block() {
-        let xml: &str = r#"
-            <block type="inner_loop" id="]Lb|t?wfd#;s)[llJx8Y">
-                <field name="COUNT">3</field>
-                <statement name="BODY">
-                </statement>
-            </block>
-        "#;
-        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
-        let root_element = get_fragment_root(&fragment).unwrap();
-
-        let block = Block::new(root_element);
-        assert_eq!(block.block_type, "inner_loop");
-        assert_eq!(block.id, "]Lb|t?wfd#;s)[llJx8Y");
-        let count_field = block.fields.get("COUNT");
-        assert!(count_field.is_some());
-        assert_eq!(count_field.unwrap(), &FieldValue::SimpleField("3".to_string()));
-    }
-
-    #[test]
-    fn test_get_next_block_element() {
-        let xml: &str = r#"
-            <block type="led_on" id="^3xb.m4E9i0;3$R10(=5">
-                <field name="TIME">300</field>
-                <next>
-                    <block type="led_off" id="HX4*sB9=gbJtq$Y{ke6b">
-                        <field name="TIME">100</field>
-                    </block>
-                </next>
-            </block>
-        "#;
-        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
-        let root_element = get_fragment_root(&fragment).unwrap();
-
-        let next_block = get_next_block_element(&root_element);
-        assert!(next_block.is_some());
-        let next_block_unwrapped = next_block.unwrap();
-        assert_eq!(get_attribute(next_block_unwrapped, "type"), Some("led_off".to_string()));
-        assert_eq!(get_attribute(next_block_unwrapped, "id"), Some("HX4*sB9=gbJtq$Y{ke6b".to_string()));
-    }
-
-    #[test]
-    fn test_program_from_xml_advanced() {
-        let xml: &str = r#"
-            <xml xmlns="http://www.w3.org/1999/xhtml">
-                <variables></variables>
-                <block type="main_loop" id="[.)/fqUYv92(mzb{?:~u" deletable="false" movable="false" x="50" y="50">
-                    <statement name="BODY">
-                        <block type="inner_loop" id="]Lb|t?wfd#;s)[llJx8Y">
-                            <field name="COUNT">3</field>
-                            <statement name="BODY">
-                                <block type="led_on" id="^3xb.m4E9i0;3$R10(=5">
-                                    <field name="TIME">300</field>
-                                    <next>
-                                        <block type="led_off" id="HX4*sB9=gbJtq$Y{ke6b">
-                                            <field name="TIME">100</field>
-                                        </block>
-                                    </next>
-                                </block>
-                            </statement>
-                            <next>
-                                <block type="led_on" id="kB~f~7W`wkGa0i4z3mHw">
-                                    <field name="TIME">100</field>
-                                    <next>
-                                        <block type="led_off" id="$fdlZB)btzA8YtB/!xz`">
-                                            <field name="TIME">100</field>
-                                        </block>
-                                    </next>
-                                </block>
-                            </next>
-                        </block>
-                    </statement>
-                </block>
-            </xml>
-        "#;
-
-        let program: Program = program_from_xml(xml);
-        assert_eq!(program.groups.len(), 1);
-
-        let group = program.groups.get(0).unwrap();
-        assert_eq!(group.blocks.len(), 1);
-
-        let main_loop_block = group.blocks.get(0).unwrap();
-        assert_eq!(main_loop_block.block_type, "main_loop");
-        assert_eq!(main_loop_block.id, "[.)/fqUYv92(mzb{?:~u");
-
-        let main_loop_statements = &main_loop_block.statements;
-        assert_eq!(main_loop_statements.len(), 1);
-        assert!(main_loop_statements.contains_key("BODY"));
-
-        let main_loop_body = main_loop_statements.get("BODY");
-        let main_loop_body_statement = main_loop_body.as_ref().unwrap();
-        assert_eq!(main_loop_body_statement.blocks.len(), 3);
-
-        let inner_loop_block = main_loop_body_statement.blocks.get(0).unwrap();
-        assert_eq!(inner_loop_block.block_type, "inner_loop");
-        assert_eq!(inner_loop_block.id, "]Lb|t?wfd#;s)[llJx8Y");
-        assert_eq!(inner_loop_block.fields.get("COUNT"), Some(&FieldValue::SimpleField("3".to_string())));
-
-        let inner_loop_statement_maybe = inner_loop_block.statements.get("BODY");
-        assert!(inner_loop_statement_maybe.is_some());
-        let inner_loop_statement = inner_loop_statement_maybe.unwrap();
-        assert_eq!(inner_loop_statement.blocks.len(), 2);
-
-        let led_on_block = inner_loop_statement.blocks.get(0).unwrap();
-        assert_eq!(led_on_block.block_type, "led_on");
-        assert_eq!(led_on_block.id, "^3xb.m4E9i0;3$R10(=5");
-        assert_eq!(led_on_block.fields.get("TIME"), Some(&FieldValue::SimpleField("300".to_string())));
-
-        let led_off_block = inner_loop_statement.blocks.get(1).unwrap();
-        assert_eq!(led_off_block.block_type, "led_off");
-        assert_eq!(led_off_block.id, "HX4*sB9=gbJtq$Y{ke6b");
-    }
-}
@@ -0,0 +1,184 @@
+use super::ParseError;
+
+/// The default for `ParserOptions::max_depth`. Every block nested in an input
+/// adds two levels (`<statement>` or `<value>`, and `<block>`); blocks joined
+/// with `<next>` add none, so long stacks are not limited.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Reject documents whose elements nest deeper than `max_depth`, before they
+/// reach the parser, which recurses once per nested input.
+///
+/// `<next>` elements, and the `<block>` or `<shadow>` directly inside them,
+/// are not counted: stacks are parsed one block after another.
+///
+/// This only tracks start and end tags, skipping comments, CDATA sections,
+/// processing instructions and quoted attribute values. It does not check
+/// well-formedness; that is left to the parser.
+pub(crate) fn check_depth(xml: &str, max_depth: usize) -> Result<(), ParseError> {
+    let bytes = xml.as_bytes();
+    let mut depth: usize = 0;
+    // For each open element, whether it is a `<next>` and whether it counts
+    // toward the depth
+    let mut open: Vec<(bool, bool)> = Vec::new();
+    let mut i = 0;
+
+    while let Some(offset) = find_byte(&bytes[i..], b'<') {
+        i += offset;
+        let rest = &bytes[i..];
+        if rest.starts_with(b"<!--") {
+            i = skip_past(bytes, i + 4, b"-->");
+        } else if rest.starts_with(b"<![CDATA[") {
+            i = skip_past(bytes, i + 9, b"]]>");
+        } else if rest.starts_with(b"<?") {
+            i = skip_past(bytes, i + 2, b"?>");
+        } else if rest.starts_with(b"<!") {
+            i = skip_past(bytes, i + 2, b">");
+        } else if rest.starts_with(b"</") {
+            if let Some((_, true)) = open.pop() {
+                depth -= 1;
+            }
+            i = skip_past(bytes, i + 2, b">");
+        } else {
+            let (end, self_closing) = skip_start_tag(bytes, i + 1);
+            if !self_closing {
+                let name = local_name(&bytes[i + 1..end]);
+                let is_next = name == b"next";
+                let in_next = open.last().is_some_and(|&(parent_is_next, _)| parent_is_next);
+                let counted = !is_next && !(in_next && (name == b"block" || name == b"shadow"));
+                open.push((is_next, counted));
+                if counted {
+                    depth += 1;
+                    if depth > max_depth {
+                        return Err(ParseError::TooDeep { max_depth });
+                    }
+                }
+            }
+            i = end;
+        }
+    }
+
+    Ok(())
+}
+
+// The tag name in a start tag's contents, without any namespace prefix
+fn local_name(tag: &[u8]) -> &[u8] {
+    let end = tag.iter()
+        .position(|&byte| byte.is_ascii_whitespace() || byte == b'/' || byte == b'>')
+        .unwrap_or(tag.len());
+    let name = &tag[..end];
+    match name.iter().position(|&byte| byte == b':') {
+        Some(colon) => &name[colon + 1..],
+        None => name,
+    }
+}
+
+pub(crate) fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    haystack.iter().position(|&byte| byte == needle)
+}
+
+// The index just after the next occurrence of `terminator`, or the end of input
+pub(crate) fn skip_past(bytes: &[u8], from: usize, terminator: &[u8]) -> usize {
+    if from >= bytes.len() {
+        return bytes.len();
+    }
+    bytes[from..]
+        .windows(terminator.len())
+        .position(|window| window == terminator)
+        .map(|offset| from + offset + terminator.len())
+        .unwrap_or(bytes.len())
+}
+
+// Returns the index just after the tag's `>`, and whether it ended with `/>`
+pub(crate) fn skip_start_tag(bytes: &[u8], from: usize) -> (usize, bool) {
+    let mut quote: Option<u8> = None;
+    let mut i = from;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        match quote {
+            Some(q) => {
+                if byte == q {
+                    quote = None;
+                }
+            },
+            None => {
+                if byte == b'"' || byte == b'\'' {
+                    quote = Some(byte);
+                } else if byte == b'>' {
+                    let self_closing = i > from && bytes[i - 1] == b'/';
+                    return (i + 1, self_closing);
+                }
+            }
+        }
+        i += 1;
+    }
+    (bytes.len(), false)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        program_from_xml,
+        program_from_xml_with_options,
+        ParserOptions,
+    };
+
+    fn nested_statements(levels: usize) -> String {
+        let mut xml = String::from("<xml>");
+        for _ in 0..levels {
+            xml.push_str(r#"<block type="inner_loop"><statement name="BODY">"#);
+        }
+        for _ in 0..levels {
+            xml.push_str("</statement></block>");
+        }
+        xml.push_str("</xml>");
+        xml
+    }
+
+    #[test]
+    fn test_check_depth() {
+        assert_eq!(check_depth("<a><b/><c></c></a>", 2), Ok(()));
+        assert_eq!(check_depth("<a><b><c></c></b></a>", 2), Err(ParseError::TooDeep { max_depth: 2 }));
+        assert_eq!(check_depth(r#"<a title="<b><c>"><!-- <b><c> --><![CDATA[<b><c>]]></a>"#, 1), Ok(()));
+        assert_eq!(check_depth("<a><b", 1), Err(ParseError::TooDeep { max_depth: 1 }));
+    }
+
+    #[test]
+    fn test_deeply_nested_input_is_rejected() {
+        let xml = nested_statements(100_000);
+        assert_eq!(program_from_xml(&xml).unwrap_err(), ParseError::TooDeep { max_depth: DEFAULT_MAX_DEPTH });
+
+        let xml = nested_statements(50);
+        assert!(program_from_xml(&xml).is_ok());
+        let options = ParserOptions {
+            max_depth: Some(20),
+            ..ParserOptions::default()
+        };
+        assert_eq!(
+            program_from_xml_with_options(&xml, &options).unwrap_err(),
+            ParseError::TooDeep { max_depth: 20 }
+        );
+    }
+
+    #[test]
+    fn test_long_stack_is_not_too_deep() {
+        let length = 5000;
+        let mut xml = String::from("<xml>");
+        for index in 0..length {
+            if index > 0 {
+                xml.push_str("<next>");
+            }
+            xml.push_str(r#"<block type="led_on"><field name="TIME">300</field>"#);
+        }
+        for index in 0..length {
+            xml.push_str("</block>");
+            if index + 1 < length {
+                xml.push_str("</next>");
+            }
+        }
+        xml.push_str("</xml>");
+        assert_eq!(check_depth(&xml, 3), Ok(()));
+        assert_eq!(program_from_xml(&xml).unwrap().groups[0].blocks.len(), length);
+    }
+}
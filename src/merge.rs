@@ -0,0 +1,491 @@
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    InputContent,
+    MergeError,
+};
+use super::diff::{
+    Attachment,
+    flatten,
+};
+
+/// The outcome of `Program::merge`.
+#[derive(PartialEq, Eq, Debug)]
+pub struct MergeResult {
+    pub program: Program,
+    /// Empty when the merge was clean.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A block both sides changed in incompatible ways, and how it was resolved.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct Conflict {
+    pub id: String,
+    pub kind: ConflictKind,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum ConflictKind {
+    /// Both sides changed the block's fields, mutation, flags or comment.
+    /// Our version was kept.
+    Edited,
+    /// Both sides moved the block to different places. Our move was kept.
+    Moved,
+    /// One side deleted the block and the other changed or moved it. The
+    /// changed block was kept.
+    DeletedAndChanged,
+    /// The place the block was attached no longer exists, because its parent
+    /// was deleted or another block took the same input. It was moved to the
+    /// top level.
+    Detached,
+}
+
+// A block without the blocks nested in it, and where it is attached
+struct Record {
+    shell: Block,
+    placement: Placement,
+}
+
+#[derive(PartialEq, Clone)]
+struct Placement {
+    attachment: Attachment,
+    shadow: bool,
+    position: Option<(i32, i32)>,
+}
+
+impl Program {
+    /// Three-way merge of two versions of a workspace edited from the common
+    /// version `base`. Blocks are matched by id, so every block needs a unique
+    /// id, as Blockly gives them, or a `MergeError` is returned. Changes made
+    /// on one side only are taken; changes made on both sides are reported as
+    /// conflicts and resolved in favour of `ours`.
+    ///
+    /// Variables and workspace comments are merged by id without conflicts,
+    /// and `extras` take whichever side changed them.
+    pub fn merge(base: &Program, ours: &Program, theirs: &Program) -> Result<MergeResult, MergeError> {
+        for program in &[base, ours, theirs] {
+            check_ids(program)?;
+        }
+        let (_, mut base_records) = records(base);
+        let (our_order, mut our_records) = records(ours);
+        let (their_order, mut their_records) = records(theirs);
+
+        let mut order = our_order;
+        let mut seen: HashSet<String> = order.iter().cloned().collect();
+        for id in their_order {
+            if seen.insert(id.clone()) {
+                order.push(id);
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        let mut merged: HashMap<String, Record> = HashMap::new();
+        for id in &order {
+            let base = base_records.remove(id);
+            let record = match (base, our_records.remove(id), their_records.remove(id)) {
+                (base, Some(ours), Some(theirs)) => {
+                    let (base_shell, base_placement) = match base {
+                        Some(base) => (Some(base.shell), Some(base.placement)),
+                        None => (None, None),
+                    };
+                    let (shell, edited) = merge3(base_shell, ours.shell, theirs.shell);
+                    let (placement, moved) = merge3(base_placement, ours.placement, theirs.placement);
+                    if edited {
+                        conflicts.push(Conflict { id: id.clone(), kind: ConflictKind::Edited });
+                    }
+                    if moved {
+                        conflicts.push(Conflict { id: id.clone(), kind: ConflictKind::Moved });
+                    }
+                    Some(Record { shell, placement })
+                },
+                // Deleted on one side: gone unless the other side changed it
+                (Some(base), Some(kept), None) | (Some(base), None, Some(kept)) => {
+                    if kept.shell == base.shell && kept.placement == base.placement {
+                        None
+                    } else {
+                        conflicts.push(Conflict { id: id.clone(), kind: ConflictKind::DeletedAndChanged });
+                        Some(kept)
+                    }
+                },
+                // Added on one side
+                (None, Some(added), None) | (None, None, Some(added)) => Some(added),
+                (_, None, None) => None,
+            };
+            if let Some(record) = record {
+                merged.insert(id.clone(), record);
+            }
+        }
+
+        let mut builder = Builder::new(merged, &order);
+        let mut program = Program::new();
+        for id in &order {
+            if builder.is_root(id) {
+                program.groups.push(builder.build_stack(id));
+            }
+        }
+        // Blocks whose place is gone, or that ended up attached inside themselves
+        for id in &order {
+            if builder.is_unplaced(id) {
+                conflicts.push(Conflict { id: id.clone(), kind: ConflictKind::Detached });
+                program.groups.push(builder.build_stack(id));
+            }
+        }
+
+        program.variables = merge_by_id(&base.variables, &ours.variables, &theirs.variables, |variable| &variable.id);
+        program.comments = merge_by_id(&base.comments, &ours.comments, &theirs.comments, |comment| &comment.id);
+        program.extras = merge3(Some(base.extras.clone()), ours.extras.clone(), theirs.extras.clone()).0;
+
+        Ok(MergeResult {
+            program,
+            conflicts,
+        })
+    }
+}
+
+// The merged value, and whether both sides changed it differently
+fn merge3<T: PartialEq>(base: Option<T>, ours: T, theirs: T) -> (T, bool) {
+    if ours == theirs {
+        return (ours, false);
+    }
+    match base {
+        Some(ref base) if *base == ours => (theirs, false),
+        Some(ref base) if *base == theirs => (ours, false),
+        _ => (ours, true),
+    }
+}
+
+// Blocks are matched by id, so each needs one of its own
+fn check_ids(program: &Program) -> Result<(), MergeError> {
+    if let Some(block) = program.iter_blocks().find(|block| block.id.is_empty()) {
+        return Err(MergeError::MissingId {
+            block_type: block.block_type.clone(),
+        });
+    }
+    program.check_unique_ids().map_err(MergeError::DuplicateIds)
+}
+
+fn records(program: &Program) -> (Vec<String>, HashMap<String, Record>) {
+    let mut order = Vec::new();
+    let mut records = HashMap::new();
+    for entry in flatten(program) {
+        let mut shell = shell(entry.block);
+        let position = shell.position.take();
+        order.push(entry.block.id.clone());
+        records.insert(entry.block.id.clone(), Record {
+            shell,
+            placement: Placement {
+                attachment: entry.attachment,
+                shadow: entry.shadow,
+                position,
+            },
+        });
+    }
+    (order, records)
+}
+
+// A copy of the block without the blocks in its fields and inputs
+fn shell(block: &Block) -> Block {
+    let mut shell = Block::new(block.block_type.clone());
+    shell.id = block.id.clone();
+    for (name, value) in &block.fields {
+        if let FieldValue::SimpleField(ref text) = *value {
+            shell.fields.insert(name.clone(), FieldValue::SimpleField(text.clone()));
+        }
+    }
+    shell.mutation = block.mutation.clone();
+    shell.position = block.position;
+    shell.flags = block.flags;
+    shell.comment = block.comment.clone();
+    shell.data = block.data.clone();
+    shell.extra_attributes = block.extra_attributes.clone();
+    shell.extra_elements = block.extra_elements.clone();
+    shell.field_variables = block.field_variables.clone();
+    shell.preserve_space = block.preserve_space.clone();
+    shell.extensions = block.extensions.clone();
+    shell
+}
+
+// Ours, without what theirs deleted, followed by what theirs added. Items
+// changed by theirs only take their version.
+fn merge_by_id<T, K>(base: &[T], ours: &[T], theirs: &[T], key: K) -> Vec<T>
+    where T: PartialEq + Clone,
+          K: Fn(&T) -> &String
+{
+    let find = |items: &'_ [T], id: &String| items.iter().position(|item| key(item) == id);
+    let mut merged = Vec::new();
+    for item in ours {
+        let id = key(item);
+        let base_item = find(base, id).map(|index| &base[index]);
+        let their_item = find(theirs, id).map(|index| &theirs[index]);
+        match (base_item, their_item) {
+            (Some(base_item), None) if base_item == item => {},
+            (Some(base_item), Some(their_item)) if base_item == item => merged.push(their_item.clone()),
+            _ => merged.push(item.clone()),
+        }
+    }
+    for item in theirs {
+        let id = key(item);
+        if find(ours, id).is_none() && find(base, id).is_none() {
+            merged.push(item.clone());
+        }
+    }
+    merged
+}
+
+// Reassembles the tree from merged records
+struct Builder {
+    records: HashMap<String, Record>,
+    // Child ids by parent id, in document order
+    children: HashMap<String, Vec<String>>,
+}
+
+impl Builder {
+    fn new(records: HashMap<String, Record>, order: &[String]) -> Self {
+        let mut children: HashMap<String, Vec<String>> = HashMap::new();
+        for id in order {
+            if let Some(record) = records.get(id) {
+                if let Some(parent) = parent(&record.placement.attachment) {
+                    children.entry(parent.to_string()).or_default().push(id.clone());
+                }
+            }
+        }
+        Self {
+            records,
+            children,
+        }
+    }
+
+    fn is_root(&self, id: &str) -> bool {
+        match self.records.get(id) {
+            Some(record) => record.placement.attachment == Attachment::TopLevel,
+            None => false,
+        }
+    }
+
+    fn is_unplaced(&self, id: &str) -> bool {
+        self.records.contains_key(id)
+    }
+
+    // The first unplaced child attached at `attachment`, if any
+    fn claim(&self, parent: &str, attachment: &Attachment) -> Option<String> {
+        self.children.get(parent)?
+            .iter()
+            .find(|id| match self.records.get(*id) {
+                Some(record) => record.placement.attachment == *attachment,
+                None => false,
+            })
+            .cloned()
+    }
+
+    fn build_stack(&mut self, first: &str) -> StatementBody {
+        let mut body = StatementBody {
+            blocks: Vec::new(),
+            shadow: self.records[first].placement.shadow,
+        };
+        let mut next = Some(first.to_string());
+        while let Some(id) = next {
+            body.blocks.push(self.build_block(&id));
+            next = self.claim(&id, &Attachment::Next { previous: id.clone() });
+        }
+        body
+    }
+
+    fn build_block(&mut self, id: &str) -> Block {
+        let record = self.records.remove(id).expect("unplaced block");
+        let mut block = record.shell;
+        block.position = match record.placement.attachment {
+            Attachment::TopLevel => record.placement.position,
+            _ => None,
+        };
+
+        let child_ids = self.children.get(id).cloned().unwrap_or_default();
+        for child in child_ids {
+            let attachment = match self.records.get(&child) {
+                Some(record) => record.placement.attachment.clone(),
+                None => continue,
+            };
+            // Only the first block claiming an input gets it
+            if self.claim(id, &attachment).as_ref() != Some(&child) {
+                continue;
+            }
+            match attachment {
+                Attachment::Field { field, .. } => {
+                    let inner = self.build_block(&child);
+                    block.fields.insert(field, FieldValue::ExpressionField(Box::new(inner)));
+                },
+                Attachment::Value { input, .. } => {
+                    let shadow = self.records[&child].placement.shadow;
+                    let inner = self.build_block(&child);
+                    let content = if shadow { InputContent::Shadow(inner) } else { InputContent::Block(inner) };
+                    block.values.insert(input, content);
+                },
+                Attachment::Statement { input, .. } => {
+                    let body = self.build_stack(&child);
+                    block.statements.insert(input, body);
+                },
+                Attachment::Next { .. } | Attachment::TopLevel => {},
+            }
+        }
+        block
+    }
+}
+
+fn parent(attachment: &Attachment) -> Option<&str> {
+    match *attachment {
+        Attachment::TopLevel => None,
+        Attachment::Next { ref previous } => Some(previous),
+        Attachment::Statement { ref parent, .. }
+        | Attachment::Value { ref parent, .. }
+        | Attachment::Field { ref parent, .. } => Some(parent),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        DuplicateIdError,
+        program_from_xml,
+    };
+
+    fn stack_ids(body: &StatementBody) -> Vec<&str> {
+        body.blocks.iter().map(|block| block.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_merge_clean() {
+        let base = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">count</variable></variables>
+                <block type="main_loop" id="main" x="10" y="20">
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <field name="TIME">300</field>
+                            <next><block type="led_off" id="off"></block></next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="buzzer" id="buzz"></block>
+            </xml>
+        "#).unwrap();
+        // Ours changes a field and deletes the buzzer
+        let ours = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">count</variable></variables>
+                <block type="main_loop" id="main" x="10" y="20">
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <field name="TIME">500</field>
+                            <next><block type="led_off" id="off"></block></next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        // Theirs renames the variable, moves the stack and appends a block
+        let theirs = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">total</variable></variables>
+                <block type="main_loop" id="main" x="50" y="60">
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <field name="TIME">300</field>
+                            <next>
+                                <block type="led_off" id="off">
+                                    <next><block type="wait" id="wait"></block></next>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="buzzer" id="buzz"></block>
+            </xml>
+        "#).unwrap();
+
+        let result = Program::merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts, vec![]);
+        let program = result.program;
+        assert_eq!(program.groups.len(), 1);
+        let main_block = &program.groups[0].blocks[0];
+        assert_eq!(main_block.position, Some((50, 60)));
+        let body = &main_block.statements["BODY"];
+        assert_eq!(stack_ids(body), vec!["on", "off", "wait"]);
+        assert_eq!(body.blocks[0].fields["TIME"], FieldValue::SimpleField("500".to_string()));
+        assert_eq!(program.variables[0].name, "total");
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let base = program_from_xml(r#"
+            <xml>
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="on"><field name="TIME">300</field></block>
+                    </statement>
+                </block>
+                <block type="buzzer" id="buzz"></block>
+            </xml>
+        "#).unwrap();
+        // Ours edits `on` and attaches a block under the buzzer
+        let ours = program_from_xml(r#"
+            <xml>
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="on"><field name="TIME">500</field></block>
+                    </statement>
+                </block>
+                <block type="buzzer" id="buzz">
+                    <next><block type="wait" id="wait"></block></next>
+                </block>
+            </xml>
+        "#).unwrap();
+        // Theirs edits `on` too and deletes the buzzer
+        let theirs = program_from_xml(r#"
+            <xml>
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="on"><field name="TIME">100</field></block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let result = Program::merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(result.conflicts, vec![
+            Conflict { id: "on".to_string(), kind: ConflictKind::Edited },
+            Conflict { id: "wait".to_string(), kind: ConflictKind::Detached },
+        ]);
+        let program = result.program;
+        let on_block = &program.groups[0].blocks[0].statements["BODY"].blocks[0];
+        assert_eq!(on_block.fields["TIME"], FieldValue::SimpleField("500".to_string()));
+        // The buzzer itself was unchanged in ours, so theirs' deletion stands
+        assert_eq!(stack_ids(&program.groups[1]), vec!["wait"]);
+    }
+
+    #[test]
+    fn test_merge_ids() {
+        let base = program_from_xml(r#"<xml><block type="buzzer" id="buzz"></block></xml>"#).unwrap();
+        let duplicates = program_from_xml(r#"
+            <xml>
+                <block type="buzzer" id="buzz"></block>
+                <block type="wait" id="buzz"></block>
+            </xml>
+        "#).unwrap();
+        assert_eq!(Program::merge(&base, &base, &duplicates), Err(MergeError::DuplicateIds(DuplicateIdError {
+            ids: vec!["buzz".to_string()],
+        })));
+
+        let missing = program_from_xml(r#"<xml><block type="buzzer" id="buzz"></block><block type="wait"></block></xml>"#).unwrap();
+        assert_eq!(Program::merge(&missing, &base, &base), Err(MergeError::MissingId {
+            block_type: "wait".to_string(),
+        }));
+    }
+}
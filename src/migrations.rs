@@ -0,0 +1,218 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::{
+    Program,
+    Block,
+    FieldValue,
+    FieldVariable,
+    InputContent,
+    ParseError,
+    Variable,
+    XHTML_NAMESPACE,
+    check_depth,
+    get_xml_element,
+    parse_package,
+    program_from_element,
+    Diagnostics,
+    ParserOptions,
+};
+use super::ids::{
+    SoupIdGenerator,
+    random_unused_id,
+};
+use super::iter::child_blocks_mut;
+
+// Blocks whose `VAR` field named a variable before Blockly gave variables ids
+const VARIABLE_BLOCKS: &[&str] = &[
+    "variables_get",
+    "variables_set",
+    "math_change",
+    "controls_for",
+    "controls_forEach",
+    "text_append",
+];
+
+// Numbers once typed into a field that are now a `math_number` shadow in a
+// value input of the same name, with the block's current type
+const FIELD_TO_SHADOW: &[(&str, &str, &str)] = &[
+    ("controls_repeat", "TIMES", "controls_repeat_ext"),
+    ("math_change", "DELTA", "math_change"),
+];
+
+// Whether the field names a variable, with an id or, in old workspaces,
+// by name alone
+pub(crate) fn is_variable_field(block: &Block, field: &str) -> bool {
+    block.field_variables.contains_key(field)
+        || (field == "VAR" && VARIABLE_BLOCKS.contains(&block.block_type.as_str()))
+}
+
+/// An upgrade made by `program_from_xml_migrate` or `Program::migrate`.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Migration {
+    /// The workspace was saved with the XHTML namespace used before
+    /// Blockly 2.x. It is written back out with the Blockly namespace.
+    XhtmlNamespace,
+    /// A declared variable without an id was given `id`.
+    AddedVariableId { name: String, id: String },
+    /// A variable field with no id, from before variables had ids, now
+    /// refers to the variable `name` with id `id`, declared if need be.
+    LinkedVariable { block: String, name: String, id: String },
+    /// The number in field `field` was moved into a `math_number` shadow.
+    FieldToShadow { block: String, field: String },
+    /// A block type was replaced with its current name.
+    RenamedBlock { block: String, from: String, to: String },
+}
+
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Migration::XhtmlNamespace => {
+                write!(f, "replaced the XHTML namespace with the Blockly namespace")
+            },
+            Migration::AddedVariableId { ref name, ref id } => {
+                write!(f, "gave variable {:?} the id {:?}", name, id)
+            },
+            Migration::LinkedVariable { ref block, ref name, ref id } => {
+                write!(f, "linked block {:?} to variable {:?} with id {:?}", block, name, id)
+            },
+            Migration::FieldToShadow { ref block, ref field } => {
+                write!(f, "moved field `{}` of block {:?} into a shadow block", field, block)
+            },
+            Migration::RenamedBlock { ref block, ref from, ref to } => {
+                write!(f, "renamed block {:?} from `{}` to `{}`", block, from, to)
+            },
+        }
+    }
+}
+
+/// A migrated program with every migration made to it.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct MigrationOutcome {
+    pub program: Program,
+    pub migrations: Vec<Migration>,
+}
+
+/// Parse a workspace saved by an old version of Blockly and upgrade it to
+/// the form current versions save. See `Program::migrate`.
+pub fn program_from_xml_migrate(xml: &str) -> Result<MigrationOutcome, ParseError> {
+    let options = ParserOptions::default();
+    if let Some(max_depth) = options.max_depth {
+        check_depth(xml, max_depth)?;
+    }
+    let package = parse_package(xml)?;
+    let document = package.as_document();
+    let root = get_xml_element(document).ok_or(ParseError::MissingRootElement)?;
+
+    let mut migrations = Vec::new();
+    if root.name().namespace_uri() == Some(XHTML_NAMESPACE) {
+        migrations.push(Migration::XhtmlNamespace);
+    }
+    let mut program = program_from_element(root, &options, &mut Diagnostics::new())?;
+    migrations.extend(program.migrate());
+    Ok(MigrationOutcome { program, migrations })
+}
+
+impl Program {
+    /// Upgrade quirks of workspaces saved by old versions of Blockly:
+    /// variables without ids, variable fields that name their variable
+    /// instead of referring to it by id, and blocks whose numbers were fields
+    /// before they became inputs. Returns what was changed; migrating twice
+    /// changes nothing.
+    pub fn migrate(&mut self) -> Vec<Migration> {
+        let mut migrations = Vec::new();
+        let mut ids = SoupIdGenerator::new();
+        let mut used: HashSet<String> = self.variables.iter().map(|variable| variable.id.clone()).collect();
+
+        for variable in &mut self.variables {
+            if variable.id.is_empty() {
+                variable.id = random_unused_id(&mut ids, &mut used);
+                migrations.push(Migration::AddedVariableId { name: variable.name.clone(), id: variable.id.clone() });
+            }
+        }
+
+        let variables = &mut self.variables;
+        let mut pending: Vec<_> = self.groups.iter_mut()
+            .flat_map(|group| group.blocks.iter_mut())
+            .collect();
+        while let Some(block) = pending.pop() {
+            if VARIABLE_BLOCKS.contains(&block.block_type.as_str()) && !block.field_variables.contains_key("VAR") {
+                if let Some(FieldValue::SimpleField(name)) = block.fields.get("VAR") {
+                    let name = name.clone();
+                    let id = match variables.iter().find(|variable| variable.name == name) {
+                        Some(variable) => variable.id.clone(),
+                        None => {
+                            let id = random_unused_id(&mut ids, &mut used);
+                            variables.push(Variable { name: name.clone(), id: id.clone(), var_type: None });
+                            id
+                        },
+                    };
+                    block.field_variables.insert("VAR".to_string(), FieldVariable { id: id.clone(), var_type: None });
+                    migrations.push(Migration::LinkedVariable { block: block.id.clone(), name, id });
+                }
+            }
+            upgrade_fields(block, &mut migrations);
+            pending.extend(child_blocks_mut(block));
+        }
+        migrations
+    }
+}
+
+fn upgrade_fields(block: &mut Block, migrations: &mut Vec<Migration>) {
+    for &(from, field, to) in FIELD_TO_SHADOW {
+        if block.block_type != from || block.values.contains_key(field) {
+            continue;
+        }
+        if let Some(FieldValue::SimpleField(number)) = block.fields.shift_remove(field) {
+            let mut shadow = Block::new("math_number");
+            shadow.fields.insert("NUM".to_string(), FieldValue::SimpleField(number));
+            block.values.insert(field.to_string(), InputContent::Shadow(shadow));
+            migrations.push(Migration::FieldToShadow { block: block.id.clone(), field: field.to_string() });
+        }
+        if from != to {
+            block.block_type = to.to_string();
+            migrations.push(Migration::RenamedBlock { block: block.id.clone(), from: from.to_string(), to: to.to_string() });
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_program_from_xml_migrate() {
+        let xml = r#"
+            <xml xmlns="http://www.w3.org/1999/xhtml">
+                <variables><variable>total</variable></variables>
+                <block type="controls_repeat" id="a" x="10" y="10">
+                    <field name="TIMES">4</field>
+                    <statement name="DO">
+                        <block type="variables_set" id="b">
+                            <field name="VAR">count</field>
+                            <next><block type="math_change" id="c"><field name="VAR">total</field><field name="DELTA">2</field></block></next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#;
+        let outcome = program_from_xml_migrate(xml).unwrap();
+        let mut program = outcome.program;
+        assert_eq!(outcome.migrations[0], Migration::XhtmlNamespace);
+        assert!(matches!(outcome.migrations[1], Migration::AddedVariableId { ref name, .. } if name == "total"));
+        assert_eq!(outcome.migrations.len(), 7);
+
+        assert_eq!(program.variables.len(), 2);
+        assert_eq!(program.variables[1].name, "count");
+        let repeat = &program.groups[0].blocks[0];
+        assert_eq!(repeat.block_type, "controls_repeat_ext");
+        assert_eq!(repeat.values["TIMES"].block().field_str("NUM"), Ok("4"));
+        let body = &repeat.statements["DO"].blocks;
+        assert_eq!(body[0].field_variables["VAR"].id, program.variables[1].id);
+        assert_eq!(body[1].field_variables["VAR"].id, program.variables[0].id);
+        assert_eq!(body[1].values["DELTA"].block().field_str("NUM"), Ok("2"));
+
+        assert!(program.migrate().is_empty());
+    }
+}
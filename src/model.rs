@@ -0,0 +1,268 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::{
+    BuildHasherDefault,
+    Hasher,
+};
+
+use indexmap::IndexMap;
+
+use super::{
+    Extensions,
+    RawElement,
+};
+
+/// The hasher of the `IndexMap`s in the parsed types. It is the same with
+/// and without the `std` feature, so the types do not change with features.
+pub type MapHasher = BuildHasherDefault<FnvHasher>;
+
+/// 64-bit FNV-1a, for maps on targets without a source of random keys.
+#[derive(Debug, Clone, Copy)]
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A parsed workspace. It owns all of its data and borrows nothing from the
+/// XML it was parsed from, so it can be cached, cloned and moved between
+/// threads freely. `ProgramRef` is the borrowing alternative.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Program {
+    pub groups: Vec<StatementBody>,
+    pub variables: Vec<Variable>,
+    pub comments: Vec<WorkspaceComment>,
+    /// Top-level elements the parser does not understand, such as a root
+    /// `<shadow>` or plugin data, so callers can tell when input was ignored.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub extras: Vec<RawElement>,
+}
+
+// Keep the parsed types owned: nothing borrowed from the XML parser, and safe
+// to share between threads
+const _: fn() = || {
+    fn assert_owned<T: Clone + Send + Sync + 'static>() {}
+    assert_owned::<Program>();
+    assert_owned::<StatementBody>();
+    assert_owned::<Block>();
+    assert_owned::<FieldValue>();
+    assert_owned::<InputContent>();
+};
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Variable {
+    pub name: String,
+    pub id: String,
+    /// The variable type, or `None` for Blockly's default untyped variables.
+    pub var_type: Option<String>,
+}
+
+/// A free-standing comment on the workspace, not attached to any block.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct WorkspaceComment {
+    pub id: String,
+    pub text: String,
+    pub position: Option<(i32, i32)>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct StatementBody {
+    pub blocks: Vec<Block>,
+    /// True when the input only holds `<shadow>` placeholder blocks.
+    pub shadow: bool,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Block {
+    pub block_type: String,
+    pub id: String,
+    pub fields: IndexMap<String, FieldValue, MapHasher>,
+    pub statements: IndexMap<String, StatementBody, MapHasher>,
+    pub values: IndexMap<String, InputContent, MapHasher>,
+    pub mutation: Option<Mutation>,
+    /// Workspace coordinates; only top-level blocks carry them.
+    pub position: Option<(i32, i32)>,
+    pub flags: BlockFlags,
+    pub comment: Option<BlockComment>,
+    /// The text of the block's `<data>` element, where plugins store their
+    /// own per-block strings.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub data: Option<String>,
+    /// Attributes the parser does not understand, kept when
+    /// `ParserOptions::preserve_unknown` is set.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "IndexMap::is_empty"))]
+    pub extra_attributes: IndexMap<String, String, MapHasher>,
+    /// Child elements the parser does not understand, kept when
+    /// `ParserOptions::preserve_unknown` is set.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub extra_elements: Vec<RawElement>,
+    /// The variables chosen in variable fields, by field name. The field's
+    /// text in `fields` is the variable's name.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "IndexMap::is_empty"))]
+    pub field_variables: IndexMap<String, FieldVariable, MapHasher>,
+    /// Fields whose whitespace is significant, from `xml:space="preserve"` on
+    /// the `<field>` or its block. `Program::normalize` leaves them untrimmed.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub preserve_space: Vec<String>,
+    /// Values attached by `ParserOptions::on_unknown_element` handlers.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub extensions: Extensions,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BlockFlags {
+    pub deletable: bool,
+    pub movable: bool,
+    pub editable: bool,
+    pub collapsed: bool,
+    pub disabled: bool,
+}
+
+/// The variable a variable field refers to, from the field's `id` and
+/// `variabletype` attributes.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct FieldVariable {
+    pub id: String,
+    /// The variable type, or `None` for untyped variables.
+    pub var_type: Option<String>,
+}
+
+/// A comment bubble attached to a block.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BlockComment {
+    pub text: String,
+    pub pinned: bool,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+/// Extra state stored by blocks that change shape, such as the number of
+/// `else if` branches on `controls_if` or the arguments of a procedure.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Mutation {
+    pub attributes: IndexMap<String, String, MapHasher>,
+    pub children: Vec<RawElement>,
+}
+
+/// The block connected to a value input. When an input holds both a real
+/// block and a `<shadow>`, the real block wins and the shadow is reported
+/// with `Warning::DroppedShadow`.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum InputContent {
+    Block(Block),
+    Shadow(Block),
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum FieldValue {
+    SimpleField(String),
+    ExpressionField(Box<Block>),
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Self {
+            groups: Vec::new(),
+            variables: Vec::new(),
+            comments: Vec::new(),
+            extras: Vec::new(),
+        }
+    }
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Block {
+    pub fn new<T: Into<String>>(block_type: T) -> Self {
+        Self {
+            block_type: block_type.into(),
+            id: String::new(),
+            fields: IndexMap::default(),
+            statements: IndexMap::default(),
+            values: IndexMap::default(),
+            mutation: None,
+            position: None,
+            flags: BlockFlags::default(),
+            comment: None,
+            data: None,
+            extra_attributes: IndexMap::default(),
+            extra_elements: Vec::new(),
+            field_variables: IndexMap::default(),
+            preserve_space: Vec::new(),
+            extensions: Extensions::new(),
+        }
+    }
+}
+
+impl Default for BlockFlags {
+    fn default() -> Self {
+        Self {
+            deletable: true,
+            movable: true,
+            editable: true,
+            collapsed: false,
+            disabled: false,
+        }
+    }
+}
+
+impl Mutation {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|value| value.as_str())
+    }
+}
+
+impl InputContent {
+    pub fn block(&self) -> &Block {
+        match *self {
+            InputContent::Block(ref block) | InputContent::Shadow(ref block) => block,
+        }
+    }
+
+    pub fn block_mut(&mut self) -> &mut Block {
+        match *self {
+            InputContent::Block(ref mut block) | InputContent::Shadow(ref mut block) => block,
+        }
+    }
+
+    pub fn is_shadow(&self) -> bool {
+        match *self {
+            InputContent::Block(_) => false,
+            InputContent::Shadow(_) => true,
+        }
+    }
+}
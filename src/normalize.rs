@@ -0,0 +1,94 @@
+use super::{
+    Program,
+    FieldValue,
+    InputContent,
+};
+use super::iter::child_blocks_mut;
+
+impl Program {
+    /// Rewrite the program into a canonical form, so programs saved by
+    /// different Blockly versions or dragged around differently compare and
+    /// hash the same:
+    ///
+    /// - top-level stacks are sorted by position, top to bottom then left to
+    ///   right, with unplaced stacks last in their original order
+    /// - positions and the collapsed flag are cleared
+    /// - inputs holding only a shadow block are removed
+    /// - whitespace around field text is trimmed, unless the field has
+    ///   `xml:space="preserve"`
+    pub fn normalize(&mut self) {
+        self.groups.sort_by_key(|group| {
+            let position = group.blocks.first().and_then(|block| block.position);
+            (position.is_none(), position.map(|(x, y)| (y, x)))
+        });
+        for comment in self.comments.iter_mut() {
+            comment.position = None;
+        }
+
+        let mut pending: Vec<_> = self.groups.iter_mut()
+            .flat_map(|group| group.blocks.iter_mut())
+            .collect();
+        while let Some(block) = pending.pop() {
+            block.position = None;
+            block.flags.collapsed = false;
+            block.values.retain(|_, input| !matches!(input, InputContent::Shadow(_)));
+            block.statements.retain(|_, body| !body.shadow);
+            for (name, field) in block.fields.iter_mut() {
+                if block.preserve_space.contains(name) {
+                    continue;
+                }
+                if let FieldValue::SimpleField(ref mut text) = *field {
+                    let trimmed = text.trim();
+                    if trimmed.len() != text.len() {
+                        *text = trimmed.to_string();
+                    }
+                }
+            }
+            pending.extend(child_blocks_mut(block));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_normalize() {
+        let mut program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="led_off" id="b" x="50" y="300"></block>
+                <block type="led_on" id="a" x="10" y="20" collapsed="true">
+                    <field name="TIME">  300
+                    </field>
+                    <value name="PIN">
+                        <shadow type="math_number" id="s"><field name="NUM">3</field></shadow>
+                    </value>
+                    <value name="LEVEL">
+                        <shadow type="math_number" id="t"><field name="NUM">1</field></shadow>
+                        <block type="variables_get" id="v"><field name="VAR"> level </field></block>
+                    </value>
+                </block>
+            </xml>
+        "#).unwrap();
+        program.normalize();
+
+        let expected = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="led_on" id="a">
+                    <field name="TIME">300</field>
+                    <value name="LEVEL">
+                        <block type="variables_get" id="v"><field name="VAR">level</field></block>
+                    </value>
+                </block>
+                <block type="led_off" id="b"></block>
+            </xml>
+        "#).unwrap();
+        assert_eq!(program, expected);
+
+        let mut again = program.clone();
+        again.normalize();
+        assert_eq!(again, program);
+    }
+}
@@ -0,0 +1,444 @@
+//! Passes that simplify a program before code generation: folding constant
+//! expressions, and removing blocks that have no effect. Passes are run one
+//! after another by a `Pipeline`.
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    IndexMap,
+};
+use super::iter::child_blocks_mut;
+
+/// A rewrite of a whole program.
+pub trait Pass {
+    /// Rewrite `program`, returning whether anything changed.
+    fn run(&self, program: &mut Program) -> bool;
+}
+
+/// Passes run in the order they were added, so put passes that may empty a
+/// loop before `RemoveEmptyLoops`.
+///
+/// ```
+/// use blockly_parser::opt::{FoldConstants, Pipeline, RemoveEmptyLoops, RemoveNoOps};
+/// use blockly_parser::program_from_xml;
+///
+/// let pipeline = Pipeline::new()
+///     .pass(FoldConstants)
+///     .pass(RemoveNoOps::new(|block| block.block_type == "led_on" && block.field_i64("TIME") == Ok(0)))
+///     .pass(RemoveEmptyLoops::default());
+///
+/// let mut program = program_from_xml(r#"
+///     <xml>
+///         <block type="controls_repeat_ext">
+///             <value name="TIMES"><block type="math_number"><field name="NUM">10</field></block></value>
+///             <statement name="DO">
+///                 <block type="led_on"><field name="TIME">0</field></block>
+///             </statement>
+///         </block>
+///     </xml>
+/// "#).unwrap();
+/// assert!(pipeline.run(&mut program));
+/// assert!(program.groups.is_empty());
+/// ```
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold constants, then remove empty loops of the standard loop blocks.
+    pub fn standard() -> Self {
+        Self::new()
+            .pass(FoldConstants)
+            .pass(RemoveEmptyLoops::default())
+    }
+
+    pub fn pass<P: Pass + 'static>(mut self, pass: P) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Run every pass once, returning whether any of them changed the program.
+    pub fn run(&self, program: &mut Program) -> bool {
+        let mut changed = false;
+        for pass in &self.passes {
+            changed |= pass.run(program);
+        }
+        changed
+    }
+}
+
+/// Replace math and logic blocks whose inputs are all literals with the
+/// literal they evaluate to, e.g. `1 + 2` with `3`. The replacement keeps the
+/// block's id. Results that are not finite numbers are left unfolded, as are
+/// blocks with empty or disabled inputs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FoldConstants;
+
+impl Pass for FoldConstants {
+    fn run(&self, program: &mut Program) -> bool {
+        let mut changed = false;
+        for block in program.groups.iter_mut().flat_map(|group| group.blocks.iter_mut()) {
+            changed |= fold(block);
+        }
+        changed
+    }
+}
+
+/// Turn shadow blocks into real blocks; see `Program::materialize_shadows`.
+/// Run it first, so later passes treat shadow defaults like real blocks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaterializeShadows;
+
+impl Pass for MaterializeShadows {
+    fn run(&self, program: &mut Program) -> bool {
+        program.materialize_shadows() > 0
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Constant {
+    Number(f64),
+    Boolean(bool),
+}
+
+fn fold(block: &mut Block) -> bool {
+    let mut changed = false;
+    for child in child_blocks_mut(block) {
+        changed |= fold(child);
+    }
+    let (block_type, field, text) = match evaluate(block) {
+        Some(Constant::Number(number)) => ("math_number", "NUM", number.to_string()),
+        Some(Constant::Boolean(boolean)) => ("logic_boolean", "BOOL", if boolean { "TRUE" } else { "FALSE" }.to_string()),
+        None => return changed,
+    };
+    block.block_type = block_type.to_string();
+    block.fields = IndexMap::default();
+    block.fields.insert(field.to_string(), FieldValue::SimpleField(text));
+    block.values.clear();
+    block.statements.clear();
+    block.mutation = None;
+    block.field_variables.clear();
+    true
+}
+
+// The literal in a value input
+fn literal(block: &Block, name: &str) -> Option<Constant> {
+    let block = block.values.get(name)?.block();
+    if block.flags.disabled {
+        return None;
+    }
+    match block.block_type.as_str() {
+        "math_number" => block.field_f64("NUM").ok().map(Constant::Number),
+        "logic_boolean" => block.field_bool("BOOL").ok().map(Constant::Boolean),
+        _ => None,
+    }
+}
+
+fn number(block: &Block, name: &str) -> Option<f64> {
+    match literal(block, name)? {
+        Constant::Number(number) => Some(number),
+        Constant::Boolean(_) => None,
+    }
+}
+
+fn boolean(block: &Block, name: &str) -> Option<bool> {
+    match literal(block, name)? {
+        Constant::Boolean(boolean) => Some(boolean),
+        Constant::Number(_) => None,
+    }
+}
+
+fn evaluate(block: &Block) -> Option<Constant> {
+    let operator = |name| block.field_str(name).ok();
+    let constant = match block.block_type.as_str() {
+        "math_arithmetic" => {
+            let (a, b) = (number(block, "A")?, number(block, "B")?);
+            Constant::Number(match operator("OP")? {
+                "ADD" => a + b,
+                "MINUS" => a - b,
+                "MULTIPLY" => a * b,
+                "DIVIDE" => a / b,
+                "POWER" => a.powf(b),
+                _ => return None,
+            })
+        },
+        "math_single" => {
+            let a = number(block, "NUM")?;
+            Constant::Number(match operator("OP")? {
+                "ROOT" => a.sqrt(),
+                "ABS" => a.abs(),
+                "NEG" => -a,
+                "LN" => a.ln(),
+                "LOG10" => a.log10(),
+                "EXP" => a.exp(),
+                "POW10" => 10f64.powf(a),
+                _ => return None,
+            })
+        },
+        "math_modulo" => Constant::Number(number(block, "DIVIDEND")? % number(block, "DIVISOR")?),
+        "logic_compare" => {
+            let ordering = match (literal(block, "A")?, literal(block, "B")?) {
+                (Constant::Number(a), Constant::Number(b)) => a.partial_cmp(&b)?,
+                (Constant::Boolean(a), Constant::Boolean(b)) => a.cmp(&b),
+                _ => return None,
+            };
+            Constant::Boolean(match operator("OP")? {
+                "EQ" => ordering.is_eq(),
+                "NEQ" => ordering.is_ne(),
+                "LT" => ordering.is_lt(),
+                "LTE" => ordering.is_le(),
+                "GT" => ordering.is_gt(),
+                "GTE" => ordering.is_ge(),
+                _ => return None,
+            })
+        },
+        "logic_operation" => {
+            let (a, b) = (boolean(block, "A")?, boolean(block, "B")?);
+            Constant::Boolean(match operator("OP")? {
+                "AND" => a && b,
+                "OR" => a || b,
+                _ => return None,
+            })
+        },
+        "logic_negate" => Constant::Boolean(!boolean(block, "BOOL")?),
+        _ => return None,
+    };
+    match constant {
+        Constant::Number(number) if !number.is_finite() => None,
+        constant => Some(constant),
+    }
+}
+
+/// Remove loops whose body is empty. A loop is only removed if its value
+/// inputs hold nothing but literals, so no side effect of working out the
+/// count is lost. The default covers `controls_repeat` and
+/// `controls_repeat_ext`; `while` loops are kept, since an empty one may
+/// never end.
+#[derive(Debug, Clone)]
+pub struct RemoveEmptyLoops {
+    // Block types, with the name of each one's body
+    loops: Vec<(String, String)>,
+}
+
+impl Default for RemoveEmptyLoops {
+    fn default() -> Self {
+        Self::new()
+            .with_loop("controls_repeat", "DO")
+            .with_loop("controls_repeat_ext", "DO")
+    }
+}
+
+impl RemoveEmptyLoops {
+    /// No loop types at all, for custom blocks only.
+    pub fn new() -> Self {
+        Self { loops: Vec::new() }
+    }
+
+    /// Also remove `block_type` loops with an empty `body` statement input.
+    pub fn with_loop<T: Into<String>, U: Into<String>>(mut self, block_type: T, body: U) -> Self {
+        self.loops.push((block_type.into(), body.into()));
+        self
+    }
+
+    fn is_empty_loop(&self, block: &Block) -> bool {
+        let body = match self.loops.iter().find(|(block_type, _)| *block_type == block.block_type) {
+            Some((_, body)) => body,
+            None => return false,
+        };
+        let empty = block.statements.get(body).is_none_or(|stack| stack.blocks.is_empty());
+        empty && block.values.values().all(|input| is_literal(input.block()))
+    }
+}
+
+impl Pass for RemoveEmptyLoops {
+    fn run(&self, program: &mut Program) -> bool {
+        remove_blocks(program, &|block| self.is_empty_loop(block))
+    }
+}
+
+fn is_literal(block: &Block) -> bool {
+    matches!(block.block_type.as_str(), "math_number" | "logic_boolean" | "text" | "math_constant")
+}
+
+/// Remove statement blocks that have no effect, as decided by a predicate,
+/// e.g. `led_on` blocks with a time of 0. The blocks below a removed block
+/// move up in its place.
+pub struct RemoveNoOps {
+    is_no_op: Box<dyn Fn(&Block) -> bool>,
+}
+
+impl RemoveNoOps {
+    pub fn new<F: Fn(&Block) -> bool + 'static>(is_no_op: F) -> Self {
+        Self { is_no_op: Box::new(is_no_op) }
+    }
+}
+
+impl Pass for RemoveNoOps {
+    fn run(&self, program: &mut Program) -> bool {
+        remove_blocks(program, &*self.is_no_op)
+    }
+}
+
+// Inner blocks are looked at before the blocks they are in, so a loop
+// emptied by removing its body can be removed as well. Statement inputs and
+// top-level stacks left empty are dropped.
+fn remove_blocks(program: &mut Program, remove: &dyn Fn(&Block) -> bool) -> bool {
+    let mut changed = false;
+    for group in program.groups.iter_mut() {
+        changed |= remove_from_stack(group, remove);
+    }
+    if changed {
+        program.groups.retain(|group| !group.blocks.is_empty());
+    }
+    changed
+}
+
+fn remove_from_stack(stack: &mut StatementBody, remove: &dyn Fn(&Block) -> bool) -> bool {
+    let mut changed = false;
+    let position = stack.blocks.first().and_then(|block| block.position);
+    stack.blocks.retain_mut(|block| {
+        changed |= remove_from_block(block, remove);
+        let removed = remove(block);
+        changed |= removed;
+        !removed
+    });
+    // A top-level stack keeps its place when its first block goes
+    if let Some(first) = stack.blocks.first_mut() {
+        if first.position.is_none() {
+            first.position = position;
+        }
+    }
+    changed
+}
+
+fn remove_from_block(block: &mut Block, remove: &dyn Fn(&Block) -> bool) -> bool {
+    let mut changed = false;
+    for field in block.fields.values_mut() {
+        if let FieldValue::ExpressionField(ref mut expression) = *field {
+            changed |= remove_from_block(expression, remove);
+        }
+    }
+    for input in block.values.values_mut() {
+        changed |= remove_from_block(input.block_mut(), remove);
+    }
+    let mut emptied = Vec::new();
+    for (name, stack) in block.statements.iter_mut() {
+        if remove_from_stack(stack, remove) {
+            changed = true;
+            if stack.blocks.is_empty() {
+                emptied.push(name.clone());
+            }
+        }
+    }
+    for name in emptied {
+        block.statements.shift_remove(&name);
+    }
+    changed
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_fold_constants() {
+        let mut program = program_from_xml(r#"
+            <xml>
+                <block type="text_print" id="print">
+                    <value name="TEXT">
+                        <block type="math_arithmetic" id="sum">
+                            <field name="OP">ADD</field>
+                            <value name="A"><shadow type="math_number"><field name="NUM">1</field></shadow></value>
+                            <value name="B">
+                                <block type="math_arithmetic" id="product">
+                                    <field name="OP">MULTIPLY</field>
+                                    <value name="A"><block type="math_number"><field name="NUM">2.5</field></block></value>
+                                    <value name="B"><block type="math_number"><field name="NUM">4</field></block></value>
+                                </block>
+                            </value>
+                        </block>
+                    </value>
+                    <next>
+                        <block type="text_print">
+                            <value name="TEXT">
+                                <block type="logic_negate">
+                                    <value name="BOOL">
+                                        <block type="logic_compare">
+                                            <field name="OP">LT</field>
+                                            <value name="A"><block type="math_number"><field name="NUM">3</field></block></value>
+                                            <value name="B"><block type="variables_get"><field name="VAR">x</field></block></value>
+                                        </block>
+                                    </value>
+                                </block>
+                            </value>
+                            <next>
+                                <block type="text_print">
+                                    <value name="TEXT">
+                                        <block type="math_arithmetic">
+                                            <field name="OP">DIVIDE</field>
+                                            <value name="A"><block type="math_number"><field name="NUM">1</field></block></value>
+                                            <value name="B"><block type="math_number"><field name="NUM">0</field></block></value>
+                                        </block>
+                                    </value>
+                                </block>
+                            </next>
+                        </block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+        assert!(FoldConstants.run(&mut program));
+
+        let blocks = &program.groups[0].blocks;
+        let sum = blocks[0].values["TEXT"].block();
+        assert_eq!((sum.block_type.as_str(), sum.id.as_str()), ("math_number", "sum"));
+        assert_eq!(sum.field_str("NUM"), Ok("11"));
+        assert!(sum.values.is_empty());
+        assert_eq!(blocks[1].values["TEXT"].block().block_type, "logic_negate");
+        assert_eq!(blocks[2].values["TEXT"].block().block_type, "math_arithmetic");
+        assert!(!FoldConstants.run(&mut program));
+    }
+
+    #[test]
+    fn test_remove_blocks() {
+        let mut program = program_from_xml(r#"
+            <xml>
+                <block type="controls_repeat_ext" id="outer" x="10" y="20">
+                    <value name="TIMES"><block type="math_number"><field name="NUM">10</field></block></value>
+                    <statement name="DO">
+                        <block type="inner_loop" id="inner">
+                            <field name="COUNT">3</field>
+                            <statement name="BODY">
+                                <block type="led_on" id="off"><field name="TIME">0</field></block>
+                            </statement>
+                        </block>
+                    </statement>
+                    <next>
+                        <block type="controls_repeat_ext" id="counted">
+                            <value name="TIMES"><block type="variables_get"><field name="VAR">n</field></block></value>
+                            <next><block type="led_on" id="on"><field name="TIME">300</field></block></next>
+                        </block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+        let pipeline = Pipeline::new()
+            .pass(RemoveNoOps::new(|block| block.block_type == "led_on" && block.field_i64("TIME") == Ok(0)))
+            .pass(RemoveEmptyLoops::default().with_loop("inner_loop", "BODY"));
+        assert!(pipeline.run(&mut program));
+
+        let ids: Vec<&str> = program.groups[0].blocks.iter().map(|block| block.id.as_str()).collect();
+        assert_eq!(ids, ["counted", "on"]);
+        assert_eq!(program.groups[0].blocks[0].position, Some((10, 20)));
+        assert!(!pipeline.run(&mut program));
+    }
+}
@@ -0,0 +1,106 @@
+use super::RawElement;
+use super::limits::DEFAULT_MAX_DEPTH;
+use super::plugins::{
+    Extensions,
+    UnknownElementHandler,
+};
+
+/// The namespace of workspaces saved by current Blockly versions.
+pub const BLOCKLY_NAMESPACE: &str = "https://developers.google.com/blockly/xml";
+/// The namespace older Blockly versions declared on `<xml>`.
+pub const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+
+/// Controls how forgiving the XML parser is.
+///
+/// The default is lenient, like Blockly itself: unknown elements are skipped
+/// and blocks without ids are accepted. Strict mode turns both into errors,
+/// for validators that want to catch hand-edited or malformed XML.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParserOptions {
+    /// Fail on elements the parser does not understand instead of skipping them.
+    pub strict_unknown_elements: bool,
+    /// Fail on blocks and variables without an `id` attribute.
+    pub require_ids: bool,
+    /// The deepest element nesting accepted, or `None` for no limit. Keep a
+    /// limit when parsing untrusted input: parsing recurses once per level.
+    pub max_depth: Option<usize>,
+    /// Namespaces whose elements are read as Blockly elements, matched by
+    /// local name whatever their prefix. `""` stands for no namespace. Elements
+    /// in other namespaces are treated as unknown. `None` accepts any namespace.
+    pub namespaces: Option<Vec<String>>,
+    /// Keep attributes and child elements of blocks that the parser does not
+    /// understand in `Block::extra_attributes` and `Block::extra_elements`,
+    /// so `program_to_xml` writes them back out. Ignored for unknown elements
+    /// when `strict_unknown_elements` is set.
+    pub preserve_unknown: bool,
+    /// Names of fields that must hold a `#rrggbb` colour, as `field_colour`
+    /// saves them. Blockly's own colour blocks call theirs `COLOUR`.
+    pub colour_fields: Vec<String>,
+    /// What to do with a `<next>` chain under a block plugged into a value
+    /// input. Blockly cannot load these, but some exporters write them.
+    pub value_next: ValueNext,
+    /// Called with unknown child elements of blocks before they are skipped
+    /// or reported; see `on_unknown_element`.
+    pub unknown_element_handler: Option<UnknownElementHandler>,
+}
+
+/// Handling of `<next>` chains on blocks in value inputs. Except with
+/// `Reject`, each one is reported with a `Warning::NextOnValueBlock`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueNext {
+    /// Drop the chain, as Blockly's own loader would.
+    Drop,
+    /// Move the chain to a new top-level stack, so none of its blocks are lost.
+    Detach,
+    /// Fail with `ParseError::UnexpectedElement`.
+    Reject,
+}
+
+impl ParserOptions {
+    pub fn lenient() -> Self {
+        Self {
+            strict_unknown_elements: false,
+            require_ids: false,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            namespaces: Some(default_namespaces()),
+            preserve_unknown: false,
+            colour_fields: Vec::new(),
+            value_next: ValueNext::Detach,
+            unknown_element_handler: None,
+        }
+    }
+
+    pub fn strict() -> Self {
+        Self {
+            strict_unknown_elements: true,
+            require_ids: true,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            namespaces: Some(default_namespaces()),
+            preserve_unknown: false,
+            colour_fields: vec!["COLOUR".to_string()],
+            value_next: ValueNext::Reject,
+            unknown_element_handler: None,
+        }
+    }
+
+    /// Let `handler` capture custom child elements of blocks, such as
+    /// `<my_plugin_meta>`, into the block's `Extensions`. Returning `true`
+    /// claims the element, so it is neither reported as unknown nor kept in
+    /// `Block::extra_elements`, even in strict mode.
+    pub fn on_unknown_element<F>(mut self, handler: F) -> Self
+        where F: Fn(&RawElement, &mut Extensions) -> bool + Send + Sync + 'static
+    {
+        self.unknown_element_handler = Some(UnknownElementHandler::new(handler));
+        self
+    }
+}
+
+fn default_namespaces() -> Vec<String> {
+    vec![BLOCKLY_NAMESPACE.to_string(), XHTML_NAMESPACE.to_string(), "".to_string()]
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self::lenient()
+    }
+}
@@ -0,0 +1,58 @@
+use super::{
+    Program,
+    StatementBody,
+};
+
+impl Program {
+    /// The top-level stacks that do not start with one of
+    /// `entry_block_types`, e.g. `&["main_loop"]`. These are blocks left
+    /// floating on the workspace, which generated code would never run.
+    /// Empty stacks count as orphans.
+    pub fn orphan_groups(&self, entry_block_types: &[&str]) -> Vec<&StatementBody> {
+        self.groups.iter()
+            .filter(|group| !is_rooted(group, entry_block_types))
+            .collect()
+    }
+
+    /// Remove the stacks `orphan_groups` finds and return them, in order.
+    pub fn remove_orphan_groups(&mut self, entry_block_types: &[&str]) -> Vec<StatementBody> {
+        let (kept, orphans) = self.groups.drain(..)
+            .partition(|group| is_rooted(group, entry_block_types));
+        self.groups = kept;
+        orphans
+    }
+}
+
+fn is_rooted(group: &StatementBody, entry_block_types: &[&str]) -> bool {
+    group.blocks.first()
+        .is_some_and(|block| entry_block_types.contains(&block.block_type.as_str()))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_orphan_groups() {
+        let mut program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="led_on" id="stray"></block>
+                <block type="main_loop" id="main"></block>
+                <block type="setup" id="setup"><next><block type="led_off" id="off"></block></next></block>
+                <block type="math_number" id="number"></block>
+            </xml>
+        "#).unwrap();
+
+        let entries = ["main_loop", "setup"];
+        let orphans: Vec<_> = program.orphan_groups(&entries).iter()
+            .map(|group| group.blocks[0].id.as_str())
+            .collect();
+        assert_eq!(orphans, ["stray", "number"]);
+
+        let removed = program.remove_orphan_groups(&entries);
+        assert_eq!(removed.len(), 2);
+        assert_eq!(program.groups.len(), 2);
+        assert!(program.orphan_groups(&entries).is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+use std::mem;
+
+use super::{
+    Program,
+    Block,
+    ParserOptions,
+    PatchError,
+    stack_from_xml,
+};
+
+impl Program {
+    /// Replace the block with this id by the block in `xml`, a `<block>`
+    /// element as produced by `Blockly.Xml.blockToDom`, without re-parsing
+    /// the rest of the workspace. Returns the block that was replaced.
+    ///
+    /// When the fragment has a `next` chain, it also replaces the blocks that
+    /// were below the old block; otherwise they stay where they are. A block in
+    /// a value input or field has no blocks below it, so a `next` chain there
+    /// is ignored. The old block's position is kept if the fragment has none.
+    pub fn patch_block_xml(&mut self, block_id: &str, xml: &str) -> Result<Block, PatchError> {
+        let stack = stack_from_xml(xml, &ParserOptions::default())?;
+        let mut blocks = stack.blocks.into_iter();
+        let mut first = blocks.next().expect("a parsed stack has a block");
+        let below: Vec<Block> = blocks.collect();
+
+        if let Some((body, index)) = self.find_stack_mut(block_id) {
+            if first.position.is_none() {
+                first.position = body.blocks[index].position;
+            }
+            let old = mem::replace(&mut body.blocks[index], first);
+            if !below.is_empty() {
+                body.blocks.truncate(index + 1);
+                body.blocks.extend(below);
+            }
+            return Ok(old);
+        }
+
+        match self.find_block_by_id_mut(block_id) {
+            Some(block) => Ok(mem::replace(block, first)),
+            None => Err(PatchError::UnknownBlock(block_id.to_string())),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        FieldValue,
+        ParseError,
+        program_from_xml,
+    };
+
+    const XML: &str = r#"
+        <xml xmlns="https://developers.google.com/blockly/xml">
+            <block type="main_loop" id="main" x="10" y="20">
+                <statement name="BODY">
+                    <block type="led_on" id="on">
+                        <field name="TIME">300</field>
+                        <value name="BRIGHTNESS">
+                            <block type="math_number" id="num"><field name="NUM">80</field></block>
+                        </value>
+                        <next>
+                            <block type="led_off" id="off"></block>
+                        </next>
+                    </block>
+                </statement>
+            </block>
+        </xml>
+    "#;
+
+    fn ids(program: &Program) -> Vec<&str> {
+        program.iter_blocks().map(|block| block.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_patch_block_xml() {
+        let mut program = program_from_xml(XML).unwrap();
+
+        let old = program.patch_block_xml("on", r#"<block type="led_on" id="on"><field name="TIME">500</field></block>"#).unwrap();
+        assert_eq!(old.fields["TIME"], FieldValue::SimpleField("300".to_string()));
+        assert_eq!(ids(&program), vec!["main", "on", "off"]);
+        assert_eq!(program.find_block_by_id("on").unwrap().fields["TIME"], FieldValue::SimpleField("500".to_string()));
+
+        program.patch_block_xml("on", r#"<block type="wait" id="wait"><next><block type="beep" id="beep"/></next></block>"#).unwrap();
+        assert_eq!(ids(&program), vec!["main", "wait", "beep"]);
+
+        program.patch_block_xml("main", r#"<block type="main_loop" id="main"></block>"#).unwrap();
+        assert_eq!(ids(&program), vec!["main"]);
+        assert_eq!(program.groups[0].blocks[0].position, Some((10, 20)));
+    }
+
+    #[test]
+    fn test_patch_errors() {
+        let mut program = program_from_xml(XML).unwrap();
+
+        program.patch_block_xml("num", r#"<shadow type="math_number" id="num2"/>"#).unwrap();
+        assert!(program.find_block_by_id("num2").is_some());
+
+        assert_eq!(program.patch_block_xml("gone", r#"<block type="a"/>"#), Err(PatchError::UnknownBlock("gone".to_string())));
+        assert_eq!(
+            program.patch_block_xml("on", r#"<xml></xml>"#),
+            Err(PatchError::Parse(ParseError::UnexpectedStructure("expected a <block> element, found <xml>".to_string())))
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt;
+use std::ptr;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+};
+
+/// Where a block sits in a program: the top-level stack, the position in it,
+/// then the inputs followed to reach the block. Displayed as slash-separated
+/// steps, e.g. `0/1/BODY/2/VALUE` for the block in input `VALUE` of the third
+/// block in statement `BODY` of the second block in the first stack.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct BlockPath {
+    pub group: usize,
+    pub index: usize,
+    pub steps: Vec<PathStep>,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum PathStep {
+    /// The block held by a field.
+    Field(String),
+    Value(String),
+    /// The block at a position in a statement input.
+    Statement(String, usize),
+}
+
+impl BlockPath {
+    /// The path of a block in a top-level stack.
+    pub fn new(group: usize, index: usize) -> Self {
+        Self {
+            group,
+            index,
+            steps: Vec::new(),
+        }
+    }
+
+    /// The path of the block `step` leads to from this one.
+    pub fn child(&self, step: PathStep) -> Self {
+        let mut path = self.clone();
+        path.steps.push(step);
+        path
+    }
+
+    /// The path of the block this one is nested in, or `None` for blocks in
+    /// a top-level stack.
+    pub fn parent(&self) -> Option<Self> {
+        let mut path = self.clone();
+        path.steps.pop()?;
+        Some(path)
+    }
+}
+
+impl fmt::Display for BlockPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.group, self.index)?;
+        for step in self.steps.iter() {
+            match *step {
+                PathStep::Field(ref name) | PathStep::Value(ref name) => write!(f, "/{}", name)?,
+                PathStep::Statement(ref name, index) => write!(f, "/{}/{}", name, index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    pub fn block_at(&self, path: &BlockPath) -> Option<&Block> {
+        let mut block = self.groups.get(path.group)?.blocks.get(path.index)?;
+        for step in path.steps.iter() {
+            block = match *step {
+                PathStep::Field(ref name) => match block.fields.get(name)? {
+                    FieldValue::ExpressionField(expression) => expression,
+                    FieldValue::SimpleField(_) => return None,
+                },
+                PathStep::Value(ref name) => block.values.get(name)?.block(),
+                PathStep::Statement(ref name, index) => block.statements.get(name)?.blocks.get(index)?,
+            };
+        }
+        Some(block)
+    }
+
+    /// The path of the first block with the given id, to locate blocks named
+    /// in diffs and validation issues.
+    pub fn path_of(&self, id: &str) -> Option<BlockPath> {
+        find_path(self, &|block| block.id == id)
+    }
+}
+
+impl Block {
+    /// Where this block is in `program`, or `None` if it is not part of it.
+    /// Blocks do not know their parent, so this searches the program.
+    pub fn path(&self, program: &Program) -> Option<BlockPath> {
+        find_path(program, &|block| ptr::eq(block, self))
+    }
+}
+
+fn find_path(program: &Program, matches: &dyn Fn(&Block) -> bool) -> Option<BlockPath> {
+    program.groups.iter()
+        .enumerate()
+        .find_map(|(group, body)| find_in_stack(body, matches, &|index| BlockPath::new(group, index)))
+}
+
+fn find_in_stack(body: &StatementBody, matches: &dyn Fn(&Block) -> bool, path: &dyn Fn(usize) -> BlockPath) -> Option<BlockPath> {
+    body.blocks.iter()
+        .enumerate()
+        .find_map(|(index, block)| find_in_block(block, matches, path(index)))
+}
+
+fn find_in_block(block: &Block, matches: &dyn Fn(&Block) -> bool, path: BlockPath) -> Option<BlockPath> {
+    if matches(block) {
+        return Some(path);
+    }
+    for (name, field) in block.fields.iter() {
+        if let FieldValue::ExpressionField(ref expression) = *field {
+            if let Some(found) = find_in_block(expression, matches, path.child(PathStep::Field(name.clone()))) {
+                return Some(found);
+            }
+        }
+    }
+    for (name, input) in block.values.iter() {
+        if let Some(found) = find_in_block(input.block(), matches, path.child(PathStep::Value(name.clone()))) {
+            return Some(found);
+        }
+    }
+    for (name, body) in block.statements.iter() {
+        let statement_path = |index| path.child(PathStep::Statement(name.clone(), index));
+        if let Some(found) = find_in_stack(body, matches, &statement_path) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_block_paths() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="setup" id="setup"></block>
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <next>
+                                <block type="led_set" id="set">
+                                    <value name="BRIGHTNESS">
+                                        <shadow type="math_number" id="num"><field name="NUM">80</field></shadow>
+                                    </value>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let path = program.path_of("num").unwrap();
+        assert_eq!(path, BlockPath {
+            group: 1,
+            index: 0,
+            steps: vec![
+                PathStep::Statement("BODY".to_string(), 1),
+                PathStep::Value("BRIGHTNESS".to_string()),
+            ],
+        });
+        assert_eq!(path.to_string(), "1/0/BODY/1/BRIGHTNESS");
+        assert_eq!(program.block_at(&path).unwrap().id, "num");
+        assert_eq!(program.block_at(&path.parent().unwrap()).unwrap().id, "set");
+
+        let setup_block = &program.groups[0].blocks[0];
+        assert_eq!(setup_block.path(&program), Some(BlockPath::new(0, 0)));
+        assert_eq!(program.path_of("missing"), None);
+        assert!(program.block_at(&BlockPath::new(0, 1)).is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::any::{
+    Any,
+    TypeId,
+};
+use core::fmt;
+
+use super::RawElement;
+
+/// Values of any type attached to a block by parser plugins, at most one per
+/// type. See `ParserOptions::on_unknown_element`.
+///
+/// Extensions are a side channel: they are ignored when comparing or hashing
+/// blocks, and are not serialized or written back out as XML.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: BTreeMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach `value`, replacing any earlier value of the same type.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) {
+        self.map.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>()).and_then(|value| value.downcast_ref())
+    }
+
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> bool {
+        self.map.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl PartialEq for Extensions {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Extensions {}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Extensions").field("len", &self.map.len()).finish()
+    }
+}
+
+type HandlerFn = dyn Fn(&RawElement, &mut Extensions) -> bool + Send + Sync;
+
+/// A callback given each child element of a `<block>` that the parser does
+/// not understand, with the block's `Extensions`. Set it with
+/// `ParserOptions::on_unknown_element`.
+#[derive(Clone)]
+pub struct UnknownElementHandler(Arc<HandlerFn>);
+
+impl UnknownElementHandler {
+    pub fn new<F>(handler: F) -> Self
+        where F: Fn(&RawElement, &mut Extensions) -> bool + Send + Sync + 'static
+    {
+        UnknownElementHandler(Arc::new(handler))
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn handle(&self, element: &RawElement, extensions: &mut Extensions) -> bool {
+        (self.0)(element, extensions)
+    }
+}
+
+impl PartialEq for UnknownElementHandler {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for UnknownElementHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("UnknownElementHandler(..)")
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{
+        ParserOptions,
+        program_from_xml_with_diagnostics,
+    };
+
+    #[derive(PartialEq, Debug)]
+    struct Meta {
+        author: String,
+    }
+
+    #[test]
+    fn test_on_unknown_element() {
+        let options = ParserOptions::strict().on_unknown_element(|element, extensions| {
+            if element.name != "my_plugin_meta" {
+                return false;
+            }
+            extensions.insert(Meta { author: element.attributes["author"].clone() });
+            true
+        });
+        let xml = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="led_on" id="a"><my_plugin_meta author="sam"></my_plugin_meta></block>
+            </xml>
+        "#;
+        let outcome = program_from_xml_with_diagnostics(xml, &options).unwrap();
+        assert!(outcome.warnings.is_empty());
+        let block = &outcome.program.groups[0].blocks[0];
+        assert_eq!(block.extensions.get::<Meta>(), Some(&Meta { author: "sam".to_string() }));
+        assert!(block.extra_elements.is_empty());
+
+        let other = r#"<xml><block type="led_on" id="a"><sparkle></sparkle></block></xml>"#;
+        assert!(program_from_xml_with_diagnostics(other, &options).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+
+use indexmap::IndexMap;
+
+use super::Program;
+
+/// Limits on what a program may contain, e.g. the blocks an assignment
+/// allows. Blocks are counted as in `Program::stats`, shadows included.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Policy {
+    /// The only block types that may be used, or `None` to allow any type
+    /// not in `denied`.
+    pub allowed: Option<Vec<String>>,
+    pub denied: Vec<String>,
+    /// The most blocks of each type, e.g. `controls_repeat` → 2.
+    pub max_counts: IndexMap<String, usize>,
+    pub max_blocks: Option<usize>,
+    /// The deepest nesting, measured like `ProgramStats::max_depth`.
+    pub max_depth: Option<usize>,
+}
+
+/// A way a program breaks a `Policy`.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Violation {
+    /// A block of a type the policy does not allow. `id` is the block's id.
+    BlockNotAllowed { id: String, block_type: String },
+    TooManyOfType { block_type: String, count: usize, max: usize },
+    TooManyBlocks { count: usize, max: usize },
+    TooDeep { depth: usize, max: usize },
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether blocks of `block_type` may be used at all.
+    pub fn allows(&self, block_type: &str) -> bool {
+        let allowed = match self.allowed {
+            Some(ref allowed) => allowed.iter().any(|allowed| allowed == block_type),
+            None => true,
+        };
+        allowed && !self.denied.iter().any(|denied| denied == block_type)
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Violation::BlockNotAllowed { ref id, ref block_type } => {
+                write!(f, "block {:?} uses `{}`, which is not allowed", id, block_type)
+            },
+            Violation::TooManyOfType { ref block_type, count, max } => {
+                write!(f, "{} `{}` blocks used, at most {} allowed", count, block_type, max)
+            },
+            Violation::TooManyBlocks { count, max } => {
+                write!(f, "{} blocks used, at most {} allowed", count, max)
+            },
+            Violation::TooDeep { depth, max } => {
+                write!(f, "blocks are nested {} deep, at most {} allowed", depth, max)
+            },
+        }
+    }
+}
+
+impl Program {
+    /// Check the program against `policy`. Disallowed blocks are listed
+    /// first in document order, then the limits in the order they are
+    /// declared.
+    pub fn check_policy(&self, policy: &Policy) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = self.iter_blocks()
+            .filter(|block| !policy.allows(&block.block_type))
+            .map(|block| Violation::BlockNotAllowed {
+                id: block.id.clone(),
+                block_type: block.block_type.clone(),
+            })
+            .collect();
+
+        let stats = self.stats();
+        for (block_type, &max) in policy.max_counts.iter() {
+            let count = stats.blocks_by_type.get(block_type).cloned().unwrap_or(0);
+            if count > max {
+                violations.push(Violation::TooManyOfType { block_type: block_type.clone(), count, max });
+            }
+        }
+        if let Some(max) = policy.max_blocks {
+            if stats.block_count > max {
+                violations.push(Violation::TooManyBlocks { count: stats.block_count, max });
+            }
+        }
+        if let Some(max) = policy.max_depth {
+            if stats.max_depth > max {
+                violations.push(Violation::TooDeep { depth: stats.max_depth, max });
+            }
+        }
+        violations
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_check_policy() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="controls_repeat" id="r1">
+                    <statement name="DO">
+                        <block type="controls_repeat" id="r2">
+                            <statement name="DO">
+                                <block type="controls_repeat" id="r3"></block>
+                            </statement>
+                        </block>
+                    </statement>
+                    <next><block type="text_print" id="print"></block></next>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        assert!(program.check_policy(&Policy::new()).is_empty());
+
+        let mut policy = Policy {
+            denied: vec!["text_print".to_string()],
+            max_blocks: Some(3),
+            max_depth: Some(2),
+            ..Policy::default()
+        };
+        policy.max_counts.insert("controls_repeat".to_string(), 2);
+        assert_eq!(program.check_policy(&policy), vec![
+            Violation::BlockNotAllowed { id: "print".to_string(), block_type: "text_print".to_string() },
+            Violation::TooManyOfType { block_type: "controls_repeat".to_string(), count: 3, max: 2 },
+            Violation::TooManyBlocks { count: 4, max: 3 },
+            Violation::TooDeep { depth: 3, max: 2 },
+        ]);
+
+        let policy = Policy {
+            allowed: Some(vec!["controls_repeat".to_string()]),
+            ..Policy::default()
+        };
+        assert_eq!(program.check_policy(&policy).len(), 1);
+        assert!(!policy.allows("text_print"));
+    }
+}
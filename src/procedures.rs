@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use super::{
+    Program,
+    Block,
+    FieldValue,
+    Mutation,
+    RawNode,
+};
+
+/// A procedure defined by a `procedures_defnoreturn` or
+/// `procedures_defreturn` block.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Procedure<'a> {
+    pub name: &'a str,
+    pub parameters: Vec<&'a str>,
+    /// True for `procedures_defreturn`.
+    pub returns: bool,
+    pub definition: &'a Block,
+}
+
+/// The procedures defined in a program, for resolving call blocks. Names are
+/// matched case-insensitively, as Blockly does.
+pub struct Procedures<'a> {
+    definitions: HashMap<String, Procedure<'a>>,
+    calls: Vec<&'a Block>,
+}
+
+impl<'a> Procedures<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let mut definitions = HashMap::new();
+        let mut calls = Vec::new();
+        for block in program.iter_blocks() {
+            match block.block_type.as_str() {
+                "procedures_defnoreturn" | "procedures_defreturn" => {
+                    let name = match block.fields.get("NAME") {
+                        Some(FieldValue::SimpleField(name)) => name.as_str(),
+                        _ => continue,
+                    };
+                    // Keep the first definition if names collide
+                    definitions.entry(name.to_lowercase()).or_insert(Procedure {
+                        name,
+                        parameters: block.mutation.as_ref().map(parameters).unwrap_or_default(),
+                        returns: block.block_type == "procedures_defreturn",
+                        definition: block,
+                    });
+                },
+                "procedures_callnoreturn" | "procedures_callreturn" => calls.push(block),
+                _ => {}
+            }
+        }
+        Self {
+            definitions,
+            calls,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Procedure<'a>> {
+        self.definitions.get(&name.to_lowercase())
+    }
+
+    /// The definition a call block refers to, or `None` if the block is not
+    /// a call or the procedure is not defined.
+    pub fn resolve(&self, call: &Block) -> Option<&Procedure<'a>> {
+        call_name(call).and_then(|name| self.get(name))
+    }
+
+    /// All call blocks, in document order.
+    pub fn calls(&self) -> &[&'a Block] {
+        &self.calls
+    }
+
+    /// Call blocks whose procedure is not defined, in document order.
+    pub fn undefined_calls(&self) -> Vec<&'a Block> {
+        self.calls.iter()
+            .filter(|call| self.resolve(call).is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Definitions sorted by name.
+    pub fn definitions(&self) -> Vec<&Procedure<'a>> {
+        let mut definitions: Vec<_> = self.definitions.values().collect();
+        definitions.sort_by_key(|procedure| procedure.name);
+        definitions
+    }
+
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+}
+
+impl Program {
+    pub fn procedures(&self) -> Procedures<'_> {
+        Procedures::new(self)
+    }
+}
+
+// The name a call block's mutation refers to
+fn call_name(call: &Block) -> Option<&str> {
+    match call.block_type.as_str() {
+        "procedures_callnoreturn" | "procedures_callreturn" => {
+            call.mutation.as_ref().and_then(|mutation| mutation.attribute("name"))
+        },
+        _ => None
+    }
+}
+
+// Parameters are `<arg name="...">` in XML, and `params` entries in JSON's
+// extraState, either as plain names or `{"name": ...}` objects
+pub(crate) fn parameters(mutation: &Mutation) -> Vec<&str> {
+    mutation.children.iter()
+        .filter_map(|child| {
+            match child.name.as_str() {
+                "arg" => child.attribute("name"),
+                "params" => child.attribute("name").or_else(|| match child.children.first() {
+                    Some(RawNode::Text(text)) => Some(text.as_str()),
+                    _ => None,
+                }),
+                _ => None
+            }
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::{
+        program_from_xml,
+        program_from_json,
+    };
+
+    #[test]
+    fn test_procedures() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="procedures_defreturn" id="def">
+                    <mutation>
+                        <arg name="x" varid="v1"></arg>
+                        <arg name="y" varid="v2"></arg>
+                    </mutation>
+                    <field name="NAME">Add Numbers</field>
+                </block>
+                <block type="procedures_callnoreturn" id="call">
+                    <mutation name="add numbers">
+                        <arg name="x"></arg>
+                        <arg name="y"></arg>
+                    </mutation>
+                    <next>
+                        <block type="procedures_callnoreturn" id="missing">
+                            <mutation name="blink"></mutation>
+                        </block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let procedures = program.procedures();
+        assert_eq!(procedures.len(), 1);
+        let call = program.find_block_by_id("call").unwrap();
+        let procedure = procedures.resolve(call).unwrap();
+        assert_eq!(procedure.name, "Add Numbers");
+        assert_eq!(procedure.parameters, vec!["x", "y"]);
+        assert!(procedure.returns);
+        assert_eq!(procedure.definition.id, "def");
+
+        assert_eq!(procedures.calls().len(), 2);
+        let undefined: Vec<_> = procedures.undefined_calls().iter().map(|block| block.id.as_str()).collect();
+        assert_eq!(undefined, vec!["missing"]);
+        assert!(procedures.resolve(procedure.definition).is_none());
+    }
+
+    #[test]
+    fn test_json_parameters() {
+        let program = program_from_json(r#"{
+            "blocks": { "blocks": [
+                {
+                    "type": "procedures_defnoreturn",
+                    "id": "def",
+                    "extraState": { "params": [{ "name": "count", "id": "v1" }] },
+                    "fields": { "NAME": "blink" }
+                },
+                {
+                    "type": "procedures_callnoreturn",
+                    "id": "call",
+                    "extraState": { "name": "blink", "params": ["count"] }
+                }
+            ] }
+        }"#).unwrap();
+
+        let procedures = program.procedures();
+        let call = program.find_block_by_id("call").unwrap();
+        assert_eq!(procedures.resolve(call).unwrap().parameters, vec!["count"]);
+        assert!(!procedures.get("BLINK").unwrap().returns);
+    }
+}
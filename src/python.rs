@@ -0,0 +1,125 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{
+    PyDict,
+    PyList,
+};
+use pyo3::IntoPyObjectExt;
+use serde_json::Value;
+
+use super::{
+    BlockRegistry,
+    Program,
+    program_from_xml,
+};
+use super::codegen::{
+    generate as generate_code,
+    Generator,
+    JavaScriptGenerator,
+    PythonGenerator,
+    RustGenerator,
+};
+
+/// Parse a workspace's XML into a dict with the same shape as the serde
+/// serialization of `Program`. Raises `ValueError` if it cannot be parsed.
+#[pyfunction]
+fn parse_workspace<'py>(py: Python<'py>, xml: &str) -> PyResult<Bound<'py, PyAny>> {
+    let program = parse(xml)?;
+    let value = serde_json::to_value(&program).map_err(|error| PyValueError::new_err(error.to_string()))?;
+    to_python(py, &value)
+}
+
+/// Check a workspace against block definitions in Blockly's JSON format,
+/// returning `(block id, issue)` pairs in document order.
+#[pyfunction]
+fn validate(xml: &str, definitions: &str) -> PyResult<Vec<(String, String)>> {
+    let program = parse(xml)?;
+    let registry = BlockRegistry::from_json(definitions).map_err(|error| PyValueError::new_err(error.to_string()))?;
+    Ok(program.validate(&registry)
+        .into_iter()
+        .map(|issue| (issue.id().to_string(), issue.to_string()))
+        .collect())
+}
+
+/// Generate code for a workspace in `language`: "javascript", "python" or
+/// "rust".
+#[pyfunction]
+fn generate(xml: &str, language: &str) -> PyResult<String> {
+    let program = parse(xml)?;
+    let generator: Box<dyn Generator> = match language {
+        "javascript" => Box::new(JavaScriptGenerator::new()),
+        "python" => Box::new(PythonGenerator::new()),
+        "rust" => Box::new(RustGenerator::new()),
+        _ => return Err(PyValueError::new_err(format!("unknown language {:?}", language))),
+    };
+    generate_code(&program, generator.as_ref()).map_err(|error| PyValueError::new_err(error.to_string()))
+}
+
+/// The `blockly_parser` Python module. Build it with
+/// `maturin build --release --features python`.
+#[pymodule]
+fn blockly_parser(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(parse_workspace, module)?)?;
+    module.add_function(wrap_pyfunction!(validate, module)?)?;
+    module.add_function(wrap_pyfunction!(generate, module)?)?;
+    Ok(())
+}
+
+fn parse(xml: &str) -> PyResult<Program> {
+    program_from_xml(xml).map_err(|error| PyValueError::new_err(error.to_string()))
+}
+
+fn to_python<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    match *value {
+        Value::Null => Ok(py.None().into_bound(py)),
+        Value::Bool(flag) => flag.into_bound_py_any(py),
+        Value::Number(ref number) => match number.as_i64() {
+            Some(integer) => integer.into_bound_py_any(py),
+            None => number.as_f64().unwrap_or(f64::NAN).into_bound_py_any(py),
+        },
+        Value::String(ref text) => text.into_bound_py_any(py),
+        Value::Array(ref items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(to_python(py, item)?)?;
+            }
+            Ok(list.into_any())
+        },
+        Value::Object(ref object) => {
+            let dict = PyDict::new(py);
+            for (key, item) in object {
+                dict.set_item(key, to_python(py, item)?)?;
+            }
+            Ok(dict.into_any())
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_python_module() {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "blockly_parser").unwrap();
+            blockly_parser(&module).unwrap();
+            let xml = r#"<xml><block type="text_print" id="a"><value name="TEXT"><block type="text" id="b"><field name="TEXT">hi</field></block></value></block></xml>"#;
+
+            let program = module.getattr("parse_workspace").unwrap().call1((xml,)).unwrap();
+            let block = program.get_item("groups").unwrap().get_item(0).unwrap().get_item("blocks").unwrap().get_item(0).unwrap();
+            assert_eq!(block.get_item("block_type").unwrap().extract::<String>().unwrap(), "text_print");
+
+            let issues: Vec<(String, String)> = module.getattr("validate").unwrap().call1((xml, "[]")).unwrap().extract().unwrap();
+            assert_eq!(issues[0], ("a".to_string(), r#"block "a" has unknown type `text_print`"#.to_string()));
+
+            let code: String = module.getattr("generate").unwrap().call1((xml, "python")).unwrap().extract().unwrap();
+            assert_eq!(code, "print('hi')\n");
+
+            let error = module.getattr("parse_workspace").unwrap().call1(("<workspace/>",)).unwrap_err();
+            assert!(error.is_instance_of::<PyValueError>(py));
+        });
+    }
+}
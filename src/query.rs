@@ -0,0 +1,191 @@
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    BlockPath,
+    PathStep,
+};
+
+/// A block found by a `Query`.
+#[derive(Debug, Clone)]
+pub struct QueryMatch<'a> {
+    pub block: &'a Block,
+    /// The blocks the match is nested in, outermost first. Empty for blocks
+    /// in a top-level stack.
+    pub ancestors: Vec<&'a Block>,
+    pub path: BlockPath,
+}
+
+type Predicate<'q> = Box<dyn Fn(&QueryMatch<'_>) -> bool + 'q>;
+
+/// Filters over every block in a program, built with `Program::query`.
+///
+/// ```
+/// use blockly_parser::program_from_xml;
+///
+/// let program = program_from_xml(r#"
+///     <xml>
+///         <block type="main_loop" id="main">
+///             <statement name="BODY">
+///                 <block type="led_on" id="on"><field name="TIME">300</field></block>
+///             </statement>
+///         </block>
+///     </xml>
+/// "#).unwrap();
+/// let matches = program.query()
+///     .of_type("led_on")
+///     .with_field("TIME", |value| value.parse::<u32>().unwrap_or(0) > 200)
+///     .inside("main_loop")
+///     .matches();
+/// assert_eq!(matches[0].block.id, "on");
+/// ```
+pub struct Query<'a, 'q> {
+    program: &'a Program,
+    predicates: Vec<Predicate<'q>>,
+}
+
+impl Program {
+    /// Start a query matching every block.
+    pub fn query(&self) -> Query<'_, '_> {
+        Query {
+            program: self,
+            predicates: Vec::new(),
+        }
+    }
+}
+
+impl<'a, 'q> Query<'a, 'q> {
+    pub fn of_type(self, block_type: &'q str) -> Self {
+        self.filter(move |found| found.block.block_type == block_type)
+    }
+
+    /// Blocks with a text field `name` whose value satisfies `predicate`.
+    pub fn with_field<F>(self, name: &'q str, predicate: F) -> Self
+        where F: Fn(&str) -> bool + 'q
+    {
+        self.filter(move |found| match found.block.fields.get(name) {
+            Some(FieldValue::SimpleField(value)) => predicate(value),
+            _ => false,
+        })
+    }
+
+    /// Blocks nested, at any depth, in a block of type `block_type`.
+    pub fn inside(self, block_type: &'q str) -> Self {
+        self.filter(move |found| found.ancestors.iter().any(|ancestor| ancestor.block_type == block_type))
+    }
+
+    pub fn filter<F>(mut self, predicate: F) -> Self
+        where F: Fn(&QueryMatch<'_>) -> bool + 'q
+    {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    /// The matching blocks, in document order. Inputs are visited in the
+    /// order fields, values, statements, each sorted by name.
+    pub fn matches(&self) -> Vec<QueryMatch<'a>> {
+        let mut matches = Vec::new();
+        let mut ancestors = Vec::new();
+        for (group, body) in self.program.groups.iter().enumerate() {
+            self.collect_body(body, &|index| BlockPath::new(group, index), &mut ancestors, &mut matches);
+        }
+        matches
+    }
+
+    pub fn blocks(&self) -> Vec<&'a Block> {
+        self.matches().into_iter().map(|found| found.block).collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.matches().len()
+    }
+
+    fn collect_body(&self, body: &'a StatementBody, path: &dyn Fn(usize) -> BlockPath, ancestors: &mut Vec<&'a Block>, matches: &mut Vec<QueryMatch<'a>>) {
+        for (index, block) in body.blocks.iter().enumerate() {
+            self.collect_block(block, path(index), ancestors, matches);
+        }
+    }
+
+    fn collect_block(&self, block: &'a Block, path: BlockPath, ancestors: &mut Vec<&'a Block>, matches: &mut Vec<QueryMatch<'a>>) {
+        let found = QueryMatch {
+            block,
+            ancestors: ancestors.clone(),
+            path: path.clone(),
+        };
+        if self.predicates.iter().all(|predicate| predicate(&found)) {
+            matches.push(found);
+        }
+
+        ancestors.push(block);
+        let mut field_names: Vec<&String> = block.fields.keys().collect();
+        field_names.sort();
+        for name in field_names {
+            if let FieldValue::ExpressionField(ref expression) = block.fields[name] {
+                self.collect_block(expression, path.child(PathStep::Field(name.clone())), ancestors, matches);
+            }
+        }
+        let mut value_names: Vec<&String> = block.values.keys().collect();
+        value_names.sort();
+        for name in value_names {
+            let value_path = path.child(PathStep::Value(name.clone()));
+            self.collect_block(block.values[name].block(), value_path, ancestors, matches);
+        }
+        let mut statement_names: Vec<&String> = block.statements.keys().collect();
+        statement_names.sort();
+        for name in statement_names {
+            let statement_path = |index| path.child(PathStep::Statement(name.clone(), index));
+            self.collect_body(&block.statements[name], &statement_path, ancestors, matches);
+        }
+        ancestors.pop();
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_query() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="long">
+                            <field name="TIME">300</field>
+                            <next>
+                                <block type="led_on" id="short">
+                                    <field name="TIME">100</field>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="led_on" id="loose">
+                    <field name="TIME">500</field>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let ids = |blocks: Vec<&super::Block>| -> Vec<String> {
+            blocks.iter().map(|block| block.id.clone()).collect()
+        };
+        assert_eq!(program.query().count(), 4);
+        assert_eq!(ids(program.query().of_type("led_on").blocks()), vec!["long", "short", "loose"]);
+        assert_eq!(ids(program.query()
+            .of_type("led_on")
+            .with_field("TIME", |value| value.parse::<u32>().unwrap_or(0) > 200)
+            .blocks()), vec!["long", "loose"]);
+
+        let matches = program.query()
+            .with_field("TIME", |value| value.parse::<u32>().unwrap_or(0) > 200)
+            .inside("main_loop")
+            .matches();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].block.id, "long");
+        assert_eq!(matches[0].ancestors[0].id, "main");
+        assert_eq!(matches[0].path.to_string(), "0/0/BODY/0");
+        assert_eq!(program.query().filter(|found| found.ancestors.is_empty()).count(), 2);
+    }
+}
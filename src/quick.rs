@@ -0,0 +1,209 @@
+use indexmap::IndexMap;
+
+use quick_xml::Reader;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{
+    BytesStart,
+    Event,
+};
+
+use super::{
+    ParseError,
+    RawElement,
+    RawNode,
+};
+
+/// Read the document's root element with quick-xml. This is more forgiving
+/// than sxd-document: end tags that do not match are closed up to the nearest
+/// matching element or ignored, unclosed elements are closed at the end of
+/// the input, and unknown entities are kept as written.
+pub(crate) fn parse_root(xml: &str) -> Result<Tree, ParseError> {
+    let mut reader = Reader::from_str(xml);
+    {
+        let config = reader.config_mut();
+        config.check_end_names = false;
+        config.allow_unmatched_ends = true;
+        config.allow_dangling_amp = true;
+    }
+
+    let mut open: Vec<RawElement> = Vec::new();
+    let mut root: Option<RawElement> = None;
+    loop {
+        let event = reader.read_event().map_err(|error| {
+            ParseError::Xml {
+                position: reader.error_position() as usize,
+                message: error.to_string(),
+            }
+        })?;
+        match event {
+            Event::Start(ref start) => {
+                let el = element(start, &open)?;
+                open.push(el);
+            },
+            Event::Empty(ref start) => {
+                let el = element(start, &open)?;
+                close(&mut open, &mut root, el);
+            },
+            Event::End(ref end) => {
+                let name = end.local_name().into_inner();
+                if let Some(index) = open.iter().rposition(|el| el.name == name) {
+                    while open.len() > index {
+                        let el = open.pop().expect("open element");
+                        close(&mut open, &mut root, el);
+                    }
+                }
+            },
+            Event::Text(ref text) => {
+                push_text(&mut open, &text.xml10_content());
+            },
+            Event::CData(ref data) => {
+                push_text(&mut open, &data.xml10_content());
+            },
+            Event::GeneralRef(ref reference) => {
+                let name = reference.xml10_content();
+                let resolved = match reference.resolve_char_ref() {
+                    Ok(Some(ch)) => ch.to_string(),
+                    _ => match resolve_predefined_entity(&name) {
+                        Some(text) => text.to_string(),
+                        None => format!("&{};", name),
+                    }
+                };
+                push_text(&mut open, &resolved);
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        if root.is_some() {
+            break;
+        }
+    }
+    while let Some(el) = open.pop() {
+        close(&mut open, &mut root, el);
+    }
+
+    root.map(Tree).ok_or(ParseError::MissingRootElement)
+}
+
+/// A parsed document. Each block in a stack nests inside the one above it,
+/// too deep for the recursive drop of `RawElement`, so it is taken apart one
+/// element at a time.
+pub(crate) struct Tree(RawElement);
+
+impl Tree {
+    pub(crate) fn root(&self) -> &RawElement {
+        &self.0
+    }
+}
+
+impl Drop for Tree {
+    fn drop(&mut self) {
+        let mut pending = std::mem::take(&mut self.0.children);
+        while let Some(node) = pending.pop() {
+            if let RawNode::Element(mut el) = node {
+                pending.append(&mut el.children);
+            }
+        }
+    }
+}
+
+// Attributes and the element keep their prefix. Prefixes declared on an
+// ancestor are declared again here, as sxd-document's `RawElement`s do.
+fn element(start: &BytesStart, open: &[RawElement]) -> Result<RawElement, ParseError> {
+    let mut attributes = IndexMap::default();
+    for attribute in start.attributes().with_checks(false) {
+        let attribute = attribute.map_err(|error| ParseError::UnexpectedStructure(error.to_string()))?;
+        let name = attribute.key.into_inner().to_string();
+        let value = attribute.normalized_value(Default::default())
+            .map(|value| value.into_owned())
+            .unwrap_or_else(|_| attribute.value.into_owned());
+        attributes.insert(name, value);
+    }
+    let prefix = start.name().prefix().map(|prefix| prefix.into_inner().to_string());
+    let prefixes: Vec<String> = attributes.keys()
+        .filter_map(|name| name.split_once(':'))
+        .map(|(prefix, _)| prefix)
+        .chain(prefix.as_deref())
+        .filter(|&prefix| prefix != "xml" && prefix != "xmlns")
+        .map(|prefix| format!("xmlns:{}", prefix))
+        .collect();
+    for declaration in prefixes {
+        if attributes.contains_key(&declaration) {
+            continue;
+        }
+        let namespace = open.iter().rev().find_map(|el| el.attributes.get(&declaration));
+        if let Some(namespace) = namespace {
+            let index = attributes.len();
+            attributes.insert(declaration.clone(), namespace.clone());
+            attributes.move_index(index, 0);
+        }
+    }
+    // Only prefixed names are resolved; a default namespace stays declared
+    // in the element's own `xmlns` attribute
+    let namespace = prefix.as_ref()
+        .and_then(|prefix| attributes.get(&format!("xmlns:{}", prefix)))
+        .cloned();
+    Ok(RawElement {
+        name: start.local_name().into_inner().to_string(),
+        namespace,
+        prefix,
+        attributes,
+        children: Vec::new(),
+    })
+}
+
+// Attach a finished element to its parent, or make it the root
+fn close(open: &mut [RawElement], root: &mut Option<RawElement>, el: RawElement) {
+    match open.last_mut() {
+        Some(parent) => parent.children.push(RawNode::Element(el)),
+        None => {
+            if root.is_none() {
+                *root = Some(el);
+            }
+        }
+    }
+}
+
+fn push_text(open: &mut [RawElement], text: &str) {
+    if let Some(parent) = open.last_mut() {
+        if let Some(&mut RawNode::Text(ref mut existing)) = parent.children.last_mut() {
+            existing.push_str(text);
+            return;
+        }
+        parent.children.push(RawNode::Text(text.to_string()));
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_parse_root() {
+        let tree = parse_root(r#"<?xml version="1.0"?><xml><block type="a" id="1"><field name="T">x &amp; &#49; &nbsp;</field></block></xml>"#).unwrap();
+        let root = tree.root();
+        assert_eq!(root.name, "xml");
+        let block = root.child_elements().next().unwrap();
+        assert_eq!(block.attribute("type"), Some("a"));
+        assert_eq!(block.child_elements().next().unwrap().text(), "x & 1 &nbsp;");
+
+        assert_eq!(parse_root("text only").err(), Some(ParseError::MissingRootElement));
+    }
+
+    #[test]
+    fn test_tolerates_broken_nesting() {
+        // The `<value>` is never closed and `</statement>` has no match
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="led_on" id="on">
+                    <value name="BRIGHTNESS">
+                        <block type="math_number" id="num"><field name="NUM">80</field></block>
+                </block>
+                </statement>
+            </xml>
+        "#).unwrap();
+        let led_on_block = &program.groups[0].blocks[0];
+        assert_eq!(led_on_block.values["BRIGHTNESS"].block().id, "num");
+    }
+}
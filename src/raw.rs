@@ -0,0 +1,101 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use indexmap::IndexMap;
+#[cfg(feature = "std")]
+use sxd_document::dom::{
+    Element,
+    ChildOfElement,
+};
+
+use super::MapHasher;
+#[cfg(feature = "std")]
+use super::dom::XmlElement;
+#[cfg(feature = "std")]
+use super::{
+    BLOCKLY_NAMESPACE,
+    XHTML_NAMESPACE,
+};
+
+/// An XML element kept as-is, for content the parser has no dedicated model for.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct RawElement {
+    /// The local name, without a prefix.
+    pub name: String,
+    /// The namespace, unless it is the document's own (Blockly's or XHTML).
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub namespace: Option<String>,
+    /// The prefix the element was written with, if any.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub prefix: Option<String>,
+    /// Namespaced attributes are keyed with their prefix, and come with an
+    /// `xmlns:` declaration for it, so the element can be written out anywhere.
+    pub attributes: IndexMap<String, String, MapHasher>,
+    pub children: Vec<RawNode>,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum RawNode {
+    Element(RawElement),
+    Text(String),
+}
+
+impl RawElement {
+    #[cfg(feature = "std")]
+    pub(crate) fn new(element: Element) -> Self {
+        let attributes = XmlElement::attributes(&element)
+            .into_iter()
+            .map(|(name, value)| (name.into_owned(), value.to_string()))
+            .collect();
+
+        let children = element.children()
+            .iter()
+            .filter_map(|child| {
+                match *child {
+                    ChildOfElement::Element(el) => Some(RawNode::Element(RawElement::new(el))),
+                    ChildOfElement::Text(text_node) => Some(RawNode::Text(text_node.text().to_string())),
+                    _ => None
+                }
+            })
+            .collect();
+
+        let namespace = element.name().namespace_uri()
+            .filter(|&namespace| namespace != BLOCKLY_NAMESPACE && namespace != XHTML_NAMESPACE);
+        Self {
+            name: element.name().local_part().to_string(),
+            namespace: namespace.map(String::from),
+            prefix: namespace.and(element.preferred_prefix()).map(String::from),
+            attributes,
+            children,
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|value| value.as_str())
+    }
+
+    /// Child elements, skipping text nodes.
+    pub fn child_elements(&self) -> impl Iterator<Item = &RawElement> {
+        self.children.iter().filter_map(|child| {
+            match *child {
+                RawNode::Element(ref el) => Some(el),
+                RawNode::Text(_) => None,
+            }
+        })
+    }
+
+    /// The concatenated text content of this element's direct text children.
+    pub fn text(&self) -> String {
+        self.children.iter()
+            .filter_map(|child| {
+                match *child {
+                    RawNode::Text(ref text) => Some(text.as_str()),
+                    RawNode::Element(_) => None,
+                }
+            })
+            .collect()
+    }
+}
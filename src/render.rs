@@ -0,0 +1,136 @@
+use std::fmt::Write;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    InputContent,
+};
+
+impl Program {
+    /// An indented outline of the program: variables, then each stack with
+    /// its blocks' types, ids, fields and inputs. Fields and inputs are
+    /// sorted by name, so the output is stable enough for snapshots.
+    ///
+    /// ```
+    /// let program = blockly_parser::program_from_xml(r#"
+    ///     <xml><block type="led_on" id="a"><field name="TIME">300</field></block></xml>
+    /// "#).unwrap();
+    /// assert_eq!(program.render_tree(), "stack 1:\n  led_on (a)\n    TIME = \"300\"\n");
+    /// ```
+    pub fn render_tree(&self) -> String {
+        let mut out = String::new();
+        if !self.variables.is_empty() {
+            out.push_str("variables:\n");
+            for variable in &self.variables {
+                match variable.var_type {
+                    Some(ref var_type) => writeln!(out, "  {} ({}, {})", variable.name, variable.id, var_type),
+                    None => writeln!(out, "  {} ({})", variable.name, variable.id),
+                }.unwrap();
+            }
+        }
+        for (index, group) in self.groups.iter().enumerate() {
+            match group.blocks.first().and_then(|block| block.position) {
+                Some((x, y)) => writeln!(out, "stack {} at ({}, {}):", index + 1, x, y),
+                None => writeln!(out, "stack {}:", index + 1),
+            }.unwrap();
+            render_stack(group, 1, &mut out);
+        }
+        out
+    }
+}
+
+fn render_stack(body: &StatementBody, depth: usize, out: &mut String) {
+    for block in &body.blocks {
+        render_block(block, depth, out);
+    }
+}
+
+fn render_block(block: &Block, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    write!(out, "{}{} ({})", indent, block.block_type, block.id).unwrap();
+    if block.flags.disabled {
+        out.push_str(" [disabled]");
+    }
+    if block.flags.collapsed {
+        out.push_str(" [collapsed]");
+    }
+    out.push('\n');
+
+    let mut fields: Vec<_> = block.fields.iter().collect();
+    fields.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, value) in fields {
+        match *value {
+            FieldValue::SimpleField(ref text) => writeln!(out, "{}  {} = {:?}", indent, name, text).unwrap(),
+            FieldValue::ExpressionField(ref inner) => {
+                writeln!(out, "{}  {} =", indent, name).unwrap();
+                render_block(inner, depth + 2, out);
+            }
+        }
+    }
+    let mut values: Vec<_> = block.values.iter().collect();
+    values.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, content) in values {
+        match *content {
+            InputContent::Block(ref inner) => {
+                writeln!(out, "{}  {}:", indent, name).unwrap();
+                render_block(inner, depth + 2, out);
+            },
+            InputContent::Shadow(ref inner) => {
+                writeln!(out, "{}  {} (shadow):", indent, name).unwrap();
+                render_block(inner, depth + 2, out);
+            }
+        }
+    }
+    let mut statements: Vec<_> = block.statements.iter().collect();
+    statements.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, body) in statements {
+        writeln!(out, "{}  {}:", indent, name).unwrap();
+        render_stack(body, depth + 2, out);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_render_tree() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables>
+                    <variable id="v1" type="Number">count</variable>
+                </variables>
+                <block type="main_loop" id="main" x="10" y="20">
+                    <statement name="BODY">
+                        <block type="led_on" id="on" disabled="true">
+                            <field name="TIME">300</field>
+                            <value name="BRIGHTNESS">
+                                <shadow type="math_number" id="num"><field name="NUM">80</field></shadow>
+                            </value>
+                            <next>
+                                <block type="led_off" id="off"></block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        assert_eq!(program.render_tree(), "\
+variables:
+  count (v1, Number)
+stack 1 at (10, 20):
+  main_loop (main)
+    BODY:
+      led_on (on) [disabled]
+        TIME = \"300\"
+        BRIGHTNESS (shadow):
+          math_number (num)
+            NUM = \"80\"
+      led_off (off)
+");
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use sxd_document::dom::{
+    ChildOfElement,
+    Document,
+    Element,
+};
+
+use super::{
+    Diagnostics,
+    ParseError,
+    ParserOptions,
+    Program,
+    Warning,
+    check_depth,
+    get_attribute,
+    get_xml_element,
+    parse_package,
+    program_from_element,
+};
+use super::ids::{
+    SoupIdGenerator,
+    random_unused_id,
+};
+use super::iter::child_blocks_mut;
+
+// Elements whose text means nothing, so any text in them is stray.
+const STRUCTURAL: &[&str] = &["xml", "block", "shadow", "statement", "value", "next", "variables"];
+
+/// A fix applied by `program_from_xml_repair`.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum Repair {
+    /// A `<block>` or `<variable>` without an id was given `id`.
+    AddedId { element: String, id: String },
+    /// A block had two fields with the same name; the last one was kept.
+    DuplicateField { block: String, field: String },
+    /// Blocks side by side in a `<statement>` were chained with `<next>`,
+    /// in document order. `block` is the id of the block holding the input.
+    ChainedStatement { block: String, statement: String, blocks: usize },
+    /// Text directly inside a structural element such as `<block>` was dropped.
+    StrayText { parent: String, text: String },
+    /// A `<next>` chain under a block in a value input was moved to a new
+    /// top-level stack.
+    DetachedNext { block: String },
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Repair::AddedId { ref element, ref id } => {
+                write!(f, "gave <{}> element without an id the id {:?}", element, id)
+            },
+            Repair::DuplicateField { ref block, ref field } => {
+                write!(f, "kept the last `{}` field of block {:?}", field, block)
+            },
+            Repair::ChainedStatement { ref block, ref statement, blocks } => {
+                write!(f, "chained {} sibling blocks in statement `{}` of block {:?}", blocks, statement, block)
+            },
+            Repair::StrayText { ref parent, ref text } => {
+                write!(f, "dropped text {:?} inside <{}>", text, parent)
+            },
+            Repair::DetachedNext { ref block } => {
+                write!(f, "moved the next blocks of value block {:?} to a new stack", block)
+            },
+        }
+    }
+}
+
+/// A repaired program with every repair applied to it.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct RepairOutcome {
+    pub program: Program,
+    pub repairs: Vec<Repair>,
+}
+
+/// Parse a workspace leniently, fixing corruptions common in old saved
+/// workspaces instead of losing the blocks involved: blocks without ids,
+/// duplicate fields, sibling blocks in a `<statement>`, `<next>` chains on
+/// value blocks and stray text.
+pub fn program_from_xml_repair(xml: &str) -> Result<RepairOutcome, ParseError> {
+    let options = ParserOptions::default();
+    if let Some(max_depth) = options.max_depth {
+        check_depth(xml, max_depth)?;
+    }
+    let package = parse_package(xml)?;
+    let document = package.as_document();
+    let root = get_xml_element(document).ok_or(ParseError::MissingRootElement)?;
+
+    let mut repairs = Vec::new();
+    repair_element(document, root, &mut repairs);
+
+    let mut diagnostics = Diagnostics::new();
+    let mut program = program_from_element(root, &options, &mut diagnostics)?;
+    for warning in diagnostics {
+        match warning {
+            Warning::DuplicateField { block, field } => repairs.push(Repair::DuplicateField { block, field }),
+            Warning::NextOnValueBlock { block } => repairs.push(Repair::DetachedNext { block }),
+            _ => {},
+        }
+    }
+    add_missing_ids(&mut program, &mut repairs);
+
+    Ok(RepairOutcome { program, repairs })
+}
+
+fn repair_element<'d>(document: Document<'d>, element: Element<'d>, repairs: &mut Vec<Repair>) {
+    let name = element.name().local_part();
+    if !STRUCTURAL.contains(&name) {
+        return;
+    }
+    if name == "statement" {
+        chain_siblings(document, element, repairs);
+    }
+    for child in element.children() {
+        match child {
+            ChildOfElement::Element(child_el) => repair_element(document, child_el, repairs),
+            ChildOfElement::Text(text) if !text.text().trim().is_empty() => {
+                repairs.push(Repair::StrayText {
+                    parent: name.to_string(),
+                    text: text.text().trim().to_string(),
+                });
+                text.set_text("");
+            },
+            _ => {},
+        }
+    }
+}
+
+// Blockly only reads the first block in a statement input, so move each
+// later one to the end of the chain before it.
+fn chain_siblings<'d>(document: Document<'d>, statement_el: Element<'d>, repairs: &mut Vec<Repair>) {
+    let blocks: Vec<Element> = statement_el.children()
+        .into_iter()
+        .filter_map(|child| child.element())
+        .filter(|el| el.name().local_part() == "block")
+        .collect();
+    if blocks.len() < 2 {
+        return;
+    }
+    let namespace = statement_el.name().namespace_uri();
+    let mut tail = blocks[0];
+    for &block_el in &blocks[1..] {
+        loop {
+            let next_el = child_named(tail, "next");
+            match next_el.and_then(|next_el| child_named(next_el, "block")) {
+                Some(next_block_el) => tail = next_block_el,
+                None => {
+                    let next_el = next_el.unwrap_or_else(|| {
+                        let next_el = match namespace {
+                            Some(namespace) => document.create_element((namespace, "next")),
+                            None => document.create_element("next"),
+                        };
+                        tail.append_child(next_el);
+                        next_el
+                    });
+                    next_el.append_child(block_el);
+                    tail = block_el;
+                    break;
+                },
+            }
+        }
+    }
+    let parent = statement_el.parent()
+        .and_then(|parent| parent.element())
+        .and_then(|block_el| get_attribute(block_el, "id"));
+    repairs.push(Repair::ChainedStatement {
+        block: parent.unwrap_or_default(),
+        statement: get_attribute(statement_el, "name").unwrap_or_default(),
+        blocks: blocks.len(),
+    });
+}
+
+fn child_named<'d>(element: Element<'d>, name: &str) -> Option<Element<'d>> {
+    element.children()
+        .into_iter()
+        .filter_map(|child| child.element())
+        .find(|el| el.name().local_part() == name)
+}
+
+fn add_missing_ids(program: &mut Program, repairs: &mut Vec<Repair>) {
+    let mut ids = SoupIdGenerator::new();
+    let mut used: HashSet<String> = program.iter_blocks().map(|block| block.id.clone()).collect();
+    let mut pending: Vec<_> = program.groups.iter_mut()
+        .flat_map(|group| group.blocks.iter_mut())
+        .collect();
+    while let Some(block) = pending.pop() {
+        if block.id.is_empty() {
+            block.id = random_unused_id(&mut ids, &mut used);
+            repairs.push(Repair::AddedId { element: "block".to_string(), id: block.id.clone() });
+        }
+        pending.extend(child_blocks_mut(block));
+    }
+
+    let mut used: HashSet<String> = program.variables.iter().map(|variable| variable.id.clone()).collect();
+    for variable in &mut program.variables {
+        if variable.id.is_empty() {
+            variable.id = random_unused_id(&mut ids, &mut used);
+            repairs.push(Repair::AddedId { element: "variable".to_string(), id: variable.id.clone() });
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_program_from_xml_repair() {
+        let outcome = program_from_xml_repair(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable>count</variable></variables>
+                <block type="main_loop" id="main">
+                    stray
+                    <statement name="BODY">
+                        <block type="led_on" id="on"><next><block type="wait" id="wait"></block></next></block>
+                        <block type="led_off" id="off"></block>
+                    </statement>
+                </block>
+                <block type="text_print" id="print">
+                    <field name="TEXT">a</field>
+                    <field name="TEXT">b</field>
+                    <value name="MESSAGE"><block type="text"><next><block type="led_on" id="lost"></block></next></block></value>
+                </block>
+            </xml>
+        "#).unwrap();
+        let program = &outcome.program;
+        let body = &program.groups[0].blocks[0].statements["BODY"];
+        assert_eq!(body.blocks.iter().map(|block| block.id.as_str()).collect::<Vec<_>>(), ["on", "wait", "off"]);
+        assert_eq!(program.groups.len(), 3);
+        assert_eq!(program.groups[2].blocks[0].id, "lost");
+        assert_eq!(program.groups[1].blocks[0].field_str("TEXT"), Ok("b"));
+        assert!(program.iter_blocks().all(|block| !block.id.is_empty()));
+        assert!(!program.variables[0].id.is_empty());
+
+        let repairs = &outcome.repairs;
+        assert_eq!(repairs[0], Repair::StrayText { parent: "block".to_string(), text: "stray".to_string() });
+        assert_eq!(repairs[1], Repair::ChainedStatement { block: "main".to_string(), statement: "BODY".to_string(), blocks: 2 });
+        assert_eq!(repairs[2], Repair::DuplicateField { block: "print".to_string(), field: "TEXT".to_string() });
+        assert_eq!(repairs[3], Repair::DetachedNext { block: "".to_string() });
+        assert!(matches!(repairs[4], Repair::AddedId { ref element, .. } if element == "block"));
+        assert!(matches!(repairs[5], Repair::AddedId { ref element, .. } if element == "variable"));
+        assert_eq!(repairs.len(), 6);
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+
+use super::{
+    Program,
+    Block,
+    FieldValue,
+    RawNode,
+};
+use super::iter::child_blocks_mut;
+use super::migrations::is_variable_field;
+
+/// What `Program::sanitize` removes. Everything is removed by default.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct SanitizeOptions {
+    /// Block comments and workspace comments.
+    pub comments: bool,
+    /// Rename variables to `var1`, `var2`, ... in declaration order, then
+    /// undeclared variables that fields and procedure parameters name, and
+    /// procedures to `proc1`, `proc2`, ...
+    pub variables: bool,
+    /// Positions of top-level blocks and workspace comments.
+    pub positions: bool,
+    /// Block ids. Variable ids are kept, since fields refer to them.
+    pub ids: bool,
+    /// Plugin data: `<data>` elements and everything kept by
+    /// `ParserOptions::preserve_unknown`.
+    pub data: bool,
+}
+
+impl Default for SanitizeOptions {
+    fn default() -> Self {
+        Self {
+            comments: true,
+            variables: true,
+            positions: true,
+            ids: true,
+            data: true,
+        }
+    }
+}
+
+impl Program {
+    /// Strip what could identify the author, e.g. before sharing a student's
+    /// workspace as a public example. The blocks themselves, and the text in
+    /// their fields, are kept.
+    pub fn sanitize(&mut self, options: &SanitizeOptions) {
+        // New names by variable id and by old name, so fields without an id
+        // and fields naming undeclared variables are renamed too
+        let mut by_id = HashMap::new();
+        let mut by_name = HashMap::new();
+        // New procedure names by old name, which calls match case-insensitively
+        let mut procedures = HashMap::new();
+        if options.variables {
+            for (index, variable) in self.variables.iter_mut().enumerate() {
+                let name = format!("var{}", index + 1);
+                if !variable.id.is_empty() {
+                    by_id.insert(variable.id.clone(), name.clone());
+                }
+                by_name.insert(std::mem::replace(&mut variable.name, name.clone()), name);
+            }
+        }
+        if options.comments {
+            self.comments.clear();
+        }
+        for comment in &mut self.comments {
+            if options.positions {
+                comment.position = None;
+            }
+            if options.ids {
+                comment.id.clear();
+            }
+        }
+        if options.data {
+            self.extras.clear();
+        }
+
+        let mut pending: Vec<_> = self.groups.iter_mut()
+            .flat_map(|group| group.blocks.iter_mut())
+            .collect();
+        while let Some(block) = pending.pop() {
+            if options.comments {
+                block.comment = None;
+            }
+            if options.positions {
+                block.position = None;
+            }
+            if options.ids {
+                block.id.clear();
+            }
+            if options.variables {
+                let fields: Vec<String> = block.fields.keys()
+                    .filter(|field| is_variable_field(block, field))
+                    .cloned()
+                    .collect();
+                for field in fields {
+                    let id = block.field_variables.get(&field).map(|variable| variable.id.as_str());
+                    if let Some(FieldValue::SimpleField(name)) = block.fields.get_mut(&field) {
+                        *name = rename_variable(id, name, &by_id, &mut by_name);
+                    }
+                }
+                rename_procedure(block, &by_id, &mut by_name, &mut procedures);
+            }
+            if options.data {
+                block.data = None;
+                block.extra_attributes.clear();
+                block.extra_elements.clear();
+            }
+            pending.extend(child_blocks_mut(block));
+        }
+    }
+}
+
+// The new name of a variable, by id if it has one, else by name
+fn rename_variable(id: Option<&str>, name: &str, by_id: &HashMap<String, String>, by_name: &mut HashMap<String, String>) -> String {
+    match id.and_then(|id| by_id.get(id)) {
+        Some(renamed) => renamed.clone(),
+        None => {
+            let count = by_name.len();
+            by_name.entry(name.to_string())
+                .or_insert_with(|| format!("var{}", count + 1))
+                .clone()
+        },
+    }
+}
+
+// Renames a definition's `NAME` field or a call's `name` attribute, and the
+// parameters in the mutation, which are `<arg name="..." varid="...">` in
+// XML and `params` entries in JSON's extraState
+fn rename_procedure(
+    block: &mut Block,
+    by_id: &HashMap<String, String>,
+    by_name: &mut HashMap<String, String>,
+    procedures: &mut HashMap<String, String>,
+) {
+    let mut rename = |name: &str| {
+        let count = procedures.len();
+        procedures.entry(name.to_lowercase())
+            .or_insert_with(|| format!("proc{}", count + 1))
+            .clone()
+    };
+    match block.block_type.as_str() {
+        "procedures_defnoreturn" | "procedures_defreturn" => {
+            if let Some(FieldValue::SimpleField(name)) = block.fields.get_mut("NAME") {
+                *name = rename(name);
+            }
+        },
+        "procedures_callnoreturn" | "procedures_callreturn" => {
+            if let Some(name) = block.mutation.as_mut().and_then(|mutation| mutation.attributes.get_mut("name")) {
+                *name = rename(name);
+            }
+        },
+        _ => return,
+    }
+
+    let mutation = match block.mutation {
+        Some(ref mut mutation) => mutation,
+        None => return,
+    };
+    for child in &mut mutation.children {
+        if child.name != "arg" && child.name != "params" {
+            continue;
+        }
+        let id = child.attribute("varid").or_else(|| child.attribute("id")).map(str::to_string);
+        if let Some(name) = child.attributes.get_mut("name") {
+            *name = rename_variable(id.as_deref(), name, by_id, by_name);
+        } else if let Some(RawNode::Text(name)) = child.children.first_mut() {
+            *name = rename_variable(None, name, by_id, by_name);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_sanitize() {
+        let mut program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">alices_score</variable><variable>bobs_score</variable></variables>
+                <comment id="c" x="1" y="2">Alice, period 3</comment>
+                <block type="variables_set" id="a" x="10" y="20">
+                    <comment>my first try</comment>
+                    <field name="VAR" id="v1">alices_score</field>
+                    <value name="VALUE"><block type="math_number" id="b"><field name="NUM">3</field></block></value>
+                    <next>
+                        <block type="variables_set" id="c">
+                            <field name="VAR">bobs_score</field>
+                            <value name="VALUE"><block type="variables_get" id="d"><field name="VAR">carols_score</field></block></value>
+                        </block>
+                    </next>
+                </block>
+            </xml>
+        "#).unwrap();
+        program.sanitize(&SanitizeOptions::default());
+
+        assert_eq!(program.variables[0].name, "var1");
+        assert_eq!(program.variables[0].id, "v1");
+        assert!(program.comments.is_empty());
+        let block = &program.groups[0].blocks[0];
+        assert_eq!(block.field_str("VAR"), Ok("var1"));
+        assert_eq!(block.position, None);
+        assert_eq!(block.comment, None);
+        assert!(program.iter_blocks().all(|block| block.id.is_empty()));
+        assert_eq!(block.values["VALUE"].block().field_str("NUM"), Ok("3"));
+
+        // Variables without ids, declared or not, are renamed by name
+        assert_eq!(program.variables[1].name, "var2");
+        let next = &program.groups[0].blocks[1];
+        assert_eq!(next.field_str("VAR"), Ok("var2"));
+        assert_eq!(next.values["VALUE"].block().field_str("VAR"), Ok("var3"));
+    }
+
+    #[test]
+    fn test_sanitize_procedures() {
+        let mut program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">alices_total</variable></variables>
+                <block type="procedures_defreturn" id="def">
+                    <mutation><arg name="alices_total" varid="v1"></arg><arg name="bobs_total"></arg></mutation>
+                    <field name="NAME">add_alices_scores</field>
+                    <value name="RETURN"><block type="variables_get"><field name="VAR" id="v1">alices_total</field></block></value>
+                </block>
+                <block type="procedures_callreturn" id="call">
+                    <mutation name="add_alices_scores"><arg name="alices_total"></arg><arg name="bobs_total"></arg></mutation>
+                </block>
+            </xml>
+        "#).unwrap();
+        program.sanitize(&SanitizeOptions::default());
+
+        let definition = &program.groups[0].blocks[0];
+        assert_eq!(definition.field_str("NAME"), Ok("proc1"));
+        let call = &program.groups[1].blocks[0];
+        assert_eq!(call.mutation.as_ref().unwrap().attribute("name"), Some("proc1"));
+        assert_eq!(program.procedures().get("proc1").unwrap().parameters, vec!["var1", "var2"]);
+        for block in &[definition, call] {
+            let args: Vec<_> = block.mutation.as_ref().unwrap().children.iter()
+                .map(|arg| arg.attribute("name").unwrap())
+                .collect();
+            assert_eq!(args, vec!["var1", "var2"]);
+        }
+        assert_eq!(definition.mutation.as_ref().unwrap().children[0].attribute("varid"), Some("v1"));
+        assert_eq!(definition.values["RETURN"].block().field_str("VAR"), Ok("var1"));
+    }
+}
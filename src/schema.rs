@@ -0,0 +1,453 @@
+use std::fmt::Write;
+
+use serde_json::{
+    Map,
+    Value,
+};
+
+use super::{
+    BlockDefinition,
+    BlockRegistry,
+    FieldKind,
+    InputKind,
+};
+use super::writer::write_attribute;
+
+// Whether connections with these type checks fit together. An empty check
+// accepts anything, as in Blockly.
+fn compatible(a: &[String], b: &[String]) -> bool {
+    a.is_empty() || b.is_empty() || a.iter().any(|check| b.contains(check))
+}
+
+// The definitions, sorted by block type so schemas come out the same each time
+fn sorted(registry: &BlockRegistry) -> Vec<&BlockDefinition> {
+    let mut definitions: Vec<_> = registry.iter().collect();
+    definitions.sort_by(|a, b| a.block_type.cmp(&b.block_type));
+    definitions
+}
+
+// The block types that can plug into a value input with `check`
+fn value_blocks<'a>(definitions: &[&'a BlockDefinition], check: &[String]) -> Vec<&'a str> {
+    definitions.iter()
+        .filter(|definition| definition.output.as_ref().is_some_and(|output| compatible(check, &output.check)))
+        .map(|definition| definition.block_type.as_str())
+        .collect()
+}
+
+// The block types that can plug into a statement input or `next` with `check`
+fn statement_blocks<'a>(definitions: &[&'a BlockDefinition], check: &[String]) -> Vec<&'a str> {
+    definitions.iter()
+        .filter(|definition| definition.previous_statement.as_ref().is_some_and(|previous| compatible(check, &previous.check)))
+        .map(|definition| definition.block_type.as_str())
+        .collect()
+}
+
+// An input's name and kind, and the block types that fit it
+type InputBlocks<'a> = (&'a str, InputKind, Vec<&'a str>);
+
+// The block types that fit each of a definition's inputs, and its `next`
+fn connections<'a>(definitions: &[&'a BlockDefinition], definition: &'a BlockDefinition) -> (Vec<InputBlocks<'a>>, Option<Vec<&'a str>>) {
+    let inputs = definition.inputs.iter()
+        .map(|input| {
+            let blocks = match input.kind {
+                InputKind::Value => value_blocks(definitions, &input.check),
+                InputKind::Statement => statement_blocks(definitions, &input.check),
+            };
+            (input.name.as_str(), input.kind, blocks)
+        })
+        .collect();
+    let next = definition.next_statement.as_ref().map(|next| statement_blocks(definitions, &next.check));
+    (inputs, next)
+}
+
+impl BlockRegistry {
+    /// A JSON Schema (draft 2020-12) for workspaces in Blockly's JSON format
+    /// that only use the registered blocks, with their fields and inputs,
+    /// and only connect blocks whose type checks fit.
+    pub fn json_schema(&self) -> Value {
+        let definitions = sorted(self);
+        let mut defs = Map::new();
+        for definition in &definitions {
+            defs.insert(format!("block:{}", definition.block_type), json_block(&definitions, definition));
+        }
+        let all: Vec<&str> = definitions.iter().map(|definition| definition.block_type.as_str()).collect();
+
+        let mut blocks = Map::new();
+        blocks.insert("languageVersion".to_string(), type_schema("integer"));
+        blocks.insert("blocks".to_string(), array_schema(any_of(&all)));
+        let mut variable = Map::new();
+        variable.insert("name".to_string(), type_schema("string"));
+        variable.insert("id".to_string(), type_schema("string"));
+        variable.insert("type".to_string(), type_schema("string"));
+
+        let mut properties = Map::new();
+        properties.insert("blocks".to_string(), object_schema(blocks, None));
+        properties.insert("variables".to_string(), array_schema(object_schema(variable, Some(&["name", "id"]))));
+        let mut schema = Map::new();
+        schema.insert("$schema".to_string(), Value::from("https://json-schema.org/draft/2020-12/schema"));
+        schema.insert("type".to_string(), Value::from("object"));
+        schema.insert("properties".to_string(), Value::Object(properties));
+        schema.insert("$defs".to_string(), Value::Object(defs));
+        Value::Object(schema)
+    }
+
+    /// An XML Schema for workspaces in Blockly XML that only use the
+    /// registered blocks, with their fields and inputs, and only connect
+    /// blocks whose type checks fit. Picking a block's definition by its
+    /// `type` needs XSD 1.1, for `xs:alternative` and `xs:assert`. Elements
+    /// are not in a namespace, so strip Blockly's `xmlns` before validating.
+    pub fn xml_schema(&self) -> String {
+        let definitions = sorted(self);
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<xs:schema xmlns:xs=\"http://www.w3.org/2001/XMLSchema\" xmlns:vc=\"http://www.w3.org/2007/XMLSchema-versioning\" vc:minVersion=\"1.1\">\n");
+        out.push_str(XSD_COMMON);
+        for tag in &["block", "shadow"] {
+            writeln!(out, "  <xs:element name=\"{}\" type=\"block\">", tag).unwrap();
+            for definition in &definitions {
+                out.push_str("    <xs:alternative");
+                write_attribute(&mut out, "test", &format!("@type = {}", xpath_string(&definition.block_type)));
+                write_attribute(&mut out, "type", &format!("block.{}", definition.block_type));
+                out.push_str("/>\n");
+            }
+            out.push_str("    <xs:alternative type=\"xs:error\"/>\n");
+            out.push_str("  </xs:element>\n");
+        }
+        for definition in &definitions {
+            xsd_block(&mut out, &definitions, definition);
+        }
+        out.push_str("</xs:schema>\n");
+        out
+    }
+}
+
+// JSON Schema
+
+fn type_schema(name: &str) -> Value {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), Value::from(name));
+    Value::Object(schema)
+}
+
+fn array_schema(items: Value) -> Value {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), Value::from("array"));
+    schema.insert("items".to_string(), items);
+    Value::Object(schema)
+}
+
+fn object_schema(properties: Map<String, Value>, required: Option<&[&str]>) -> Value {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), Value::from("object"));
+    schema.insert("properties".to_string(), Value::Object(properties));
+    if let Some(required) = required {
+        schema.insert("required".to_string(), required.iter().map(|&key| Value::from(key)).collect());
+    }
+    Value::Object(schema)
+}
+
+// A schema for a block of any of these types. `false` if there are none.
+fn any_of(block_types: &[&str]) -> Value {
+    if block_types.is_empty() {
+        return Value::Bool(false);
+    }
+    let refs = block_types.iter()
+        .map(|block_type| {
+            // Escape the type for a JSON Pointer
+            let name = block_type.replace('~', "~0").replace('/', "~1");
+            let mut reference = Map::new();
+            reference.insert("$ref".to_string(), Value::from(format!("#/$defs/block:{}", name)));
+            Value::Object(reference)
+        })
+        .collect();
+    let mut schema = Map::new();
+    schema.insert("anyOf".to_string(), Value::Array(refs));
+    Value::Object(schema)
+}
+
+// An input or `next`: `{ "block": ..., "shadow": ... }`
+fn json_connection(block_types: &[&str]) -> Value {
+    let mut properties = Map::new();
+    properties.insert("block".to_string(), any_of(block_types));
+    properties.insert("shadow".to_string(), any_of(block_types));
+    object_schema(properties, None)
+}
+
+fn closed(schema: Value) -> Value {
+    match schema {
+        Value::Object(mut schema) => {
+            schema.insert("additionalProperties".to_string(), Value::Bool(false));
+            Value::Object(schema)
+        },
+        other => other,
+    }
+}
+
+fn json_field(kind: &FieldKind) -> Value {
+    let mut schema = Map::new();
+    match *kind {
+        FieldKind::Text | FieldKind::Colour => {
+            schema.insert("type".to_string(), Value::from("string"));
+        },
+        FieldKind::Number { min, max, .. } => {
+            schema.insert("type".to_string(), Value::from("number"));
+            if let Some(min) = min {
+                schema.insert("minimum".to_string(), Value::from(min));
+            }
+            if let Some(max) = max {
+                schema.insert("maximum".to_string(), Value::from(max));
+            }
+        },
+        FieldKind::Angle => {
+            schema.insert("type".to_string(), Value::from("number"));
+        },
+        FieldKind::Dropdown(ref options) => {
+            schema.insert("enum".to_string(), options.iter().map(|option| Value::from(option.value.as_str())).collect());
+        },
+        FieldKind::Checkbox => {
+            schema.insert("enum".to_string(), vec![Value::Bool(true), Value::Bool(false), Value::from("TRUE"), Value::from("FALSE")].into());
+        },
+        FieldKind::Variable { .. } => {
+            let mut properties = Map::new();
+            properties.insert("id".to_string(), type_schema("string"));
+            return object_schema(properties, Some(&["id"]));
+        },
+        FieldKind::Label | FieldKind::Other(_) => {},
+    }
+    Value::Object(schema)
+}
+
+fn json_block(definitions: &[&BlockDefinition], definition: &BlockDefinition) -> Value {
+    let (inputs, next) = connections(definitions, definition);
+
+    let mut properties = Map::new();
+    let mut block_type = Map::new();
+    block_type.insert("const".to_string(), Value::from(definition.block_type.as_str()));
+    properties.insert("type".to_string(), Value::Object(block_type));
+    properties.insert("id".to_string(), type_schema("string"));
+    properties.insert("x".to_string(), type_schema("number"));
+    properties.insert("y".to_string(), type_schema("number"));
+
+    let fields = definition.fields.iter()
+        .map(|field| (field.name.clone(), json_field(&field.kind)))
+        .collect();
+    properties.insert("fields".to_string(), closed(object_schema(fields, None)));
+    let inputs = inputs.iter()
+        .map(|(name, _, blocks)| (name.to_string(), json_connection(blocks)))
+        .collect();
+    properties.insert("inputs".to_string(), closed(object_schema(inputs, None)));
+    let next = match next {
+        Some(blocks) => json_connection(&blocks),
+        None => Value::Bool(false),
+    };
+    properties.insert("next".to_string(), next);
+    object_schema(properties, Some(&["type"]))
+}
+
+// XML Schema
+
+const XSD_COMMON: &str = r#"  <xs:element name="xml">
+    <xs:complexType>
+      <xs:choice minOccurs="0" maxOccurs="unbounded">
+        <xs:element name="variables" type="any"/>
+        <xs:element ref="block"/>
+        <xs:element ref="shadow"/>
+        <xs:element name="comment" type="any"/>
+      </xs:choice>
+      <xs:anyAttribute processContents="skip"/>
+    </xs:complexType>
+  </xs:element>
+  <xs:complexType name="any" mixed="true">
+    <xs:sequence>
+      <xs:any minOccurs="0" maxOccurs="unbounded" processContents="skip"/>
+    </xs:sequence>
+    <xs:anyAttribute processContents="skip"/>
+  </xs:complexType>
+  <xs:complexType name="field">
+    <xs:simpleContent>
+      <xs:extension base="xs:string">
+        <xs:anyAttribute processContents="skip"/>
+      </xs:extension>
+    </xs:simpleContent>
+  </xs:complexType>
+  <xs:complexType name="input">
+    <xs:choice minOccurs="0" maxOccurs="2">
+      <xs:element ref="shadow"/>
+      <xs:element ref="block"/>
+    </xs:choice>
+    <xs:attribute name="name" type="xs:string" use="required"/>
+  </xs:complexType>
+  <xs:complexType name="next">
+    <xs:choice minOccurs="0" maxOccurs="2">
+      <xs:element ref="shadow"/>
+      <xs:element ref="block"/>
+    </xs:choice>
+  </xs:complexType>
+  <xs:complexType name="block">
+    <xs:choice minOccurs="0" maxOccurs="unbounded">
+      <xs:element name="mutation" type="any"/>
+      <xs:element name="comment" type="any"/>
+      <xs:element name="data" type="xs:string"/>
+      <xs:element name="field" type="field"/>
+      <xs:element name="value" type="input"/>
+      <xs:element name="statement" type="input"/>
+      <xs:element name="next" type="next"/>
+    </xs:choice>
+    <xs:attribute name="type" type="xs:string" use="required"/>
+    <xs:anyAttribute processContents="skip"/>
+  </xs:complexType>
+"#;
+
+// An XPath string literal
+fn xpath_string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+// An XPath sequence of string literals, e.g. `('A', 'B')`
+fn xpath_strings<'a, I: IntoIterator<Item = &'a str>>(texts: I) -> String {
+    let texts: Vec<String> = texts.into_iter().map(xpath_string).collect();
+    format!("({})", texts.join(", "))
+}
+
+// An XPath test that every item of `path` has `attribute` in `allowed`
+fn xpath_only(path: &str, attribute: &str, allowed: &[&str]) -> String {
+    if allowed.is_empty() {
+        return format!("empty({})", path);
+    }
+    format!("every $x in {} satisfies $x/@{} = {}", path, attribute, xpath_strings(allowed.iter().cloned()))
+}
+
+fn xsd_block(out: &mut String, definitions: &[&BlockDefinition], definition: &BlockDefinition) {
+    let (inputs, next) = connections(definitions, definition);
+
+    out.push_str("  <xs:complexType");
+    write_attribute(out, "name", &format!("block.{}", definition.block_type));
+    out.push_str(">\n    <xs:complexContent>\n      <xs:restriction base=\"block\">\n");
+    out.push_str("        <xs:choice minOccurs=\"0\" maxOccurs=\"unbounded\">\n");
+    for &(name, kind) in &[
+        ("mutation", "any"),
+        ("comment", "any"),
+        ("data", "xs:string"),
+        ("field", "field"),
+        ("value", "input"),
+        ("statement", "input"),
+        ("next", "next"),
+    ] {
+        if name != "next" || next.is_some() {
+            writeln!(out, "          <xs:element name=\"{}\" type=\"{}\"/>", name, kind).unwrap();
+        }
+    }
+    out.push_str("        </xs:choice>\n");
+    out.push_str("        <xs:attribute name=\"type\" type=\"xs:string\" use=\"required\"/>\n");
+    out.push_str("        <xs:anyAttribute processContents=\"skip\"/>\n");
+
+    let mut asserts = Vec::new();
+    let fields: Vec<&str> = definition.fields.iter().map(|field| field.name.as_str()).collect();
+    asserts.push(xpath_only("field", "name", &fields));
+    for (kind, tag) in &[(InputKind::Value, "value"), (InputKind::Statement, "statement")] {
+        let names: Vec<&str> = inputs.iter()
+            .filter(|&&(_, input_kind, _)| input_kind == *kind)
+            .map(|&(name, _, _)| name)
+            .collect();
+        asserts.push(xpath_only(tag, "name", &names));
+    }
+    for field in &definition.fields {
+        let path = format!("field[@name = {}]", xpath_string(&field.name));
+        let test = match field.kind {
+            FieldKind::Number { min, max, .. } => {
+                let mut test = "$x castable as xs:double".to_string();
+                if let Some(min) = min {
+                    write!(test, " and xs:double($x) ge {}", min).unwrap();
+                }
+                if let Some(max) = max {
+                    write!(test, " and xs:double($x) le {}", max).unwrap();
+                }
+                test
+            },
+            FieldKind::Angle => "$x castable as xs:double".to_string(),
+            FieldKind::Dropdown(ref options) => {
+                format!("string($x) = {}", xpath_strings(options.iter().map(|option| option.value.as_str())))
+            },
+            FieldKind::Checkbox => "string($x) = ('TRUE', 'FALSE')".to_string(),
+            _ => continue,
+        };
+        asserts.push(format!("every $x in {} satisfies {}", path, test));
+    }
+    for (name, kind, blocks) in &inputs {
+        let tag = match *kind {
+            InputKind::Value => "value",
+            InputKind::Statement => "statement",
+        };
+        let path = format!("{}[@name = {}]/(block | shadow)", tag, xpath_string(name));
+        asserts.push(xpath_only(&path, "type", blocks));
+    }
+    if let Some(ref blocks) = next {
+        asserts.push(xpath_only("next/(block | shadow)", "type", blocks));
+    }
+    for test in asserts {
+        out.push_str("        <xs:assert");
+        write_attribute(out, "test", &test);
+        out.push_str("/>\n");
+    }
+    out.push_str("      </xs:restriction>\n    </xs:complexContent>\n  </xs:complexType>\n");
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DEFINITIONS: &str = r#"[
+        {
+            "type": "led_set",
+            "message0": "set LED %1 to %2",
+            "args0": [
+                { "type": "field_dropdown", "name": "LED", "options": [["left", "L"], ["right", "R"]] },
+                { "type": "input_value", "name": "LEVEL", "check": "Number" }
+            ],
+            "previousStatement": null,
+            "nextStatement": null
+        },
+        {
+            "type": "math_number",
+            "message0": "%1",
+            "args0": [{ "type": "field_number", "name": "NUM", "min": 0 }],
+            "output": "Number"
+        },
+        {
+            "type": "text",
+            "message0": "%1",
+            "args0": [{ "type": "field_input", "name": "TEXT" }],
+            "output": "String"
+        }
+    ]"#;
+
+    #[test]
+    fn test_json_schema() {
+        let registry = BlockRegistry::from_json(DEFINITIONS).unwrap();
+        let schema = registry.json_schema();
+        let led = &schema["$defs"]["block:led_set"]["properties"];
+        assert_eq!(led["type"]["const"], "led_set");
+        assert_eq!(led["fields"]["properties"]["LED"]["enum"], serde_json::json!(["L", "R"]));
+        assert_eq!(led["inputs"]["properties"]["LEVEL"]["properties"]["block"]["anyOf"], serde_json::json!([
+            { "$ref": "#/$defs/block:math_number" },
+        ]));
+        assert_eq!(led["next"]["properties"]["block"]["anyOf"][0]["$ref"], "#/$defs/block:led_set");
+        assert_eq!(schema["$defs"]["block:text"]["properties"]["next"], false);
+        assert_eq!(schema["properties"]["blocks"]["properties"]["blocks"]["items"]["anyOf"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_xml_schema() {
+        let registry = BlockRegistry::from_json(DEFINITIONS).unwrap();
+        let schema = registry.xml_schema();
+        assert!(schema.contains(r#"<xs:alternative test="@type = 'led_set'" type="block.led_set"/>"#));
+        assert!(schema.contains(r#"<xs:assert test="every $x in field satisfies $x/@name = ('LED')"/>"#));
+        assert!(schema.contains(r#"<xs:assert test="every $x in value[@name = 'LEVEL']/(block | shadow) satisfies $x/@type = ('math_number')"/>"#));
+        assert!(schema.contains(r#"<xs:assert test="every $x in field[@name = 'NUM'] satisfies $x castable as xs:double and xs:double($x) ge 0"/>"#));
+        assert!(schema.contains(r#"<xs:assert test="empty(statement)"/>"#));
+        // Only statement blocks get a `next`
+        let text = &schema[schema.find("name=\"block.text\"").unwrap()..];
+        assert!(!text[..text.find("</xs:complexType>").unwrap()].contains("name=\"next\""));
+    }
+}
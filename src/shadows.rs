@@ -0,0 +1,94 @@
+use std::mem;
+
+use indexmap::IndexMap;
+
+use super::{
+    Program,
+    Block,
+    FieldValue,
+    InputContent,
+};
+use super::iter::child_blocks_mut;
+
+impl Program {
+    /// Turn every shadow block into a real block, so tools that only look at
+    /// real blocks see the defaults Blockly's generators use when nothing is
+    /// connected to an input. Returns the number of inputs changed.
+    pub fn materialize_shadows(&mut self) -> usize {
+        let mut changed = 0;
+        let mut pending: Vec<_> = self.groups.iter_mut()
+            .flat_map(|group| group.blocks.iter_mut())
+            .collect();
+        while let Some(block) = pending.pop() {
+            for input in block.values.values_mut() {
+                if let InputContent::Shadow(ref mut shadow) = *input {
+                    let shadow = mem::replace(shadow, Block::new(""));
+                    *input = InputContent::Block(shadow);
+                    changed += 1;
+                }
+            }
+            for body in block.statements.values_mut() {
+                if body.shadow {
+                    body.shadow = false;
+                    changed += 1;
+                }
+            }
+            pending.extend(child_blocks_mut(block));
+        }
+        changed
+    }
+}
+
+impl Block {
+    /// The default values that shadow blocks give this block's value inputs,
+    /// by input name, e.g. `TIMES: "10"` for a repeat block holding a
+    /// `math_number` shadow. Only shadows with a single plain field count.
+    pub fn shadow_defaults(&self) -> IndexMap<&str, &str> {
+        self.values.iter()
+            .filter_map(|(name, input)| match *input {
+                InputContent::Shadow(ref shadow) if shadow.fields.len() == 1 => {
+                    match shadow.fields.values().next() {
+                        Some(FieldValue::SimpleField(text)) => Some((name.as_str(), text.as_str())),
+                        _ => None,
+                    }
+                },
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_materialize_shadows() {
+        let mut program = program_from_xml(r#"
+            <xml>
+                <block type="controls_repeat_ext">
+                    <value name="TIMES"><shadow type="math_number"><field name="NUM">10</field></shadow></value>
+                    <statement name="DO">
+                        <block type="text_print">
+                            <value name="TEXT">
+                                <shadow type="text"><field name="TEXT">hi</field></shadow>
+                                <block type="variables_get"><field name="VAR">name</field></block>
+                            </value>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let repeat = &program.groups[0].blocks[0];
+        assert_eq!(repeat.shadow_defaults().into_iter().collect::<Vec<_>>(), [("TIMES", "10")]);
+        assert!(repeat.statements["DO"].blocks[0].shadow_defaults().is_empty());
+
+        assert_eq!(program.materialize_shadows(), 1);
+        let repeat = &program.groups[0].blocks[0];
+        assert!(matches!(repeat.values["TIMES"], InputContent::Block(ref block) if block.block_type == "math_number"));
+        assert!(repeat.shadow_defaults().is_empty());
+        assert_eq!(program.materialize_shadows(), 0);
+    }
+}
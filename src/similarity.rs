@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+};
+use super::fingerprint::{
+    Fnv,
+    sorted,
+};
+use super::migrations::is_variable_field;
+
+/// The shingles of a program: hashes of every block's subtree, of each block
+/// with the types of its children, and of each pair of blocks in a stack.
+/// Block ids, positions, variable names and mutations do not affect them,
+/// so renaming variables or moving stacks around does not hide a copy.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Signature {
+    shingles: HashMap<u64, usize>,
+}
+
+impl Signature {
+    /// How much two programs have in common, from 0 to 1: the weighted
+    /// Jaccard similarity of their shingles. Two empty programs count as
+    /// the same.
+    pub fn similarity(&self, other: &Signature) -> f64 {
+        let mut shared = 0;
+        let mut total = 0;
+        for (shingle, &count) in &self.shingles {
+            let other_count = other.shingles.get(shingle).copied().unwrap_or(0);
+            shared += count.min(other_count);
+            total += count.max(other_count);
+        }
+        for (shingle, &count) in &other.shingles {
+            if !self.shingles.contains_key(shingle) {
+                total += count;
+            }
+        }
+        if total == 0 {
+            1.0
+        } else {
+            shared as f64 / total as f64
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shingles.is_empty()
+    }
+}
+
+impl Program {
+    pub fn signature(&self) -> Signature {
+        let mut signature = Signature::default();
+        for group in &self.groups {
+            shingle_stack(group, &mut signature);
+        }
+        signature
+    }
+
+    /// Shorthand for comparing the two programs' `signature`s. To compare a
+    /// whole class, compute each signature once and use `similar_pairs`.
+    pub fn similarity(&self, other: &Program) -> f64 {
+        self.signature().similarity(&other.signature())
+    }
+}
+
+/// Every pair of signatures at least `threshold` similar, as indices into
+/// `signatures` with their similarity, most similar first.
+pub fn similar_pairs(signatures: &[Signature], threshold: f64) -> Vec<(usize, usize, f64)> {
+    let mut pairs = Vec::new();
+    for (i, first) in signatures.iter().enumerate() {
+        for (j, second) in signatures.iter().enumerate().skip(i + 1) {
+            let similarity = first.similarity(second);
+            if similarity >= threshold {
+                pairs.push((i, j, similarity));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.2.total_cmp(&a.2));
+    pairs
+}
+
+fn add(signature: &mut Signature, shingle: u64) {
+    *signature.shingles.entry(shingle).or_insert(0) += 1;
+}
+
+// Returns the hashes of the stack's blocks
+fn shingle_stack(body: &StatementBody, signature: &mut Signature) -> Vec<u64> {
+    let hashes: Vec<u64> = body.blocks.iter()
+        .map(|block| shingle_block(block, signature))
+        .collect();
+    for pair in body.blocks.windows(2) {
+        let mut hasher = Fnv::new();
+        hasher.tag(2);
+        hasher.str(&pair[0].block_type);
+        hasher.str(&pair[1].block_type);
+        add(signature, hasher.0);
+    }
+    hashes
+}
+
+// Returns the hash of the block's whole subtree
+fn shingle_block(block: &Block, signature: &mut Signature) -> u64 {
+    let mut subtree = Fnv::new();
+    let mut shape = Fnv::new();
+    subtree.tag(0);
+    subtree.str(&block.block_type);
+    shape.tag(1);
+    shape.str(&block.block_type);
+
+    for (name, value) in sorted(&block.fields) {
+        subtree.str(name);
+        match *value {
+            FieldValue::SimpleField(_) if is_variable_field(block, name) => subtree.tag(0),
+            FieldValue::SimpleField(ref text) => {
+                subtree.tag(1);
+                subtree.str(text);
+            },
+            FieldValue::ExpressionField(ref inner) => {
+                subtree.tag(2);
+                subtree.u64(shingle_block(inner, signature));
+                shape.str(name);
+                shape.str(&inner.block_type);
+            },
+        }
+    }
+    for (name, input) in sorted(&block.values) {
+        subtree.str(name);
+        subtree.u64(shingle_block(input.block(), signature));
+        shape.str(name);
+        shape.str(&input.block().block_type);
+    }
+    for (name, body) in sorted(&block.statements) {
+        subtree.str(name);
+        let hashes = shingle_stack(body, signature);
+        subtree.u64(hashes.len() as u64);
+        for hash in hashes {
+            subtree.u64(hash);
+        }
+        shape.str(name);
+        for inner in &body.blocks {
+            shape.str(&inner.block_type);
+        }
+    }
+
+    add(signature, subtree.0);
+    add(signature, shape.0);
+    subtree.0
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    fn submission(variable: &str, times: &str, extra: &str) -> Program {
+        program_from_xml(&format!(r#"
+            <xml>
+                <block type="controls_repeat_ext" id="{var}-a" x="10" y="10">
+                    <value name="TIMES"><block type="math_number" id="{var}-b"><field name="NUM">{times}</field></block></value>
+                    <statement name="DO">
+                        <block type="variables_set" id="{var}-c">
+                            <field name="VAR" id="{var}">{var}</field>
+                            <value name="VALUE"><block type="math_number" id="{var}-d"><field name="NUM">1</field></block></value>
+                            <next><block type="led_on" id="{var}-e">{extra}</block></next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#, var = variable, times = times, extra = extra)).unwrap()
+    }
+
+    #[test]
+    fn test_similarity() {
+        let original = submission("count", "10", "");
+        let renamed = submission("total", "10", "");
+        let edited = submission("count", "12", "");
+        let other = program_from_xml(r#"<xml><block type="text_print" id="a"><value name="TEXT"><block type="text" id="b"><field name="TEXT">hi</field></block></value></block></xml>"#).unwrap();
+
+        assert_eq!(original.similarity(&renamed), 1.0);
+        let edited_similarity = original.similarity(&edited);
+        assert!(edited_similarity > 0.5 && edited_similarity < 1.0);
+        assert_eq!(original.similarity(&other), 0.0);
+        assert_eq!(Program::new().similarity(&Program::new()), 1.0);
+
+        // Variables from before Blockly gave them ids are anonymous too
+        let legacy = |variable: &str| program_from_xml(&format!(r#"
+            <xml><block type="variables_set" id="a"><field name="VAR">{}</field><value name="VALUE"><block type="math_number" id="b"><field name="NUM">1</field></block></value></block></xml>
+        "#, variable)).unwrap();
+        assert_eq!(legacy("count").similarity(&legacy("total")), 1.0);
+
+        let signatures: Vec<Signature> = [&original, &other, &renamed, &edited].iter().map(|program| program.signature()).collect();
+        let pairs = similar_pairs(&signatures, 0.5);
+        assert_eq!(pairs.iter().map(|&(i, j, _)| (i, j)).collect::<Vec<_>>(), [(0, 2), (0, 3), (2, 3)]);
+    }
+}
@@ -0,0 +1,370 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::{
+    BlockPath,
+    PathStep,
+    RawElement,
+    ValidationIssue,
+};
+use super::dom::XmlElement;
+use super::limits::{
+    find_byte,
+    skip_past,
+    skip_start_tag,
+};
+
+/// A region of the source XML as byte offsets, from the `<` of an element's
+/// start tag to just after the `>` of its end tag.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The 1-based line and column where the span starts in `source`, the
+    /// text it was parsed from. Columns count characters, not bytes.
+    pub fn line_column(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+        let line = before.matches('\n').count() + 1;
+        (line, before[line_start..].chars().count() + 1)
+    }
+}
+
+/// Where each block of a parsed program came from in the source XML, to
+/// point at blocks named by validation issues or diffs.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct SourceMap {
+    by_path: HashMap<BlockPath, Span>,
+    by_id: HashMap<String, Span>,
+}
+
+impl SourceMap {
+    pub fn block(&self, path: &BlockPath) -> Option<Span> {
+        self.by_path.get(path).cloned()
+    }
+
+    /// The span of the first block with the given id.
+    pub fn block_with_id(&self, id: &str) -> Option<Span> {
+        self.by_id.get(id).cloned()
+    }
+
+    /// The span of the block an issue is about.
+    pub fn issue(&self, issue: &ValidationIssue) -> Option<Span> {
+        self.block_with_id(issue.id())
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_path.is_empty()
+    }
+
+    // Follows the same rules as the parser to work out each block's path
+    pub(crate) fn new(outline: &Outline) -> Self {
+        let mut map = Self::default();
+        let root = match outline.roots.first() {
+            Some(&root) if outline.nodes[root].name == "xml" => root,
+            _ => return map,
+        };
+        let stacks = outline.nodes[root].children.iter()
+            .filter(|&&child| outline.nodes[child].name == "block");
+        for (group, &first) in stacks.enumerate() {
+            map.add_stack(outline, first, &|index| BlockPath::new(group, index));
+        }
+        map
+    }
+
+    fn add_stack(&mut self, outline: &Outline, first: usize, path: &dyn Fn(usize) -> BlockPath) {
+        let mut current = Some(first);
+        let mut index = 0;
+        while let Some(node) = current {
+            self.add_block(outline, node, path(index));
+            current = outline.child(node, &["next"]).and_then(|next| outline.child(next, &["block", "shadow"]));
+            index += 1;
+        }
+    }
+
+    fn add_block(&mut self, outline: &Outline, node: usize, path: BlockPath) {
+        let block = &outline.nodes[node];
+        if let Some(ref id) = block.id {
+            self.by_id.entry(id.clone()).or_insert(block.span);
+        }
+        for &child in block.children.iter() {
+            let input = &outline.nodes[child];
+            let name = match input.input_name {
+                Some(ref name) => name.clone(),
+                None => continue,
+            };
+            match input.name.as_str() {
+                "field" => {
+                    if let Some(&first) = input.children.first() {
+                        if ["block", "shadow"].contains(&outline.nodes[first].name.as_str()) {
+                            self.add_block(outline, first, path.child(PathStep::Field(name)));
+                        }
+                    }
+                },
+                "value" => {
+                    if let Some(inner) = outline.input_block(child) {
+                        self.add_block(outline, inner, path.child(PathStep::Value(name)));
+                    }
+                },
+                "statement" => {
+                    if let Some(inner) = outline.input_block(child) {
+                        self.add_stack(outline, inner, &|index| path.child(PathStep::Statement(name.clone(), index)));
+                    }
+                },
+                _ => {}
+            }
+        }
+        self.by_path.insert(path, block.span);
+    }
+}
+
+/// The element structure of a document with the span of each element,
+/// found by scanning tags like `check_depth` does.
+#[derive(Debug, Default)]
+pub(crate) struct Outline {
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+}
+
+#[derive(Debug)]
+struct Node {
+    name: String,
+    // The `name` attribute of fields and inputs
+    input_name: Option<String>,
+    id: Option<String>,
+    span: Span,
+    children: Vec<usize>,
+}
+
+impl Outline {
+    pub(crate) fn scan(xml: &str) -> Self {
+        let bytes = xml.as_bytes();
+        let mut outline = Self::default();
+        let mut open: Vec<usize> = Vec::new();
+        let mut i = 0;
+
+        while let Some(offset) = find_byte(&bytes[i..], b'<') {
+            i += offset;
+            let rest = &bytes[i..];
+            if rest.starts_with(b"<!--") {
+                i = skip_past(bytes, i + 4, b"-->");
+            } else if rest.starts_with(b"<![CDATA[") {
+                i = skip_past(bytes, i + 9, b"]]>");
+            } else if rest.starts_with(b"<?") {
+                i = skip_past(bytes, i + 2, b"?>");
+            } else if rest.starts_with(b"<!") {
+                i = skip_past(bytes, i + 2, b">");
+            } else if rest.starts_with(b"</") {
+                i = skip_past(bytes, i + 2, b">");
+                if let Some(index) = open.pop() {
+                    outline.nodes[index].span.end = i;
+                }
+            } else {
+                let (end, self_closing) = skip_start_tag(bytes, i + 1);
+                let index = outline.nodes.len();
+                outline.nodes.push(Node::from_tag(&xml[i + 1..end], Span { start: i, end }));
+                match open.last() {
+                    Some(&parent) => outline.nodes[parent].children.push(index),
+                    None => outline.roots.push(index),
+                }
+                if !self_closing {
+                    open.push(index);
+                }
+                i = end;
+            }
+        }
+
+        outline
+    }
+
+    pub(crate) fn root(&self) -> Option<usize> {
+        self.roots.first().cloned()
+    }
+
+    fn child(&self, node: usize, names: &[&str]) -> Option<usize> {
+        self.nodes[node].children.iter()
+            .cloned()
+            .find(|&child| names.contains(&self.nodes[child].name.as_str()))
+    }
+
+    // A block in an input takes the place of its shadow
+    fn input_block(&self, input: usize) -> Option<usize> {
+        self.child(input, &["block"]).or_else(|| self.child(input, &["shadow"]))
+    }
+}
+
+impl Node {
+    // `tag` is the start tag without its `<`
+    fn from_tag(tag: &str, span: Span) -> Self {
+        let tag = tag.trim_end_matches('>').trim_end_matches('/');
+        let name_end = tag.find(|ch: char| ch.is_whitespace()).unwrap_or(tag.len());
+        let mut node = Self {
+            name: local_name(&tag[..name_end]).to_string(),
+            input_name: None,
+            id: None,
+            span,
+            children: Vec::new(),
+        };
+
+        let mut rest = &tag[name_end..];
+        while let Some(equals) = rest.find('=') {
+            let attribute = local_name(rest[..equals].trim());
+            let value = rest[equals + 1..].trim_start();
+            let quote = match value.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => quote,
+                _ => break,
+            };
+            let value_end = value[1..].find(quote).map(|index| index + 1).unwrap_or(value.len());
+            match attribute {
+                "name" => node.input_name = Some(unescape(&value[1..value_end])),
+                "id" => node.id = Some(unescape(&value[1..value_end])),
+                _ => {}
+            }
+            rest = &value[(value_end + 1).min(value.len())..];
+        }
+        node
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+// Attribute values are short, so this only handles the references Blockly
+// itself writes
+fn unescape(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+    value.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// An element paired with its node in an `Outline`, so errors can say where
+/// in the source they happened.
+#[derive(Clone, Copy)]
+pub(crate) struct Spanned<'s, E> {
+    element: E,
+    node: Option<usize>,
+    outline: &'s Outline,
+}
+
+impl<'s, E: XmlElement> Spanned<'s, E> {
+    pub(crate) fn new(element: E, outline: &'s Outline) -> Self {
+        Self {
+            element,
+            node: outline.root(),
+            outline,
+        }
+    }
+}
+
+impl<'s, E: XmlElement> XmlElement for Spanned<'s, E> {
+    fn name(&self) -> &str {
+        self.element.name()
+    }
+
+    fn namespace(&self) -> Option<&str> {
+        self.element.namespace()
+    }
+
+    fn display_name(&self) -> String {
+        self.element.display_name()
+    }
+
+    fn span(&self) -> Option<Span> {
+        self.node.map(|node| self.outline.nodes[node].span)
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.element.attribute(name)
+    }
+
+    fn attributes(&self) -> Vec<(Cow<'_, str>, &str)> {
+        self.element.attributes()
+    }
+
+    // If the scan disagrees with the parser about the children, e.g. for
+    // malformed input, their spans are left out rather than guessed
+    fn child_elements(&self) -> Vec<Self> {
+        let children = self.element.child_elements();
+        let nodes = self.node
+            .map(|node| &self.outline.nodes[node].children)
+            .filter(|nodes| nodes.len() == children.len());
+        children.into_iter()
+            .enumerate()
+            .map(|(index, child)| Spanned {
+                element: child,
+                node: nodes.map(|nodes| nodes[index]),
+                outline: self.outline,
+            })
+            .collect()
+    }
+
+    fn text(&self) -> String {
+        self.element.text()
+    }
+
+    fn to_raw(&self) -> RawElement {
+        self.element.to_raw()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        BlockRegistry,
+        ParseError,
+        ParserOptions,
+        program_from_xml_with_diagnostics,
+    };
+
+    const XML: &str = "<xml>\n  <block type=\"controls_if\" id=\"if\">\n    <value name=\"IF0\"><block type=\"logic_boolean\" id=\"bool\"/></value>\n    <statement name=\"DO0\"><block type=\"led_on\" id=\"on\"></block></statement>\n  </block>\n</xml>";
+
+    #[test]
+    fn test_block_spans() {
+        let outcome = program_from_xml_with_diagnostics(XML, &ParserOptions::default()).unwrap();
+        let spans = &outcome.spans;
+        assert_eq!(spans.len(), 3);
+
+        let span = spans.block(&BlockPath::new(0, 0)).unwrap();
+        assert!(XML[span.start..span.end].starts_with("<block type=\"controls_if\""));
+        assert!(XML[span.start..span.end].ends_with("</statement>\n  </block>"));
+        assert_eq!(span.line_column(XML), (2, 3));
+
+        let path = BlockPath::new(0, 0).child(PathStep::Value("IF0".to_string()));
+        let span = spans.block(&path).unwrap();
+        assert_eq!(&XML[span.start..span.end], r#"<block type="logic_boolean" id="bool"/>"#);
+
+        let issues = outcome.program.validate(&BlockRegistry::new());
+        let span = spans.issue(&issues[2]).unwrap();
+        assert_eq!(&XML[span.start..span.end], r#"<block type="led_on" id="on"></block>"#);
+        assert_eq!(span.line_column(XML), (4, 27));
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let xml = "<xml>\n  <block type=\"led_on\"><field>1</field></block>\n</xml>";
+        let error = program_from_xml_with_diagnostics(xml, &ParserOptions::default()).unwrap_err();
+        let span = error.span().unwrap();
+        assert_eq!(&xml[span.start..span.end], "<field>1</field>");
+        assert_eq!(span.line_column(xml), (2, 24));
+        assert_eq!(error, ParseError::MissingAttribute {
+            element: "field".to_string(),
+            attribute: "name".to_string(),
+            span: Some(span),
+        });
+    }
+}
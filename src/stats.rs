@@ -0,0 +1,95 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::Program;
+use super::iter::child_blocks;
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ProgramStats {
+    pub block_count: usize,
+    pub blocks_by_type: HashMap<String, usize>,
+    /// How deeply blocks are nested. Top-level blocks are at depth 1, and each
+    /// statement input, value input or expression field adds a level; blocks
+    /// joined with `next` share a depth. An empty program has depth 0.
+    pub max_depth: usize,
+    /// The number of top-level stacks.
+    pub stack_count: usize,
+}
+
+impl Program {
+    pub fn stats(&self) -> ProgramStats {
+        let mut stats = ProgramStats {
+            stack_count: self.groups.len(),
+            ..ProgramStats::default()
+        };
+
+        let mut pending: Vec<_> = self.groups.iter()
+            .flat_map(|group| group.blocks.iter())
+            .map(|block| (block, 1))
+            .collect();
+        while let Some((block, depth)) = pending.pop() {
+            stats.block_count += 1;
+            *stats.blocks_by_type.entry(block.block_type.clone()).or_insert(0) += 1;
+            stats.max_depth = stats.max_depth.max(depth);
+            pending.extend(child_blocks(block).into_iter().map(|child| (child, depth + 1)));
+        }
+
+        stats
+    }
+
+    /// Every block type the program uses, at any depth.
+    pub fn block_types(&self) -> BTreeSet<&str> {
+        self.iter_blocks().map(|block| block.block_type.as_str()).collect()
+    }
+
+    /// How many blocks of each type the program has, at any depth.
+    pub fn block_type_counts(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for block in self.iter_blocks() {
+            *counts.entry(block.block_type.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_stats() {
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="1">
+                    <statement name="BODY">
+                        <block type="inner_loop" id="2">
+                            <value name="COUNT">
+                                <block type="math_number" id="3">
+                                    <field name="NUM">3</field>
+                                </block>
+                            </value>
+                            <next>
+                                <block type="led_on" id="4"></block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="led_on" id="5"></block>
+            </xml>
+        "#).unwrap();
+
+        let stats = program.stats();
+        assert_eq!(stats.block_count, 5);
+        assert_eq!(stats.blocks_by_type["led_on"], 2);
+        assert_eq!(stats.blocks_by_type["math_number"], 1);
+        assert_eq!(stats.max_depth, 3);
+        assert_eq!(stats.stack_count, 2);
+
+        assert_eq!(Program::new().stats(), ProgramStats::default());
+
+        assert_eq!(program.block_types().into_iter().collect::<Vec<_>>(), ["inner_loop", "led_on", "main_loop", "math_number"]);
+        assert_eq!(program.block_type_counts()["led_on"], 2);
+    }
+}
@@ -0,0 +1,272 @@
+use indexmap::IndexMap;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+};
+use super::procedures::Procedures;
+
+/// An upper bound on how many blocks a run of a program executes, from
+/// `Program::max_steps`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Steps {
+    Bounded(u64),
+    /// A loop may run forever, or its count is only known at runtime.
+    Unbounded,
+}
+
+impl Steps {
+    /// Whether a run is sure to finish within `max` steps.
+    pub fn is_within(&self, max: u64) -> bool {
+        match *self {
+            Steps::Bounded(steps) => steps <= max,
+            Steps::Unbounded => false,
+        }
+    }
+}
+
+/// Where a loop block's repeat count comes from.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum LoopCount {
+    /// A number field, e.g. `COUNT`.
+    Field(String),
+    /// A value input. Only a `math_number` block there gives a bound.
+    Value(String),
+    /// The loop may repeat forever, e.g. a main loop or `while`.
+    Forever,
+}
+
+/// The loop blocks `Program::max_steps` knows about, with the statement
+/// input each one repeats.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct LoopRules {
+    loops: IndexMap<String, (String, LoopCount)>,
+}
+
+impl LoopRules {
+    /// No loop blocks at all, for custom blocks only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Blockly's standard loops. `controls_for` and `controls_forEach` are
+    /// taken to be unbounded.
+    pub fn standard() -> Self {
+        Self::new()
+            .with_loop("controls_repeat", "DO", LoopCount::Field("TIMES".to_string()))
+            .with_loop("controls_repeat_ext", "DO", LoopCount::Value("TIMES".to_string()))
+            .with_loop("controls_whileUntil", "DO", LoopCount::Forever)
+            .with_loop("controls_for", "DO", LoopCount::Forever)
+            .with_loop("controls_forEach", "DO", LoopCount::Forever)
+    }
+
+    pub fn with_loop<T: Into<String>, U: Into<String>>(mut self, block_type: T, body: U, count: LoopCount) -> Self {
+        self.loops.insert(block_type.into(), (body.into(), count));
+        self
+    }
+}
+
+impl Program {
+    /// An upper bound on the number of blocks a run executes, counting each
+    /// block every time it runs, value blocks included. Loops repeat their
+    /// body as `rules` say. Any other block runs each of its statement inputs
+    /// at most once, except that only the longest branch of a `controls_if`
+    /// counts. Procedure definitions count when they are called; recursion
+    /// makes the program unbounded.
+    pub fn max_steps(&self, rules: &LoopRules) -> Steps {
+        let procedures = self.procedures();
+        let mut counter = Counter {
+            rules,
+            procedures: &procedures,
+            calling: Vec::new(),
+        };
+        let steps = self.groups.iter()
+            .filter(|group| group.blocks.first().is_none_or(|block| !is_definition(block)))
+            .try_fold(0u64, |total, group| total.checked_add(counter.stack(group)?));
+        match steps {
+            Some(steps) => Steps::Bounded(steps),
+            None => Steps::Unbounded,
+        }
+    }
+}
+
+fn is_definition(block: &Block) -> bool {
+    block.block_type.starts_with("procedures_def")
+}
+
+// Counts are `None` once they are unbounded
+struct Counter<'a> {
+    rules: &'a LoopRules,
+    procedures: &'a Procedures<'a>,
+    // The procedures being counted, to catch recursion
+    calling: Vec<String>,
+}
+
+impl<'a> Counter<'a> {
+    fn stack(&mut self, body: &StatementBody) -> Option<u64> {
+        body.blocks.iter().try_fold(0u64, |total, block| total.checked_add(self.block(block)?))
+    }
+
+    fn block(&mut self, block: &Block) -> Option<u64> {
+        let mut steps: u64 = 1;
+        for field in block.fields.values() {
+            if let FieldValue::ExpressionField(ref expression) = *field {
+                steps = steps.checked_add(self.block(expression)?)?;
+            }
+        }
+        for input in block.values.values() {
+            steps = steps.checked_add(self.block(input.block())?)?;
+        }
+
+        if let Some((body, count)) = self.rules.loops.get(&block.block_type) {
+            let times = repeat_count(block, count)?;
+            let body = match block.statements.get(body) {
+                Some(body) => self.stack(body)?,
+                None => 0,
+            };
+            return steps.checked_add(times.checked_mul(body)?);
+        }
+
+        if block.block_type == "controls_if" {
+            let mut longest = 0;
+            for body in block.statements.values() {
+                longest = longest.max(self.stack(body)?);
+            }
+            steps = steps.checked_add(longest)?;
+        } else {
+            for body in block.statements.values() {
+                steps = steps.checked_add(self.stack(body)?)?;
+            }
+        }
+
+        if block.block_type.starts_with("procedures_call") {
+            steps = steps.checked_add(self.call(block)?)?;
+        }
+        Some(steps)
+    }
+
+    // The steps of the called procedure's body. Calls to undefined
+    // procedures do nothing.
+    fn call(&mut self, call: &Block) -> Option<u64> {
+        let procedure = match self.procedures.resolve(call) {
+            Some(procedure) => procedure,
+            None => return Some(0),
+        };
+        let name = procedure.name.to_lowercase();
+        if self.calling.contains(&name) {
+            return None;
+        }
+        self.calling.push(name);
+        let definition = procedure.definition;
+        let mut steps: u64 = 0;
+        for input in definition.values.values() {
+            steps = steps.checked_add(self.block(input.block())?)?;
+        }
+        for body in definition.statements.values() {
+            steps = steps.checked_add(self.stack(body)?)?;
+        }
+        self.calling.pop();
+        Some(steps)
+    }
+}
+
+fn repeat_count(block: &Block, count: &LoopCount) -> Option<u64> {
+    let times = match *count {
+        LoopCount::Field(ref name) => block.field_f64(name).ok()?,
+        LoopCount::Value(ref name) => match block.values.get(name) {
+            Some(input) if input.block().block_type == "math_number" => input.block().field_f64("NUM").ok()?,
+            Some(_) => return None,
+            None => 0.0,
+        },
+        LoopCount::Forever => return None,
+    };
+    if !times.is_finite() {
+        return None;
+    }
+    // Blockly's loops run while the counter is below the count
+    Some(times.max(0.0).ceil() as u64)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_max_steps() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="inner_loop">
+                    <field name="COUNT">1000</field>
+                    <statement name="BODY">
+                        <block type="controls_repeat_ext">
+                            <value name="TIMES"><shadow type="math_number"><field name="NUM">2.5</field></shadow></value>
+                            <statement name="DO">
+                                <block type="led_on"><next><block type="led_off"></block></next></block>
+                            </statement>
+                        </block>
+                    </statement>
+                    <next>
+                        <block type="controls_if">
+                            <statement name="DO0"><block type="led_on"></block></statement>
+                            <statement name="ELSE">
+                                <block type="procedures_callnoreturn"><mutation name="blink"></mutation></block>
+                            </statement>
+                        </block>
+                    </next>
+                </block>
+                <block type="procedures_defnoreturn">
+                    <field name="NAME">blink</field>
+                    <statement name="STACK">
+                        <block type="led_on"><next><block type="led_off"></block></next></block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let rules = LoopRules::standard().with_loop("inner_loop", "BODY", LoopCount::Field("COUNT".to_string()));
+        // 1 + 1000 * (2 + 3 * 2) for the loops, 1 + 3 for the longest branch
+        assert_eq!(program.max_steps(&rules), Steps::Bounded(8005));
+        assert!(program.max_steps(&rules).is_within(10_000));
+        assert!(!program.max_steps(&rules).is_within(8_000));
+        assert_eq!(program.max_steps(&LoopRules::standard()), Steps::Bounded(1 + 8 + 4));
+        assert_eq!(Program::new().max_steps(&rules), Steps::Bounded(0));
+    }
+
+    #[test]
+    fn test_max_steps_unbounded() {
+        let forever = program_from_xml(r#"
+            <xml>
+                <block type="main_loop">
+                    <statement name="BODY"><block type="led_on"></block></statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let rules = LoopRules::new().with_loop("main_loop", "BODY", LoopCount::Forever);
+        assert_eq!(forever.max_steps(&rules), Steps::Unbounded);
+
+        let counted_by_variable = program_from_xml(r#"
+            <xml>
+                <block type="controls_repeat_ext">
+                    <value name="TIMES"><block type="variables_get"><field name="VAR">n</field></block></value>
+                </block>
+            </xml>
+        "#).unwrap();
+        assert_eq!(counted_by_variable.max_steps(&LoopRules::standard()), Steps::Unbounded);
+
+        let recursive = program_from_xml(r#"
+            <xml>
+                <block type="procedures_defnoreturn">
+                    <field name="NAME">again</field>
+                    <statement name="STACK">
+                        <block type="procedures_callnoreturn"><mutation name="again"></mutation></block>
+                    </statement>
+                </block>
+                <block type="procedures_callnoreturn"><mutation name="again"></mutation></block>
+            </xml>
+        "#).unwrap();
+        assert_eq!(recursive.max_steps(&LoopRules::standard()), Steps::Unbounded);
+    }
+}
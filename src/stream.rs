@@ -0,0 +1,330 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+use quick_xml::Reader;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{
+    BytesStart,
+    Event,
+};
+
+use super::ParseError;
+
+/// An event produced by `BlockEventReader`.
+///
+/// Blocks joined with `next` are reported one after the other, each closed
+/// by `EndBlock` before the next one starts, as if they were siblings.
+#[derive(PartialEq, Debug, Clone)]
+pub enum BlockEvent {
+    Variable { id: String, name: String, var_type: Option<String> },
+    StartBlock { block_type: String, id: String, shadow: bool, position: Option<(i32, i32)> },
+    EndBlock,
+    /// A field holding plain text.
+    Field { name: String, value: String },
+    /// A field holding a block; the block's events follow.
+    StartFieldBlock { name: String },
+    EndFieldBlock,
+    StartStatement { name: String },
+    EndStatement,
+    StartValue { name: String },
+    EndValue,
+}
+
+/// A pull parser that reports blocks while reading the XML, without building
+/// the whole `Program`. Mutations, comments and `<data>` are skipped.
+///
+/// ```
+/// use blockly_parser::{BlockEvent, BlockEventReader};
+///
+/// let xml = r#"<xml><block type="led_on" id="a"><field name="TIME">300</field></block></xml>"#;
+/// let block_types: Vec<String> = BlockEventReader::new(xml.as_bytes())
+///     .filter_map(|event| match event.unwrap() {
+///         BlockEvent::StartBlock { block_type, .. } => Some(block_type),
+///         _ => None,
+///     })
+///     .collect();
+/// assert_eq!(block_types, vec!["led_on"]);
+/// ```
+pub struct BlockEventReader<R: BufRead> {
+    reader: Reader<R>,
+    buffer: Vec<u8>,
+    open: Vec<Frame>,
+    pending: VecDeque<BlockEvent>,
+    finished: bool,
+}
+
+// An open element, and what to report when it closes
+enum Frame {
+    Block { ended: bool },
+    Statement,
+    Value,
+    Field { name: String, text: String, has_block: bool },
+    Variable { id: String, name: String, var_type: Option<String> },
+    // Elements whose content is passed through, e.g. `<xml>` and `<next>`
+    Container,
+    // Elements whose content is ignored, e.g. `<mutation>`
+    Skipped,
+}
+
+impl<R: BufRead> BlockEventReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: Reader::from_reader(reader),
+            buffer: Vec::new(),
+            open: Vec::new(),
+            pending: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    fn read(&mut self) -> Result<(), ParseError> {
+        self.buffer.clear();
+        let event = match self.reader.read_event_into(&mut self.buffer) {
+            Ok(event) => event.into_owned(),
+            Err(error) => {
+                return Err(ParseError::Xml {
+                    position: self.reader.error_position() as usize,
+                    message: error.to_string(),
+                });
+            }
+        };
+        match event {
+            Event::Start(ref start) => {
+                self.start(start)?;
+            },
+            Event::Empty(ref start) => {
+                self.start(start)?;
+                self.end();
+            },
+            Event::End(_) => {
+                self.end();
+            },
+            Event::Text(ref text) => {
+                self.push_text(&text.xml10_content());
+            },
+            Event::CData(ref data) => {
+                self.push_text(&data.xml10_content());
+            },
+            Event::GeneralRef(ref reference) => {
+                let name = reference.xml10_content();
+                let resolved = match reference.resolve_char_ref() {
+                    Ok(Some(ch)) => ch.to_string(),
+                    _ => resolve_predefined_entity(&name).unwrap_or("").to_string(),
+                };
+                self.push_text(&resolved);
+            },
+            Event::Eof => {
+                self.finished = true;
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn start(&mut self, start: &BytesStart) -> Result<(), ParseError> {
+        if let Some(&Frame::Skipped) = self.open.last() {
+            self.open.push(Frame::Skipped);
+            return Ok(());
+        }
+
+        let element = start.local_name().into_inner().to_string();
+        let frame = match element.as_str() {
+            "xml" | "variables" => Frame::Container,
+            "next" => {
+                // The block is complete once its `next` starts
+                if let Some(&mut Frame::Block { ref mut ended }) = self.open.last_mut() {
+                    *ended = true;
+                    self.pending.push_back(BlockEvent::EndBlock);
+                }
+                Frame::Container
+            },
+            "variable" => Frame::Variable {
+                id: attribute(start, "id")?.unwrap_or_default(),
+                name: String::new(),
+                var_type: attribute(start, "type")?.and_then(|var_type| {
+                    if var_type.is_empty() { None } else { Some(var_type) }
+                }),
+            },
+            "block" | "shadow" => {
+                if let Some(&mut Frame::Field { ref name, ref mut has_block, .. }) = self.open.last_mut() {
+                    *has_block = true;
+                    self.pending.push_back(BlockEvent::StartFieldBlock { name: name.clone() });
+                }
+                let position = match (attribute(start, "x")?, attribute(start, "y")?) {
+                    (Some(x), Some(y)) => Some((coordinate(&element, "x", &x)?, coordinate(&element, "y", &y)?)),
+                    _ => None
+                };
+                self.pending.push_back(BlockEvent::StartBlock {
+                    block_type: require_attribute(start, &element, "type")?,
+                    id: attribute(start, "id")?.unwrap_or_default(),
+                    shadow: element == "shadow",
+                    position,
+                });
+                Frame::Block { ended: false }
+            },
+            "statement" => {
+                let name = require_attribute(start, &element, "name")?;
+                self.pending.push_back(BlockEvent::StartStatement { name });
+                Frame::Statement
+            },
+            "value" => {
+                let name = require_attribute(start, &element, "name")?;
+                self.pending.push_back(BlockEvent::StartValue { name });
+                Frame::Value
+            },
+            "field" => Frame::Field {
+                name: require_attribute(start, &element, "name")?,
+                text: String::new(),
+                has_block: false,
+            },
+            _ => Frame::Skipped
+        };
+        self.open.push(frame);
+        Ok(())
+    }
+
+    fn end(&mut self) {
+        let event = match self.open.pop() {
+            Some(Frame::Block { ended: false }) => BlockEvent::EndBlock,
+            Some(Frame::Statement) => BlockEvent::EndStatement,
+            Some(Frame::Value) => BlockEvent::EndValue,
+            Some(Frame::Field { has_block: true, .. }) => BlockEvent::EndFieldBlock,
+            Some(Frame::Field { name, text, has_block: false }) => BlockEvent::Field { name, value: text },
+            Some(Frame::Variable { id, name, var_type }) => BlockEvent::Variable { id, name, var_type },
+            _ => return
+        };
+        self.pending.push_back(event);
+    }
+
+    fn push_text(&mut self, content: &str) {
+        match self.open.last_mut() {
+            Some(&mut Frame::Field { ref mut text, has_block: false, .. }) => text.push_str(content),
+            Some(&mut Frame::Variable { ref mut name, .. }) => name.push_str(content),
+            _ => {}
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for BlockEventReader<R> {
+    type Item = Result<BlockEvent, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.finished {
+            if let Err(error) = self.read() {
+                self.finished = true;
+                return Some(Err(error));
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+fn attribute(start: &BytesStart, name: &str) -> Result<Option<String>, ParseError> {
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|error| ParseError::UnexpectedStructure(error.to_string()))?;
+        if attribute.key.into_inner() == name {
+            let value = attribute.normalized_value(Default::default())
+                .map_err(|error| ParseError::UnexpectedStructure(error.to_string()))?;
+            return Ok(Some(value.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+fn require_attribute(start: &BytesStart, element: &str, name: &str) -> Result<String, ParseError> {
+    attribute(start, name)?.ok_or_else(|| {
+        ParseError::MissingAttribute {
+            element: element.to_string(),
+            attribute: name.to_string(),
+            span: None,
+        }
+    })
+}
+
+fn coordinate(element: &str, name: &str, value: &str) -> Result<i32, ParseError> {
+    value.trim()
+        .parse::<f64>()
+        .map(|coordinate| coordinate.round() as i32)
+        .map_err(|_| ParseError::InvalidAttribute {
+            element: element.to_string(),
+            attribute: name.to_string(),
+            value: value.to_string(),
+            span: None,
+        })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_all(xml: &str) -> Result<Vec<BlockEvent>, ParseError> {
+        BlockEventReader::new(xml.as_bytes()).collect()
+    }
+
+    #[test]
+    fn test_block_events() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables>
+                    <variable id="v1">count</variable>
+                </variables>
+                <block type="main_loop" id="main" x="10" y="20">
+                    <mutation items="2"><arg name="x"></arg></mutation>
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <field name="LABEL">on &amp; off</field>
+                            <value name="BRIGHTNESS">
+                                <shadow type="math_number" id="num">
+                                    <field name="NUM">80</field>
+                                </shadow>
+                            </value>
+                            <next>
+                                <block type="led_off" id="off"/>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#;
+        let start_block = |block_type: &str, id: &str, shadow: bool, position: Option<(i32, i32)>| {
+            BlockEvent::StartBlock {
+                block_type: block_type.to_string(),
+                id: id.to_string(),
+                shadow,
+                position,
+            }
+        };
+
+        assert_eq!(read_all(xml).unwrap(), vec![
+            BlockEvent::Variable { id: "v1".to_string(), name: "count".to_string(), var_type: None },
+            start_block("main_loop", "main", false, Some((10, 20))),
+            BlockEvent::StartStatement { name: "BODY".to_string() },
+            start_block("led_on", "on", false, None),
+            BlockEvent::Field { name: "LABEL".to_string(), value: "on & off".to_string() },
+            BlockEvent::StartValue { name: "BRIGHTNESS".to_string() },
+            start_block("math_number", "num", true, None),
+            BlockEvent::Field { name: "NUM".to_string(), value: "80".to_string() },
+            BlockEvent::EndBlock,
+            BlockEvent::EndValue,
+            BlockEvent::EndBlock,
+            start_block("led_off", "off", false, None),
+            BlockEvent::EndBlock,
+            BlockEvent::EndStatement,
+            BlockEvent::EndBlock,
+        ]);
+    }
+
+    #[test]
+    fn test_block_event_errors() {
+        assert_eq!(read_all(r#"<xml><block id="a"></block></xml>"#).unwrap_err(), ParseError::MissingAttribute {
+            element: "block".to_string(),
+            attribute: "type".to_string(),
+            span: None,
+        });
+        match read_all(r#"<xml><block type="a"></statement></xml>"#) {
+            Err(ParseError::Xml { .. }) => {},
+            other => panic!("expected an XML error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::BTreeSet;
+
+use serde_json::{
+    Map,
+    Value,
+};
+use sxd_document::dom::ChildOfRoot;
+
+use super::{
+    Block,
+    BlockRegistry,
+    ParseError,
+    ParserOptions,
+    Diagnostics,
+    parse_package,
+    get_attribute,
+    stack_from_xml,
+};
+use super::dom::XmlElement;
+use super::json::{
+    as_array,
+    as_object,
+    block_from_json,
+    get_string,
+    parse_json,
+    require_string,
+    scalar_to_string,
+};
+
+/// A Blockly toolbox: the blocks a user can drag onto the workspace, either
+/// in one flyout or sorted into categories.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Toolbox {
+    pub kind: ToolboxKind,
+    pub contents: Vec<ToolboxItem>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum ToolboxKind {
+    /// A single flyout of blocks.
+    Flyout,
+    /// Categories, each opening its own flyout.
+    Category,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum ToolboxItem {
+    /// A block, with any preset fields and inputs.
+    Block(Box<Block>),
+    Category(ToolboxCategory),
+    /// A gap between items, in pixels if given.
+    Separator { gap: Option<i32> },
+    Button { text: String, callback_key: String },
+    Label { text: String },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ToolboxCategory {
+    pub name: String,
+    pub colour: Option<String>,
+    /// A dynamic category filled in by the app, e.g. `VARIABLE` or
+    /// `PROCEDURE`.
+    pub custom: Option<String>,
+    pub contents: Vec<ToolboxItem>,
+}
+
+impl Toolbox {
+    /// Every block type in the toolbox, in any category, including blocks
+    /// preset in other blocks' inputs.
+    pub fn block_types(&self) -> BTreeSet<&str> {
+        let mut types = BTreeSet::new();
+        let mut pending: Vec<&ToolboxItem> = self.contents.iter().collect();
+        while let Some(item) = pending.pop() {
+            match *item {
+                ToolboxItem::Block(ref block) => {
+                    types.insert(block.block_type.as_str());
+                    types.extend(block.descendants().map(|block| block.block_type.as_str()));
+                },
+                ToolboxItem::Category(ref category) => pending.extend(category.contents.iter()),
+                _ => {},
+            }
+        }
+        types
+    }
+
+    /// Whether the toolbox offers blocks of `block_type`. To check that a
+    /// program only uses toolbox blocks:
+    ///
+    /// ```
+    /// # use blockly_parser::{program_from_xml, toolbox_from_xml};
+    /// # let toolbox = toolbox_from_xml(r#"<xml><block type="led_on"></block></xml>"#).unwrap();
+    /// # let program = program_from_xml(r#"<xml><block type="led_on"></block></xml>"#).unwrap();
+    /// let extra: Vec<_> = program.block_types().into_iter().filter(|block_type| !toolbox.contains(block_type)).collect();
+    /// assert!(extra.is_empty());
+    /// ```
+    pub fn contains(&self, block_type: &str) -> bool {
+        self.block_types().contains(block_type)
+    }
+}
+
+/// Parse a toolbox written in XML, as passed to `Blockly.inject`.
+pub fn toolbox_from_xml(xml: &str) -> Result<Toolbox, ParseError> {
+    let package = parse_package(xml)?;
+    let document = package.as_document();
+    let root = document.root()
+        .children()
+        .into_iter()
+        .find_map(|child| match child {
+            ChildOfRoot::Element(el) => Some(el),
+            _ => None,
+        })
+        .ok_or(ParseError::MissingRootElement)?;
+    let contents = items_from_xml(root)?;
+    Ok(Toolbox {
+        kind: kind_of(&contents),
+        contents,
+    })
+}
+
+/// Parse a toolbox written in JSON, e.g.
+/// `{"kind": "categoryToolbox", "contents": [...]}`.
+pub fn toolbox_from_json(json: &str) -> Result<Toolbox, ParseError> {
+    let root = parse_json(json)?;
+    let root = as_object(&root, "toolbox")?;
+    let contents = items_from_json(root)?;
+    let kind = match get_string(root, "kind").as_deref() {
+        Some("categoryToolbox") => ToolboxKind::Category,
+        Some("flyoutToolbox") => ToolboxKind::Flyout,
+        _ => kind_of(&contents),
+    };
+    Ok(Toolbox { kind, contents })
+}
+
+fn kind_of(contents: &[ToolboxItem]) -> ToolboxKind {
+    if contents.iter().any(|item| matches!(*item, ToolboxItem::Category(_))) {
+        ToolboxKind::Category
+    } else {
+        ToolboxKind::Flyout
+    }
+}
+
+fn items_from_xml<E: XmlElement>(parent: E) -> Result<Vec<ToolboxItem>, ParseError> {
+    let mut items = Vec::new();
+    for el in parent.child_elements() {
+        let item = match el.name() {
+            "block" | "shadow" => ToolboxItem::Block(Box::new(Block::from_element(el, &ParserOptions::default(), &mut Diagnostics::new())?)),
+            "category" => ToolboxItem::Category(ToolboxCategory {
+                name: get_attribute(el, "name").unwrap_or_default(),
+                colour: get_attribute(el, "colour"),
+                custom: get_attribute(el, "custom"),
+                contents: items_from_xml(el)?,
+            }),
+            "sep" => ToolboxItem::Separator {
+                gap: get_attribute(el, "gap").and_then(|gap| gap.trim().parse().ok()),
+            },
+            "button" => ToolboxItem::Button {
+                text: get_attribute(el, "text").unwrap_or_default(),
+                callback_key: get_attribute(el, "callbackKey").unwrap_or_default(),
+            },
+            "label" => ToolboxItem::Label {
+                text: get_attribute(el, "text").unwrap_or_default(),
+            },
+            _ => continue,
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+fn items_from_json(parent: &Map<String, Value>) -> Result<Vec<ToolboxItem>, ParseError> {
+    let contents = match parent.get("contents") {
+        Some(contents) => as_array(contents, "contents")?,
+        None => return Ok(Vec::new()),
+    };
+    let mut items = Vec::new();
+    for item in contents {
+        let object = as_object(item, "toolbox item")?;
+        let item = match require_string(object, "toolbox item", "kind")?.to_lowercase().as_str() {
+            "block" => match get_string(object, "blockxml") {
+                Some(xml) => {
+                    let mut stack = stack_from_xml(&xml, &ParserOptions::default())?;
+                    if stack.blocks.is_empty() {
+                        continue;
+                    }
+                    ToolboxItem::Block(Box::new(stack.blocks.remove(0)))
+                },
+                None => ToolboxItem::Block(Box::new(block_from_json(item, &[], &BlockRegistry::new(), &mut Diagnostics::new())?)),
+            },
+            "category" => ToolboxItem::Category(ToolboxCategory {
+                name: get_string(object, "name").unwrap_or_default(),
+                colour: object.get("colour").map(scalar_to_string),
+                custom: get_string(object, "custom"),
+                contents: items_from_json(object)?,
+            }),
+            "sep" => ToolboxItem::Separator {
+                gap: object.get("gap").and_then(|gap| match *gap {
+                    Value::String(ref gap) => gap.trim().parse().ok(),
+                    ref gap => gap.as_i64().map(|gap| gap as i32),
+                }),
+            },
+            "button" => ToolboxItem::Button {
+                text: get_string(object, "text").unwrap_or_default(),
+                callback_key: get_string(object, "callbackKey").or_else(|| get_string(object, "callbackkey")).unwrap_or_default(),
+            },
+            "label" => ToolboxItem::Label {
+                text: get_string(object, "text").unwrap_or_default(),
+            },
+            _ => continue,
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_toolbox_from_xml() {
+        let toolbox = toolbox_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml" id="toolbox" style="display: none">
+                <category name="Logic" colour="210">
+                    <block type="controls_if"></block>
+                    <sep gap="32"></sep>
+                    <block type="logic_compare"><field name="OP">EQ</field></block>
+                </category>
+                <category name="Variables" custom="VARIABLE"></category>
+                <label text="Buttons"></label>
+                <button text="New variable" callbackKey="createVariable"></button>
+            </xml>
+        "#).unwrap();
+        assert_eq!(toolbox.kind, ToolboxKind::Category);
+        let logic = match toolbox.contents[0] {
+            ToolboxItem::Category(ref category) => category,
+            ref other => panic!("expected a category, got {:?}", other),
+        };
+        assert_eq!(logic.colour.as_deref(), Some("210"));
+        assert_eq!(logic.contents[1], ToolboxItem::Separator { gap: Some(32) });
+        match logic.contents[2] {
+            ToolboxItem::Block(ref block) => assert_eq!(block.field_str("OP"), Ok("EQ")),
+            ref other => panic!("expected a block, got {:?}", other),
+        }
+        assert_eq!(toolbox.contents[3], ToolboxItem::Button {
+            text: "New variable".to_string(),
+            callback_key: "createVariable".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_toolbox_from_json() {
+        let json = toolbox_from_json(r#"
+            {
+                "kind": "categoryToolbox",
+                "contents": [
+                    {
+                        "kind": "category",
+                        "name": "Logic",
+                        "colour": 210,
+                        "contents": [
+                            { "kind": "block", "type": "controls_if" },
+                            { "kind": "sep", "gap": "32" },
+                            { "kind": "block", "type": "logic_compare", "fields": { "OP": "EQ" } }
+                        ]
+                    },
+                    { "kind": "category", "name": "Variables", "custom": "VARIABLE" },
+                    { "kind": "label", "text": "Buttons" },
+                    { "kind": "button", "text": "New variable", "callbackKey": "createVariable" }
+                ]
+            }
+        "#).unwrap();
+        let xml = toolbox_from_xml(r#"
+            <xml>
+                <category name="Logic" colour="210">
+                    <block type="controls_if"></block>
+                    <sep gap="32"></sep>
+                    <block type="logic_compare"><field name="OP">EQ</field></block>
+                </category>
+                <category name="Variables" custom="VARIABLE"></category>
+                <label text="Buttons"></label>
+                <button text="New variable" callbackKey="createVariable"></button>
+            </xml>
+        "#).unwrap();
+        assert_eq!(json, xml);
+
+        let flyout = toolbox_from_json(r#"
+            { "kind": "flyoutToolbox", "contents": [{ "kind": "block", "blockxml": "<block type='text'><field name='TEXT'>hi</field></block>" }] }
+        "#).unwrap();
+        assert_eq!(flyout.kind, ToolboxKind::Flyout);
+        assert!(matches!(flyout.contents[0], ToolboxItem::Block(ref block) if block.block_type == "text"));
+    }
+
+    #[test]
+    fn test_toolbox_contains() {
+        let toolbox = toolbox_from_json(r#"
+            {
+                "kind": "categoryToolbox",
+                "contents": [
+                    {
+                        "kind": "category",
+                        "name": "Loops",
+                        "contents": [
+                            {
+                                "kind": "block",
+                                "type": "controls_repeat_ext",
+                                "inputs": { "TIMES": { "shadow": { "type": "math_number", "fields": { "NUM": 10 } } } }
+                            }
+                        ]
+                    },
+                    { "kind": "block", "type": "led_on" }
+                ]
+            }
+        "#).unwrap();
+        assert_eq!(toolbox.block_types().into_iter().collect::<Vec<_>>(), ["controls_repeat_ext", "led_on", "math_number"]);
+        assert!(toolbox.contains("math_number"));
+        assert!(!toolbox.contains("led_off"));
+    }
+}
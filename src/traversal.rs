@@ -0,0 +1,89 @@
+use super::Block;
+
+/// How a traversal treats blocks with `BlockFlags::disabled` set. Blocks
+/// nested inside a skipped block are always skipped with it.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum DisabledBlocks {
+    Include,
+    /// Skip the disabled block, but carry on with the blocks below it in its
+    /// stack. This is what Blockly's code generators do.
+    Skip,
+    /// Skip the disabled block and the rest of its stack.
+    SkipWithNext,
+}
+
+/// Options for `Program::iter_blocks_with`, `Program::accept_with` and
+/// `codegen::generate_with`. The default visits every block.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct TraversalOptions {
+    pub disabled: DisabledBlocks,
+    /// Walk into the inputs of collapsed blocks. The collapsed block itself is
+    /// visited either way.
+    pub enter_collapsed: bool,
+}
+
+impl TraversalOptions {
+    /// Skip disabled blocks like Blockly's code generators.
+    pub fn skip_disabled() -> Self {
+        Self {
+            disabled: DisabledBlocks::Skip,
+            ..Self::default()
+        }
+    }
+
+    /// Whether a block attached to a value input or field is visited.
+    pub(crate) fn includes(&self, block: &Block) -> bool {
+        !block.flags.disabled || self.disabled == DisabledBlocks::Include
+    }
+
+    /// Whether the inputs of a visited block are walked.
+    pub(crate) fn enters(&self, block: &Block) -> bool {
+        !block.flags.collapsed || self.enter_collapsed
+    }
+
+    /// The blocks of a stack that are visited, in order.
+    pub(crate) fn stack<'a>(&self, blocks: &'a [Block]) -> Vec<&'a Block> {
+        match self.disabled {
+            DisabledBlocks::Include => blocks.iter().collect(),
+            DisabledBlocks::Skip => blocks.iter().filter(|block| !block.flags.disabled).collect(),
+            DisabledBlocks::SkipWithNext => blocks.iter().take_while(|block| !block.flags.disabled).collect(),
+        }
+    }
+}
+
+impl Default for TraversalOptions {
+    fn default() -> Self {
+        Self {
+            disabled: DisabledBlocks::Include,
+            enter_collapsed: true,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_stack() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="a" id="1">
+                    <next><block type="b" id="2" disabled="true">
+                        <next><block type="c" id="3"></block></next>
+                    </block></next>
+                </block>
+            </xml>
+        "#).unwrap();
+        let blocks = &program.groups[0].blocks;
+        let ids = |options: TraversalOptions| -> Vec<&str> {
+            options.stack(blocks).iter().map(|block| block.id.as_str()).collect()
+        };
+
+        assert_eq!(ids(TraversalOptions::default()), vec!["1", "2", "3"]);
+        assert_eq!(ids(TraversalOptions::skip_disabled()), vec!["1", "3"]);
+        assert_eq!(ids(TraversalOptions { disabled: DisabledBlocks::SkipWithNext, ..TraversalOptions::default() }), vec!["1"]);
+    }
+}
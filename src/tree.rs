@@ -0,0 +1,459 @@
+//! A plain JSON tree of a program, for scripting layers and services in
+//! other languages. Unlike Blockly's own JSON format it keeps everything a
+//! `Program` holds, and its schema only changes with `TREE_VERSION`.
+//!
+//! ```text
+//! Program   { "version": 1, "stacks": [Stack], "variables": [Variable],
+//!             "comments": [Comment], "extras": [Element] }
+//! Stack     { "blocks": [Block], "shadow": bool }
+//! Block     { "type": string, "id": string, "x": int, "y": int,
+//!             "fields": { name: string | Block },
+//!             "variables": { field name: { "id": string, "type": string | null } },
+//!             "values": { name: { "block": Block, "shadow": bool } },
+//!             "statements": { name: Stack },
+//!             "mutation": { "attributes": { name: string }, "children": [Element] },
+//!             "flags": { "deletable": bool, "movable": bool, "editable": bool,
+//!                        "collapsed": bool, "disabled": bool },
+//!             "comment": { "text": string, "pinned": bool, "width": int, "height": int },
+//!             "data": string, "preserveSpace": [field name],
+//!             "extraAttributes": { name: string }, "extraElements": [Element] }
+//! Variable  { "name": string, "id": string, "type": string | null }
+//! Comment   { "id": string, "text": string, "x": int, "y": int, "width": int, "height": int }
+//! Element   { "name": string, "attributes": { name: string }, "children": [Element | string] }
+//! ```
+//!
+//! `to_value` leaves out keys whose value is absent or empty. `from_value`
+//! only requires a block's `type`, and reads anything else that is missing
+//! as absent, empty, or Blockly's default flags.
+
+use std::convert::TryFrom;
+
+use indexmap::IndexMap;
+use serde_json::{
+    Map,
+    Value,
+};
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    BlockComment,
+    BlockFlags,
+    FieldValue,
+    FieldVariable,
+    InputContent,
+    Mutation,
+    Variable,
+    WorkspaceComment,
+    RawElement,
+    RawNode,
+    MapHasher,
+    ParseError,
+};
+use super::json::{
+    as_array,
+    as_object,
+    get_bool,
+    get_string,
+    require_string,
+};
+
+/// The version of the tree schema, written as the program's `version`.
+pub const TREE_VERSION: u64 = 1;
+
+impl Program {
+    /// The program as a plain JSON tree; see the `tree` module's schema.
+    pub fn to_value(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("version".to_string(), Value::from(TREE_VERSION));
+        object.insert("stacks".to_string(), self.groups.iter().map(stack_to_value).collect());
+        insert_array(&mut object, "variables", self.variables.iter().map(variable_to_value).collect());
+        insert_array(&mut object, "comments", self.comments.iter().map(comment_to_value).collect());
+        insert_array(&mut object, "extras", self.extras.iter().map(element_to_value).collect());
+        Value::Object(object)
+    }
+
+    /// Read a tree written by `to_value`, or by anything else following its
+    /// schema.
+    pub fn from_value(value: &Value) -> Result<Program, ParseError> {
+        let object = as_object(value, "program")?;
+        if let Some(version) = object.get("version") {
+            if version.as_u64() != Some(TREE_VERSION) {
+                return Err(ParseError::UnexpectedStructure(format!("unsupported tree version {}", version)));
+            }
+        }
+        let mut program = Program::new();
+        for stack in get_array(object, "stacks")? {
+            program.groups.push(stack_from_value(stack)?);
+        }
+        for variable in get_array(object, "variables")? {
+            let variable = as_object(variable, "variable")?;
+            program.variables.push(Variable {
+                name: require_string(variable, "variable", "name")?,
+                id: get_string(variable, "id").unwrap_or_default(),
+                var_type: get_string(variable, "type"),
+            });
+        }
+        for comment in get_array(object, "comments")? {
+            let comment = as_object(comment, "comment")?;
+            program.comments.push(WorkspaceComment {
+                id: get_string(comment, "id").unwrap_or_default(),
+                text: get_string(comment, "text").unwrap_or_default(),
+                position: get_position(comment)?,
+                width: get_int(comment, "width")?,
+                height: get_int(comment, "height")?,
+            });
+        }
+        for element in get_array(object, "extras")? {
+            program.extras.push(element_from_value(element)?);
+        }
+        Ok(program)
+    }
+}
+
+fn insert_array(object: &mut Map<String, Value>, key: &str, items: Vec<Value>) {
+    if !items.is_empty() {
+        object.insert(key.to_string(), Value::Array(items));
+    }
+}
+
+fn insert_object(object: &mut Map<String, Value>, key: &str, entries: Map<String, Value>) {
+    if !entries.is_empty() {
+        object.insert(key.to_string(), Value::Object(entries));
+    }
+}
+
+fn strings_to_value(strings: &IndexMap<String, String, MapHasher>) -> Map<String, Value> {
+    strings.iter()
+        .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+        .collect()
+}
+
+fn stack_to_value(stack: &StatementBody) -> Value {
+    let mut object = Map::new();
+    object.insert("blocks".to_string(), stack.blocks.iter().map(block_to_value).collect());
+    object.insert("shadow".to_string(), Value::Bool(stack.shadow));
+    Value::Object(object)
+}
+
+fn block_to_value(block: &Block) -> Value {
+    let mut object = Map::new();
+    object.insert("type".to_string(), Value::from(block.block_type.as_str()));
+    object.insert("id".to_string(), Value::from(block.id.as_str()));
+    if let Some((x, y)) = block.position {
+        object.insert("x".to_string(), Value::from(x));
+        object.insert("y".to_string(), Value::from(y));
+    }
+
+    let fields = block.fields.iter()
+        .map(|(name, field)| {
+            let value = match *field {
+                FieldValue::SimpleField(ref text) => Value::from(text.as_str()),
+                FieldValue::ExpressionField(ref expression) => block_to_value(expression),
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    insert_object(&mut object, "fields", fields);
+
+    let variables = block.field_variables.iter()
+        .map(|(name, variable)| {
+            let mut entry = Map::new();
+            entry.insert("id".to_string(), Value::from(variable.id.as_str()));
+            entry.insert("type".to_string(), variable.var_type.as_deref().map_or(Value::Null, Value::from));
+            (name.clone(), Value::Object(entry))
+        })
+        .collect();
+    insert_object(&mut object, "variables", variables);
+
+    let values = block.values.iter()
+        .map(|(name, input)| {
+            let mut entry = Map::new();
+            entry.insert("block".to_string(), block_to_value(input.block()));
+            entry.insert("shadow".to_string(), Value::Bool(matches!(*input, InputContent::Shadow(_))));
+            (name.clone(), Value::Object(entry))
+        })
+        .collect();
+    insert_object(&mut object, "values", values);
+
+    let statements = block.statements.iter()
+        .map(|(name, stack)| (name.clone(), stack_to_value(stack)))
+        .collect();
+    insert_object(&mut object, "statements", statements);
+
+    if let Some(ref mutation) = block.mutation {
+        let mut entry = Map::new();
+        entry.insert("attributes".to_string(), Value::Object(strings_to_value(&mutation.attributes)));
+        entry.insert("children".to_string(), mutation.children.iter().map(element_to_value).collect());
+        object.insert("mutation".to_string(), Value::Object(entry));
+    }
+
+    let flags = block.flags;
+    let mut entry = Map::new();
+    entry.insert("deletable".to_string(), Value::Bool(flags.deletable));
+    entry.insert("movable".to_string(), Value::Bool(flags.movable));
+    entry.insert("editable".to_string(), Value::Bool(flags.editable));
+    entry.insert("collapsed".to_string(), Value::Bool(flags.collapsed));
+    entry.insert("disabled".to_string(), Value::Bool(flags.disabled));
+    object.insert("flags".to_string(), Value::Object(entry));
+
+    if let Some(ref comment) = block.comment {
+        let mut entry = Map::new();
+        entry.insert("text".to_string(), Value::from(comment.text.as_str()));
+        entry.insert("pinned".to_string(), Value::Bool(comment.pinned));
+        insert_int(&mut entry, "width", comment.width);
+        insert_int(&mut entry, "height", comment.height);
+        object.insert("comment".to_string(), Value::Object(entry));
+    }
+    if let Some(ref data) = block.data {
+        object.insert("data".to_string(), Value::from(data.as_str()));
+    }
+    insert_array(&mut object, "preserveSpace", block.preserve_space.iter().map(|name| Value::from(name.as_str())).collect());
+    insert_object(&mut object, "extraAttributes", strings_to_value(&block.extra_attributes));
+    insert_array(&mut object, "extraElements", block.extra_elements.iter().map(element_to_value).collect());
+    Value::Object(object)
+}
+
+fn insert_int(object: &mut Map<String, Value>, key: &str, value: Option<i32>) {
+    if let Some(value) = value {
+        object.insert(key.to_string(), Value::from(value));
+    }
+}
+
+fn variable_to_value(variable: &Variable) -> Value {
+    let mut object = Map::new();
+    object.insert("name".to_string(), Value::from(variable.name.as_str()));
+    object.insert("id".to_string(), Value::from(variable.id.as_str()));
+    object.insert("type".to_string(), variable.var_type.as_deref().map_or(Value::Null, Value::from));
+    Value::Object(object)
+}
+
+fn comment_to_value(comment: &WorkspaceComment) -> Value {
+    let mut object = Map::new();
+    object.insert("id".to_string(), Value::from(comment.id.as_str()));
+    object.insert("text".to_string(), Value::from(comment.text.as_str()));
+    if let Some((x, y)) = comment.position {
+        object.insert("x".to_string(), Value::from(x));
+        object.insert("y".to_string(), Value::from(y));
+    }
+    insert_int(&mut object, "width", comment.width);
+    insert_int(&mut object, "height", comment.height);
+    Value::Object(object)
+}
+
+fn element_to_value(element: &RawElement) -> Value {
+    let children = element.children.iter()
+        .map(|child| match *child {
+            RawNode::Element(ref child) => element_to_value(child),
+            RawNode::Text(ref text) => Value::from(text.as_str()),
+        })
+        .collect();
+    let mut object = Map::new();
+    object.insert("name".to_string(), Value::from(element.name.as_str()));
+    if let Some(ref namespace) = element.namespace {
+        object.insert("namespace".to_string(), Value::from(namespace.as_str()));
+    }
+    if let Some(ref prefix) = element.prefix {
+        object.insert("prefix".to_string(), Value::from(prefix.as_str()));
+    }
+    object.insert("attributes".to_string(), Value::Object(strings_to_value(&element.attributes)));
+    object.insert("children".to_string(), Value::Array(children));
+    Value::Object(object)
+}
+
+// Reading
+
+fn get_array<'a>(object: &'a Map<String, Value>, key: &str) -> Result<&'a [Value], ParseError> {
+    match object.get(key) {
+        Some(value) => Ok(as_array(value, key)?),
+        None => Ok(&[]),
+    }
+}
+
+fn get_entries<'a>(object: &'a Map<String, Value>, key: &str) -> Result<Option<&'a Map<String, Value>>, ParseError> {
+    object.get(key).map(|value| as_object(value, key)).transpose()
+}
+
+fn get_int(object: &Map<String, Value>, key: &str) -> Result<Option<i32>, ParseError> {
+    match object.get(key) {
+        Some(value) => value.as_i64()
+            .and_then(|value| i32::try_from(value).ok())
+            .map(Some)
+            .ok_or_else(|| ParseError::UnexpectedStructure(format!("expected {} to be an integer", key))),
+        None => Ok(None),
+    }
+}
+
+fn get_position(object: &Map<String, Value>) -> Result<Option<(i32, i32)>, ParseError> {
+    match (get_int(object, "x")?, get_int(object, "y")?) {
+        (Some(x), Some(y)) => Ok(Some((x, y))),
+        _ => Ok(None),
+    }
+}
+
+fn strings_from_value(object: &Map<String, Value>, what: &str) -> Result<IndexMap<String, String, MapHasher>, ParseError> {
+    object.iter()
+        .map(|(name, value)| {
+            let value = value.as_str()
+                .ok_or_else(|| ParseError::UnexpectedStructure(format!("expected {} {:?} to be a string", what, name)))?;
+            Ok((name.clone(), value.to_string()))
+        })
+        .collect()
+}
+
+fn stack_from_value(value: &Value) -> Result<StatementBody, ParseError> {
+    let object = as_object(value, "stack")?;
+    Ok(StatementBody {
+        blocks: get_array(object, "blocks")?.iter().map(block_from_value).collect::<Result<_, _>>()?,
+        shadow: get_bool(object, "shadow")?.unwrap_or(false),
+    })
+}
+
+fn block_from_value(value: &Value) -> Result<Block, ParseError> {
+    let object = as_object(value, "block")?;
+    let mut block = Block::new(require_string(object, "block", "type")?);
+    block.id = get_string(object, "id").unwrap_or_default();
+    block.position = get_position(object)?;
+
+    for (name, field) in get_entries(object, "fields")?.into_iter().flatten() {
+        let field = match *field {
+            Value::String(ref text) => FieldValue::SimpleField(text.clone()),
+            Value::Object(_) => FieldValue::ExpressionField(Box::new(block_from_value(field)?)),
+            _ => return Err(ParseError::UnexpectedStructure(format!("expected field {:?} to be a string or a block", name))),
+        };
+        block.fields.insert(name.clone(), field);
+    }
+    for (name, variable) in get_entries(object, "variables")?.into_iter().flatten() {
+        let variable = as_object(variable, "variable")?;
+        block.field_variables.insert(name.clone(), FieldVariable {
+            id: require_string(variable, "variable", "id")?,
+            var_type: get_string(variable, "type"),
+        });
+    }
+    for (name, input) in get_entries(object, "values")?.into_iter().flatten() {
+        let input = as_object(input, "value")?;
+        let inner = block_from_value(input.get("block").ok_or_else(|| {
+            ParseError::UnexpectedStructure(format!("value {:?} has no block", name))
+        })?)?;
+        let content = match get_bool(input, "shadow")? {
+            Some(true) => InputContent::Shadow(inner),
+            _ => InputContent::Block(inner),
+        };
+        block.values.insert(name.clone(), content);
+    }
+    for (name, stack) in get_entries(object, "statements")?.into_iter().flatten() {
+        block.statements.insert(name.clone(), stack_from_value(stack)?);
+    }
+
+    if let Some(mutation) = get_entries(object, "mutation")? {
+        block.mutation = Some(Mutation {
+            attributes: match get_entries(mutation, "attributes")? {
+                Some(attributes) => strings_from_value(attributes, "mutation attribute")?,
+                None => IndexMap::default(),
+            },
+            children: get_array(mutation, "children")?.iter().map(element_from_value).collect::<Result<_, _>>()?,
+        });
+    }
+    if let Some(flags) = get_entries(object, "flags")? {
+        let defaults = BlockFlags::default();
+        block.flags = BlockFlags {
+            deletable: get_bool(flags, "deletable")?.unwrap_or(defaults.deletable),
+            movable: get_bool(flags, "movable")?.unwrap_or(defaults.movable),
+            editable: get_bool(flags, "editable")?.unwrap_or(defaults.editable),
+            collapsed: get_bool(flags, "collapsed")?.unwrap_or(defaults.collapsed),
+            disabled: get_bool(flags, "disabled")?.unwrap_or(defaults.disabled),
+        };
+    }
+    if let Some(comment) = get_entries(object, "comment")? {
+        block.comment = Some(BlockComment {
+            text: get_string(comment, "text").unwrap_or_default(),
+            pinned: get_bool(comment, "pinned")?.unwrap_or(false),
+            width: get_int(comment, "width")?,
+            height: get_int(comment, "height")?,
+        });
+    }
+    block.data = get_string(object, "data");
+    for name in get_array(object, "preserveSpace")? {
+        match *name {
+            Value::String(ref name) => block.preserve_space.push(name.clone()),
+            _ => return Err(ParseError::UnexpectedStructure("expected `preserveSpace` to hold field names".to_string())),
+        }
+    }
+    if let Some(attributes) = get_entries(object, "extraAttributes")? {
+        block.extra_attributes = strings_from_value(attributes, "attribute")?;
+    }
+    for element in get_array(object, "extraElements")? {
+        block.extra_elements.push(element_from_value(element)?);
+    }
+    Ok(block)
+}
+
+fn element_from_value(value: &Value) -> Result<RawElement, ParseError> {
+    let object = as_object(value, "element")?;
+    let attributes = match get_entries(object, "attributes")? {
+        Some(attributes) => strings_from_value(attributes, "attribute")?,
+        None => IndexMap::default(),
+    };
+    let children = get_array(object, "children")?.iter()
+        .map(|child| match *child {
+            Value::String(ref text) => Ok(RawNode::Text(text.clone())),
+            _ => element_from_value(child).map(RawNode::Element),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(RawElement {
+        name: require_string(object, "element", "name")?,
+        namespace: get_string(object, "namespace"),
+        prefix: get_string(object, "prefix"),
+        attributes,
+        children,
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_tree_round_trip() {
+        let program = program_from_xml(r#"
+            <xml>
+                <variables><variable id="v1" type="Number">count</variable></variables>
+                <block type="controls_if" id="if" x="10" y="20" deletable="false">
+                    <mutation else="1"></mutation>
+                    <value name="IF0"><shadow type="logic_boolean" id="b"><field name="BOOL">TRUE</field></shadow></value>
+                    <statement name="DO0">
+                        <block type="variables_set" id="set">
+                            <field name="VAR" id="v1" variabletype="Number">count</field>
+                            <comment pinned="true" h="80" w="160">Reset</comment>
+                            <data>pin 3</data>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+        let value = program.to_value();
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["stacks"][0]["blocks"][0]["x"], 10);
+        assert_eq!(value["stacks"][0]["blocks"][0]["flags"]["deletable"], false);
+        assert_eq!(value["stacks"][0]["blocks"][0]["values"]["IF0"]["shadow"], true);
+        assert_eq!(value["stacks"][0]["blocks"][0]["statements"]["DO0"]["blocks"][0]["fields"]["VAR"], "count");
+        assert_eq!(Program::from_value(&value).unwrap(), program);
+    }
+
+    #[test]
+    fn test_tree_from_minimal_value() {
+        let value: Value = serde_json::from_str(r#"
+            { "stacks": [{ "blocks": [{ "type": "led_on", "fields": { "PIN": "3" } }] }] }
+        "#).unwrap();
+        let program = Program::from_value(&value).unwrap();
+        let block = &program.groups[0].blocks[0];
+        assert_eq!(block.field_str("PIN"), Ok("3"));
+        assert_eq!(block.flags, BlockFlags::default());
+
+        let future: Value = serde_json::from_str(r#"{ "version": 2 }"#).unwrap();
+        assert!(Program::from_value(&future).is_err());
+    }
+}
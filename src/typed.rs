@@ -0,0 +1,265 @@
+use super::{
+    Block,
+    BlockRegistry,
+    FieldError,
+    FieldKind,
+    FieldValue,
+    ParseError,
+    ParserOptions,
+    Span,
+};
+
+/// A field's text converted to the value it stands for.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum TypedField {
+    Number(f64),
+    /// Degrees.
+    Angle(f64),
+    Checkbox(bool),
+    Colour(Rgb),
+    /// The value of the selected option, not its label.
+    Dropdown(String),
+    /// A variable, by name. `id` is `None` when the field does not say which
+    /// variable it refers to, as in hand-written XML.
+    Variable { id: Option<String>, name: String },
+    Text(String),
+    Date(Date),
+}
+
+/// A colour from a `field_colour` field.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Parse a `#rrggbb` colour, as Blockly saves them. Upper case digits and
+    /// surrounding whitespace are accepted.
+    pub fn from_hex(text: &str) -> Option<Rgb> {
+        let hex = text.trim().strip_prefix('#')?;
+        if hex.len() != 6 || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+        Some(Rgb {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+
+    /// The colour as Blockly saves it, e.g. `#ff8000`.
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl FieldValue {
+    /// The field's text as a colour, or `None` if it is not a `#rrggbb`
+    /// colour or the field holds a block.
+    pub fn as_rgb(&self) -> Option<Rgb> {
+        match *self {
+            FieldValue::SimpleField(ref text) => Rgb::from_hex(text),
+            FieldValue::ExpressionField(_) => None,
+        }
+    }
+}
+
+/// A calendar date, as saved by the `field_date` plugin (`YYYY-MM-DD`).
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl TypedField {
+    /// Convert a field's text according to its definition. Labels and field
+    /// types this crate does not know are `Text`, except `field_date`.
+    pub fn parse(kind: &FieldKind, text: &str) -> Option<TypedField> {
+        let field = match *kind {
+            FieldKind::Number { .. } => TypedField::Number(text.trim().parse().ok()?),
+            FieldKind::Angle => TypedField::Angle(text.trim().parse().ok()?),
+            FieldKind::Checkbox => TypedField::Checkbox(parse_bool(text)?),
+            FieldKind::Colour => TypedField::Colour(Rgb::from_hex(text)?),
+            FieldKind::Dropdown(_) => TypedField::Dropdown(text.to_string()),
+            FieldKind::Variable { .. } => TypedField::Variable { id: None, name: text.to_string() },
+            FieldKind::Other(ref field_type) if field_type == "field_date" => TypedField::Date(parse_date(text)?),
+            FieldKind::Text | FieldKind::Label | FieldKind::Other(_) => TypedField::Text(text.to_string()),
+        };
+        Some(field)
+    }
+
+    /// Guess a field's type from its text alone: `TRUE` and `FALSE` are
+    /// checkboxes, `#rrggbb` colours, `YYYY-MM-DD` dates, and anything that
+    /// parses as a number a number. Angles, dropdowns and variables cannot be
+    /// told apart from numbers and text this way.
+    pub fn infer(text: &str) -> TypedField {
+        if let Some(value) = parse_bool(text) {
+            return TypedField::Checkbox(value);
+        }
+        if let Some(rgb) = Rgb::from_hex(text) {
+            return TypedField::Colour(rgb);
+        }
+        if let Some(date) = parse_date(text) {
+            return TypedField::Date(date);
+        }
+        match text.trim().parse::<f64>() {
+            Ok(number) if number.is_finite() => TypedField::Number(number),
+            _ => TypedField::Text(text.to_string()),
+        }
+    }
+
+    fn expected(kind: &FieldKind) -> &'static str {
+        match *kind {
+            FieldKind::Number { .. } | FieldKind::Angle => "a number",
+            FieldKind::Checkbox => "TRUE or FALSE",
+            FieldKind::Colour => "a colour like #ff0000",
+            _ => "a date like 2024-01-31",
+        }
+    }
+}
+
+impl Block {
+    /// A text field converted according to the block's definition in
+    /// `registry`, or by `TypedField::infer` when the registry has no
+    /// definition for the block or the field. Fields with a variable id are
+    /// always variables.
+    pub fn typed_field(&self, name: &str, registry: &BlockRegistry) -> Result<TypedField, FieldError> {
+        let text = self.field_str(name)?;
+        let kind = registry.get(&self.block_type)
+            .and_then(|definition| definition.field(name))
+            .map(|field| &field.kind);
+        if let Some(variable) = self.field_variables.get(name) {
+            return Ok(TypedField::Variable {
+                id: Some(variable.id.clone()),
+                name: text.to_string(),
+            });
+        }
+        match kind {
+            Some(kind) => TypedField::parse(kind, text).ok_or_else(|| FieldError::Invalid {
+                field: name.to_string(),
+                value: text.to_string(),
+                expected: TypedField::expected(kind),
+            }),
+            None => Ok(TypedField::infer(text)),
+        }
+    }
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim() {
+        "TRUE" | "true" => Some(true),
+        "FALSE" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+// Fails on fields listed in `ParserOptions::colour_fields` that do not hold
+// a colour. `span` is the field element's.
+pub(crate) fn check_colour_field(name: &str, text: &str, options: &ParserOptions, span: Option<Span>) -> Result<(), ParseError> {
+    if options.colour_fields.iter().any(|field| field == name) && Rgb::from_hex(text).is_none() {
+        return Err(ParseError::InvalidField {
+            field: name.to_string(),
+            value: text.to_string(),
+            expected: "a colour like #ff0000",
+            span,
+        });
+    }
+    Ok(())
+}
+
+fn parse_date(text: &str) -> Option<Date> {
+    let mut parts = text.trim().splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let date = Date {
+        year: year.parse().ok()?,
+        month: month.parse().ok()?,
+        day: day.parse().ok()?,
+    };
+    if date.month < 1 || date.month > 12 || date.day < 1 || date.day > 31 {
+        return None;
+    }
+    Some(date)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        program_from_xml,
+        program_from_xml_with_options,
+        BlockBuilder,
+    };
+
+    #[test]
+    fn test_infer() {
+        assert_eq!(TypedField::infer("300"), TypedField::Number(300.0));
+        assert_eq!(TypedField::infer("TRUE"), TypedField::Checkbox(true));
+        assert_eq!(TypedField::infer("#ff8000"), TypedField::Colour(Rgb { r: 255, g: 128, b: 0 }));
+        assert_eq!(TypedField::infer("2024-02-29"), TypedField::Date(Date { year: 2024, month: 2, day: 29 }));
+        assert_eq!(TypedField::infer("kitchen"), TypedField::Text("kitchen".to_string()));
+        assert_eq!(TypedField::infer("NaN"), TypedField::Text("NaN".to_string()));
+    }
+
+    #[test]
+    fn test_typed_field_from_definition() {
+        let registry = BlockRegistry::from_json(r#"{
+            "type": "led_on",
+            "message0": "%1 %2 %3 %4",
+            "args0": [
+                { "type": "field_number", "name": "TIME" },
+                { "type": "field_angle", "name": "HEADING" },
+                { "type": "field_dropdown", "name": "PIN", "options": [["one", "1"], ["two", "2"]] },
+                { "type": "field_variable", "name": "VAR" }
+            ]
+        }"#).unwrap();
+        let block = BlockBuilder::new("led_on")
+            .field("TIME", "fast")
+            .field("HEADING", "90")
+            .field("PIN", "1")
+            .field("VAR", "count")
+            .build();
+
+        assert_eq!(block.typed_field("HEADING", &registry), Ok(TypedField::Angle(90.0)));
+        assert_eq!(block.typed_field("PIN", &registry), Ok(TypedField::Dropdown("1".to_string())));
+        assert_eq!(block.typed_field("VAR", &registry), Ok(TypedField::Variable { id: None, name: "count".to_string() }));
+        assert_eq!(block.typed_field("TIME", &registry), Err(FieldError::Invalid {
+            field: "TIME".to_string(),
+            value: "fast".to_string(),
+            expected: "a number",
+        }));
+        assert_eq!(block.typed_field("MISSING", &registry), Err(FieldError::Missing("MISSING".to_string())));
+    }
+
+    #[test]
+    fn test_colours() {
+        let rgb = Rgb::from_hex("#FF8000").unwrap();
+        assert_eq!(rgb, Rgb { r: 255, g: 128, b: 0 });
+        assert_eq!(rgb.to_hex(), "#ff8000");
+        assert_eq!(Rgb::from_hex("ff8000"), None);
+        assert_eq!(Rgb::from_hex("#ff80"), None);
+        assert_eq!(FieldValue::SimpleField("#000000".to_string()).as_rgb(), Some(Rgb { r: 0, g: 0, b: 0 }));
+
+        let block = BlockBuilder::new("colour_picker").field("COLOUR", "#00ff00").build();
+        assert_eq!(block.field_colour("COLOUR"), Ok(Rgb { r: 0, g: 255, b: 0 }));
+
+        let xml: &str = r#"<xml><block type="colour_picker" id="a"><field name="COLOUR">red</field></block></xml>"#;
+        assert!(program_from_xml(xml).is_ok());
+        assert_eq!(program_from_xml_with_options(xml, &ParserOptions::strict()).unwrap_err(), ParseError::InvalidField {
+            field: "COLOUR".to_string(),
+            value: "red".to_string(),
+            expected: "a colour like #ff0000",
+            span: None,
+        });
+    }
+}
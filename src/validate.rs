@@ -0,0 +1,209 @@
+use std::fmt;
+
+use super::{
+    Program,
+    Block,
+    FieldValue,
+    BlockRegistry,
+    FieldKind,
+    InputKind,
+};
+
+/// A problem found by `Program::validate`. Blocks are referred to by id.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum ValidationIssue {
+    /// The registry has no definition for the block's type.
+    UnknownBlockType { id: String, block_type: String },
+    /// The definition has a field the block does not.
+    MissingField { id: String, field: String },
+    /// A dropdown field holds a value that is not one of its options.
+    InvalidFieldValue { id: String, field: String, value: String },
+    /// Blocks are attached to an input as the wrong kind, e.g. a statement
+    /// stack in what the definition says is a value input.
+    WrongInputKind { id: String, input: String, expected: InputKind },
+}
+
+impl ValidationIssue {
+    /// The id of the block the issue is about.
+    pub fn id(&self) -> &str {
+        match *self {
+            ValidationIssue::UnknownBlockType { ref id, .. } |
+            ValidationIssue::MissingField { ref id, .. } |
+            ValidationIssue::InvalidFieldValue { ref id, .. } |
+            ValidationIssue::WrongInputKind { ref id, .. } => id,
+        }
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationIssue::UnknownBlockType { ref id, ref block_type } => {
+                write!(f, "block {:?} has unknown type `{}`", id, block_type)
+            },
+            ValidationIssue::MissingField { ref id, ref field } => {
+                write!(f, "block {:?} is missing field `{}`", id, field)
+            },
+            ValidationIssue::InvalidFieldValue { ref id, ref field, ref value } => {
+                write!(f, "field `{}` of block {:?} has invalid value {:?}", field, id, value)
+            },
+            ValidationIssue::WrongInputKind { ref id, ref input, expected } => {
+                let expected = match expected {
+                    InputKind::Value => "value",
+                    InputKind::Statement => "statement",
+                };
+                write!(f, "input `{}` of block {:?} should be a {} input", input, id, expected)
+            },
+        }
+    }
+}
+
+impl Program {
+    /// Check every block against its definition in `registry`. Issues are
+    /// listed in document order.
+    pub fn validate(&self, registry: &BlockRegistry) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        for block in self.iter_blocks() {
+            validate_block(block, registry, &mut issues);
+        }
+        issues
+    }
+}
+
+fn validate_block(block: &Block, registry: &BlockRegistry, issues: &mut Vec<ValidationIssue>) {
+    let definition = match registry.get(&block.block_type) {
+        Some(definition) => definition,
+        None => {
+            issues.push(ValidationIssue::UnknownBlockType {
+                id: block.id.clone(),
+                block_type: block.block_type.clone(),
+            });
+            return;
+        }
+    };
+
+    for field in definition.fields.iter() {
+        let value = match block.fields.get(&field.name) {
+            Some(FieldValue::SimpleField(value)) => value,
+            Some(FieldValue::ExpressionField(_)) => continue,
+            None => {
+                issues.push(ValidationIssue::MissingField {
+                    id: block.id.clone(),
+                    field: field.name.clone(),
+                });
+                continue;
+            }
+        };
+        if let FieldKind::Dropdown(ref options) = field.kind {
+            if !options.iter().any(|option| option.value == *value) {
+                issues.push(ValidationIssue::InvalidFieldValue {
+                    id: block.id.clone(),
+                    field: field.name.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+    }
+
+    // Sorted so the issues come out in a stable order
+    let mut statement_names: Vec<&String> = block.statements.keys().collect();
+    statement_names.sort();
+    for name in statement_names {
+        if let Some(input) = definition.input(name) {
+            if input.kind == InputKind::Value {
+                issues.push(wrong_input_kind(block, name, InputKind::Value));
+            }
+        }
+    }
+    let mut value_names: Vec<&String> = block.values.keys().collect();
+    value_names.sort();
+    for name in value_names {
+        if let Some(input) = definition.input(name) {
+            if input.kind == InputKind::Statement {
+                issues.push(wrong_input_kind(block, name, InputKind::Statement));
+            }
+        }
+    }
+}
+
+fn wrong_input_kind(block: &Block, input: &str, expected: InputKind) -> ValidationIssue {
+    ValidationIssue::WrongInputKind {
+        id: block.id.clone(),
+        input: input.to_string(),
+        expected,
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    #[test]
+    fn test_validate() {
+        let registry = BlockRegistry::from_json(r#"
+            [
+                {
+                    "type": "main_loop",
+                    "message0": "forever %1",
+                    "args0": [{ "type": "input_statement", "name": "BODY" }]
+                },
+                {
+                    "type": "led_on",
+                    "message0": "turn %1 LED on for %2 ms",
+                    "args0": [
+                        { "type": "field_dropdown", "name": "COLOUR", "options": [["red", "RED"], ["green", "GREEN"]] },
+                        { "type": "field_number", "name": "TIME" }
+                    ],
+                    "previousStatement": null,
+                    "nextStatement": null
+                },
+                {
+                    "type": "set_brightness",
+                    "message0": "set brightness %1",
+                    "args0": [{ "type": "input_value", "name": "LEVEL", "check": "Number" }]
+                }
+            ]
+        "#).unwrap();
+
+        let program = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="main">
+                    <statement name="BODY">
+                        <block type="led_on" id="on">
+                            <field name="COLOUR">BLUE</field>
+                            <next>
+                                <block type="buzzer" id="buzz"></block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+                <block type="set_brightness" id="bright">
+                    <statement name="LEVEL">
+                        <block type="led_on" id="on2">
+                            <field name="COLOUR">RED</field>
+                            <field name="TIME">100</field>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        assert_eq!(program.validate(&registry), vec![
+            ValidationIssue::InvalidFieldValue {
+                id: "on".to_string(),
+                field: "COLOUR".to_string(),
+                value: "BLUE".to_string(),
+            },
+            ValidationIssue::MissingField { id: "on".to_string(), field: "TIME".to_string() },
+            ValidationIssue::UnknownBlockType { id: "buzz".to_string(), block_type: "buzzer".to_string() },
+            ValidationIssue::WrongInputKind {
+                id: "bright".to_string(),
+                input: "LEVEL".to_string(),
+                expected: InputKind::Value,
+            },
+        ]);
+        assert_eq!(program.validate(&registry)[3].to_string(), r#"input `LEVEL` of block "bright" should be a value input"#);
+    }
+}
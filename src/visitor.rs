@@ -0,0 +1,191 @@
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    FieldValue,
+    InputContent,
+    TraversalOptions,
+};
+
+/// Callbacks for walking a program. Every method has an empty default, so an
+/// implementation only overrides the hooks it needs. `visit_*` hooks run
+/// before the children are walked (pre-order) and `leave_*` hooks after
+/// (post-order).
+pub trait Visitor {
+    /// A top-level stack of blocks.
+    fn visit_group(&mut self, _group: &StatementBody) {}
+    fn leave_group(&mut self, _group: &StatementBody) {}
+
+    fn visit_block(&mut self, _block: &Block) {}
+    fn leave_block(&mut self, _block: &Block) {}
+
+    fn visit_field(&mut self, _block: &Block, _name: &str, _value: &FieldValue) {}
+
+    fn visit_value(&mut self, _block: &Block, _name: &str, _input: &InputContent) {}
+    fn leave_value(&mut self, _block: &Block, _name: &str, _input: &InputContent) {}
+
+    fn visit_statement(&mut self, _block: &Block, _name: &str, _body: &StatementBody) {}
+    fn leave_statement(&mut self, _block: &Block, _name: &str, _body: &StatementBody) {}
+}
+
+impl Program {
+    pub fn accept<V: Visitor>(&self, visitor: &mut V) {
+        self.accept_with(visitor, &TraversalOptions::default());
+    }
+
+    /// Like `accept`, skipping disabled blocks or the insides of collapsed
+    /// blocks as `options` say. The field and input hooks still run for
+    /// inputs whose block is skipped.
+    pub fn accept_with<V: Visitor>(&self, visitor: &mut V, options: &TraversalOptions) {
+        for group in self.groups.iter() {
+            visitor.visit_group(group);
+            group.accept_with(visitor, options);
+            visitor.leave_group(group);
+        }
+    }
+}
+
+impl StatementBody {
+    pub fn accept<V: Visitor>(&self, visitor: &mut V) {
+        self.accept_with(visitor, &TraversalOptions::default());
+    }
+
+    pub fn accept_with<V: Visitor>(&self, visitor: &mut V, options: &TraversalOptions) {
+        for block in options.stack(&self.blocks) {
+            block.accept_with(visitor, options);
+        }
+    }
+}
+
+impl Block {
+    /// Walk this block and everything nested inside it: fields, then value
+    /// inputs, then statement inputs.
+    pub fn accept<V: Visitor>(&self, visitor: &mut V) {
+        self.accept_with(visitor, &TraversalOptions::default());
+    }
+
+    /// Like `accept`, with `options` applied to the blocks nested inside.
+    /// This block is visited even if it is disabled.
+    pub fn accept_with<V: Visitor>(&self, visitor: &mut V, options: &TraversalOptions) {
+        visitor.visit_block(self);
+        if !options.enters(self) {
+            visitor.leave_block(self);
+            return;
+        }
+
+        for (name, value) in self.fields.iter() {
+            visitor.visit_field(self, name, value);
+            if let FieldValue::ExpressionField(ref expression) = *value {
+                if options.includes(expression) {
+                    expression.accept_with(visitor, options);
+                }
+            }
+        }
+
+        for (name, input) in self.values.iter() {
+            visitor.visit_value(self, name, input);
+            if options.includes(input.block()) {
+                input.block().accept_with(visitor, options);
+            }
+            visitor.leave_value(self, name, input);
+        }
+
+        for (name, body) in self.statements.iter() {
+            visitor.visit_statement(self, name, body);
+            body.accept_with(visitor, options);
+            visitor.leave_statement(self, name, body);
+        }
+
+        visitor.leave_block(self);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    struct Outline {
+        lines: Vec<String>,
+        depth: usize,
+    }
+
+    impl Visitor for Outline {
+        fn visit_block(&mut self, block: &Block) {
+            self.lines.push(format!("{}{}", "  ".repeat(self.depth), block.block_type));
+        }
+
+        fn visit_field(&mut self, _block: &Block, name: &str, value: &FieldValue) {
+            if let FieldValue::SimpleField(ref text) = *value {
+                self.lines.push(format!("{}{}={}", "  ".repeat(self.depth + 1), name, text));
+            }
+        }
+
+        fn visit_statement(&mut self, _block: &Block, _name: &str, _body: &StatementBody) {
+            self.depth += 1;
+        }
+
+        fn leave_statement(&mut self, _block: &Block, _name: &str, _body: &StatementBody) {
+            self.depth -= 1;
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="1">
+                    <statement name="BODY">
+                        <block type="led_on" id="2">
+                            <field name="TIME">300</field>
+                            <next>
+                                <block type="led_off" id="3">
+                                    <field name="TIME">100</field>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#;
+        let program = program_from_xml(xml).unwrap();
+
+        let mut outline = Outline { lines: Vec::new(), depth: 0 };
+        program.accept(&mut outline);
+        assert_eq!(outline.lines, vec![
+            "main_loop",
+            "  led_on",
+            "    TIME=300",
+            "  led_off",
+            "    TIME=100",
+        ]);
+        assert_eq!(outline.depth, 0);
+    }
+
+    #[test]
+    fn test_visitor_skips_disabled() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="main_loop" id="1">
+                    <statement name="BODY">
+                        <block type="led_on" id="2" disabled="true">
+                            <field name="TIME">300</field>
+                            <next>
+                                <block type="led_off" id="3"><field name="TIME">100</field></block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#).unwrap();
+
+        let mut outline = Outline { lines: Vec::new(), depth: 0 };
+        program.accept_with(&mut outline, &TraversalOptions::skip_disabled());
+        assert_eq!(outline.lines, vec![
+            "main_loop",
+            "  led_off",
+            "    TIME=100",
+        ]);
+    }
+}
@@ -0,0 +1,16 @@
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use super::program_from_xml;
+
+/// Parse a workspace's XML into a plain JavaScript object with the same shape
+/// as the serde serialization of `Program`. Throws an `Error` with the
+/// `ParseError` message if the XML cannot be parsed.
+#[wasm_bindgen]
+pub fn parse_workspace(xml: &str) -> Result<JsValue, JsError> {
+    let program = program_from_xml(xml).map_err(|error| JsError::new(&error.to_string()))?;
+
+    // Maps become plain objects rather than JavaScript `Map`s
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    program.serialize(&serializer).map_err(|error| JsError::new(&error.to_string()))
+}
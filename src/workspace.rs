@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+use indexmap::IndexMap;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    BlockComment,
+    BlockFlags,
+    Extensions,
+    FieldValue,
+    FieldVariable,
+    InputContent,
+    MapHasher,
+    Mutation,
+    RawElement,
+    Variable,
+    WorkspaceComment,
+};
+
+/// A handle to a block in a `Workspace`. Handles stay valid until the block
+/// is removed, and are never reused.
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct BlockId(usize);
+
+/// How a block is attached to its parent.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Link {
+    /// Below the parent in a stack.
+    Next,
+    Field(String),
+    Value(String),
+    /// The first block of the parent's statement input.
+    Statement(String),
+}
+
+/// A block in a `Workspace`: the parts of a `Block` other than the blocks
+/// attached to it, which are referred to by handle.
+#[derive(PartialEq, Eq, Debug)]
+pub struct BlockData {
+    pub block_type: String,
+    pub id: String,
+    /// Text fields. Fields holding blocks are in `field_blocks`.
+    pub fields: IndexMap<String, String, MapHasher>,
+    pub mutation: Option<Mutation>,
+    pub position: Option<(i32, i32)>,
+    pub flags: BlockFlags,
+    pub comment: Option<BlockComment>,
+    pub data: Option<String>,
+    pub extra_attributes: IndexMap<String, String, MapHasher>,
+    pub extra_elements: Vec<RawElement>,
+    pub field_variables: IndexMap<String, FieldVariable, MapHasher>,
+    pub preserve_space: Vec<String>,
+    pub extensions: Extensions,
+    /// Whether the block is a `<shadow>`.
+    pub shadow: bool,
+    parent: Option<(BlockId, Link)>,
+    next: Option<BlockId>,
+    field_blocks: IndexMap<String, BlockId>,
+    values: IndexMap<String, BlockId>,
+    statements: IndexMap<String, BlockId>,
+}
+
+impl BlockData {
+    /// The block this one is attached to, and how.
+    pub fn parent(&self) -> Option<(BlockId, &Link)> {
+        self.parent.as_ref().map(|&(parent, ref link)| (parent, link))
+    }
+
+    pub fn next(&self) -> Option<BlockId> {
+        self.next
+    }
+
+    pub fn field_block(&self, name: &str) -> Option<BlockId> {
+        self.field_blocks.get(name).cloned()
+    }
+
+    pub fn value(&self, name: &str) -> Option<BlockId> {
+        self.values.get(name).cloned()
+    }
+
+    /// The first block of a statement input.
+    pub fn statement(&self, name: &str) -> Option<BlockId> {
+        self.statements.get(name).cloned()
+    }
+}
+
+/// A program stored as a flat arena of blocks linked by `BlockId` handles,
+/// rather than as a tree. Looking up a block by id or finding its parent is
+/// O(1), and moving or removing blocks does not rebuild the tree.
+/// `to_program` projects it back to the usual tree.
+#[derive(Debug, Default)]
+pub struct Workspace {
+    blocks: Vec<Option<BlockData>>,
+    // The first block of each top-level stack
+    roots: Vec<BlockId>,
+    // Blocks with an id, by the first handle given it
+    by_id: HashMap<String, BlockId>,
+    // Blocks not yet removed, since ids may be missing or repeated
+    count: usize,
+    pub variables: Vec<Variable>,
+    pub comments: Vec<WorkspaceComment>,
+    pub extras: Vec<RawElement>,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, handle: BlockId) -> Option<&BlockData> {
+        self.blocks.get(handle.0)?.as_ref()
+    }
+
+    /// Edit a block's own data. Use `detach` and `remove` to change how blocks
+    /// are connected.
+    pub fn get_mut(&mut self, handle: BlockId) -> Option<&mut BlockData> {
+        self.blocks.get_mut(handle.0)?.as_mut()
+    }
+
+    /// The handle of the block with the given Blockly id.
+    pub fn find(&self, id: &str) -> Option<BlockId> {
+        self.by_id.get(id).cloned()
+    }
+
+    /// The first block of each top-level stack.
+    pub fn roots(&self) -> &[BlockId] {
+        &self.roots
+    }
+
+    pub fn parent(&self, handle: BlockId) -> Option<BlockId> {
+        self.get(handle)?.parent().map(|(parent, _)| parent)
+    }
+
+    /// The block with the given handle and the blocks below it in its stack.
+    pub fn stack(&self, first: BlockId) -> Vec<BlockId> {
+        let mut stack = Vec::new();
+        let mut next = Some(first);
+        while let Some(handle) = next {
+            stack.push(handle);
+            next = self.get(handle).and_then(|block| block.next);
+        }
+        stack
+    }
+
+    /// The number of blocks.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Every block handle, in no particular order.
+    pub fn handles(&self) -> impl Iterator<Item = BlockId> + '_ {
+        self.blocks.iter()
+            .enumerate()
+            .filter(|(_, block)| block.is_some())
+            .map(|(index, _)| BlockId(index))
+    }
+
+    /// Disconnect a block, with the blocks below it, from its parent and make
+    /// it a new top-level stack at `position`.
+    pub fn detach(&mut self, handle: BlockId, position: Option<(i32, i32)>) {
+        self.unlink(handle);
+        if let Some(block) = self.get_mut(handle) {
+            block.position = position;
+            self.roots.push(handle);
+        }
+    }
+
+    /// Remove a block and the blocks nested in it. The blocks below it in its
+    /// stack move up to take its place. Returns false if there is no such
+    /// block.
+    pub fn remove(&mut self, handle: BlockId) -> bool {
+        let next = match self.get(handle) {
+            Some(block) => block.next,
+            None => return false,
+        };
+        let parent = self.get(handle).and_then(|block| block.parent.clone());
+        let root_index = self.roots.iter().position(|&root| root == handle);
+        self.unlink(handle);
+
+        if let Some(next) = next {
+            self.get_mut(handle).expect("block").next = None;
+            let position = self.get(handle).and_then(|block| block.position);
+            match parent {
+                Some((parent, link)) => self.link(parent, link, next),
+                None => {
+                    let next_block = self.get_mut(next).expect("next block");
+                    next_block.parent = None;
+                    next_block.position = position;
+                    self.roots.insert(root_index.unwrap_or(self.roots.len()), next);
+                }
+            }
+        }
+
+        let mut pending = vec![handle];
+        while let Some(current) = pending.pop() {
+            if let Some(block) = self.blocks[current.0].take() {
+                self.count -= 1;
+                if self.by_id.get(&block.id) == Some(&current) {
+                    self.by_id.remove(&block.id);
+                }
+                pending.extend(block.field_blocks.values());
+                pending.extend(block.values.values());
+                pending.extend(block.statements.values());
+                pending.extend(block.next);
+            }
+        }
+        true
+    }
+
+    /// Build the tree representation.
+    pub fn to_program(&self) -> Program {
+        let mut program = Program::new();
+        program.groups = self.roots.iter().map(|&root| self.build_stack(root)).collect();
+        program.variables = self.variables.clone();
+        program.comments = self.comments.clone();
+        program.extras = self.extras.clone();
+        program
+    }
+
+    // Disconnect a block from its parent or from the list of roots
+    fn unlink(&mut self, handle: BlockId) {
+        let parent = match self.get_mut(handle) {
+            Some(block) => block.parent.take(),
+            None => return,
+        };
+        match parent {
+            Some((parent, link)) => {
+                let parent = self.get_mut(parent).expect("parent block");
+                match link {
+                    Link::Next => { parent.next = None; },
+                    Link::Field(name) => { parent.field_blocks.shift_remove(&name); },
+                    Link::Value(name) => { parent.values.shift_remove(&name); },
+                    Link::Statement(name) => { parent.statements.shift_remove(&name); },
+                }
+            },
+            None => self.roots.retain(|&root| root != handle),
+        }
+    }
+
+    fn link(&mut self, parent: BlockId, link: Link, child: BlockId) {
+        let parent_block = self.get_mut(parent).expect("parent block");
+        match link {
+            Link::Next => parent_block.next = Some(child),
+            Link::Field(ref name) => { parent_block.field_blocks.insert(name.clone(), child); },
+            Link::Value(ref name) => { parent_block.values.insert(name.clone(), child); },
+            Link::Statement(ref name) => { parent_block.statements.insert(name.clone(), child); },
+        }
+        self.get_mut(child).expect("child block").parent = Some((parent, link));
+    }
+
+    fn insert_stack(&mut self, body: StatementBody, parent: Option<(BlockId, Link)>) -> Option<BlockId> {
+        let mut first = None;
+        let mut previous: Option<BlockId> = None;
+        for block in body.blocks {
+            let handle = self.insert_block(block, body.shadow);
+            match previous {
+                Some(previous) => self.link(previous, Link::Next, handle),
+                None => {
+                    first = Some(handle);
+                    if let Some((parent, ref link)) = parent {
+                        self.link(parent, link.clone(), handle);
+                    }
+                }
+            }
+            previous = Some(handle);
+        }
+        first
+    }
+
+    fn insert_block(&mut self, block: Block, shadow: bool) -> BlockId {
+        let handle = BlockId(self.blocks.len());
+        let mut fields = IndexMap::default();
+        let mut field_blocks = Vec::new();
+        for (name, value) in block.fields {
+            match value {
+                FieldValue::SimpleField(text) => { fields.insert(name, text); },
+                FieldValue::ExpressionField(inner) => field_blocks.push((name, *inner)),
+            }
+        }
+        if !block.id.is_empty() {
+            self.by_id.entry(block.id.clone()).or_insert(handle);
+        }
+        self.count += 1;
+        self.blocks.push(Some(BlockData {
+            block_type: block.block_type,
+            id: block.id,
+            fields,
+            mutation: block.mutation,
+            position: block.position,
+            flags: block.flags,
+            comment: block.comment,
+            data: block.data,
+            extra_attributes: block.extra_attributes,
+            extra_elements: block.extra_elements,
+            field_variables: block.field_variables,
+            preserve_space: block.preserve_space,
+            extensions: block.extensions,
+            shadow,
+            parent: None,
+            next: None,
+            field_blocks: IndexMap::new(),
+            values: IndexMap::new(),
+            statements: IndexMap::new(),
+        }));
+
+        for (name, inner) in field_blocks {
+            let child = self.insert_block(inner, false);
+            self.link(handle, Link::Field(name), child);
+        }
+        for (name, input) in block.values {
+            let (inner, shadow) = match input {
+                InputContent::Block(inner) => (inner, false),
+                InputContent::Shadow(inner) => (inner, true),
+            };
+            let child = self.insert_block(inner, shadow);
+            self.link(handle, Link::Value(name), child);
+        }
+        for (name, body) in block.statements {
+            self.insert_stack(body, Some((handle, Link::Statement(name))));
+        }
+        handle
+    }
+
+    fn build_stack(&self, first: BlockId) -> StatementBody {
+        let blocks: Vec<Block> = self.stack(first).into_iter().map(|handle| self.build_block(handle)).collect();
+        StatementBody {
+            shadow: self.get(first).is_some_and(|block| block.shadow),
+            blocks,
+        }
+    }
+
+    fn build_block(&self, handle: BlockId) -> Block {
+        let data = self.get(handle).expect("block");
+        let mut block = Block::new(data.block_type.clone());
+        block.id = data.id.clone();
+        for (name, text) in data.fields.iter() {
+            block.fields.insert(name.clone(), FieldValue::SimpleField(text.clone()));
+        }
+        for (name, &child) in data.field_blocks.iter() {
+            block.fields.insert(name.clone(), FieldValue::ExpressionField(Box::new(self.build_block(child))));
+        }
+        for (name, &child) in data.values.iter() {
+            let inner = self.build_block(child);
+            let content = if self.get(child).is_some_and(|child| child.shadow) {
+                InputContent::Shadow(inner)
+            } else {
+                InputContent::Block(inner)
+            };
+            block.values.insert(name.clone(), content);
+        }
+        for (name, &first) in data.statements.iter() {
+            block.statements.insert(name.clone(), self.build_stack(first));
+        }
+        block.mutation = data.mutation.clone();
+        block.position = data.position;
+        block.flags = data.flags;
+        block.comment = data.comment.clone();
+        block.data = data.data.clone();
+        block.extra_attributes = data.extra_attributes.clone();
+        block.extra_elements = data.extra_elements.clone();
+        block.field_variables = data.field_variables.clone();
+        block.preserve_space = data.preserve_space.clone();
+        block.extensions = data.extensions.clone();
+        block
+    }
+}
+
+impl From<Program> for Workspace {
+    fn from(program: Program) -> Self {
+        let mut workspace = Workspace::new();
+        for group in program.groups {
+            if let Some(root) = workspace.insert_stack(group, None) {
+                workspace.roots.push(root);
+            }
+        }
+        workspace.variables = program.variables;
+        workspace.comments = program.comments;
+        workspace.extras = program.extras;
+        workspace
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::program_from_xml;
+
+    const XML: &str = r#"
+        <xml xmlns="https://developers.google.com/blockly/xml">
+            <variables><variable id="v1">count</variable></variables>
+            <block type="main_loop" id="main" x="10" y="20">
+                <statement name="BODY">
+                    <block type="led_on" id="on">
+                        <value name="BRIGHTNESS">
+                            <shadow type="math_number" id="num"><field name="NUM">80</field></shadow>
+                        </value>
+                        <next>
+                            <block type="led_off" id="off">
+                                <next><block type="wait" id="wait"></block></next>
+                            </block>
+                        </next>
+                    </block>
+                </statement>
+            </block>
+        </xml>
+    "#;
+
+    #[test]
+    fn test_round_trip() {
+        let program = program_from_xml(XML).unwrap();
+        let workspace = Workspace::from(program_from_xml(XML).unwrap());
+        assert_eq!(workspace.len(), 5);
+        assert!(workspace.to_program().eq_ignore_ids(&program));
+        assert_eq!(workspace.to_program().groups, program.groups);
+        assert_eq!(workspace.to_program().variables, program.variables);
+
+        let num = workspace.find("num").unwrap();
+        assert!(workspace.get(num).unwrap().shadow);
+        let on = workspace.parent(num).unwrap();
+        assert_eq!(workspace.get(on).unwrap().id, "on");
+        assert_eq!(workspace.get(num).unwrap().parent(), Some((on, &Link::Value("BRIGHTNESS".to_string()))));
+        let main = workspace.parent(on).unwrap();
+        assert_eq!(workspace.roots(), &[main]);
+        assert_eq!(workspace.stack(on).len(), 3);
+    }
+
+    #[test]
+    fn test_edits() {
+        let mut workspace = Workspace::from(program_from_xml(XML).unwrap());
+        let on = workspace.find("on").unwrap();
+        let wait = workspace.find("wait").unwrap();
+
+        workspace.detach(wait, Some((100, 100)));
+        assert!(workspace.remove(on));
+        assert_eq!(workspace.find("num"), None);
+        assert_eq!(workspace.len(), 3);
+
+        let program = workspace.to_program();
+        assert_eq!(program.groups.len(), 2);
+        let body = &program.groups[0].blocks[0].statements["BODY"];
+        assert_eq!(body.blocks.len(), 1);
+        assert_eq!(body.blocks[0].id, "off");
+        assert_eq!(program.groups[1].blocks[0].id, "wait");
+        assert_eq!(program.groups[1].blocks[0].position, Some((100, 100)));
+    }
+
+    #[test]
+    fn test_missing_and_duplicate_ids() {
+        let mut workspace = Workspace::from(program_from_xml(r#"
+            <xml>
+                <block type="a"></block>
+                <block type="b"></block>
+                <block type="c" id="x"></block>
+                <block type="d" id="x"></block>
+            </xml>
+        "#).unwrap());
+        assert_eq!(workspace.len(), 4);
+        assert_eq!(workspace.handles().count(), 4);
+        assert_eq!(workspace.find(""), None);
+
+        // Removing the second block with an id leaves the first findable
+        let roots = workspace.roots().to_vec();
+        let first = workspace.find("x").unwrap();
+        assert_eq!(first, roots[2]);
+        assert!(workspace.remove(roots[3]));
+        assert_eq!(workspace.find("x"), Some(first));
+        assert!(workspace.remove(roots[0]));
+        assert_eq!(workspace.len(), 2);
+    }
+}
@@ -0,0 +1,380 @@
+use std::fmt::Write;
+
+use indexmap::IndexMap;
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    BlockFlags,
+    FieldValue,
+    InputContent,
+    MapHasher,
+    Mutation,
+    RawElement,
+    RawNode,
+    RepeatedIdError,
+    BLOCKLY_NAMESPACE,
+};
+use super::ids::IdGenerator;
+
+/// Serialize a program as Blockly XML, in the compact form produced by
+/// `Blockly.Xml.domToText`. Fields, inputs and attributes are written in the
+/// order they were parsed or inserted. `Program::extras` and the extra
+/// attributes and elements kept by `ParserOptions::preserve_unknown` are
+/// written back out.
+pub fn program_to_xml(program: &Program) -> String {
+    let mut out = String::new();
+    write!(out, "<xml xmlns=\"{}\">", BLOCKLY_NAMESPACE).unwrap();
+    if !program.variables.is_empty() {
+        out.push_str("<variables>");
+        for variable in &program.variables {
+            out.push_str("<variable");
+            if let Some(ref var_type) = variable.var_type {
+                write_attribute(&mut out, "type", var_type);
+            }
+            write_attribute(&mut out, "id", &variable.id);
+            out.push('>');
+            write_text(&mut out, &variable.name);
+            out.push_str("</variable>");
+        }
+        out.push_str("</variables>");
+    }
+    for group in &program.groups {
+        write_stack(&mut out, group);
+    }
+    for comment in &program.comments {
+        out.push_str("<comment");
+        write_attribute(&mut out, "id", &comment.id);
+        if let Some((x, y)) = comment.position {
+            write_attribute(&mut out, "x", &x.to_string());
+            write_attribute(&mut out, "y", &y.to_string());
+        }
+        write_size(&mut out, comment.width, comment.height);
+        out.push('>');
+        write_text(&mut out, &comment.text);
+        out.push_str("</comment>");
+    }
+    for el in &program.extras {
+        write_raw(&mut out, el);
+    }
+    out.push_str("</xml>");
+    out
+}
+
+/// Like `program_to_xml`, writing ids from `ids` for the blocks and
+/// variables that have none.
+pub fn program_to_xml_with_ids<G: IdGenerator>(program: &Program, ids: &mut G) -> Result<String, RepeatedIdError> {
+    let mut program = program.clone();
+    program.fill_missing_ids(ids)?;
+    Ok(program_to_xml(&program))
+}
+
+// Blocks joined with `next` nest inside each other, so their end tags are
+// written once the whole stack is
+fn write_stack(out: &mut String, body: &StatementBody) {
+    let tag = if body.shadow { "shadow" } else { "block" };
+    for (index, block) in body.blocks.iter().enumerate() {
+        if index > 0 {
+            out.push_str("<next>");
+        }
+        write_block_start(out, tag, block);
+    }
+    for index in (0..body.blocks.len()).rev() {
+        write!(out, "</{}>", tag).unwrap();
+        if index > 0 {
+            out.push_str("</next>");
+        }
+    }
+}
+
+fn write_block(out: &mut String, tag: &str, block: &Block) {
+    write_block_start(out, tag, block);
+    write!(out, "</{}>", tag).unwrap();
+}
+
+// The start tag and content of a block, up to its `next`
+fn write_block_start(out: &mut String, tag: &str, block: &Block) {
+    write!(out, "<{}", tag).unwrap();
+    write_attribute(out, "type", &block.block_type);
+    if !block.id.is_empty() {
+        write_attribute(out, "id", &block.id);
+    }
+    let defaults = BlockFlags::default();
+    let flags = [
+        ("deletable", block.flags.deletable, defaults.deletable),
+        ("movable", block.flags.movable, defaults.movable),
+        ("editable", block.flags.editable, defaults.editable),
+        ("collapsed", block.flags.collapsed, defaults.collapsed),
+        ("disabled", block.flags.disabled, defaults.disabled),
+    ];
+    for &(name, value, default) in &flags {
+        if value != default {
+            write_attribute(out, name, &value.to_string());
+        }
+    }
+    if let Some((x, y)) = block.position {
+        write_attribute(out, "x", &x.to_string());
+        write_attribute(out, "y", &y.to_string());
+    }
+    write_attributes(out, &block.extra_attributes);
+    out.push('>');
+
+    if let Some(ref mutation) = block.mutation {
+        write_mutation(out, mutation);
+    }
+    if let Some(ref comment) = block.comment {
+        out.push_str("<comment");
+        if comment.pinned {
+            write_attribute(out, "pinned", "true");
+        }
+        write_size(out, comment.width, comment.height);
+        out.push('>');
+        write_text(out, &comment.text);
+        out.push_str("</comment>");
+    }
+    if let Some(ref data) = block.data {
+        out.push_str("<data>");
+        write_text(out, data);
+        out.push_str("</data>");
+    }
+
+    for (name, value) in &block.fields {
+        out.push_str("<field");
+        write_attribute(out, "name", name);
+        if let Some(variable) = block.field_variables.get(name) {
+            write_attribute(out, "id", &variable.id);
+            write_attribute(out, "variabletype", variable.var_type.as_deref().unwrap_or(""));
+        }
+        if block.preserve_space.contains(name) {
+            write_attribute(out, "xml:space", "preserve");
+        }
+        out.push('>');
+        match *value {
+            FieldValue::SimpleField(ref text) => write_text(out, text),
+            FieldValue::ExpressionField(ref inner) => write_block(out, "block", inner),
+        }
+        out.push_str("</field>");
+    }
+    for (name, content) in &block.values {
+        out.push_str("<value");
+        write_attribute(out, "name", name);
+        out.push('>');
+        match *content {
+            InputContent::Block(ref inner) => write_block(out, "block", inner),
+            InputContent::Shadow(ref inner) => write_block(out, "shadow", inner),
+        }
+        out.push_str("</value>");
+    }
+    for (name, body) in &block.statements {
+        out.push_str("<statement");
+        write_attribute(out, "name", name);
+        out.push('>');
+        write_stack(out, body);
+        out.push_str("</statement>");
+    }
+    for el in &block.extra_elements {
+        write_raw(out, el);
+    }
+}
+
+pub(crate) fn write_mutation(out: &mut String, mutation: &Mutation) {
+    out.push_str("<mutation");
+    write_attributes(out, &mutation.attributes);
+    out.push('>');
+    for child in &mutation.children {
+        write_raw(out, child);
+    }
+    out.push_str("</mutation>");
+}
+
+fn write_raw(out: &mut String, el: &RawElement) {
+    let name = match el.prefix {
+        Some(ref prefix) => format!("{}:{}", prefix, el.name),
+        None => el.name.clone(),
+    };
+    write!(out, "<{}", name).unwrap();
+    if let Some(ref namespace) = el.namespace {
+        let declaration = match el.prefix {
+            Some(ref prefix) => format!("xmlns:{}", prefix),
+            None => "xmlns".to_string(),
+        };
+        if !el.attributes.contains_key(&declaration) {
+            write_attribute(out, &declaration, namespace);
+        }
+    }
+    write_attributes(out, &el.attributes);
+    out.push('>');
+    for child in &el.children {
+        match *child {
+            RawNode::Element(ref child_el) => write_raw(out, child_el),
+            RawNode::Text(ref text) => write_text(out, text),
+        }
+    }
+    write!(out, "</{}>", name).unwrap();
+}
+
+fn write_size(out: &mut String, width: Option<i32>, height: Option<i32>) {
+    if let Some(height) = height {
+        write_attribute(out, "h", &height.to_string());
+    }
+    if let Some(width) = width {
+        write_attribute(out, "w", &width.to_string());
+    }
+}
+
+fn write_attributes(out: &mut String, attributes: &IndexMap<String, String, MapHasher>) {
+    for (name, value) in attributes {
+        write_attribute(out, name, value);
+    }
+}
+
+pub(crate) fn write_attribute(out: &mut String, name: &str, value: &str) {
+    write!(out, " {}=\"", name).unwrap();
+    for ch in value.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str("&#10;"),
+            '\r' => out.push_str("&#13;"),
+            '\t' => out.push_str("&#9;"),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+fn write_text(out: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            _ => out.push(ch),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{
+        ParserOptions,
+        program_from_xml,
+        program_from_xml_with_options,
+    };
+
+    #[test]
+    fn test_program_to_xml() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables>
+                    <variable type="Number" id="v1">count &amp; total</variable>
+                </variables>
+                <block type="main_loop" id="main" deletable="false" x="10" y="20">
+                    <comment pinned="true" w="100" h="50">Runs "forever"</comment>
+                    <statement name="BODY">
+                        <block type="controls_if" id="if" disabled="true">
+                            <mutation else="1"></mutation>
+                            <value name="IF0">
+                                <shadow type="logic_boolean" id="bool"><field name="BOOL">TRUE</field></shadow>
+                            </value>
+                            <next>
+                                <block type="led_on" id="on">
+                                    <field name="TIME">300</field>
+                                    <next>
+                                        <block type="led_off" id="off"></block>
+                                    </next>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+                <comment id="note" x="5" y="6" w="7" h="8">a &lt; b</comment>
+            </xml>
+        "#;
+        let program = program_from_xml(xml).unwrap();
+        let written = program_to_xml(&program);
+        assert_eq!(program_from_xml(&written).unwrap(), program);
+
+        assert_eq!(program_to_xml(&program_from_xml(r#"<xml><block type="a" id="1"><next><block type="b" id="2"><field name="F">x</field></block></next></block></xml>"#).unwrap()),
+            r#"<xml xmlns="https://developers.google.com/blockly/xml"><block type="a" id="1"><next><block type="b" id="2"><field name="F">x</field></block></next></block></xml>"#);
+    }
+
+    #[test]
+    fn test_preserve_unknown() {
+        let xml: &str = r#"<xml><block type="led_on" id="a" plugin-color="red"><data>{"pin": 3}</data><field name="TIME">300</field><custom kind="x"><part>1</part></custom></block></xml>"#;
+        let options = ParserOptions {
+            preserve_unknown: true,
+            ..ParserOptions::default()
+        };
+        let program = program_from_xml_with_options(xml, &options).unwrap();
+        let block = &program.groups[0].blocks[0];
+        assert_eq!(block.extra_attributes["plugin-color"], "red");
+        assert_eq!(block.extra_elements.len(), 1);
+
+        let written = program_to_xml(&program);
+        assert_eq!(written, r#"<xml xmlns="https://developers.google.com/blockly/xml"><block type="led_on" id="a" plugin-color="red"><data>{"pin": 3}</data><field name="TIME">300</field><custom kind="x"><part>1</part></custom></block></xml>"#);
+        assert_eq!(program_from_xml_with_options(&written, &options).unwrap(), program);
+
+        let lossy = program_from_xml(xml).unwrap();
+        assert!(lossy.groups[0].blocks[0].extra_attributes.is_empty());
+        assert!(lossy.groups[0].blocks[0].extra_elements.is_empty());
+    }
+
+    #[test]
+    fn test_namespaces_and_xml_space() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml" xmlns:p="https://example.com/plugin">
+                <block type="text" id="a" p:type="fancy">
+                    <field name="TEXT" xml:space="preserve">  hi  </field>
+                    <custom p:kind="x"></custom>
+                    <p:gadget></p:gadget>
+                    <q:widget xmlns:q="https://example.com/other" size="2"></q:widget>
+                </block>
+            </xml>
+        "#;
+        let options = ParserOptions {
+            preserve_unknown: true,
+            ..ParserOptions::default()
+        };
+        let mut program = program_from_xml_with_options(xml, &options).unwrap();
+        let block = &program.groups[0].blocks[0];
+        assert_eq!(block.block_type, "text");
+        assert_eq!(block.preserve_space, ["TEXT"]);
+        assert_eq!(block.extra_attributes.iter().collect::<Vec<_>>(), [
+            (&"xmlns:p".to_string(), &"https://example.com/plugin".to_string()),
+            (&"p:type".to_string(), &"fancy".to_string()),
+        ]);
+        assert_eq!(block.extra_elements[0].attribute("p:kind"), Some("x"));
+
+        let written = program_to_xml(&program);
+        assert!(written.contains(r#"<field name="TEXT" xml:space="preserve">  hi  </field>"#));
+        assert!(written.contains(r#"<p:gadget xmlns:p="https://example.com/plugin"></p:gadget>"#));
+        assert!(written.contains(r#"<q:widget xmlns:q="https://example.com/other" size="2"></q:widget>"#));
+        assert_eq!(program_from_xml_with_options(&written, &options).unwrap(), program);
+
+        program.normalize();
+        assert_eq!(program.groups[0].blocks[0].field_str("TEXT"), Ok("  hi  "));
+    }
+
+    #[test]
+    fn test_document_order() {
+        let xml: &str = r#"<xml xmlns="https://developers.google.com/blockly/xml"><block type="text_join" id="a"><mutation items="2" style="plain"></mutation><field name="Z">1</field><field name="A">2</field><value name="ITEM1"><block type="text" id="b"></block></value><value name="ITEM0"><block type="text" id="c"></block></value></block></xml>"#;
+        let program = program_from_xml(xml).unwrap();
+        let block = &program.groups[0].blocks[0];
+        assert_eq!(block.fields.keys().collect::<Vec<_>>(), ["Z", "A"]);
+        assert_eq!(block.values.keys().collect::<Vec<_>>(), ["ITEM1", "ITEM0"]);
+        assert_eq!(program_to_xml(&program), xml);
+    }
+
+    #[test]
+    fn test_extras() {
+        let xml: &str = r#"<xml xmlns="https://developers.google.com/blockly/xml"><block type="a" id="1"></block><plugin-state version="2">saved</plugin-state></xml>"#;
+        let program = program_from_xml(xml).unwrap();
+        assert_eq!(program_to_xml(&program), xml);
+    }
+}
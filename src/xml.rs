@@ -0,0 +1,1283 @@
+use std::fs::File;
+use std::io::{
+    BufReader,
+    Read,
+};
+use std::path::Path;
+
+use sxd_document::{
+    parser,
+    Package,
+};
+use sxd_document::dom::{
+    Document,
+    ChildOfRoot,
+    Element,
+};
+
+use super::{
+    Program,
+    StatementBody,
+    Block,
+    BlockComment,
+    FieldValue,
+    FieldVariable,
+    InputContent,
+    Mutation,
+    Variable,
+    WorkspaceComment,
+    RawNode,
+    Diagnostics,
+    ParseOutcome,
+    ParseError,
+    ParserOptions,
+    SourceMap,
+    ValueNext,
+    Warning,
+};
+use super::dom::{
+    Scoped,
+    XmlElement,
+};
+use super::limits::check_depth;
+use super::span::{
+    Outline,
+    Spanned,
+};
+use super::typed::check_colour_field;
+
+impl StatementBody {
+    fn from_input<E: XmlElement>(input_el: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<Self, ParseError> {
+        check_input_children(input_el, options, diagnostics)?;
+        if let Some(block_el) = get_child_element(input_el, "block") {
+            StatementBody::from_element(Some(block_el), options, diagnostics)
+        } else if let Some(shadow_el) = get_child_element(input_el, "shadow") {
+            let mut body = StatementBody::from_element(Some(shadow_el), options, diagnostics)?;
+            body.shadow = true;
+            Ok(body)
+        } else {
+            StatementBody::from_element(None::<E>, options, diagnostics)
+        }
+    }
+
+    fn from_element<E: XmlElement>(first_block: Option<E>, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<Self, ParseError> {
+        let mut blocks = Vec::new();
+        if let Some(el) = first_block {
+            // Create each block, put them into the statement body
+            let mut block_el: E;
+            block_el = el;
+            loop {
+                let (block, next_block) = Block::from_element_with_next(block_el, options, diagnostics)?;
+                blocks.push(block);
+                if let Some(next_block) = next_block {
+                    block_el = next_block;
+                } else {
+                    break;
+                }
+            }
+        }
+        Ok(Self {
+            blocks,
+            shadow: false,
+        })
+    }
+}
+
+impl Block {
+    pub(crate) fn from_element<E: XmlElement>(block_el: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<Self, ParseError> {
+        Self::from_element_with_next(block_el, options, diagnostics).map(|(block, _)| block)
+    }
+
+    /// Also returns the block in the `<next>` element, found in the same pass
+    /// over the children so long stacks are not scanned twice.
+    fn from_element_with_next<E: XmlElement>(block_el: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<(Self, Option<E>), ParseError> {
+        let mut block = Self::new("");
+        let mut next_block_el = None;
+
+        let mut has_type = false;
+        let mut x = None;
+        let mut y = None;
+        let mut preserve_space = false;
+        for (name, value) in block_el.attributes() {
+            let name: &str = &name;
+            let value = value.to_string();
+            match name {
+                "type" => {
+                    block.block_type = value;
+                    has_type = true;
+                },
+                "id" => { block.id = value; },
+                "x" => { x = Some(parse_coordinate(block_el, name, &value)?); },
+                "y" => { y = Some(parse_coordinate(block_el, name, &value)?); },
+                "deletable" => { block.flags.deletable = parse_bool(block_el, name, &value)?; },
+                "movable" => { block.flags.movable = parse_bool(block_el, name, &value)?; },
+                "editable" => { block.flags.editable = parse_bool(block_el, name, &value)?; },
+                "collapsed" => { block.flags.collapsed = parse_bool(block_el, name, &value)?; },
+                "disabled" => { block.flags.disabled = parse_bool(block_el, name, &value)?; },
+                "xml:space" => { preserve_space = value == "preserve"; },
+                _ => {
+                    if options.preserve_unknown {
+                        block.extra_attributes.insert(name.to_string(), value);
+                    }
+                }
+            }
+        }
+        if !has_type {
+            return Err(missing_attribute(block_el, "type"));
+        }
+        if block.id.is_empty() {
+            if options.require_ids {
+                return Err(missing_attribute(block_el, "id"));
+            }
+            diagnostics.push(Warning::MissingId { element: block_el.display_name() });
+        }
+        match (x, y) {
+            (Some(x), Some(y)) => block.position = Some((x, y)),
+            (None, None) => {},
+            _ => diagnostics.push(Warning::PartialPosition { block: block.id.clone() }),
+        }
+
+        for child_el in block_el.child_elements() {
+            let child_name = child_el.name();
+            match child_name {
+                "statement" => {
+                    let statement_el = child_el;
+                    let statement_name = require_attribute(statement_el, "name")?;
+                    if get_child_element(statement_el, "block").is_some() && get_child_element(statement_el, "shadow").is_some() {
+                        diagnostics.push(Warning::DroppedShadow { block: block.id.clone(), input: statement_name.clone() });
+                    }
+                    let statement_body = StatementBody::from_input(statement_el, options, diagnostics)?;
+                    block.statements.insert(statement_name, statement_body);
+                },
+                "value" => {
+                    let value_el = child_el;
+                    let value_name = require_attribute(value_el, "name")?;
+                    if get_child_element(value_el, "block").is_some() && get_child_element(value_el, "shadow").is_some() {
+                        diagnostics.push(Warning::DroppedShadow { block: block.id.clone(), input: value_name.clone() });
+                    }
+                    if let Some(content) = InputContent::from_element(value_el, options, diagnostics)? {
+                        block.values.insert(value_name, content);
+                    }
+                },
+                "mutation" => {
+                    block.mutation = Some(Mutation::from_element(child_el));
+                },
+                "comment" => {
+                    block.comment = Some(BlockComment::from_element(child_el)?);
+                },
+                "field" => {
+                    let field_el = child_el;
+                    let field_name = require_attribute(field_el, "name")?;
+                    let field_value = FieldValue::from_element(field_el, options, diagnostics)?;
+                    if let FieldValue::SimpleField(ref text) = field_value {
+                        check_colour_field(&field_name, text, options, field_el.span())?;
+                        if text.is_empty() {
+                            diagnostics.push(Warning::EmptyField { block: block.id.clone(), field: field_name.clone() });
+                        }
+                    }
+                    if let (FieldValue::SimpleField(_), Some(id)) = (&field_value, get_attribute(field_el, "id")) {
+                        let var_type = get_attribute(field_el, "variabletype").filter(|var_type| !var_type.is_empty());
+                        block.field_variables.insert(field_name.clone(), FieldVariable { id, var_type });
+                    }
+                    let space = get_attribute(field_el, "xml:space");
+                    let preserved = space.map_or(preserve_space, |space| space == "preserve");
+                    if preserved && matches!(field_value, FieldValue::SimpleField(_)) && !block.preserve_space.contains(&field_name) {
+                        block.preserve_space.push(field_name.clone());
+                    }
+                    if block.fields.contains_key(&field_name) {
+                        diagnostics.push(Warning::DuplicateField { block: block.id.clone(), field: field_name.clone() });
+                    }
+                    block.fields.insert(field_name, field_value);
+                },
+                // Returned for StatementBody::from_element to parse
+                "next" => {
+                    if next_block_el.is_none() {
+                        next_block_el = Some(child_el.child_elements()
+                            .into_iter()
+                            .find(|el| el.name() == "block" || el.name() == "shadow"));
+                    }
+                },
+                "data" => {
+                    block.data = Some(get_text(child_el));
+                },
+                _ => {
+                    if let Some(ref handler) = options.unknown_element_handler {
+                        if handler.handle(&child_el.to_raw(), &mut block.extensions) {
+                            continue;
+                        }
+                    }
+                    if options.strict_unknown_elements {
+                        return Err(unexpected_element(block_el, child_el));
+                    }
+                    diagnostics.push(unknown_element(block_el, child_el));
+                    if options.preserve_unknown {
+                        block.extra_elements.push(child_el.to_raw());
+                    }
+                }
+            }
+        }
+
+        Ok((block, next_block_el.flatten()))
+    }
+}
+
+impl BlockComment {
+    fn from_element<E: XmlElement>(comment_el: E) -> Result<Self, ParseError> {
+        let mut comment = Self {
+            text: get_text(comment_el),
+            pinned: false,
+            width: None,
+            height: None,
+        };
+        for (name, value) in comment_el.attributes() {
+            let name: &str = &name;
+            match name {
+                "pinned" => { comment.pinned = parse_bool(comment_el, name, value)?; },
+                "w" => { comment.width = Some(parse_coordinate(comment_el, name, value)?); },
+                "h" => { comment.height = Some(parse_coordinate(comment_el, name, value)?); },
+                _ => {}
+            }
+        }
+        Ok(comment)
+    }
+}
+
+impl WorkspaceComment {
+    fn from_element<E: XmlElement>(comment_el: E) -> Result<Self, ParseError> {
+        let mut comment = Self {
+            id: "".to_string(),
+            text: get_text(comment_el),
+            position: None,
+            width: None,
+            height: None,
+        };
+        let mut x = None;
+        let mut y = None;
+        for (name, value) in comment_el.attributes() {
+            let name: &str = &name;
+            match name {
+                "id" => { comment.id = value.to_string(); },
+                "x" => { x = Some(parse_coordinate(comment_el, name, value)?); },
+                "y" => { y = Some(parse_coordinate(comment_el, name, value)?); },
+                "w" => { comment.width = Some(parse_coordinate(comment_el, name, value)?); },
+                "h" => { comment.height = Some(parse_coordinate(comment_el, name, value)?); },
+                _ => {}
+            }
+        }
+        if let (Some(x), Some(y)) = (x, y) {
+            comment.position = Some((x, y));
+        }
+        Ok(comment)
+    }
+}
+
+impl Variable {
+    fn from_element<E: XmlElement>(variable_el: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<Self, ParseError> {
+        let var_type = get_attribute(variable_el, "type")
+            .and_then(|var_type| if var_type.is_empty() { None } else { Some(var_type) });
+        let id = if options.require_ids {
+            require_attribute(variable_el, "id")?
+        } else {
+            get_attribute(variable_el, "id").unwrap_or_else(|| {
+                diagnostics.push(Warning::MissingId { element: variable_el.display_name() });
+                String::new()
+            })
+        };
+        Ok(Self {
+            name: get_text(variable_el),
+            id,
+            var_type,
+        })
+    }
+}
+
+impl Mutation {
+    pub(crate) fn from_element<E: XmlElement>(mutation_el: E) -> Self {
+        let raw = mutation_el.to_raw();
+        let children = raw.children
+            .into_iter()
+            .filter_map(|child| {
+                match child {
+                    RawNode::Element(el) => Some(el),
+                    RawNode::Text(_) => None,
+                }
+            })
+            .collect();
+        Self {
+            attributes: raw.attributes,
+            children,
+        }
+    }
+}
+
+impl InputContent {
+    fn from_element<E: XmlElement>(input_el: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<Option<Self>, ParseError> {
+        check_input_children(input_el, options, diagnostics)?;
+        if let Some(block_el) = get_child_element(input_el, "block") {
+            Ok(Some(InputContent::Block(value_block_from_element(block_el, options, diagnostics)?)))
+        } else if let Some(shadow_el) = get_child_element(input_el, "shadow") {
+            Ok(Some(InputContent::Shadow(value_block_from_element(shadow_el, options, diagnostics)?)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl FieldValue {
+    fn from_element<E: XmlElement>(field_el: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<Self, ParseError> {
+        if let Some(child_el) = field_el.child_elements().into_iter().next() {
+            return match child_el.name() {
+                "block" | "shadow" => {
+                    let block = value_block_from_element(child_el, options, diagnostics)?;
+                    Ok(FieldValue::ExpressionField(Box::new(block)))
+                },
+                _ => Err(unexpected_element(field_el, child_el))
+            };
+        }
+
+        // Exported workspaces contain empty fields, e.g. a blank text input.
+        Ok(FieldValue::SimpleField(get_text(field_el)))
+    }
+}
+
+// Utilities for creating Blockly data structures
+
+pub fn program_from_xml(xml: &str) -> Result<Program, ParseError> {
+    program_from_xml_with_options(xml, &ParserOptions::default())
+}
+
+/// Read a workspace from any reader, e.g. an HTTP body or stdin.
+pub fn program_from_reader<R: Read>(reader: R) -> Result<Program, ParseError> {
+    let mut xml = String::new();
+    BufReader::new(reader).read_to_string(&mut xml)?;
+    program_from_xml(&xml)
+}
+
+pub fn program_from_file<P: AsRef<Path>>(path: P) -> Result<Program, ParseError> {
+    program_from_reader(File::open(path)?)
+}
+
+pub fn program_from_xml_with_options(xml: &str, options: &ParserOptions) -> Result<Program, ParseError> {
+    if let Some(max_depth) = options.max_depth {
+        check_depth(xml, max_depth)?;
+    }
+    parse_program(xml, options, &mut Diagnostics::new(), &Outline::default())
+}
+
+/// Parse a workspace and also report the recoverable problems that
+/// lenient parsing skips over, such as empty fields and missing ids, and
+/// where each block and error is in `xml`. Finding the spans takes an extra
+/// pass over the input.
+pub fn program_from_xml_with_diagnostics(xml: &str, options: &ParserOptions) -> Result<ParseOutcome, ParseError> {
+    if let Some(max_depth) = options.max_depth {
+        check_depth(xml, max_depth)?;
+    }
+    let outline = Outline::scan(xml);
+    let mut warnings = Diagnostics::new();
+    let program = parse_program(xml, options, &mut warnings, &outline)?;
+    Ok(ParseOutcome {
+        program,
+        warnings,
+        spans: SourceMap::new(&outline),
+    })
+}
+
+#[cfg(not(feature = "quick-xml-backend"))]
+fn parse_program(xml: &str, options: &ParserOptions, diagnostics: &mut Diagnostics, outline: &Outline) -> Result<Program, ParseError> {
+    let package: Package = parse_package(xml)?;
+    let document: Document = package.as_document();
+
+    let xml_element = get_xml_element(document).ok_or(ParseError::MissingRootElement)?;
+    program_from_element(Spanned::new(xml_element, outline), options, diagnostics)
+}
+
+#[cfg(feature = "quick-xml-backend")]
+fn parse_program(xml: &str, options: &ParserOptions, diagnostics: &mut Diagnostics, outline: &Outline) -> Result<Program, ParseError> {
+    let tree = super::quick::parse_root(xml)?;
+    program_from_element(Spanned::new(tree.root(), outline), options, diagnostics)
+}
+
+/// Parse a `<block>` or `<shadow>` element on its own, with the blocks in
+/// its `next` chain.
+pub(crate) fn stack_from_xml(xml: &str, options: &ParserOptions) -> Result<StatementBody, ParseError> {
+    if let Some(max_depth) = options.max_depth {
+        check_depth(xml, max_depth)?;
+    }
+    parse_stack(xml, options)
+}
+
+#[cfg(not(feature = "quick-xml-backend"))]
+fn parse_stack(xml: &str, options: &ParserOptions) -> Result<StatementBody, ParseError> {
+    let package: Package = parse_package(xml)?;
+    let document: Document = package.as_document();
+
+    let root = document.root()
+        .children()
+        .into_iter()
+        .find_map(|child| child.element())
+        .ok_or(ParseError::MissingRootElement)?;
+    stack_from_element(root, options, &mut Diagnostics::new())
+}
+
+#[cfg(feature = "quick-xml-backend")]
+fn parse_stack(xml: &str, options: &ParserOptions) -> Result<StatementBody, ParseError> {
+    let tree = super::quick::parse_root(xml)?;
+    stack_from_element(tree.root(), options, &mut Diagnostics::new())
+}
+
+pub(crate) fn stack_from_element<E: XmlElement>(block_el: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<StatementBody, ParseError> {
+    let block_el = Scoped::new(block_el, options.namespaces.as_deref());
+    let shadow = match block_el.name() {
+        "block" => false,
+        "shadow" => true,
+        _ => {
+            return Err(ParseError::UnexpectedStructure(
+                format!("expected a <block> element, found <{}>", block_el.display_name())
+            ));
+        }
+    };
+    let mut body = StatementBody::from_element(Some(block_el), options, diagnostics)?;
+    body.shadow = shadow;
+    Ok(body)
+}
+
+pub(crate) fn program_from_element<E: XmlElement>(xml_element: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<Program, ParseError> {
+    let xml_element = Scoped::new(xml_element, options.namespaces.as_deref());
+    if xml_element.name() != "xml" {
+        return Err(ParseError::MissingRootElement);
+    }
+    let mut program = Program::new();
+
+    for el in xml_element.child_elements() {
+        match el.name() {
+            "block" => {
+                program.groups.push(StatementBody::from_element(Some(el), options, diagnostics)?);
+            },
+            "variables" => {
+                for variable_el in el.child_elements() {
+                    if variable_el.name() == "variable" {
+                        program.variables.push(Variable::from_element(variable_el, options, diagnostics)?);
+                    } else if options.strict_unknown_elements {
+                        return Err(unexpected_element(el, variable_el));
+                    } else {
+                        diagnostics.push(unknown_element(el, variable_el));
+                    }
+                }
+            },
+            "comment" => {
+                program.comments.push(WorkspaceComment::from_element(el)?);
+            },
+            _ => {
+                if options.strict_unknown_elements {
+                    return Err(unexpected_element(xml_element, el));
+                }
+                diagnostics.push(unknown_element(xml_element, el));
+                program.extras.push(el.to_raw());
+            }
+        }
+    }
+    program.groups.extend(diagnostics.take_detached());
+
+    Ok(program)
+}
+
+// Blocks in value inputs have no next connection, so a `<next>` under one is
+// handled as `ParserOptions::value_next` says.
+fn value_block_from_element<E: XmlElement>(block_el: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<Block, ParseError> {
+    let (block, next_block_el) = Block::from_element_with_next(block_el, options, diagnostics)?;
+    if let Some(next_block_el) = next_block_el {
+        match options.value_next {
+            ValueNext::Drop => {},
+            ValueNext::Detach => {
+                let stack = StatementBody::from_element(Some(next_block_el), options, diagnostics)?;
+                diagnostics.detach(stack);
+            },
+            ValueNext::Reject => {
+                return Err(ParseError::UnexpectedElement {
+                    parent: block_el.display_name(),
+                    element: "next".to_string(),
+                    span: next_block_el.span(),
+                });
+            },
+        }
+        diagnostics.push(Warning::NextOnValueBlock { block: block.id.clone() });
+    }
+    Ok(block)
+}
+
+// Inputs may only hold a block and its shadow. Anything else is an error in
+// strict mode and a warning otherwise.
+pub(crate) fn check_input_children<E: XmlElement>(input_el: E, options: &ParserOptions, diagnostics: &mut Diagnostics) -> Result<(), ParseError> {
+    for child_el in input_el.child_elements() {
+        let child_name = child_el.name();
+        if child_name != "block" && child_name != "shadow" {
+            if options.strict_unknown_elements {
+                return Err(unexpected_element(input_el, child_el));
+            }
+            diagnostics.push(unknown_element(input_el, child_el));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn parse_package(xml: &str) -> Result<Package, ParseError> {
+    parser::parse(xml).map_err(|(position, errors)| {
+        let message = errors.iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        ParseError::Xml { position, message }
+    })
+}
+
+pub(crate) fn get_next_block_element<E: XmlElement>(block_el: E) -> Option<E> {
+    let next_el = get_child_element(block_el, "next")?;
+    next_el.child_elements()
+        .into_iter()
+        .find(|el| el.name() == "block" || el.name() == "shadow")
+}
+
+// General DOM utilities
+
+pub(crate) fn get_xml_element(document: Document) -> Option<Element> {
+    document.root()
+        .children()
+        .iter()
+        .filter_map(|child| {
+            if let &ChildOfRoot::Element(el) = child {
+                if el.name().local_part() == "xml" {
+                    return Some(el);
+                }
+            }
+            None
+        })
+        .next()
+}
+
+pub(crate) fn get_child_element<E: XmlElement>(element: E, name: &str) -> Option<E> {
+    element.child_elements()
+        .into_iter()
+        .find(|el| el.name() == name)
+}
+
+pub(crate) fn get_text<E: XmlElement>(element: E) -> String {
+    element.text()
+}
+
+pub(crate) fn get_attribute<E: XmlElement>(element: E, attribute_name: &str) -> Option<String> {
+    element.attribute(attribute_name).map(|value| value.to_string())
+}
+
+// Blockly writes whole numbers, but coordinates from scaled workspaces can be fractional.
+pub(crate) fn parse_coordinate<E: XmlElement>(element: E, attribute_name: &str, value: &str) -> Result<i32, ParseError> {
+    value.trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(round_coordinate)
+        .ok_or_else(|| invalid_attribute(element, attribute_name, value))
+}
+
+// The nearest `i32`, or `None` for NaN, infinities and values out of range,
+// which `as` would turn into 0 or saturate
+pub(crate) fn round_coordinate(coordinate: f64) -> Option<i32> {
+    let rounded = coordinate.round();
+    if rounded >= f64::from(i32::MIN) && rounded <= f64::from(i32::MAX) {
+        Some(rounded as i32)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn parse_bool<E: XmlElement>(element: E, attribute_name: &str, value: &str) -> Result<bool, ParseError> {
+    match value.trim() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(invalid_attribute(element, attribute_name, value))
+    }
+}
+
+pub(crate) fn require_attribute<E: XmlElement>(element: E, attribute_name: &str) -> Result<String, ParseError> {
+    get_attribute(element, attribute_name)
+        .ok_or_else(|| missing_attribute(element, attribute_name))
+}
+
+pub(crate) fn missing_attribute<E: XmlElement>(element: E, attribute_name: &str) -> ParseError {
+    ParseError::MissingAttribute {
+        element: element.display_name(),
+        attribute: attribute_name.to_string(),
+        span: element.span(),
+    }
+}
+
+pub(crate) fn unexpected_element<E: XmlElement>(parent: E, element: E) -> ParseError {
+    ParseError::UnexpectedElement {
+        parent: parent.display_name(),
+        element: element.display_name(),
+        span: element.span(),
+    }
+}
+
+fn unknown_element<E: XmlElement>(parent: E, element: E) -> Warning {
+    Warning::UnknownElement {
+        parent: parent.display_name(),
+        element: element.display_name(),
+    }
+}
+
+fn invalid_attribute<E: XmlElement>(element: E, attribute_name: &str, value: &str) -> ParseError {
+    ParseError::InvalidAttribute {
+        element: element.display_name(),
+        attribute: attribute_name.to_string(),
+        value: value.to_string(),
+        span: element.span(),
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::BlockFlags;
+
+    fn get_fragment_root<'a>(package: &'a Package) -> Option<Element<'a>> {
+        package.as_document()
+            .root()
+            .children()
+            .iter()
+            .filter_map(|child| {
+                if let &ChildOfRoot::Element(el) = child {
+                    return Some(el);
+                }
+                None
+            })
+            .next()
+    }
+
+    #[test]
+    fn test_new_block() {
+        let xml: &str = r#"
+            <block type="inner_loop" id="]Lb|t?wfd#;s)[llJx8Y">
+                <field name="COUNT">3</field>
+                <statement name="BODY">
+                </statement>
+            </block>
+        "#;
+        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
+        let root_element = get_fragment_root(&fragment).unwrap();
+
+        let block = Block::from_element(root_element, &ParserOptions::default(), &mut Diagnostics::new()).unwrap();
+        assert_eq!(block.block_type, "inner_loop");
+        assert_eq!(block.id, "]Lb|t?wfd#;s)[llJx8Y");
+        let count_field = block.fields.get("COUNT");
+        assert!(count_field.is_some());
+        assert_eq!(count_field.unwrap(), &FieldValue::SimpleField("3".to_string()));
+    }
+
+    #[test]
+    fn test_get_next_block_element() {
+        let xml: &str = r#"
+            <block type="led_on" id="^3xb.m4E9i0;3$R10(=5">
+                <field name="TIME">300</field>
+                <next>
+                    <block type="led_off" id="HX4*sB9=gbJtq$Y{ke6b">
+                        <field name="TIME">100</field>
+                    </block>
+                </next>
+            </block>
+        "#;
+        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
+        let root_element = get_fragment_root(&fragment).unwrap();
+
+        let next_block = get_next_block_element(root_element);
+        assert!(next_block.is_some());
+        let next_block_unwrapped = next_block.unwrap();
+        assert_eq!(get_attribute(next_block_unwrapped, "type"), Some("led_off".to_string()));
+        assert_eq!(get_attribute(next_block_unwrapped, "id"), Some("HX4*sB9=gbJtq$Y{ke6b".to_string()));
+    }
+
+    #[test]
+    fn test_program_from_xml_advanced() {
+        let xml: &str = r#"
+            <xml xmlns="http://www.w3.org/1999/xhtml">
+                <variables></variables>
+                <block type="main_loop" id="[.)/fqUYv92(mzb{?:~u" deletable="false" movable="false" x="50" y="50">
+                    <statement name="BODY">
+                        <block type="inner_loop" id="]Lb|t?wfd#;s)[llJx8Y">
+                            <field name="COUNT">3</field>
+                            <statement name="BODY">
+                                <block type="led_on" id="^3xb.m4E9i0;3$R10(=5">
+                                    <field name="TIME">300</field>
+                                    <next>
+                                        <block type="led_off" id="HX4*sB9=gbJtq$Y{ke6b">
+                                            <field name="TIME">100</field>
+                                        </block>
+                                    </next>
+                                </block>
+                            </statement>
+                            <next>
+                                <block type="led_on" id="kB~f~7W`wkGa0i4z3mHw">
+                                    <field name="TIME">100</field>
+                                    <next>
+                                        <block type="led_off" id="$fdlZB)btzA8YtB/!xz`">
+                                            <field name="TIME">100</field>
+                                        </block>
+                                    </next>
+                                </block>
+                            </next>
+                        </block>
+                    </statement>
+                </block>
+            </xml>
+        "#;
+
+        let program: Program = program_from_xml(xml).unwrap();
+        assert_eq!(program.groups.len(), 1);
+
+        let group = program.groups.first().unwrap();
+        assert_eq!(group.blocks.len(), 1);
+
+        let main_loop_block = group.blocks.first().unwrap();
+        assert_eq!(main_loop_block.block_type, "main_loop");
+        assert_eq!(main_loop_block.id, "[.)/fqUYv92(mzb{?:~u");
+
+        let main_loop_statements = &main_loop_block.statements;
+        assert_eq!(main_loop_statements.len(), 1);
+        assert!(main_loop_statements.contains_key("BODY"));
+
+        let main_loop_body = main_loop_statements.get("BODY");
+        let main_loop_body_statement = main_loop_body.as_ref().unwrap();
+        assert_eq!(main_loop_body_statement.blocks.len(), 3);
+
+        let inner_loop_block = main_loop_body_statement.blocks.first().unwrap();
+        assert_eq!(inner_loop_block.block_type, "inner_loop");
+        assert_eq!(inner_loop_block.id, "]Lb|t?wfd#;s)[llJx8Y");
+        assert_eq!(inner_loop_block.fields.get("COUNT"), Some(&FieldValue::SimpleField("3".to_string())));
+
+        let inner_loop_statement_maybe = inner_loop_block.statements.get("BODY");
+        assert!(inner_loop_statement_maybe.is_some());
+        let inner_loop_statement = inner_loop_statement_maybe.unwrap();
+        assert_eq!(inner_loop_statement.blocks.len(), 2);
+
+        let led_on_block = inner_loop_statement.blocks.first().unwrap();
+        assert_eq!(led_on_block.block_type, "led_on");
+        assert_eq!(led_on_block.id, "^3xb.m4E9i0;3$R10(=5");
+        assert_eq!(led_on_block.fields.get("TIME"), Some(&FieldValue::SimpleField("300".to_string())));
+
+        let led_off_block = inner_loop_statement.blocks.get(1).unwrap();
+        assert_eq!(led_off_block.block_type, "led_off");
+        assert_eq!(led_off_block.id, "HX4*sB9=gbJtq$Y{ke6b");
+    }
+
+    #[test]
+    fn test_block_position_and_flags() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="main" deletable="false" movable="false" x="50" y="50">
+                    <statement name="BODY">
+                        <block type="inner_loop" id="inner"></block>
+                    </statement>
+                </block>
+                <block type="led_on" id="on" x="10"></block>
+            </xml>
+        "#;
+        let outcome = program_from_xml_with_diagnostics(xml, &ParserOptions::default()).unwrap();
+        let main_loop_block = &outcome.program.groups[0].blocks[0];
+        assert_eq!(main_loop_block.position, Some((50, 50)));
+        assert!(!main_loop_block.flags.deletable);
+        assert!(!main_loop_block.flags.movable);
+        assert!(main_loop_block.flags.editable);
+
+        let inner_loop_block = &main_loop_block.statements["BODY"].blocks[0];
+        assert_eq!(inner_loop_block.position, None);
+        assert_eq!(inner_loop_block.flags, BlockFlags::default());
+
+        assert_eq!(outcome.program.groups[1].blocks[0].position, None);
+        assert_eq!(outcome.warnings.into_vec(), [Warning::PartialPosition { block: "on".to_string() }]);
+    }
+
+    #[test]
+    fn test_invalid_coordinates() {
+        for value in &["NaN", "inf", "-infinity", "1e400", "3000000000", "-2147483649"] {
+            let xml = format!(r#"<xml><block type="led_on" x="{}" y="0"></block></xml>"#, value);
+            assert_eq!(program_from_xml(&xml).unwrap_err(), ParseError::InvalidAttribute {
+                element: "block".to_string(),
+                attribute: "x".to_string(),
+                value: value.to_string(),
+                span: None,
+            });
+        }
+
+        let program = program_from_xml(r#"<xml><block type="led_on" x="12.6" y="-2147483648"></block></xml>"#).unwrap();
+        assert_eq!(program.groups[0].blocks[0].position, Some((13, i32::MIN)));
+    }
+
+    #[test]
+    fn test_program_from_xml_errors() {
+        // The quick-xml backend closes the `<block>` instead
+        #[cfg(not(feature = "quick-xml-backend"))]
+        match program_from_xml("<xml><block></xml>") {
+            Err(ParseError::Xml { .. }) => {},
+            other => panic!("Expected XML error, got {:?}", other),
+        }
+
+        assert_eq!(
+            program_from_xml("<workspace></workspace>").unwrap_err(),
+            ParseError::MissingRootElement
+        );
+
+        assert_eq!(
+            program_from_xml(r#"<xml><block id="abc"></block></xml>"#).unwrap_err(),
+            ParseError::MissingAttribute {
+                element: "block".to_string(),
+                attribute: "type".to_string(),
+                span: None,
+            }
+        );
+
+        assert_eq!(
+            program_from_xml(r#"<xml><block type="led_on"><field>300</field></block></xml>"#).unwrap_err(),
+            ParseError::MissingAttribute {
+                element: "field".to_string(),
+                attribute: "name".to_string(),
+                span: None,
+            }
+        );
+
+        assert_eq!(
+            program_from_xml(r#"<xml><block type="led_on" collapsed="yes"></block></xml>"#).unwrap_err(),
+            ParseError::InvalidAttribute {
+                element: "block".to_string(),
+                attribute: "collapsed".to_string(),
+                value: "yes".to_string(),
+                span: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_new_block_with_values() {
+        let xml: &str = r#"
+            <block type="math_arithmetic" id="a">
+                <field name="OP">ADD</field>
+                <value name="A">
+                    <block type="math_number" id="b">
+                        <field name="NUM">1</field>
+                    </block>
+                </value>
+                <value name="B">
+                    <block type="variables_get" id="c">
+                        <field name="VAR">count</field>
+                    </block>
+                </value>
+                <value name="C"></value>
+            </block>
+        "#;
+        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
+        let root_element = get_fragment_root(&fragment).unwrap();
+
+        let block = Block::from_element(root_element, &ParserOptions::default(), &mut Diagnostics::new()).unwrap();
+        assert_eq!(block.values.len(), 2);
+
+        let a = block.values.get("A").unwrap().block();
+        assert_eq!(a.block_type, "math_number");
+        assert_eq!(a.fields.get("NUM"), Some(&FieldValue::SimpleField("1".to_string())));
+
+        let b = block.values.get("B").unwrap().block();
+        assert_eq!(b.block_type, "variables_get");
+        assert_eq!(b.id, "c");
+    }
+
+    #[test]
+    fn test_new_block_with_shadows() {
+        let xml: &str = r#"
+            <block type="controls_repeat_ext" id="a">
+                <value name="TIMES">
+                    <shadow type="math_number" id="b">
+                        <field name="NUM">10</field>
+                    </shadow>
+                    <block type="variables_get" id="c">
+                        <field name="VAR">count</field>
+                    </block>
+                </value>
+                <value name="LIMIT">
+                    <shadow type="math_number" id="d">
+                        <field name="NUM">5</field>
+                    </shadow>
+                </value>
+                <statement name="DO">
+                    <shadow type="led_on" id="e">
+                        <field name="TIME">100</field>
+                    </shadow>
+                </statement>
+                <statement name="ELSE">
+                    <shadow type="led_on" id="f"></shadow>
+                    <block type="led_off" id="g"></block>
+                </statement>
+            </block>
+        "#;
+        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
+        let root_element = get_fragment_root(&fragment).unwrap();
+
+        let mut diagnostics = Diagnostics::new();
+        let block = Block::from_element(root_element, &ParserOptions::default(), &mut diagnostics).unwrap();
+        assert_eq!(diagnostics.into_vec(), [
+            Warning::DroppedShadow { block: "a".to_string(), input: "TIMES".to_string() },
+            Warning::DroppedShadow { block: "a".to_string(), input: "ELSE".to_string() },
+        ]);
+
+        let times = block.values.get("TIMES").unwrap();
+        assert!(!times.is_shadow());
+        assert_eq!(times.block().block_type, "variables_get");
+
+        match block.values.get("LIMIT").unwrap() {
+            InputContent::Shadow(shadow) => assert_eq!(shadow.id, "d"),
+            other => panic!("Expected a shadow block, got {:?}", other),
+        }
+
+        let body = block.statements.get("DO").unwrap();
+        assert!(body.shadow);
+        assert_eq!(body.blocks.len(), 1);
+        assert_eq!(body.blocks[0].block_type, "led_on");
+
+        let body = block.statements.get("ELSE").unwrap();
+        assert!(!body.shadow);
+        assert_eq!(body.blocks[0].id, "g");
+    }
+
+    #[test]
+    fn test_new_block_with_mutation() {
+        let xml: &str = r#"
+            <block type="procedures_defnoreturn" id="a">
+                <mutation statements="false">
+                    <arg name="x" varid="v1"></arg>
+                    <arg name="y" varid="v2"></arg>
+                </mutation>
+                <field name="NAME">do something</field>
+            </block>
+        "#;
+        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
+        let root_element = get_fragment_root(&fragment).unwrap();
+
+        let block = Block::from_element(root_element, &ParserOptions::default(), &mut Diagnostics::new()).unwrap();
+        let mutation = block.mutation.unwrap();
+        assert_eq!(mutation.attribute("statements"), Some("false"));
+        assert_eq!(mutation.children.len(), 2);
+        assert_eq!(mutation.children[0].name, "arg");
+        assert_eq!(mutation.children[0].attribute("name"), Some("x"));
+        assert_eq!(mutation.children[1].attribute("varid"), Some("v2"));
+    }
+
+    #[test]
+    fn test_program_from_xml_variables() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables>
+                    <variable type="" id="q!2Tj3Fx">count</variable>
+                    <variable type="Colour" id="b_Z@8uN)">colour</variable>
+                </variables>
+            </xml>
+        "#;
+
+        let program: Program = program_from_xml(xml).unwrap();
+        assert_eq!(program.variables, vec![
+            Variable {
+                name: "count".to_string(),
+                id: "q!2Tj3Fx".to_string(),
+                var_type: None,
+            },
+            Variable {
+                name: "colour".to_string(),
+                id: "b_Z@8uN)".to_string(),
+                var_type: Some("Colour".to_string()),
+            },
+        ]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="led_on" id="a" x="10" y="20">
+                    <field name="TIME">300</field>
+                    <value name="BRIGHTNESS">
+                        <shadow type="math_number" id="b">
+                            <field name="NUM">50</field>
+                        </shadow>
+                    </value>
+                </block>
+            </xml>
+        "#;
+        let program = program_from_xml(xml).unwrap();
+
+        let json = serde_json::to_value(&program).unwrap();
+        let led_on_json = &json["groups"][0]["blocks"][0];
+        assert_eq!(led_on_json["fields"]["TIME"], "300");
+        assert_eq!(led_on_json["values"]["BRIGHTNESS"]["shadow"]["block_type"], "math_number");
+
+        let restored: Program = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.groups, program.groups);
+    }
+
+    #[test]
+    fn test_new_block_with_empty_field() {
+        let xml: &str = r#"
+            <block type="text" id="a">
+                <field name="TEXT"></field>
+            </block>
+        "#;
+        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
+        let root_element = get_fragment_root(&fragment).unwrap();
+
+        let block = Block::from_element(root_element, &ParserOptions::default(), &mut Diagnostics::new()).unwrap();
+        assert_eq!(block.fields.get("TEXT"), Some(&FieldValue::SimpleField("".to_string())));
+    }
+
+    #[test]
+    fn test_new_block_with_expression_field() {
+        let xml: &str = r#"
+            <block type="led_on" id="a">
+                <field name="TIME">
+                    <block type="math_number" id="b">
+                        <field name="NUM">300</field>
+                    </block>
+                </field>
+            </block>
+        "#;
+        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
+        let root_element = get_fragment_root(&fragment).unwrap();
+
+        let block = Block::from_element(root_element, &ParserOptions::default(), &mut Diagnostics::new()).unwrap();
+        match block.fields.get("TIME") {
+            Some(FieldValue::ExpressionField(expression)) => {
+                assert_eq!(expression.block_type, "math_number");
+                assert_eq!(expression.fields.get("NUM"), Some(&FieldValue::SimpleField("300".to_string())));
+            },
+            other => panic!("Expected an expression field, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_new_block_with_comment() {
+        let xml: &str = r#"
+            <block type="led_on" id="a">
+                <comment pinned="true" h="80" w="160">Turn the light on for dinner</comment>
+                <field name="TIME">300</field>
+            </block>
+        "#;
+        let fragment: Package = parser::parse(xml).expect("Failed to parse XML!");
+        let root_element = get_fragment_root(&fragment).unwrap();
+
+        let block = Block::from_element(root_element, &ParserOptions::default(), &mut Diagnostics::new()).unwrap();
+        assert_eq!(block.comment, Some(BlockComment {
+            text: "Turn the light on for dinner".to_string(),
+            pinned: true,
+            width: Some(160),
+            height: Some(80),
+        }));
+    }
+
+    #[test]
+    fn test_block_data() {
+        let program = program_from_xml(r#"
+            <xml>
+                <block type="led_on" id="a"><data>{"pin": 3} &amp; more</data></block>
+                <block type="led_off" id="b"></block>
+            </xml>
+        "#).unwrap();
+        assert_eq!(program.groups[0].blocks[0].data, Some(r#"{"pin": 3} & more"#.to_string()));
+        assert_eq!(program.groups[1].blocks[0].data, None);
+    }
+
+    #[test]
+    fn test_program_from_xml_workspace_comments() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <comment id="c1" x="120" y="40" w="200" h="100">Blink the LED three times</comment>
+                <block type="main_loop" id="a"></block>
+            </xml>
+        "#;
+
+        let program: Program = program_from_xml(xml).unwrap();
+        assert_eq!(program.groups.len(), 1);
+        assert_eq!(program.comments, vec![
+            WorkspaceComment {
+                id: "c1".to_string(),
+                text: "Blink the LED three times".to_string(),
+                position: Some((120, 40)),
+                width: Some(200),
+                height: Some(100),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_program_from_xml_extras() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="main_loop" id="a"></block>
+                <shadow type="math_number" id="s"><field name="NUM">1</field></shadow>
+                <plugin-state version="2">saved</plugin-state>
+            </xml>
+        "#;
+
+        let program: Program = program_from_xml(xml).unwrap();
+        assert_eq!(program.groups.len(), 1);
+        let names: Vec<_> = program.extras.iter().map(|el| el.name.as_str()).collect();
+        assert_eq!(names, ["shadow", "plugin-state"]);
+        assert_eq!(program.extras[1].attribute("version"), Some("2"));
+        assert_eq!(program.extras[1].text(), "saved");
+
+        assert!(program_from_xml(r#"<xml><block type="a"></block></xml>"#).unwrap().extras.is_empty());
+    }
+
+    #[test]
+    fn test_program_from_xml_with_options() {
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <block type="led_on" id="a">
+                    <field name="TIME">300</field>
+                    <plugin_meta author="sam"></plugin_meta>
+                </block>
+            </xml>
+        "#;
+        assert!(program_from_xml_with_options(xml, &ParserOptions::lenient()).is_ok());
+        assert_eq!(
+            program_from_xml_with_options(xml, &ParserOptions::strict()).unwrap_err(),
+            ParseError::UnexpectedElement {
+                parent: "block".to_string(),
+                element: "plugin_meta".to_string(),
+                span: None,
+            }
+        );
+
+        let xml: &str = r#"<xml><block type="led_on"></block></xml>"#;
+        assert!(program_from_xml_with_options(xml, &ParserOptions::lenient()).is_ok());
+        let options = ParserOptions {
+            require_ids: true,
+            ..ParserOptions::default()
+        };
+        assert_eq!(
+            program_from_xml_with_options(xml, &options).unwrap_err(),
+            ParseError::MissingAttribute {
+                element: "block".to_string(),
+                attribute: "id".to_string(),
+                span: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_program_from_reader_and_file() {
+        let xml: &str = r#"<xml><block type="led_on" id="a"></block></xml>"#;
+        let program = program_from_reader(xml.as_bytes()).unwrap();
+        assert_eq!(program.groups[0].blocks[0].block_type, "led_on");
+
+        let invalid_utf8: &[u8] = &[0x3c, 0x78, 0xff, 0xfe];
+        match program_from_reader(invalid_utf8) {
+            Err(ParseError::Io { kind, .. }) => assert_eq!(kind, std::io::ErrorKind::InvalidData),
+            other => panic!("Expected IO error, got {:?}", other),
+        }
+
+        match program_from_file("does/not/exist.xml") {
+            Err(ParseError::Io { kind, .. }) => assert_eq!(kind, std::io::ErrorKind::NotFound),
+            other => panic!("Expected IO error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "quick-xml-backend"))]
+    fn test_program_from_xml_namespaces() {
+        use super::super::BLOCKLY_NAMESPACE;
+
+        let variants = [
+            r#"<xml xmlns="https://developers.google.com/blockly/xml"><block type="led_on" id="a"></block></xml>"#,
+            r#"<xml xmlns="http://www.w3.org/1999/xhtml"><block type="led_on" id="a"></block></xml>"#,
+            r#"<xml><block type="led_on" id="a"></block></xml>"#,
+            r#"<b:xml xmlns:b="https://developers.google.com/blockly/xml"><b:block type="led_on" id="a"></b:block></b:xml>"#,
+        ];
+        for xml in variants.iter() {
+            let program = program_from_xml(xml).unwrap();
+            assert_eq!(program.groups[0].blocks[0].block_type, "led_on", "{}", xml);
+        }
+
+        let xml: &str = r#"
+            <xml xmlns="https://developers.google.com/blockly/xml" xmlns:ext="https://example.com/ext">
+                <block type="led_on" id="a"><ext:block type="hidden"></ext:block></block>
+                <ext:block type="hidden"></ext:block>
+            </xml>
+        "#;
+        assert_eq!(program_from_xml(xml).unwrap().groups.len(), 1);
+        assert_eq!(
+            program_from_xml_with_options(xml, &ParserOptions::strict()).unwrap_err(),
+            ParseError::UnexpectedElement {
+                parent: "block".to_string(),
+                element: "{https://example.com/ext}block".to_string(),
+                span: None,
+            }
+        );
+        let options = ParserOptions {
+            namespaces: None,
+            ..ParserOptions::default()
+        };
+        assert_eq!(program_from_xml_with_options(xml, &options).unwrap().groups.len(), 2);
+
+        let options = ParserOptions {
+            namespaces: Some(vec![BLOCKLY_NAMESPACE.to_string()]),
+            ..ParserOptions::default()
+        };
+        assert_eq!(
+            program_from_xml_with_options(r#"<xml><block type="led_on"></block></xml>"#, &options).unwrap_err(),
+            ParseError::MissingRootElement
+        );
+    }
+
+    #[test]
+    fn test_next_on_value_block() {
+        let xml: &str = r#"
+            <xml>
+                <block type="text_print" id="print">
+                    <value name="TEXT">
+                        <block type="text" id="text">
+                            <field name="TEXT">hi</field>
+                            <next><block type="led_on" id="on"><next><block type="led_off" id="off"></block></next></block></next>
+                        </block>
+                    </value>
+                </block>
+            </xml>
+        "#;
+        let outcome = program_from_xml_with_diagnostics(xml, &ParserOptions::default()).unwrap();
+        assert_eq!(outcome.warnings.into_vec(), [Warning::NextOnValueBlock { block: "text".to_string() }]);
+        let groups = &outcome.program.groups;
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].blocks[0].values["TEXT"].block().block_type, "text");
+        assert_eq!(groups[1].blocks.iter().map(|block| block.id.as_str()).collect::<Vec<_>>(), ["on", "off"]);
+
+        let options = ParserOptions {
+            value_next: ValueNext::Drop,
+            ..ParserOptions::default()
+        };
+        assert_eq!(program_from_xml_with_options(xml, &options).unwrap().groups.len(), 1);
+        assert!(matches!(
+            program_from_xml_with_options(xml, &ParserOptions::strict()).unwrap_err(),
+            ParseError::UnexpectedElement { ref element, .. } if element == "next"
+        ));
+    }
+
+    #[test]
+    fn test_clone_program() {
+        let template = program_from_xml(r#"
+            <xml xmlns="https://developers.google.com/blockly/xml">
+                <variables><variable id="v1">count</variable></variables>
+                <block type="led_on" id="a"><field name="TIME">300</field></block>
+            </xml>
+        "#).unwrap();
+
+        let mut copy = template.clone();
+        copy.groups[0].blocks[0].fields.insert("TIME".to_string(), FieldValue::SimpleField("100".to_string()));
+        copy.variables.clear();
+
+        assert_eq!(template.groups[0].blocks[0].fields["TIME"], FieldValue::SimpleField("300".to_string()));
+        assert_eq!(template.variables.len(), 1);
+    }
+}